@@ -0,0 +1,373 @@
+//! Generates typed LLUDP message structs and their codecs from message_template.msg.
+//!
+//! The output lands in `$OUT_DIR/generated_messages.rs` and is included by
+//! `src/networking/protocol/generated.rs`. The template is the one committed in the crate root,
+//! so the generated API never depends on the network or on upstream changes; it is copied to
+//! `$OUT_DIR/message_template.msg` for the runtime codec.
+
+use std::collections::HashMap;
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[path = "src/networking/protocol/template_parser.rs"]
+#[allow(dead_code)]
+mod template_parser;
+
+use template_parser::{BlockDefinition, Cardinality, FieldDefinition, Frequency, MessageDefinition, TrustLevel, Encoding};
+
+const RUST_KEYWORDS: &[&str] = &[
+    "as", "break", "const", "continue", "crate", "else", "enum", "extern", "false", "fn", "for",
+    "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return",
+    "self", "static", "struct", "super", "trait", "true", "type", "unsafe", "use", "where",
+    "while", "async", "await", "dyn", "abstract", "become", "box", "do", "final", "macro",
+    "override", "priv", "typeof", "unsized", "virtual", "yield", "try",
+];
+
+/// Keywords that cannot be raw identifiers either.
+const RESERVED_PATH_KEYWORDS: &[&str] = &["self", "super", "crate"];
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=src/networking/protocol/template_parser.rs");
+
+    let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let (template_path, content) = load_template(&manifest_dir);
+    let template = template_parser::parse(&content)
+        .unwrap_or_else(|e| panic!("Failed to parse {}: {}", template_path.display(), e));
+    check_numbers(&template.messages);
+    fs::write(out_dir.join("message_template.msg"), &content)
+        .unwrap_or_else(|e| panic!("Failed to copy {} into OUT_DIR: {}", template_path.display(), e));
+
+    let mut out = String::new();
+    writeln!(out, "// @generated by build.rs from {}. Do not edit.", template_path.file_name().unwrap().to_string_lossy()).unwrap();
+    writeln!(out).unwrap();
+    for message in &template.messages {
+        generate_message(&mut out, message);
+    }
+    generate_enum(&mut out, &template.messages);
+
+    let out_path = out_dir.join("generated_messages.rs");
+    fs::write(&out_path, out).unwrap_or_else(|e| panic!("Failed to write {}: {}", out_path.display(), e));
+}
+
+fn load_template(manifest_dir: &Path) -> (PathBuf, String) {
+    let path = manifest_dir.join("message_template.msg");
+    println!("cargo:rerun-if-changed={}", path.display());
+    let content = fs::read_to_string(&path).unwrap_or_else(|e| panic!("Failed to read {}: {}", path.display(), e));
+    (path, content)
+}
+
+/// Two messages with the same number would make decoding ambiguous.
+fn check_numbers(messages: &[MessageDefinition]) {
+    let mut seen = HashMap::new();
+    for message in messages {
+        let number = message_number(message);
+        if let Some(other) = seen.insert(number.clone(), &message.name) {
+            panic!("{} and {} are both {}", other, message.name, number);
+        }
+    }
+}
+
+/// CamelCase template names to snake_case Rust identifiers ("CPUClassID" -> "cpu_class_id").
+fn snake_case(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut out = String::with_capacity(name.len() + 4);
+    for (i, &c) in chars.iter().enumerate() {
+        if c.is_ascii_uppercase() {
+            if i > 0 {
+                let prev = chars[i - 1];
+                let next_is_lower = chars.get(i + 1).is_some_and(|n| n.is_ascii_lowercase());
+                if prev.is_ascii_lowercase() || prev.is_ascii_digit() || (prev.is_ascii_uppercase() && next_is_lower) {
+                    out.push('_');
+                }
+            }
+            out.push(c.to_ascii_lowercase());
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+fn ident(name: &str) -> String {
+    let snake = snake_case(name);
+    if RESERVED_PATH_KEYWORDS.contains(&snake.as_str()) {
+        format!("{}_", snake)
+    } else if RUST_KEYWORDS.contains(&snake.as_str()) {
+        format!("r#{}", snake)
+    } else {
+        snake
+    }
+}
+
+fn message_number(message: &MessageDefinition) -> String {
+    match message.frequency {
+        Frequency::High => format!("MessageNumber::High({})", message.id),
+        Frequency::Medium => format!("MessageNumber::Medium({})", message.id),
+        Frequency::Low => format!("MessageNumber::Low({})", message.id),
+        Frequency::Fixed => format!("MessageNumber::Fixed(0x{:02X})", message.id & 0xFF),
+    }
+}
+
+/// Maps a template field type to (Rust type, read expression, write statement).
+fn field_codec(field: &FieldDefinition, message: &str) -> (String, String, String) {
+    let name = ident(&field.name);
+    let simple = |ty: &str, read: &str, put: &str, by_ref: bool| {
+        let arg = if by_ref { format!("&self.{}", name) } else { format!("self.{}", name) };
+        (ty.to_string(), format!("r.{}()?", read), format!("w.{}({});", put, arg))
+    };
+    let parts: Vec<&str> = field.type_name.split_whitespace().collect();
+    match parts.as_slice() {
+        ["U8"] => simple("u8", "read_u8", "put_u8", false),
+        ["U16"] => simple("u16", "read_u16", "put_u16", false),
+        ["U32"] => simple("u32", "read_u32", "put_u32", false),
+        ["U64"] => simple("u64", "read_u64", "put_u64", false),
+        ["S8"] => simple("i8", "read_i8", "put_i8", false),
+        ["S16"] => simple("i16", "read_i16", "put_i16", false),
+        ["S32"] => simple("i32", "read_i32", "put_i32", false),
+        ["S64"] => simple("i64", "read_i64", "put_i64", false),
+        ["F32"] => simple("f32", "read_f32", "put_f32", false),
+        ["F64"] => simple("f64", "read_f64", "put_f64", false),
+        ["BOOL"] => simple("bool", "read_bool", "put_bool", false),
+        ["LLUUID"] => simple("uuid::Uuid", "read_uuid", "put_uuid", true),
+        ["LLVector3"] => simple("glam::Vec3", "read_vec3", "put_vec3", false),
+        ["LLVector3d"] => simple("glam::DVec3", "read_vec3d", "put_vec3d", false),
+        ["LLVector4"] => simple("glam::Vec4", "read_vec4", "put_vec4", false),
+        ["LLQuaternion"] => simple("glam::Quat", "read_quat", "put_quat", false),
+        ["IPADDR"] => simple("std::net::Ipv4Addr", "read_ipaddr", "put_ipaddr", false),
+        ["IPPORT"] => simple("u16", "read_ipport", "put_ipport", false),
+        ["Variable", "1"] => (
+            "Vec<u8>".to_string(),
+            "r.read_variable1()?".to_string(),
+            format!("w.put_variable1(\"{}\", &self.{})?;", field.name, name),
+        ),
+        ["Variable", "2"] => (
+            "Vec<u8>".to_string(),
+            "r.read_variable2()?".to_string(),
+            format!("w.put_variable2(\"{}\", &self.{})?;", field.name, name),
+        ),
+        ["Fixed", n] => {
+            let n: usize = n.parse().unwrap_or_else(|_| panic!("Bad Fixed size in {}.{}", message, field.name));
+            (format!("[u8; {}]", n), format!("r.read_fixed::<{}>()?", n), format!("w.put_bytes(&self.{});", name))
+        }
+        _ => panic!("Unsupported field type '{}' in {}.{}", field.type_name, message, field.name),
+    }
+}
+
+fn generate_block(out: &mut String, message: &MessageDefinition, block: &BlockDefinition) {
+    writeln!(out, "    /// `{}` block of `{}` ({:?}{}).", block.name, message.name, block.cardinality,
+        block.count.map(|c| format!(" {}", c)).unwrap_or_default()).unwrap();
+    writeln!(out, "    #[derive(Debug, Clone, PartialEq)]").unwrap();
+    writeln!(out, "    pub struct {} {{", block.name).unwrap();
+    let codecs: Vec<_> = block.fields.iter().map(|f| (f, field_codec(f, &message.name))).collect();
+    for (field, (ty, _, _)) in &codecs {
+        writeln!(out, "        pub {}: {},", ident(&field.name), ty).unwrap();
+    }
+    writeln!(out, "    }}").unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "    impl {} {{", block.name).unwrap();
+    let reader = if codecs.is_empty() { "_r" } else { "r" };
+    writeln!(out, "        pub fn decode({}: &mut WireReader<'_>) -> std::result::Result<Self, CodecError> {{", reader).unwrap();
+    writeln!(out, "            Ok(Self {{").unwrap();
+    for (field, (_, read, _)) in &codecs {
+        writeln!(out, "                {}: {},", ident(&field.name), read).unwrap();
+    }
+    writeln!(out, "            }})").unwrap();
+    writeln!(out, "        }}").unwrap();
+    writeln!(out).unwrap();
+    let writer = if codecs.is_empty() { "_w" } else { "w" };
+    writeln!(out, "        pub fn encode(&self, {}: &mut WireWriter) -> std::result::Result<(), CodecError> {{", writer).unwrap();
+    for (_, (_, _, write)) in &codecs {
+        writeln!(out, "            {}", write).unwrap();
+    }
+    writeln!(out, "            Ok(())").unwrap();
+    writeln!(out, "        }}").unwrap();
+    writeln!(out, "    }}").unwrap();
+    writeln!(out).unwrap();
+}
+
+fn generate_message(out: &mut String, message: &MessageDefinition) {
+    let module = ident(&message.name);
+    let trusted = message.trust == TrustLevel::Trusted;
+    let zerocoded = message.encoding == Encoding::Zerocoded;
+
+    let id = match message.frequency {
+        Frequency::Fixed => format!("0x{:08X}", message.id),
+        _ => message.id.to_string(),
+    };
+    let flags: String = message.flags.iter().map(|f| format!(", {}", f)).collect();
+    writeln!(out, "/// `{}` ({:?} {}, {:?}, {:?}{}).", message.name, message.frequency, id, message.trust, message.encoding, flags)
+        .unwrap();
+    writeln!(out, "#[derive(Debug, Clone, PartialEq)]").unwrap();
+    writeln!(out, "pub struct {} {{", message.name).unwrap();
+    for block in &message.blocks {
+        let ty = match block.cardinality {
+            Cardinality::Single => format!("{}::{}", module, block.name),
+            Cardinality::Multiple | Cardinality::Variable => format!("Vec<{}::{}>", module, block.name),
+        };
+        writeln!(out, "    pub {}: {},", ident(&block.name), ty).unwrap();
+    }
+    writeln!(out, "}}").unwrap();
+    writeln!(out).unwrap();
+
+    writeln!(out, "pub mod {} {{", module).unwrap();
+    // Messages without blocks (e.g. CloseCircuit) get an empty module with nothing to import.
+    if !message.blocks.is_empty() {
+        writeln!(out, "    use crate::networking::protocol::wire::{{CodecError, WireReader, WireWriter}};").unwrap();
+        writeln!(out).unwrap();
+    }
+    for block in &message.blocks {
+        generate_block(out, message, block);
+    }
+    writeln!(out, "}}").unwrap();
+    writeln!(out).unwrap();
+
+    let reader = if message.blocks.is_empty() { "_r" } else { "r" };
+    let writer = if message.blocks.is_empty() { "_w" } else { "w" };
+    writeln!(out, "impl {} {{", message.name).unwrap();
+    writeln!(out, "    pub const NAME: &'static str = \"{}\";", message.name).unwrap();
+    writeln!(out, "    pub const NUMBER: MessageNumber = {};", message_number(message)).unwrap();
+    writeln!(out, "    pub const TRUSTED: bool = {};", trusted).unwrap();
+    writeln!(out, "    pub const ZEROCODED: bool = {};", zerocoded).unwrap();
+    writeln!(out, "    pub const DEPRECATED: bool = {};", message.is_deprecated()).unwrap();
+    writeln!(out, "    pub const UDP_BLACKLISTED: bool = {};", message.is_udp_blacklisted()).unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "    pub fn decode_body({}: &mut WireReader<'_>) -> Result<Self, CodecError> {{", reader).unwrap();
+    for block in &message.blocks {
+        let field = ident(&block.name);
+        let path = format!("{}::{}", module, block.name);
+        match block.cardinality {
+            Cardinality::Single => {
+                writeln!(out, "        let {} = {}::decode(r)?;", field, path).unwrap();
+            }
+            Cardinality::Multiple => {
+                let count = block.count.unwrap_or(1);
+                writeln!(out, "        let mut {} = Vec::with_capacity({});", field, count).unwrap();
+                writeln!(out, "        for _ in 0..{} {{", count).unwrap();
+                writeln!(out, "            {}.push({}::decode(r)?);", field, path).unwrap();
+                writeln!(out, "        }}").unwrap();
+            }
+            Cardinality::Variable => {
+                writeln!(out, "        let count = r.read_block_count()?;").unwrap();
+                writeln!(out, "        let mut {} = Vec::with_capacity(count);", field).unwrap();
+                writeln!(out, "        for _ in 0..count {{").unwrap();
+                writeln!(out, "            {}.push({}::decode(r)?);", field, path).unwrap();
+                writeln!(out, "        }}").unwrap();
+            }
+        }
+    }
+    let fields: Vec<String> = message.blocks.iter().map(|b| ident(&b.name)).collect();
+    writeln!(out, "        Ok(Self {{ {} }})", fields.join(", ")).unwrap();
+    writeln!(out, "    }}").unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "    pub fn encode_body(&self, {}: &mut WireWriter) -> Result<(), CodecError> {{", writer).unwrap();
+    for block in &message.blocks {
+        let field = ident(&block.name);
+        match block.cardinality {
+            Cardinality::Single => {
+                writeln!(out, "        self.{}.encode(w)?;", field).unwrap();
+            }
+            Cardinality::Multiple => {
+                writeln!(out, "        w.check_multiple(\"{}\", {}, self.{}.len())?;", block.name, block.count.unwrap_or(1), field).unwrap();
+                writeln!(out, "        for block in &self.{} {{", field).unwrap();
+                writeln!(out, "            block.encode(w)?;").unwrap();
+                writeln!(out, "        }}").unwrap();
+            }
+            Cardinality::Variable => {
+                writeln!(out, "        w.put_block_count(\"{}\", self.{}.len())?;", block.name, field).unwrap();
+                writeln!(out, "        for block in &self.{} {{", field).unwrap();
+                writeln!(out, "            block.encode(w)?;").unwrap();
+                writeln!(out, "        }}").unwrap();
+            }
+        }
+    }
+    writeln!(out, "        Ok(())").unwrap();
+    writeln!(out, "    }}").unwrap();
+    writeln!(out, "}}").unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "impl From<{0}> for Message {{", message.name).unwrap();
+    writeln!(out, "    fn from(m: {0}) -> Self {{", message.name).unwrap();
+    writeln!(out, "        Message::{}(m)", message.name).unwrap();
+    writeln!(out, "    }}").unwrap();
+    writeln!(out, "}}").unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "impl TemplateMessage for {} {{", message.name).unwrap();
+    writeln!(out, "    const NAME: &'static str = Self::NAME;").unwrap();
+    writeln!(out, "    const NUMBER: MessageNumber = Self::NUMBER;").unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "    fn from_message(message: &Message) -> Option<&Self> {{").unwrap();
+    writeln!(out, "        match message {{").unwrap();
+    writeln!(out, "            Message::{}(m) => Some(m),", message.name).unwrap();
+    writeln!(out, "            _ => None,").unwrap();
+    writeln!(out, "        }}").unwrap();
+    writeln!(out, "    }}").unwrap();
+    writeln!(out, "}}").unwrap();
+    writeln!(out).unwrap();
+}
+
+fn generate_enum(out: &mut String, messages: &[MessageDefinition]) {
+    writeln!(out, "/// Every message in the template.").unwrap();
+    writeln!(out, "#[derive(Debug, Clone, PartialEq)]").unwrap();
+    writeln!(out, "pub enum Message {{").unwrap();
+    for m in messages {
+        writeln!(out, "    {0}({0}),", m.name).unwrap();
+    }
+    writeln!(out, "}}").unwrap();
+    writeln!(out).unwrap();
+
+    writeln!(out, "impl Message {{").unwrap();
+    let accessors = [
+        ("name", "&'static str", "NAME"),
+        ("number", "MessageNumber", "NUMBER"),
+        ("is_trusted", "bool", "TRUSTED"),
+        ("is_zerocoded", "bool", "ZEROCODED"),
+        ("is_deprecated", "bool", "DEPRECATED"),
+        ("is_udp_blacklisted", "bool", "UDP_BLACKLISTED"),
+    ];
+    for (method, ty, konst) in accessors {
+        writeln!(out, "    pub fn {}(&self) -> {} {{", method, ty).unwrap();
+        writeln!(out, "        match self {{").unwrap();
+        for m in messages {
+            writeln!(out, "            Message::{0}(_) => {0}::{1},", m.name, konst).unwrap();
+        }
+        writeln!(out, "        }}").unwrap();
+        writeln!(out, "    }}").unwrap();
+        writeln!(out).unwrap();
+    }
+
+    writeln!(out, "    /// Look up the template name for a message number.").unwrap();
+    writeln!(out, "    pub fn name_of(number: MessageNumber) -> Option<&'static str> {{").unwrap();
+    writeln!(out, "        match number {{").unwrap();
+    for m in messages {
+        writeln!(out, "            {0}::NUMBER => Some({0}::NAME),", m.name).unwrap();
+    }
+    writeln!(out, "            _ => None,").unwrap();
+    writeln!(out, "        }}").unwrap();
+    writeln!(out, "    }}").unwrap();
+    writeln!(out).unwrap();
+
+    writeln!(out, "    /// Decode the body that follows an already-read message number.").unwrap();
+    writeln!(out, "    pub fn decode_body(number: MessageNumber, r: &mut WireReader<'_>) -> Result<Self, CodecError> {{").unwrap();
+    writeln!(out, "        match number {{").unwrap();
+    for m in messages {
+        writeln!(out, "            {0}::NUMBER => Ok(Message::{0}({0}::decode_body(r)?)),", m.name).unwrap();
+    }
+    writeln!(out, "            _ => Err(CodecError::UnknownMessage(number)),").unwrap();
+    writeln!(out, "        }}").unwrap();
+    writeln!(out, "    }}").unwrap();
+    writeln!(out).unwrap();
+
+    writeln!(out, "    /// Encode the message number followed by the body.").unwrap();
+    writeln!(out, "    pub fn encode(&self, w: &mut WireWriter) -> Result<(), CodecError> {{").unwrap();
+    writeln!(out, "        self.number().write(w);").unwrap();
+    writeln!(out, "        match self {{").unwrap();
+    for m in messages {
+        writeln!(out, "            Message::{}(m) => m.encode_body(w),", m.name).unwrap();
+    }
+    writeln!(out, "        }}").unwrap();
+    writeln!(out, "    }}").unwrap();
+    writeln!(out, "}}").unwrap();
+}
@@ -5,6 +5,8 @@ use std::path::Path;
 use std::sync::Arc;
 use tracing::{info, error};
 
+use super::volume::{Volume, VolumeFace};
+
 pub struct Mesh {
     pub vertex_buffer: Arc<Buffer>,
     pub index_buffer: Arc<Buffer>,
@@ -51,6 +53,42 @@ impl MeshLoader {
     pub fn new(device: Arc<wgpu::Device>) -> Self {
         Self { device }
     }
+
+    /// Upload a generated prim, one mesh per face so each can carry its own texture.
+    pub fn load_volume(&self, label: &str, volume: &Volume) -> Vec<Mesh> {
+        volume.faces.iter().map(|face| self.load_volume_face(&format!("{} {:?}", label, face.kind), face)).collect()
+    }
+
+    pub fn load_volume_face(&self, label: &str, face: &VolumeFace) -> Mesh {
+        let vertices: Vec<Vertex> = face
+            .vertices
+            .iter()
+            .map(|v| Vertex { position: v.position.to_array(), normal: v.normal.to_array(), tex_coords: v.uv.to_array() })
+            .collect();
+        self.create_mesh(label, &vertices, &face.indices)
+    }
+
+    fn create_mesh(&self, label: &str, vertices: &[Vertex], indices: &[u16]) -> Mesh {
+        let vertex_buffer = self.device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some(&format!("{} Vertex Buffer", label)),
+                contents: bytemuck::cast_slice(vertices),
+                usage: BufferUsages::VERTEX,
+            }
+        );
+        let index_buffer = self.device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some(&format!("{} Index Buffer", label)),
+                contents: bytemuck::cast_slice(indices),
+                usage: BufferUsages::INDEX,
+            }
+        );
+        Mesh {
+            vertex_buffer: Arc::new(vertex_buffer),
+            index_buffer: Arc::new(index_buffer),
+            num_indices: indices.len() as u32,
+        }
+    }
 }
 
 #[async_trait]
@@ -106,26 +144,7 @@ impl super::manager::AssetLoader<Mesh> for MeshLoader {
                 // Bottom
                 20, 22, 21, 20, 23, 22,
             ];
-            let vertex_buffer = self.device.create_buffer_init(
-                &wgpu::util::BufferInitDescriptor {
-                    label: Some(&format!("{:?} Vertex Buffer", path)),
-                    contents: bytemuck::cast_slice(VERTICES),
-                    usage: BufferUsages::VERTEX,
-                }
-            );
-            let index_buffer = self.device.create_buffer_init(
-                &wgpu::util::BufferInitDescriptor {
-                    label: Some(&format!("{:?} Index Buffer", path)),
-                    contents: bytemuck::cast_slice(INDICES),
-                    usage: BufferUsages::INDEX,
-                }
-            );
-            let num_indices = INDICES.len() as u32;
-            return Ok(Mesh {
-                vertex_buffer: Arc::new(vertex_buffer),
-                index_buffer: Arc::new(index_buffer),
-                num_indices,
-            });
+            return Ok(self.create_mesh(&format!("{:?}", path), VERTICES, INDICES));
         }
 
         // For now, we'll just create a dummy mesh.
@@ -142,26 +161,6 @@ impl super::manager::AssetLoader<Mesh> for MeshLoader {
             0, 1, 2,
         ];
 
-        let vertex_buffer = self.device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor {
-                label: Some(&format!("{:?} Vertex Buffer", path)),
-                contents: bytemuck::cast_slice(VERTICES),
-                usage: BufferUsages::VERTEX,
-            }
-        );
-        let index_buffer = self.device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor {
-                label: Some(&format!("{:?} Index Buffer", path)),
-                contents: bytemuck::cast_slice(INDICES),
-                usage: BufferUsages::INDEX,
-            }
-        );
-        let num_indices = INDICES.len() as u32;
-
-        Ok(Mesh {
-            vertex_buffer: Arc::new(vertex_buffer),
-            index_buffer: Arc::new(index_buffer),
-            num_indices,
-        })
+        Ok(self.create_mesh(&format!("{:?}", path), VERTICES, INDICES))
     }
 }
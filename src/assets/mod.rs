@@ -1,6 +1,7 @@
 pub mod manager;
 pub mod cache;
 pub mod mesh;
+pub mod volume;
 pub mod texture;
 pub mod material;
 pub mod shader;
@@ -0,0 +1,647 @@
+//! Procedural prim geometry, after the viewer's LLVolume.
+//!
+//! A prim is a 2D profile (circle, square, triangle or half circle, optionally cut and hollowed)
+//! swept along a path: a straight line for boxes, cylinders and prisms, or a circle for spheres,
+//! tori, tubes and rings. On the way the profile can twist, taper, shear and skew. Generation runs
+//! on the CPU and yields one [`VolumeFace`] per texturable face, which
+//! [`MeshLoader::load_volume`](super::mesh::MeshLoader::load_volume) uploads.
+//!
+//! Positions are in the prim's unit box, -0.5..0.5 on each axis, before the object's scale.
+//! Sculpted and mesh prims are described by extra params instead and are not generated here.
+
+use std::f32::consts::{FRAC_1_SQRT_2, PI, TAU};
+
+use glam::{Quat, Vec2, Vec3};
+
+use crate::world::object_update::PrimShape;
+
+pub const PROFILE_CIRCLE: u8 = 0x00;
+pub const PROFILE_SQUARE: u8 = 0x01;
+pub const PROFILE_ISOTRI: u8 = 0x02;
+pub const PROFILE_EQUALTRI: u8 = 0x03;
+pub const PROFILE_RIGHTTRI: u8 = 0x04;
+pub const PROFILE_CIRCLE_HALF: u8 = 0x05;
+const PROFILE_MASK: u8 = 0x0F;
+
+pub const HOLE_SAME: u8 = 0x00;
+pub const HOLE_CIRCLE: u8 = 0x10;
+pub const HOLE_SQUARE: u8 = 0x20;
+pub const HOLE_TRIANGLE: u8 = 0x30;
+const HOLE_MASK: u8 = 0xF0;
+
+pub const PATH_LINE: u8 = 0x10;
+pub const PATH_CIRCLE: u8 = 0x20;
+pub const PATH_CIRCLE2: u8 = 0x30;
+pub const PATH_TEST: u8 = 0x40;
+pub const PATH_FLEXIBLE: u8 = 0x80;
+
+const CUT_QUANTA: f32 = 0.00002;
+const HOLLOW_QUANTA: f32 = 0.00002;
+const SCALE_QUANTA: f32 = 0.01;
+const SHEAR_QUANTA: f32 = 0.01;
+const TAPER_QUANTA: f32 = 0.01;
+const REV_QUANTA: f32 = 0.015;
+
+/// The narrowest cut and the largest hollow the viewer builds.
+const MIN_CUT: f32 = 0.02;
+const MAX_HOLLOW: f32 = 0.95;
+
+/// Sides of a full circle at detail 1.
+const MIN_DETAIL_FACES: f32 = 6.0;
+
+/// Radius that makes an n-gon with few sides fill the unit box, by side count.
+const TABLE_SCALE: [f32; 8] = [1.0, 1.0, 1.0, 0.5, FRAC_1_SQRT_2, 0.53, 0.525, 0.5];
+
+/// Cuts closer than this to a corner land on it.
+const EPSILON: f32 = 1e-4;
+
+/// Level of detail. Flat sides stay as they are; round ones get more segments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Lod {
+    Low,
+    Medium,
+    High,
+    Highest,
+}
+
+impl Lod {
+    pub const ALL: [Lod; 4] = [Lod::Low, Lod::Medium, Lod::High, Lod::Highest];
+
+    /// The viewer's detail scale for the level.
+    pub fn detail(self) -> f32 {
+        match self {
+            Lod::Low => 1.0,
+            Lod::Medium => 1.5,
+            Lod::High => 2.5,
+            Lod::Highest => 4.0,
+        }
+    }
+}
+
+/// The cross section. The low bits of `curve` pick the shape, the high bits the hollow's shape.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProfileParams {
+    pub curve: u8,
+    pub begin: f32,
+    pub end: f32,
+    pub hollow: f32,
+}
+
+/// What the profile is swept along.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PathParams {
+    pub curve: u8,
+    pub begin: f32,
+    pub end: f32,
+    /// Top size on a line path (0..2; below 1 narrows the top, above it the bottom), hole size
+    /// on a circular one.
+    pub scale: Vec2,
+    pub shear: Vec2,
+    /// In half turns on a line path and full turns on a circular one.
+    pub twist_begin: f32,
+    pub twist_end: f32,
+    pub radius_offset: f32,
+    pub taper: Vec2,
+    pub revolutions: f32,
+    pub skew: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VolumeParams {
+    pub profile: ProfileParams,
+    pub path: PathParams,
+}
+
+impl Default for VolumeParams {
+    /// A plain box.
+    fn default() -> Self {
+        Self {
+            profile: ProfileParams { curve: PROFILE_SQUARE, begin: 0.0, end: 1.0, hollow: 0.0 },
+            path: PathParams {
+                curve: PATH_LINE,
+                begin: 0.0,
+                end: 1.0,
+                scale: Vec2::ONE,
+                shear: Vec2::ZERO,
+                twist_begin: 0.0,
+                twist_end: 0.0,
+                radius_offset: 0.0,
+                taper: Vec2::ZERO,
+                revolutions: 1.0,
+                skew: 0.0,
+            },
+        }
+    }
+}
+
+impl VolumeParams {
+    /// Dequantize the shape of an object update.
+    pub fn from_shape(shape: &PrimShape) -> Self {
+        let scale = |v: u8| (200 - i32::from(v)).clamp(0, 200) as f32 * SCALE_QUANTA;
+        Self {
+            profile: ProfileParams {
+                curve: shape.profile_curve,
+                begin: f32::from(shape.profile_begin) * CUT_QUANTA,
+                end: 1.0 - f32::from(shape.profile_end) * CUT_QUANTA,
+                hollow: f32::from(shape.profile_hollow) * HOLLOW_QUANTA,
+            },
+            path: PathParams {
+                curve: shape.path_curve,
+                begin: f32::from(shape.path_begin) * CUT_QUANTA,
+                end: 1.0 - f32::from(shape.path_end) * CUT_QUANTA,
+                scale: Vec2::new(scale(shape.path_scale_x), scale(shape.path_scale_y)),
+                shear: Vec2::new(f32::from(shape.path_shear_x as i8), f32::from(shape.path_shear_y as i8)) * SHEAR_QUANTA,
+                twist_begin: f32::from(shape.path_twist_begin) * SCALE_QUANTA,
+                twist_end: f32::from(shape.path_twist) * SCALE_QUANTA,
+                radius_offset: f32::from(shape.path_radius_offset) * SCALE_QUANTA,
+                taper: Vec2::new(f32::from(shape.path_taper_x), f32::from(shape.path_taper_y)) * TAPER_QUANTA,
+                revolutions: 1.0 + f32::from(shape.path_revolutions) * REV_QUANTA,
+                skew: f32::from(shape.path_skew) * SCALE_QUANTA,
+            },
+        }
+    }
+}
+
+/// Which part of the prim a face is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FaceKind {
+    /// The cap where the path starts, the bottom of a box.
+    PathBegin,
+    /// The cap where the path ends, the top of a box.
+    PathEnd,
+    /// An outer side: one per side of a flat-sided profile, one in all for a round one.
+    Outer(u8),
+    /// The inside of a hollow.
+    Inner,
+    /// The faces a profile cut opens up.
+    ProfileBegin,
+    ProfileEnd,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VolumeVertex {
+    pub position: Vec3,
+    pub normal: Vec3,
+    pub uv: Vec2,
+}
+
+/// One face as an indexed triangle list, wound counter-clockwise seen from outside.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VolumeFace {
+    pub kind: FaceKind,
+    pub vertices: Vec<VolumeVertex>,
+    pub indices: Vec<u16>,
+}
+
+/// A generated prim. Faces come in the order the viewer numbers a box's: top cap, outer sides,
+/// hollow, cut faces, bottom cap.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Volume {
+    pub faces: Vec<VolumeFace>,
+}
+
+impl Volume {
+    pub fn generate(params: &VolumeParams, lod: Lod) -> Self {
+        let detail = lod.detail();
+        let profile = Profile::generate(&params.profile, detail);
+        let path = Path::generate(&params.path, detail);
+        let mut faces = Vec::new();
+
+        if path.open {
+            let mut face = VolumeFace::new(FaceKind::PathEnd);
+            face.cap(&path.points[path.points.len() - 1], &profile, false);
+            faces.push(face);
+        }
+        for (side, run) in split_sides(&profile.outer, profile.outer_sides) {
+            let mut face = VolumeFace::new(FaceKind::Outer(side as u8));
+            face.sweep(&path, &columns(&run, profile.outer_sides, side), profile.closed && profile.outer_sides.is_none());
+            faces.push(face);
+        }
+        if let Some(inner) = &profile.inner {
+            // Walked backwards so the triangles face into the hollow.
+            let mut face = VolumeFace::new(FaceKind::Inner);
+            for (side, run) in split_sides(inner, profile.hole_sides).into_iter().rev() {
+                let row: Vec<(Vec2, f32)> =
+                    columns(&run, profile.hole_sides, side).into_iter().rev().map(|(pos, u)| (pos, 1.0 - u)).collect();
+                face.sweep(&path, &row, profile.closed && profile.hole_sides.is_none());
+            }
+            faces.push(face);
+        }
+        if profile.cut {
+            let inner = profile.inner.as_deref();
+            let inner_first = inner.and_then(|points| points.first()).map_or(Vec2::ZERO, |p| p.pos);
+            let inner_last = inner.and_then(|points| points.last()).map_or(Vec2::ZERO, |p| p.pos);
+            let mut face = VolumeFace::new(FaceKind::ProfileBegin);
+            face.sweep(&path, &[(inner_first, 0.0), (profile.outer[0].pos, 1.0)], false);
+            faces.push(face);
+            let mut face = VolumeFace::new(FaceKind::ProfileEnd);
+            face.sweep(&path, &[(profile.outer[profile.outer.len() - 1].pos, 0.0), (inner_last, 1.0)], false);
+            faces.push(face);
+        }
+        if path.open {
+            let mut face = VolumeFace::new(FaceKind::PathBegin);
+            face.cap(&path.points[0], &profile, true);
+            faces.push(face);
+        }
+
+        faces.retain(|face| !face.indices.is_empty());
+        Self { faces }
+    }
+
+    pub fn face(&self, kind: FaceKind) -> Option<&VolumeFace> {
+        self.faces.iter().find(|face| face.kind == kind)
+    }
+}
+
+impl VolumeFace {
+    fn new(kind: FaceKind) -> Self {
+        Self { kind, vertices: Vec::new(), indices: Vec::new() }
+    }
+
+    /// Sweep a row of profile points, given with their u coordinate, along the path: one row of
+    /// vertices per path point. `wrap` shares the normals of the row's ends, for a closed round
+    /// profile.
+    fn sweep(&mut self, path: &Path, row: &[(Vec2, f32)], wrap: bool) {
+        let (base, first_index) = (self.vertices.len(), self.indices.len());
+        let (rows, cols) = (path.points.len(), row.len());
+        for point in &path.points {
+            self.vertices.extend(
+                row.iter()
+                    .map(|&(pos, u)| VolumeVertex { position: point.place(pos), normal: Vec3::ZERO, uv: Vec2::new(u, point.t) }),
+            );
+        }
+        for r in 0..rows - 1 {
+            for c in 0..cols - 1 {
+                let a = (base + r * cols + c) as u16;
+                let d = a + cols as u16;
+                self.indices.extend_from_slice(&[a, a + 1, d + 1, a, d + 1, d]);
+            }
+        }
+
+        self.accumulate_normals(first_index);
+        let at = |r: usize, c: usize| base + r * cols + c;
+        if wrap {
+            for r in 0..rows {
+                self.share_normal(at(r, 0), at(r, cols - 1));
+            }
+        }
+        if !path.open {
+            for c in 0..cols {
+                self.share_normal(at(0, c), at(rows - 1, c));
+            }
+        }
+        for vertex in &mut self.vertices[base..] {
+            vertex.normal = vertex.normal.normalize_or_zero();
+        }
+    }
+
+    /// A flat cap across the profile at one end of the path. `flip` turns it to face backwards
+    /// along the path.
+    fn cap(&mut self, point: &PathPoint, profile: &Profile, flip: bool) {
+        let base = self.vertices.len();
+        let normal = if flip { -(point.rot * Vec3::Z) } else { point.rot * Vec3::Z };
+        let vertex = |pos: Vec2| VolumeVertex {
+            position: point.place(pos),
+            normal,
+            uv: Vec2::new(if flip { 0.5 - pos.x } else { pos.x + 0.5 }, pos.y + 0.5),
+        };
+        let outer = profile.outer.len();
+        self.vertices.extend(profile.outer.iter().map(|p| vertex(p.pos)));
+
+        let mut triangles = Vec::new();
+        match &profile.inner {
+            Some(inner) => {
+                // Zip the two edges together, advancing along whichever is further behind.
+                self.vertices.extend(inner.iter().map(|p| vertex(p.pos)));
+                let (mut i, mut j) = (0, 0);
+                while i + 1 < outer || j + 1 < inner.len() {
+                    if j + 1 == inner.len() || (i + 1 < outer && profile.outer[i + 1].t <= inner[j + 1].t) {
+                        triangles.push([i, i + 1, outer + j]);
+                        i += 1;
+                    } else {
+                        triangles.push([i, outer + j + 1, outer + j]);
+                        j += 1;
+                    }
+                }
+            }
+            None => {
+                self.vertices.push(vertex(Vec2::ZERO));
+                triangles.extend((0..outer - 1).map(|i| [outer, i, i + 1]));
+            }
+        }
+        for [a, b, c] in triangles {
+            let (b, c) = if flip { (c, b) } else { (b, c) };
+            self.indices.extend([a, b, c].map(|i| (base + i) as u16));
+        }
+    }
+
+    fn accumulate_normals(&mut self, first_index: usize) {
+        for tri in self.indices[first_index..].chunks(3) {
+            let [a, b, c] = [tri[0], tri[1], tri[2]].map(usize::from);
+            let (pa, pb, pc) = (self.vertices[a].position, self.vertices[b].position, self.vertices[c].position);
+            let normal = (pb - pa).cross(pc - pa);
+            for i in [a, b, c] {
+                self.vertices[i].normal += normal;
+            }
+        }
+    }
+
+    fn share_normal(&mut self, a: usize, b: usize) {
+        let normal = self.vertices[a].normal + self.vertices[b].normal;
+        self.vertices[a].normal = normal;
+        self.vertices[b].normal = normal;
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ProfilePoint {
+    pos: Vec2,
+    /// How far round the profile, 0..1.
+    t: f32,
+}
+
+struct Profile {
+    outer: Vec<ProfilePoint>,
+    /// The hollow's edge, running the same way as `outer`.
+    inner: Option<Vec<ProfilePoint>>,
+    /// Side counts of flat-sided shapes, whose sides are textured and shaded separately.
+    outer_sides: Option<usize>,
+    hole_sides: Option<usize>,
+    /// The profile goes all the way round.
+    closed: bool,
+    /// The profile has ends that need closing off.
+    cut: bool,
+}
+
+impl Profile {
+    fn generate(params: &ProfileParams, detail: f32) -> Self {
+        let kind = params.curve & PROFILE_MASK;
+        let half = kind == PROFILE_CIRCLE_HALF;
+        let begin = params.begin.clamp(0.0, 1.0 - MIN_CUT);
+        let end = params.end.clamp(begin + MIN_CUT, 1.0);
+        let hollow = params.hollow.clamp(0.0, MAX_HOLLOW);
+
+        let shape = Shape::of(kind, detail);
+        let outer = ngon(shape.sides, shape.offset, shape.ang_scale, begin, end, 1.0);
+        let hole = (hollow > 0.0).then(|| match params.curve & HOLE_MASK {
+            _ if half => shape,
+            HOLE_CIRCLE => Shape::of(PROFILE_CIRCLE, detail),
+            HOLE_SQUARE => Shape::of(PROFILE_SQUARE, detail),
+            HOLE_TRIANGLE => Shape::of(PROFILE_EQUALTRI, detail),
+            _ => shape,
+        });
+        // The hole starts where the outside does, so cuts go straight across.
+        let inner = hole.map(|hole| ngon(hole.sides, shape.offset, shape.ang_scale, begin, end, hollow));
+        let whole = end - begin > 1.0 - EPSILON;
+
+        Self {
+            outer,
+            inner,
+            outer_sides: shape.faceted.then_some(shape.sides),
+            hole_sides: hole.filter(|hole| hole.faceted).map(|hole| hole.sides),
+            closed: whole && !half,
+            // A half circle's ends lie on the axis it spins round, so only a hollow opens them.
+            cut: !whole || (half && hollow > 0.0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Shape {
+    sides: usize,
+    /// Where corner 0 is, in turns.
+    offset: f32,
+    /// How much of a full turn the shape spans.
+    ang_scale: f32,
+    faceted: bool,
+}
+
+impl Shape {
+    fn of(kind: u8, detail: f32) -> Self {
+        let circle = (MIN_DETAIL_FACES * detail).floor() as usize;
+        match kind {
+            PROFILE_SQUARE => Self { sides: 4, offset: -0.375, ang_scale: 1.0, faceted: true },
+            PROFILE_ISOTRI | PROFILE_EQUALTRI | PROFILE_RIGHTTRI => Self { sides: 3, offset: 0.0, ang_scale: 1.0, faceted: true },
+            PROFILE_CIRCLE_HALF => Self { sides: circle / 2, offset: 0.5, ang_scale: 0.5, faceted: false },
+            _ => Self { sides: circle, offset: 0.0, ang_scale: 1.0, faceted: false },
+        }
+    }
+}
+
+/// Points round an n-gon from `begin` to `end`: both cut positions and every corner between.
+fn ngon(sides: usize, offset: f32, ang_scale: f32, begin: f32, end: f32, scale: f32) -> Vec<ProfilePoint> {
+    let n = sides as f32;
+    let total_sides = (n / ang_scale).round() as usize;
+    let radius = TABLE_SCALE.get(total_sides).copied().unwrap_or(0.5) * scale;
+    let corner = |k: usize| {
+        let ang = TAU * (k as f32 / n * ang_scale + offset);
+        Vec2::new(ang.cos(), ang.sin()) * radius
+    };
+    let at = |t: f32| {
+        let k = ((t * n).floor() as usize).min(sides - 1);
+        ProfilePoint { pos: corner(k).lerp(corner(k + 1), t * n - k as f32), t }
+    };
+
+    let mut points = vec![at(begin)];
+    points.extend((1..sides).map(|k| k as f32 / n).filter(|&t| t > begin + EPSILON && t < end - EPSILON).map(at));
+    points.push(at(end));
+    points
+}
+
+/// Break a run of points at a flat-sided shape's corners, labelling each piece with its side.
+fn split_sides(points: &[ProfilePoint], sides: Option<usize>) -> Vec<(usize, Vec<ProfilePoint>)> {
+    let Some(n) = sides else {
+        return vec![(0, points.to_vec())];
+    };
+    let mut runs: Vec<(usize, Vec<ProfilePoint>)> = Vec::new();
+    for pair in points.windows(2) {
+        let side = (((pair[0].t + pair[1].t) * 0.5 * n as f32) as usize).min(n - 1);
+        match runs.last_mut() {
+            Some((last, run)) if *last == side => run.push(pair[1]),
+            _ => runs.push((side, pair.to_vec())),
+        }
+    }
+    runs
+}
+
+/// Profile points with their u coordinate, which runs across each side of a flat-sided shape
+/// and round the whole of a round one.
+fn columns(run: &[ProfilePoint], sides: Option<usize>, side: usize) -> Vec<(Vec2, f32)> {
+    run.iter()
+        .map(|p| match sides {
+            Some(n) => (p.pos, (p.t * n as f32 - side as f32).clamp(0.0, 1.0)),
+            None => (p.pos, p.t),
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy)]
+struct PathPoint {
+    pos: Vec3,
+    rot: Quat,
+    scale: Vec2,
+    /// How far along the path, 0..1.
+    t: f32,
+}
+
+impl PathPoint {
+    fn place(&self, p: Vec2) -> Vec3 {
+        self.pos + self.rot * Vec3::new(p.x * self.scale.x, p.y * self.scale.y, 0.0)
+    }
+}
+
+struct Path {
+    points: Vec<PathPoint>,
+    /// The ends do not meet, so they need caps.
+    open: bool,
+}
+
+impl Path {
+    fn generate(params: &PathParams, detail: f32) -> Self {
+        let begin = params.begin.clamp(0.0, 1.0 - MIN_CUT);
+        let end = params.end.clamp(begin + MIN_CUT, 1.0);
+        // Twisting needs more segments to stay smooth.
+        let twist_mag = (params.twist_end - params.twist_begin).abs();
+        let twist_detail = twist_mag * 3.5 * (detail - 0.5);
+        match params.curve {
+            PATH_CIRCLE | PATH_CIRCLE2 | PATH_TEST => Self::circle(params, begin, end, MIN_DETAIL_FACES * detail + twist_detail),
+            _ => Self::line(params, begin, end, twist_detail),
+        }
+    }
+
+    /// Up the z axis. Scale tapers one end and shear slides the top against the bottom.
+    fn line(params: &PathParams, begin: f32, end: f32, twist_detail: f32) -> Self {
+        let begin_scale = Vec2::new(2.0 - params.scale.x.max(1.0), 2.0 - params.scale.y.max(1.0));
+        let end_scale = params.scale.min(Vec2::ONE);
+        let segments = (twist_detail.floor() as usize).max(1);
+        let points = (0..=segments)
+            .map(|i| {
+                let t = lerp(begin, end, i as f32 / segments as f32);
+                PathPoint {
+                    pos: Vec3::new(params.shear.x * (t - 0.5), params.shear.y * (t - 0.5), t - 0.5),
+                    rot: Quat::from_rotation_z(lerp(params.twist_begin, params.twist_end, t) * PI),
+                    scale: begin_scale.lerp(end_scale, t),
+                    t,
+                }
+            })
+            .collect();
+        Self { points, open: true }
+    }
+
+    /// Round the x axis, `revolutions` times. Scale is the size of the hole in the middle, and
+    /// taper, radius offset and skew change the ring along the way.
+    fn circle(params: &PathParams, begin: f32, end: f32, detail_sides: f32) -> Self {
+        let sides = ((detail_sides.floor() * params.revolutions).floor() as usize).max(3);
+        let skew_mag = params.skew.abs();
+        let hole = Vec2::new(params.scale.x * (1.0 - skew_mag), params.scale.y);
+        let taper = Vec2::ONE - params.taper;
+        let taper_begin = Vec2::new(2.0 - taper.x.max(1.0), 2.0 - taper.y.max(1.0));
+        let taper_end = taper.min(Vec2::ONE);
+
+        let mut radius_begin = TABLE_SCALE.get(sides).copied().unwrap_or(0.5) * (1.0 - hole.y);
+        let mut radius_end = radius_begin;
+        if params.radius_offset < 0.0 {
+            radius_begin *= 1.0 + params.radius_offset;
+        } else {
+            radius_end *= 1.0 - params.radius_offset;
+        }
+        let open = end - begin < 1.0 - EPSILON
+            || skew_mag > 0.001
+            || (taper_end - taper_begin).abs().max_element() > 0.001
+            || (radius_end - radius_begin).abs() > 0.001;
+
+        let point = |t: f32| {
+            let ang = TAU * params.revolutions * t;
+            let radius = lerp(radius_begin, radius_end, t);
+            let (s, c) = (ang.sin() * radius, ang.cos() * radius);
+            let twist = Quat::from_rotation_z(lerp(params.twist_begin, params.twist_end, t) * TAU - PI);
+            PathPoint {
+                pos: Vec3::new(params.shear.x * s + lerp(-params.skew, params.skew, t) * 0.5, c + params.shear.y * s, s),
+                rot: Quat::from_rotation_x(ang) * twist,
+                scale: hole * taper_begin.lerp(taper_end, t),
+                t,
+            }
+        };
+        let n = sides as f32;
+        let mut points = vec![point(begin)];
+        points.extend((1..sides).map(|k| k as f32 / n).filter(|&t| t > begin + EPSILON && t < end - EPSILON).map(point));
+        points.push(point(end));
+        Self { points, open }
+    }
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use FaceKind::*;
+
+    fn shape(path_curve: u8, profile_curve: u8) -> PrimShape {
+        PrimShape { path_curve, profile_curve, path_scale_x: 100, path_scale_y: 100, ..PrimShape::default() }
+    }
+
+    fn kinds(volume: &Volume) -> Vec<FaceKind> {
+        volume.faces.iter().map(|face| face.kind).collect()
+    }
+
+    fn vertices(volume: &Volume) -> impl Iterator<Item = &VolumeVertex> {
+        volume.faces.iter().flat_map(|face| face.vertices.iter())
+    }
+
+    /// On a convex prim every triangle faces away from the middle.
+    fn assert_outward(volume: &Volume) {
+        for face in &volume.faces {
+            for tri in face.indices.chunks(3) {
+                let [a, b, c] = [tri[0], tri[1], tri[2]].map(|i| face.vertices[usize::from(i)].position);
+                let normal = (b - a).cross(c - a);
+                assert!(normal.length() < 1e-6 || normal.dot(a + b + c) > 0.0, "{:?} has a triangle facing in", face.kind);
+            }
+        }
+    }
+
+    #[test]
+    fn test_box_faces_and_cuts() {
+        let params = VolumeParams::from_shape(&shape(PATH_LINE, PROFILE_SQUARE));
+        assert!((params.path.scale - Vec2::ONE).abs().max_element() < 1e-6);
+        let volume = Volume::generate(&params, Lod::Low);
+        assert_eq!(kinds(&volume), [PathEnd, Outer(0), Outer(1), Outer(2), Outer(3), PathBegin]);
+        assert!(vertices(&volume).all(|v| v.position.abs().max_element() < 0.5 + 1e-5));
+        assert!(vertices(&volume).all(|v| (v.normal.length() - 1.0).abs() < 1e-4));
+        let top = volume.face(PathEnd).unwrap();
+        assert!(top.vertices.iter().all(|v| (v.normal - Vec3::Z).length() < 1e-6 && (v.position.z - 0.5).abs() < 1e-6));
+        assert_outward(&volume);
+        // Flat sides do not get more detailed.
+        assert_eq!(Volume::generate(&params, Lod::Highest), volume);
+
+        // Cutting away half and hollowing exposes the inside and the cut faces.
+        let mut cut = shape(PATH_LINE, PROFILE_SQUARE);
+        (cut.profile_begin, cut.profile_end, cut.profile_hollow) = (12500, 12500, 25000);
+        let volume = Volume::generate(&VolumeParams::from_shape(&cut), Lod::Low);
+        assert_eq!(kinds(&volume), [PathEnd, Outer(1), Outer(2), Inner, ProfileBegin, ProfileEnd, PathBegin]);
+        let begin = volume.face(ProfileBegin).unwrap();
+        assert!(begin.vertices.iter().all(|v| (v.normal + Vec3::new(1.0, 1.0, 0.0).normalize()).length() < 1e-4));
+    }
+
+    #[test]
+    fn test_round_prims_and_lods() {
+        let cylinder = VolumeParams::from_shape(&shape(PATH_LINE, PROFILE_CIRCLE));
+        let counts = Lod::ALL.map(|lod| vertices(&Volume::generate(&cylinder, lod)).count());
+        assert!(counts.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", counts);
+
+        // A sphere is one closed face, every vertex on its surface.
+        let sphere = Volume::generate(&VolumeParams::from_shape(&shape(PATH_CIRCLE, PROFILE_CIRCLE_HALF)), Lod::Highest);
+        assert_eq!(kinds(&sphere), [Outer(0)]);
+        assert!(vertices(&sphere).all(|v| (v.position.length() - 0.5).abs() < 1e-4 && v.normal.dot(v.position) > 0.0));
+        assert_outward(&sphere);
+
+        // A torus with a square hollow and a quarter of its ring cut away gets caps.
+        let mut torus = shape(PATH_CIRCLE, PROFILE_CIRCLE | HOLE_SQUARE);
+        (torus.path_scale_y, torus.path_end, torus.profile_hollow) = (175, 12500, 25000);
+        let volume = Volume::generate(&VolumeParams::from_shape(&torus), Lod::Medium);
+        assert_eq!(kinds(&volume), [PathEnd, Outer(0), Inner, PathBegin]);
+        assert!(vertices(&volume).all(|v| v.position.abs().max_element() < 0.5 + 1e-5));
+    }
+}
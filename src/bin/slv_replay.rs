@@ -0,0 +1,156 @@
+//! Replay an LLUDP packet capture through the client's decoders.
+//!
+//! Usage: `slv-replay [--verbose] [--inbound] <capture>`
+//!
+//! Record a capture by running the client with `SLV_CAPTURE=<path>`. Every datagram is framed,
+//! decoded against the message template and run through `MessageCodec`. Inbound messages are
+//! dispatched on one `MessageBus` per simulator carrying the networking actor's handlers, so
+//! chat, teleport and object updates go through the same code as a live session; `--verbose`
+//! prints the events and commands they produce. The first simulator is treated as the agent's
+//! region and the rest as neighbours until a CrossedRegion says otherwise.
+//!
+//! Messages the template does not define are counted but are not failures. Exits non-zero if
+//! any datagram fails to frame or decode, so a capture can be used as a regression test.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::process::ExitCode;
+
+use slv_rust::networking::actor::{self, NetCommand};
+use slv_rust::networking::capture::{self, CaptureReader, Direction, ReplayError};
+use slv_rust::networking::circuit::{self, CircuitRole};
+use slv_rust::networking::handlers::MessageBus;
+use slv_rust::world::objects::ObjectStore;
+
+/// What a live circuit to one simulator would have: its bus and role.
+struct ReplayCircuit {
+    bus: MessageBus,
+    role: CircuitRole,
+}
+
+fn main() -> ExitCode {
+    let mut verbose = false;
+    let mut inbound_only = false;
+    let mut path = None;
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "-v" | "--verbose" => verbose = true,
+            "--inbound" => inbound_only = true,
+            "-h" | "--help" => {
+                println!("usage: slv-replay [--verbose] [--inbound] <capture>");
+                return ExitCode::SUCCESS;
+            }
+            _ if path.is_none() => path = Some(arg),
+            _ => {
+                eprintln!("usage: slv-replay [--verbose] [--inbound] <capture>");
+                return ExitCode::from(2);
+            }
+        }
+    }
+    let Some(path) = path else {
+        eprintln!("usage: slv-replay [--verbose] [--inbound] <capture>");
+        return ExitCode::from(2);
+    };
+
+    let reader = match CaptureReader::open(&path) {
+        Ok(reader) => reader,
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let objects = ObjectStore::shared();
+    let (event_tx, events) = crossbeam_channel::unbounded();
+    let (command_tx, mut commands) = tokio::sync::mpsc::unbounded_channel();
+    let mut circuits: HashMap<SocketAddr, ReplayCircuit> = HashMap::new();
+    let mut start = None;
+    let (mut datagrams, mut decoded, mut legacy, mut unknown) = (0usize, 0usize, 0usize, 0usize);
+    let mut damaged = false;
+    for record in reader {
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                damaged = true;
+                break;
+            }
+        };
+        if inbound_only && record.direction == Direction::Outbound {
+            continue;
+        }
+        datagrams += 1;
+        let elapsed = record.timestamp.saturating_sub(*start.get_or_insert(record.timestamp));
+        let arrow = match record.direction {
+            Direction::Inbound => "<-",
+            Direction::Outbound => "->",
+        };
+        let region_count = circuits.len() as u64;
+        let replay_circuit = circuits.entry(record.circuit).or_insert_with(|| {
+            // Stand-in region handle: the capture does not say which region a simulator runs.
+            let region_handle = region_count + 1;
+            let bus = MessageBus::default();
+            let role = CircuitRole::new(region_count > 0);
+            actor::attach_handlers(&bus, &role, region_handle, &objects, &event_tx, &command_tx.downgrade());
+            ReplayCircuit { bus, role }
+        });
+        match capture::replay_record(&record, &replay_circuit.bus) {
+            Ok(replayed) => {
+                decoded += 1;
+                legacy += replayed.legacy_decoded as usize;
+                println!("{:>10.6} {} {} #{} {}", elapsed.as_secs_f64(), arrow, record.circuit, replayed.sequence, replayed.message.name());
+                if verbose {
+                    println!("    {:?}", replayed.message);
+                    if let Some(reply) = circuit::automatic_reply(&replayed.message).filter(|_| record.direction == Direction::Inbound) {
+                        println!("    reply: {}", reply.name());
+                    }
+                }
+            }
+            Err(ReplayError::UnknownMessage(number)) => {
+                unknown += 1;
+                println!("{:>10.6} {} {} not in template: {}", elapsed.as_secs_f64(), arrow, record.circuit, number);
+            }
+            Err(e) => {
+                println!("{:>10.6} {} {} FAILED ({} bytes): {}", elapsed.as_secs_f64(), arrow, record.circuit, record.data.len(), e);
+                if verbose {
+                    println!("    {:02X?}", record.data);
+                }
+            }
+        }
+
+        for event in events.try_iter() {
+            if verbose {
+                println!("    event: {:?}", event);
+            }
+        }
+        while let Ok(command) = commands.try_recv() {
+            if verbose {
+                println!("    command: {:?}", command);
+            }
+            // The actor would promote the new region's circuit and demote the rest.
+            if let NetCommand::CrossedRegion(crossing) = &command {
+                if circuits.contains_key(&crossing.sim_addr) {
+                    for (addr, replay_circuit) in &circuits {
+                        replay_circuit.role.set_child(*addr != crossing.sim_addr);
+                    }
+                }
+            }
+        }
+    }
+
+    let failed = datagrams - decoded - unknown;
+    println!(
+        "{} datagrams, {} decoded ({} by MessageCodec), {} not in template, {} failed; {} objects",
+        datagrams,
+        decoded,
+        legacy,
+        unknown,
+        failed,
+        objects.lock().unwrap().len()
+    );
+    if damaged || failed > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
@@ -0,0 +1,278 @@
+//! Saved accounts, kept encrypted in the config dir.
+//!
+//! The account list is TOML encrypted with ChaCha20-Poly1305. Only the `$1$` MD5 password
+//! hash the login protocol accepts is stored, never the password. The `mfa_hash` tokens that let
+//! a grid skip the MFA challenge live in the same file. The key comes from a [`SecretBackend`];
+//! the default keeps it in a private file next to the store.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use directories::ProjectDirs;
+use serde::{Serialize, Deserialize};
+
+const ACCOUNTS_FILE: &str = "accounts.dat";
+const KEY_FILE: &str = "accounts.key";
+const MAGIC: &[u8; 5] = b"SLVA1";
+const NONCE_LEN: usize = 12;
+
+#[derive(Debug, thiserror::Error)]
+pub enum AccountStoreError {
+    #[error("account store I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("account store is corrupt or was encrypted with another key")]
+    Corrupt,
+}
+
+/// A remembered login.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedAccount {
+    pub first: String,
+    pub last: String,
+    /// Nick of the grid in the grid registry.
+    pub grid: String,
+    /// `last`, `home` or a `uri:region&x&y&z` start location.
+    pub last_start: String,
+    /// `$1$` + MD5 of the password, as sent in the login `passwd` field.
+    pub password_hash: String,
+}
+
+impl SavedAccount {
+    /// Display name, e.g. `Test Resident`.
+    pub fn name(&self) -> String {
+        format!("{} {}", self.first, self.last)
+    }
+
+    fn matches(&self, other: &SavedAccount) -> bool {
+        self.first.eq_ignore_ascii_case(&other.first) && self.last.eq_ignore_ascii_case(&other.last) && self.grid == other.grid
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct AccountsToml {
+    accounts: Vec<SavedAccount>,
+    /// `mfa_hash` from the last successful MFA login, keyed by [`mfa_key`]. Kept for accounts
+    /// that are not remembered too.
+    #[serde(default)]
+    mfa_hashes: BTreeMap<String, String>,
+    /// The SOCKS5 proxy password, which the plaintext settings file leaves out.
+    #[serde(default)]
+    socks5_password: String,
+}
+
+/// Key an account's MFA hash the same however its name was typed.
+fn mfa_key(first: &str, last: &str, grid: &str) -> String {
+    format!("{} {}@{}", first, last, grid).to_lowercase()
+}
+
+/// Where the store's encryption key lives: an OS keyring, or a file for tests and platforms without one.
+pub trait SecretBackend: Send + Sync {
+    fn load_key(&self) -> io::Result<Option<[u8; 32]>>;
+    fn store_key(&self, key: &[u8; 32]) -> io::Result<()>;
+}
+
+/// Keeps the key in a file readable only by the user.
+pub struct FileSecretBackend {
+    path: PathBuf,
+}
+
+impl FileSecretBackend {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl SecretBackend for FileSecretBackend {
+    fn load_key(&self) -> io::Result<Option<[u8; 32]>> {
+        match fs::read(&self.path) {
+            Ok(bytes) => bytes
+                .try_into()
+                .map(Some)
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("{} is not a 32-byte key", self.path.display()))),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn store_key(&self, key: &[u8; 32]) -> io::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        write_private(&self.path, key)
+    }
+}
+
+pub struct AccountStore<B: SecretBackend = FileSecretBackend> {
+    path: PathBuf,
+    backend: B,
+}
+
+impl AccountStore<FileSecretBackend> {
+    /// The store in the user's config dir, with its key file beside it.
+    pub fn open_default() -> Option<Self> {
+        let dir = ProjectDirs::from("com", "slv", "slv-rust")?.config_dir().to_path_buf();
+        Some(Self::new(dir.join(ACCOUNTS_FILE), FileSecretBackend::new(dir.join(KEY_FILE))))
+    }
+}
+
+impl<B: SecretBackend> AccountStore<B> {
+    pub fn new(path: impl Into<PathBuf>, backend: B) -> Self {
+        Self { path: path.into(), backend }
+    }
+
+    /// The saved accounts; none if nothing has been saved yet.
+    pub fn load(&self) -> Result<Vec<SavedAccount>, AccountStoreError> {
+        Ok(self.read()?.accounts)
+    }
+
+    pub fn save(&self, accounts: &[SavedAccount]) -> Result<(), AccountStoreError> {
+        let mut toml = self.read()?;
+        toml.accounts = accounts.to_vec();
+        self.write(&toml)
+    }
+
+    /// The `mfa_hash` to send when logging in to `grid` as `first last`, if an MFA login succeeded before.
+    pub fn mfa_hash(&self, first: &str, last: &str, grid: &str) -> Result<Option<String>, AccountStoreError> {
+        Ok(self.read()?.mfa_hashes.remove(&mfa_key(first, last, grid)))
+    }
+
+    pub fn save_mfa_hash(&self, first: &str, last: &str, grid: &str, hash: &str) -> Result<(), AccountStoreError> {
+        let mut toml = self.read()?;
+        toml.mfa_hashes.insert(mfa_key(first, last, grid), hash.to_string());
+        self.write(&toml)
+    }
+
+    pub fn socks5_password(&self) -> Result<String, AccountStoreError> {
+        Ok(self.read()?.socks5_password)
+    }
+
+    pub fn save_socks5_password(&self, password: &str) -> Result<(), AccountStoreError> {
+        let mut toml = self.read()?;
+        if toml.socks5_password == password {
+            return Ok(());
+        }
+        toml.socks5_password = password.to_string();
+        self.write(&toml)
+    }
+
+    fn read(&self) -> Result<AccountsToml, AccountStoreError> {
+        let data = match fs::read(&self.path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(AccountsToml::default()),
+            Err(e) => return Err(e.into()),
+        };
+        let Some(key) = self.backend.load_key()? else {
+            return Err(AccountStoreError::Corrupt);
+        };
+        let body = data.strip_prefix(MAGIC.as_slice()).filter(|body| body.len() > NONCE_LEN).ok_or(AccountStoreError::Corrupt)?;
+        let (nonce, ciphertext) = body.split_at(NONCE_LEN);
+        let plaintext = ChaCha20Poly1305::new(Key::from_slice(&key))
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| AccountStoreError::Corrupt)?;
+        let text = String::from_utf8(plaintext).map_err(|_| AccountStoreError::Corrupt)?;
+        toml::from_str(&text).map_err(|_| AccountStoreError::Corrupt)
+    }
+
+    fn write(&self, toml: &AccountsToml) -> Result<(), AccountStoreError> {
+        let key = self.key()?;
+        let text = toml::to_string_pretty(toml).unwrap();
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = ChaCha20Poly1305::new(Key::from_slice(&key))
+            .encrypt(&nonce, text.as_bytes())
+            .map_err(|_| AccountStoreError::Corrupt)?;
+        let mut data = Vec::with_capacity(MAGIC.len() + NONCE_LEN + ciphertext.len());
+        data.extend_from_slice(MAGIC);
+        data.extend_from_slice(&nonce);
+        data.extend_from_slice(&ciphertext);
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        write_private(&self.path, &data)?;
+        Ok(())
+    }
+
+    /// Add or update an account (matched by name and grid), most recent first.
+    pub fn remember(&self, account: SavedAccount) -> Result<(), AccountStoreError> {
+        let mut accounts = self.load()?;
+        accounts.retain(|existing| !existing.matches(&account));
+        accounts.insert(0, account);
+        self.save(&accounts)
+    }
+
+    /// Drop an account and its MFA hash.
+    pub fn forget(&self, account: &SavedAccount) -> Result<(), AccountStoreError> {
+        let mut toml = self.read()?;
+        toml.accounts.retain(|existing| !existing.matches(account));
+        toml.mfa_hashes.remove(&mfa_key(&account.first, &account.last, &account.grid));
+        self.write(&toml)
+    }
+
+    fn key(&self) -> io::Result<[u8; 32]> {
+        if let Some(key) = self.backend.load_key()? {
+            return Ok(key);
+        }
+        let key: [u8; 32] = ChaCha20Poly1305::generate_key(&mut OsRng).into();
+        self.backend.store_key(&key)?;
+        Ok(key)
+    }
+}
+
+fn write_private(path: &Path, data: &[u8]) -> io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    io::Write::write_all(&mut options.open(path)?, data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accounts_roundtrip_encrypted() {
+        let dir = std::env::temp_dir().join(format!("slv-accounts-{}", uuid::Uuid::new_v4()));
+        let store = AccountStore::new(dir.join(ACCOUNTS_FILE), FileSecretBackend::new(dir.join(KEY_FILE)));
+        assert!(store.load().unwrap().is_empty());
+
+        let account = SavedAccount {
+            first: "Test".to_string(),
+            last: "Resident".to_string(),
+            grid: "agni".to_string(),
+            last_start: "last".to_string(),
+            password_hash: "$1$5f4dcc3b5aa765d61d8327deb882cf99".to_string(),
+        };
+        store.remember(account.clone()).unwrap();
+        store.remember(SavedAccount { last_start: "home".to_string(), first: "test".to_string(), ..account.clone() }).unwrap();
+        let accounts = store.load().unwrap();
+        assert_eq!(accounts.len(), 1);
+        assert_eq!(accounts[0].last_start, "home");
+        assert!(!fs::read(dir.join(ACCOUNTS_FILE)).unwrap().windows(8).any(|w| w == b"5f4dcc3b"));
+
+        // A different key cannot read the store
+        let other = AccountStore::new(dir.join(ACCOUNTS_FILE), FileSecretBackend::new(dir.join("other.key")));
+        other.backend.store_key(&[7; 32]).unwrap();
+        assert!(matches!(other.load(), Err(AccountStoreError::Corrupt)));
+
+        store.save_mfa_hash("TEST", "resident", "agni", "0123456789abcdef").unwrap();
+        assert_eq!(store.mfa_hash("Test", "Resident", "agni").unwrap().as_deref(), Some("0123456789abcdef"));
+        assert_eq!(store.mfa_hash("Test", "Resident", "aditi").unwrap(), None);
+        assert!(!fs::read(dir.join(ACCOUNTS_FILE)).unwrap().windows(8).any(|w| w == b"01234567"));
+
+        store.save_socks5_password("hunter22").unwrap();
+        assert_eq!(store.socks5_password().unwrap(), "hunter22");
+        assert!(!fs::read(dir.join(ACCOUNTS_FILE)).unwrap().windows(8).any(|w| w == b"hunter22"));
+
+        store.forget(&account).unwrap();
+        assert!(store.load().unwrap().is_empty());
+        assert_eq!(store.mfa_hash("Test", "Resident", "agni").unwrap(), None);
+        let _ = fs::remove_dir_all(dir);
+    }
+}
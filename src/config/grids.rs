@@ -0,0 +1,182 @@
+use serde::{Serialize, Deserialize};
+
+/// Which login protocol dialect a grid speaks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum GridKind {
+    #[default]
+    SecondLife,
+    OpenSim,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum GridError {
+    #[error("invalid grid info: {0}")]
+    Xml(String),
+    #[error("grid info has no <{0}>")]
+    Missing(&'static str),
+}
+
+/// A grid the client can log into.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Grid {
+    /// Short unique id, e.g. `agni` or the OpenSim `gridnick`.
+    pub nick: String,
+    pub name: String,
+    pub login_uri: String,
+    #[serde(default)]
+    pub helper_uri: String,
+    #[serde(default)]
+    pub web_profile_url: String,
+    /// Where the login `openid_token` is posted; only Second Life grids have one.
+    #[serde(default)]
+    pub openid_url: String,
+    #[serde(default)]
+    pub kind: GridKind,
+}
+
+impl Grid {
+    /// The Second Life main grid.
+    pub fn agni() -> Self {
+        Self {
+            nick: "agni".to_string(),
+            name: "Second Life".to_string(),
+            login_uri: "https://login.agni.lindenlab.com/cgi-bin/login.cgi".to_string(),
+            helper_uri: "https://secondlife.com/helpers/".to_string(),
+            web_profile_url: "https://my.secondlife.com/".to_string(),
+            openid_url: "https://id.secondlife.com/openid/webkit".to_string(),
+            kind: GridKind::SecondLife,
+        }
+    }
+
+    /// The Second Life beta grid.
+    pub fn aditi() -> Self {
+        Self {
+            nick: "aditi".to_string(),
+            name: "Second Life Beta".to_string(),
+            login_uri: "https://login.aditi.lindenlab.com/cgi-bin/login.cgi".to_string(),
+            helper_uri: "https://secondlife.aditi.lindenlab.com/helpers/".to_string(),
+            web_profile_url: "https://my.aditi.lindenlab.com/".to_string(),
+            openid_url: "https://id.aditi.lindenlab.com/openid/webkit".to_string(),
+            kind: GridKind::SecondLife,
+        }
+    }
+
+    /// The Terms of Service page for `tos_id`, on the same site as the grid's helpers. OpenSim
+    /// grids have none.
+    pub fn tos_url(&self, tos_id: &str) -> Option<String> {
+        if self.kind != GridKind::SecondLife {
+            return None;
+        }
+        let host = self.helper_uri.find("://")? + 3;
+        let origin = match self.helper_uri[host..].find('/') {
+            Some(path) => &self.helper_uri[..host + path],
+            None => &self.helper_uri,
+        };
+        Some(format!("{}/app/tos/tos.php?id={}", origin, tos_id))
+    }
+
+    /// URL of the OpenSim grid info document for a login URI.
+    pub fn grid_info_url(login_uri: &str) -> String {
+        format!("{}/get_grid_info", login_uri.trim_end_matches('/'))
+    }
+
+    /// Build an OpenSim grid from a `get_grid_info` response. `login_uri` is used when the
+    /// document does not name its own login server.
+    pub fn from_grid_info(xml: &str, login_uri: &str) -> Result<Self, GridError> {
+        let doc = roxmltree::Document::parse(xml).map_err(|e| GridError::Xml(e.to_string()))?;
+        let root = doc.root_element();
+        if !root.has_tag_name("gridinfo") {
+            return Err(GridError::Xml(format!("root element is <{}>, expected <gridinfo>", root.tag_name().name())));
+        }
+        let field = |names: &[&str]| {
+            names.iter().find_map(|name| {
+                root.children()
+                    .find(|n| n.has_tag_name(*name))
+                    .and_then(|n| n.text())
+                    .map(str::trim)
+                    .filter(|text| !text.is_empty())
+                    .map(str::to_string)
+            })
+        };
+        let name = field(&["gridname"]).ok_or(GridError::Missing("gridname"))?;
+        let nick = field(&["gridnick"]).unwrap_or_else(|| name.to_lowercase().replace(' ', ""));
+        Ok(Self {
+            nick,
+            name,
+            login_uri: field(&["login", "loginuri"]).unwrap_or_else(|| login_uri.to_string()),
+            helper_uri: field(&["helperuri", "economy"]).unwrap_or_default(),
+            web_profile_url: field(&["web_profile_url", "profile"]).unwrap_or_default(),
+            openid_url: String::new(),
+            kind: GridKind::OpenSim,
+        })
+    }
+}
+
+/// The known grids and which one the login screen uses.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GridRegistry {
+    pub grids: Vec<Grid>,
+    pub selected: String,
+}
+
+impl Default for GridRegistry {
+    fn default() -> Self {
+        Self { grids: vec![Grid::agni(), Grid::aditi()], selected: "agni".to_string() }
+    }
+}
+
+impl GridRegistry {
+    pub fn get(&self, nick: &str) -> Option<&Grid> {
+        self.grids.iter().find(|grid| grid.nick == nick)
+    }
+
+    /// The selected grid, falling back to the first one (or Agni if the list is empty).
+    pub fn selected(&self) -> Grid {
+        self.get(&self.selected).or_else(|| self.grids.first()).cloned().unwrap_or_else(Grid::agni)
+    }
+
+    /// Add a grid, replacing any grid with the same nick.
+    pub fn insert(&mut self, grid: Grid) {
+        match self.grids.iter_mut().find(|existing| existing.nick == grid.nick) {
+            Some(existing) => *existing = grid,
+            None => self.grids.push(grid),
+        }
+    }
+
+    pub fn remove(&mut self, nick: &str) -> Option<Grid> {
+        let index = self.grids.iter().position(|grid| grid.nick == nick)?;
+        Some(self.grids.remove(index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_import_grid_info() {
+        let xml = r#"<gridinfo>
+  <platform>OpenSim</platform>
+  <gridname>Test Grid</gridname>
+  <login>http://127.0.0.1:9000/</login>
+  <economy>http://127.0.0.1:9000/</economy>
+  <profile></profile>
+</gridinfo>"#;
+        let grid = Grid::from_grid_info(xml, "http://localhost:9000").unwrap();
+        assert_eq!(grid.nick, "testgrid");
+        assert_eq!(grid.login_uri, "http://127.0.0.1:9000/");
+        assert_eq!(grid.helper_uri, "http://127.0.0.1:9000/");
+        assert_eq!(grid.web_profile_url, "");
+        assert_eq!(grid.kind, GridKind::OpenSim);
+        assert_eq!(Grid::grid_info_url(&grid.login_uri), "http://127.0.0.1:9000/get_grid_info");
+        assert_eq!(grid.tos_url("abc"), None);
+        assert_eq!(Grid::agni().tos_url("abc").as_deref(), Some("https://secondlife.com/app/tos/tos.php?id=abc"));
+
+        let mut registry = GridRegistry::default();
+        registry.insert(grid.clone());
+        registry.selected = "testgrid".to_string();
+        assert_eq!(registry.selected(), grid);
+        registry.remove("testgrid");
+        assert_eq!(registry.selected().nick, "agni");
+    }
+}
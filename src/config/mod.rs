@@ -1 +1,3 @@
+pub mod accounts;
+pub mod grids;
 pub mod settings;
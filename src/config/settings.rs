@@ -1,5 +1,7 @@
 use crate::ui::PreferencesState;
 use crate::ui::proxy::ProxySettings;
+use crate::config::grids::GridRegistry;
+use crate::utils::platform::MachineIds;
 use std::fs;
 use std::path::PathBuf;
 use directories::ProjectDirs;
@@ -17,6 +19,8 @@ pub struct PreferencesToml {
     pub render_distance: u32,
     pub max_bandwidth: u32,
     pub timeout: u32,
+    #[serde(default)]
+    pub randomize_machine_ids: bool,
 }
 
 impl From<&PreferencesState> for PreferencesToml {
@@ -29,6 +33,7 @@ impl From<&PreferencesState> for PreferencesToml {
             render_distance: p.render_distance,
             max_bandwidth: p.max_bandwidth,
             timeout: p.timeout,
+            randomize_machine_ids: p.randomize_machine_ids,
         }
     }
 }
@@ -43,6 +48,7 @@ impl Into<PreferencesState> for PreferencesToml {
             render_distance: self.render_distance,
             max_bandwidth: self.max_bandwidth,
             timeout: self.timeout,
+            randomize_machine_ids: self.randomize_machine_ids,
             udp_test_result: None,
             udp_test_in_progress: false,
         }
@@ -122,3 +128,51 @@ pub fn load_general_settings() -> Option<(PreferencesState, ProxySettings)> {
     }
     None
 }
+
+const GRIDS_FILE: &str = "grids.toml";
+
+fn grids_path() -> Option<PathBuf> {
+    ProjectDirs::from("com", "slv", "slv-rust")
+        .map(|proj| proj.config_dir().join(GRIDS_FILE))
+}
+
+pub fn save_grids(grids: &GridRegistry) -> std::io::Result<()> {
+    if let Some(path) = grids_path() {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let toml = toml::to_string_pretty(grids).unwrap();
+        fs::write(path, toml)?;
+    }
+    Ok(())
+}
+
+/// The saved grid registry, or Agni and Aditi if none has been saved.
+pub fn load_grids() -> GridRegistry {
+    grids_path()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|data| toml::from_str(&data).ok())
+        .unwrap_or_default()
+}
+
+const MACHINE_IDS_FILE: &str = "machine_ids.toml";
+
+/// Machine ids for the login request: detected from the hardware, or with
+/// `randomize` a random set generated once and kept for this install.
+pub fn machine_ids(randomize: bool) -> MachineIds {
+    if !randomize {
+        return MachineIds::detect();
+    }
+    let path = ProjectDirs::from("com", "slv", "slv-rust").map(|proj| proj.config_dir().join(MACHINE_IDS_FILE));
+    if let Some(ids) = path.as_ref().and_then(|path| fs::read_to_string(path).ok()).and_then(|data| toml::from_str(&data).ok()) {
+        return ids;
+    }
+    let ids = MachineIds::random();
+    if let Some(path) = path {
+        if let Some(parent) = path.parent() {
+            let _ = fs::create_dir_all(parent);
+        }
+        let _ = fs::write(path, toml::to_string_pretty(&ids).unwrap());
+    }
+    ids
+}
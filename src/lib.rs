@@ -0,0 +1,10 @@
+//! The viewer's modules, shared by the `slv-rust` client and the tools in `src/bin`.
+
+pub mod utils;
+pub mod networking;
+pub mod ui;
+pub mod assets;
+pub mod rendering;
+pub mod config;
+pub mod world;
+pub mod app;
@@ -1,15 +1,6 @@
 use tokio; // Add this import for the runtime
-use crate::utils::lludp::{LluPacket, LluPacketFlags, build_use_circuit_code_packet};
-use crate::utils::logging;
-
-mod utils;
-mod networking;
-mod ui;
-mod assets;
-mod rendering;
-mod config;
-mod world;
-mod app;
+use slv_rust::ui;
+use slv_rust::utils::logging;
 
 struct MyApp {
     ui_state: ui::UiState,
@@ -0,0 +1,873 @@
+//! Networking actor: owns the circuits and is driven by `NetCommand`s from the UI.
+//!
+//! The UI never touches the socket or the circuit. It sends commands through a [`NetHandle`]
+//! and polls [`NetEvent`]s from a crossbeam receiver once per frame, the same way it polls
+//! `UiEvent`s.
+
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::sync::{mpsc, Mutex};
+use tokio::time::Duration;
+use uuid::Uuid;
+
+use crate::networking::circuit::{AgentState, Circuit, CircuitParams, CircuitRole};
+use crate::networking::circuit_manager::{self, CircuitManager};
+use crate::networking::crossing::{rebase_agent, RegionCrossing};
+use crate::networking::event_queue::{self, EventQueueEvent, EventQueueHandle};
+use crate::networking::handlers::MessageBus;
+use crate::networking::llsd::Llsd;
+use crate::networking::protocol::{builders, generated};
+use crate::networking::session::LoginSessionInfo;
+use crate::networking::stats::CircuitStats;
+use crate::networking::teleport::{TeleportFinish, TeleportTarget, TeleportUpdate};
+use crate::ui::proxy::ProxySettings;
+use crate::world::object_update::{self, ObjectUpdate};
+use crate::world::object_cache::ObjectCache;
+use crate::world::objects::{flush_cache, load_region_cache, MissingObjects, ObjectStore, SharedObjectStore};
+
+/// How long the UseCircuitCode..AgentUpdate handshake may take before the connection is abandoned.
+const HANDSHAKE_TIMEOUT_SECS: u64 = 30;
+
+/// Child circuits are best effort; give up on an unresponsive neighbour sooner.
+const CHILD_HANDSHAKE_TIMEOUT_SECS: u64 = 10;
+
+/// How often changed object caches are written out, so a crash loses little.
+const CACHE_FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Requests from the UI to the networking actor.
+#[derive(Debug, Clone)]
+pub enum NetCommand {
+    /// Open the circuit to the login simulator and run the handshake.
+    Connect {
+        session: Box<LoginSessionInfo>,
+        sim_addr: SocketAddr,
+        udp_port: u16,
+        proxy_settings: Option<ProxySettings>,
+    },
+    /// Say something in local chat (or on another channel).
+    SendChat { message: String, channel: i32 },
+    /// Update the agent state sent in the periodic AgentUpdate.
+    MoveAgent {
+        position: (f32, f32, f32),
+        camera_at: (f32, f32, f32),
+        camera_eye: (f32, f32, f32),
+        controls: u32,
+    },
+    /// Start a teleport; progress comes back as `NetEvent::Teleport*`.
+    Teleport(TeleportTarget),
+    /// Teleport progress from the simulator or the event queue.
+    TeleportUpdate(TeleportUpdate),
+    /// The agent walked into a neighbouring region (CrossedRegion from the simulator or the event queue).
+    CrossedRegion(RegionCrossing),
+    /// Request full updates for objects in a region by local id, with a `builders::CACHE_MISS_*` type.
+    RequestObjects { region_handle: u64, local_ids: Vec<u32>, cache_miss_type: u8 },
+    /// Open a child-agent circuit to a neighbouring region (from an EnableSimulator message or event).
+    EnableSimulator { region_handle: u64, sim_addr: SocketAddr },
+    /// Close a neighbouring region's child circuit.
+    DisableSimulator { region_handle: u64 },
+    /// A region's event queue gave up: restart it for the agent's region, close the region otherwise.
+    EventQueueFailed { region_handle: u64, error: String },
+    /// Ask for a `NetEvent::Stats` snapshot.
+    QueryStats,
+    Logout,
+}
+
+/// Notifications from the networking actor to the UI.
+#[derive(Debug, Clone)]
+pub enum NetEvent {
+    Connecting { sim_addr: SocketAddr },
+    /// The circuit is open and the handshake finished.
+    Connected { sim_addr: SocketAddr },
+    ConnectFailed(String),
+    /// The simulator placed our avatar in the region.
+    AgentMovementComplete { region_handle: u64, position: (f32, f32, f32) },
+    ChatReceived {
+        from_name: String,
+        source_id: Uuid,
+        chat_type: u8,
+        message: String,
+    },
+    /// Someone invited us into a group or conference chat session.
+    ChatSessionInvitation { session_id: Uuid, from_name: String, message: String },
+    /// AgentStateUpdate from the event queue: agent preferences and navmesh permissions.
+    AgentStateUpdate(Llsd),
+    /// The simulator accepted a teleport request (or is teleporting us on its own, e.g. a lure).
+    TeleportStarted,
+    TeleportProgress { message: String },
+    /// The agent is in the destination region and the old circuits are closed.
+    TeleportFinished { region_handle: u64, sim_addr: SocketAddr },
+    TeleportFailed { reason: String },
+    /// The agent crossed into another region, which is now the primary one.
+    RegionCrossed { region_handle: u64, sim_addr: SocketAddr },
+    /// A child circuit to a neighbouring region is open.
+    RegionEnabled { region_handle: u64, sim_addr: SocketAddr },
+    /// A neighbouring region's child circuit was closed.
+    RegionDisabled { region_handle: u64 },
+    Stats(CircuitStats),
+    /// A reliable packet ran out of resends on the primary circuit; the simulator is unreachable.
+    CircuitDead { sim_addr: SocketAddr },
+    LoggedOut,
+    /// A command could not be carried out.
+    Error(String),
+}
+
+/// Cheap, cloneable sender for `NetCommand`s.
+#[derive(Clone)]
+pub struct NetHandle {
+    commands: mpsc::UnboundedSender<NetCommand>,
+    objects: SharedObjectStore,
+}
+
+impl NetHandle {
+    /// Queue a command. Returns false if the actor has stopped.
+    pub fn send(&self, command: NetCommand) -> bool {
+        self.commands.send(command).is_ok()
+    }
+
+    /// Teleport to a region-local position in the region with the given handle.
+    pub fn teleport_to(&self, region_handle: u64, position: (f32, f32, f32), look_at: (f32, f32, f32)) -> bool {
+        self.send(NetCommand::Teleport(TeleportTarget::Location { region_handle, position, look_at }))
+    }
+
+    /// Objects in every region the agent has a circuit to, kept up to date by the actor.
+    pub fn objects(&self) -> SharedObjectStore {
+        Arc::clone(&self.objects)
+    }
+
+    /// Teleport to the location stored in a landmark.
+    pub fn teleport_to_landmark(&self, landmark_id: Uuid) -> bool {
+        self.send(NetCommand::Teleport(TeleportTarget::Landmark { landmark_id }))
+    }
+}
+
+/// Spawn the networking actor on the current tokio runtime, caching objects in the user's cache dir.
+pub fn spawn_network_actor() -> (NetHandle, Receiver<NetEvent>) {
+    spawn_network_actor_with_cache(ObjectCache::open_default())
+}
+
+/// Spawn the networking actor with its object cache somewhere else, or without one.
+pub fn spawn_network_actor_with_cache(cache: Option<ObjectCache>) -> (NetHandle, Receiver<NetEvent>) {
+    let (command_tx, command_rx) = mpsc::unbounded_channel();
+    let (event_tx, event_rx) = unbounded();
+    let objects = ObjectStore::shared();
+    if let Some(cache) = cache {
+        objects.lock().unwrap().set_cache(cache);
+    }
+    let (handshake_tx, handshake_rx) = mpsc::unbounded_channel();
+    let actor = NetworkActor {
+        events: event_tx,
+        commands: command_tx.downgrade(),
+        handshakes: handshake_tx,
+        objects: Arc::clone(&objects),
+        connection: None,
+        epoch: 0,
+    };
+    tokio::spawn(actor.run(command_rx, handshake_rx));
+    (NetHandle { commands: command_tx, objects }, event_rx)
+}
+
+/// Why a circuit was opened; decides what happens once its handshake finishes.
+enum Opening {
+    Login { seed_capability: String },
+    Teleport(TeleportFinish),
+    Crossing { crossing: RegionCrossing, old_handle: u64 },
+    Child,
+}
+
+/// A circuit handshake run off the actor loop, reported back when it finishes.
+struct Handshake {
+    /// The connection the circuit belongs to; results for an earlier one are dropped.
+    epoch: u64,
+    region_handle: u64,
+    sim_addr: SocketAddr,
+    opening: Opening,
+    result: io::Result<Circuit>,
+}
+
+struct Connection {
+    epoch: u64,
+    circuits: CircuitManager,
+    agent_id: Uuid,
+    session_id: Uuid,
+    circuit_code: u32,
+    /// Teleport we requested that has not finished or failed yet.
+    teleport: Option<TeleportTarget>,
+    udp_port: u16,
+    proxy_settings: Option<ProxySettings>,
+    session_cookie: Option<String>,
+    /// Event queue pollers by region handle: the region the agent is in and regions it
+    /// crossed out of that still hold a child agent.
+    event_queues: HashMap<u64, EventQueueHandle>,
+}
+
+impl Connection {
+    fn circuit(&self) -> Result<&Circuit, String> {
+        self.circuits.primary().map(|region| &region.circuit).ok_or_else(|| "No primary circuit".to_string())
+    }
+
+    /// Poll the event queue of the region the agent just arrived in. If it gives up, the actor
+    /// hears about it with `NetCommand::EventQueueFailed`.
+    fn follow_event_queue(
+        &mut self,
+        region_handle: u64,
+        seed_capability: String,
+        events: &Sender<NetEvent>,
+        commands: &mpsc::WeakUnboundedSender<NetCommand>,
+    ) {
+        let (events, commands) = (events.clone(), commands.clone());
+        let failed = commands.clone();
+        let queue = event_queue::spawn(
+            seed_capability,
+            self.udp_port,
+            self.proxy_settings.clone(),
+            self.session_cookie.clone(),
+            move |batch| {
+                for event in batch {
+                    dispatch_event_queue(event, region_handle, &events, &commands);
+                }
+            },
+            move |error| {
+                if let Some(commands) = failed.upgrade() {
+                    let _ = commands.send(NetCommand::EventQueueFailed { region_handle, error });
+                }
+            },
+        );
+        // Replacing a poller drops (and cancels) the old one.
+        self.event_queues.insert(region_handle, queue);
+    }
+
+    /// Stop polling a region's event queue, telling the simulator we are done with it.
+    fn stop_event_queue(&mut self, region_handle: u64) {
+        if let Some(queue) = self.event_queues.remove(&region_handle) {
+            tokio::spawn(queue.shutdown());
+        }
+    }
+}
+
+struct NetworkActor {
+    events: Sender<NetEvent>,
+    /// Lets message handlers queue commands without keeping the actor alive.
+    commands: mpsc::WeakUnboundedSender<NetCommand>,
+    /// Handshakes report back here so a slow simulator does not hold up other commands.
+    handshakes: mpsc::UnboundedSender<Handshake>,
+    objects: SharedObjectStore,
+    connection: Option<Connection>,
+    /// Bumped for every Connect.
+    epoch: u64,
+}
+
+impl NetworkActor {
+    async fn run(mut self, mut commands: mpsc::UnboundedReceiver<NetCommand>, mut handshakes: mpsc::UnboundedReceiver<Handshake>) {
+        let mut flush = tokio::time::interval(CACHE_FLUSH_INTERVAL);
+        loop {
+            let result = tokio::select! {
+                command = commands.recv() => match command {
+                    Some(command) => self.handle(command).await,
+                    None => break,
+                },
+                Some(handshake) = handshakes.recv() => self.finish_handshake(handshake).await,
+                _ = flush.tick() => {
+                    flush_cache(&self.objects);
+                    Ok(())
+                }
+            };
+            if let Err(e) = result {
+                let _ = self.events.send(NetEvent::Error(e));
+            }
+        }
+        // The UI dropped every handle; say goodbye to the simulator on the way out.
+        if self.connection.is_some() {
+            let _ = self.handle(NetCommand::Logout).await;
+        }
+    }
+
+    async fn handle(&mut self, command: NetCommand) -> Result<(), String> {
+        match command {
+            NetCommand::Connect { session, sim_addr, udp_port, proxy_settings } => {
+                if self.connection.is_some() {
+                    return Err("Already connected".to_string());
+                }
+                let _ = self.events.send(NetEvent::Connecting { sim_addr });
+                if let Err(e) = self.connect(&session, sim_addr, udp_port, proxy_settings.as_ref()).await {
+                    let _ = self.events.send(NetEvent::ConnectFailed(e));
+                }
+                Ok(())
+            }
+            NetCommand::SendChat { message, channel } => {
+                let conn = self.connection()?;
+                let chat = builders::chat_from_viewer(conn.agent_id, conn.session_id, &message, builders::CHAT_TYPE_NORMAL, channel);
+                conn.circuit()?.send(&chat, true).await.map(|_| ()).map_err(|e| format!("Failed to send chat: {}", e))
+            }
+            NetCommand::MoveAgent { position, camera_at, camera_eye, controls } => {
+                let conn = self.connection()?;
+                let agent_state = conn.circuits.agent_state();
+                let mut state = agent_state.lock().await;
+                state.position = position;
+                state.camera_at = camera_at;
+                state.camera_eye = camera_eye;
+                state.controls = controls;
+                Ok(())
+            }
+            NetCommand::Teleport(target) => {
+                let conn = self.connection.as_mut().ok_or_else(|| "Not connected".to_string())?;
+                if conn.teleport.is_some() {
+                    return Err("A teleport is already in progress".to_string());
+                }
+                let request = target.request(conn.agent_id, conn.session_id);
+                conn.circuit()?.send(&request, true).await.map_err(|e| format!("Failed to request teleport: {}", e))?;
+                conn.teleport = Some(target);
+                Ok(())
+            }
+            NetCommand::TeleportUpdate(update) => self.teleport_update(update).await,
+            NetCommand::CrossedRegion(crossing) => self.cross_region(crossing).await,
+            NetCommand::RequestObjects { region_handle, local_ids, cache_miss_type } => {
+                let conn = self.connection()?;
+                let region = conn.circuits.get(region_handle).ok_or_else(|| format!("No circuit to region {}", region_handle))?;
+                for chunk in local_ids.chunks(255) {
+                    let request = builders::request_multiple_objects(conn.agent_id, conn.session_id, chunk, cache_miss_type);
+                    region.circuit.send(&request, true).await.map_err(|e| format!("Failed to request objects: {}", e))?;
+                }
+                Ok(())
+            }
+            NetCommand::EnableSimulator { region_handle, sim_addr } => self.enable_simulator(region_handle, sim_addr).await,
+            NetCommand::DisableSimulator { region_handle } => {
+                let conn = self.connection.as_mut().ok_or_else(|| "Not connected".to_string())?;
+                if conn.circuits.primary_handle() != Some(region_handle) && conn.circuits.close(region_handle).await {
+                    conn.stop_event_queue(region_handle);
+                    self.objects.lock().unwrap().remove_region(region_handle);
+                    flush_cache(&self.objects);
+                    let _ = self.events.send(NetEvent::RegionDisabled { region_handle });
+                }
+                Ok(())
+            }
+            NetCommand::EventQueueFailed { region_handle, error } => self.event_queue_failed(region_handle, error).await,
+            NetCommand::QueryStats => {
+                let stats = self.connection()?.circuit()?.stats().await;
+                let _ = self.events.send(NetEvent::Stats(stats));
+                Ok(())
+            }
+            NetCommand::Logout => {
+                if let Some(mut conn) = self.connection.take() {
+                    for (_, queue) in conn.event_queues.drain() {
+                        queue.shutdown().await;
+                    }
+                    conn.circuits.logout().await;
+                }
+                self.objects.lock().unwrap().clear();
+                flush_cache(&self.objects);
+                let _ = self.events.send(NetEvent::LoggedOut);
+                Ok(())
+            }
+        }
+    }
+
+    fn connection(&self) -> Result<&Connection, String> {
+        self.connection.as_ref().ok_or_else(|| "Not connected".to_string())
+    }
+
+    /// Bind the socket and start the circuit to the login region. The connection is usable
+    /// once its handshake reports back; see `finish_handshake`.
+    async fn connect(
+        &mut self,
+        session: &LoginSessionInfo,
+        sim_addr: SocketAddr,
+        udp_port: u16,
+        proxy_settings: Option<&ProxySettings>,
+    ) -> Result<(), String> {
+        let agent_id = Uuid::parse_str(&session.agent_id).map_err(|e| format!("Bad agent_id: {}", e))?;
+        let session_id = Uuid::parse_str(&session.session_id).map_err(|e| format!("Bad session_id: {}", e))?;
+        let agent_state = Arc::new(Mutex::new(AgentState {
+            position: (0.0, 0.0, 0.0),
+            camera_at: (0.0, 0.0, 0.0),
+            camera_eye: (0.0, 0.0, 0.0),
+            controls: 0,
+        }));
+        let mut circuits = CircuitManager::bind(udp_port, proxy_settings, agent_state)
+            .await
+            .map_err(|e| format!("Failed to bind UDP socket: {}", e))?;
+
+        let region_handle = circuit_manager::region_handle(session.region_x as u32, session.region_y as u32);
+        let params = CircuitParams::new(agent_id, session_id, session.circuit_code);
+        let circuit = circuits
+            .start(region_handle, sim_addr, params, |circuit| {
+                attach_region(circuit, region_handle, &self.objects, &self.events, &self.commands);
+            })
+            .await
+            .map_err(|e| format!("Failed to open circuit: {}", e))?;
+        self.epoch += 1;
+        self.connection = Some(Connection {
+            epoch: self.epoch,
+            circuits,
+            agent_id,
+            session_id,
+            circuit_code: session.circuit_code,
+            teleport: None,
+            udp_port,
+            proxy_settings: proxy_settings.cloned(),
+            session_cookie: session.session_cookie.clone(),
+            event_queues: HashMap::new(),
+        });
+        let opening = Opening::Login { seed_capability: session.seed_capability.clone() };
+        self.spawn_handshake(region_handle, sim_addr, circuit, HANDSHAKE_TIMEOUT_SECS, opening);
+        Ok(())
+    }
+
+    /// Run a started circuit's handshake on its own task; the result comes back to `finish_handshake`.
+    fn spawn_handshake(&self, region_handle: u64, sim_addr: SocketAddr, circuit: Circuit, timeout_secs: u64, opening: Opening) {
+        let epoch = self.epoch;
+        let handshakes = self.handshakes.clone();
+        tokio::spawn(async move {
+            let result = circuit_manager::handshake(circuit, sim_addr, Duration::from_secs(timeout_secs)).await;
+            let _ = handshakes.send(Handshake { epoch, region_handle, sim_addr, opening, result });
+        });
+    }
+
+    /// Add a circuit whose handshake finished and carry on with whatever opened it.
+    async fn finish_handshake(&mut self, handshake: Handshake) -> Result<(), String> {
+        let Handshake { epoch, region_handle, sim_addr, opening, result } = handshake;
+        let Some(conn) = self.connection.as_mut().filter(|conn| conn.epoch == epoch) else {
+            // Logged out (and maybe back in) while the handshake ran.
+            return Ok(());
+        };
+        let circuit = match result {
+            Ok(circuit) => circuit,
+            Err(e) => {
+                conn.circuits.abandon(sim_addr);
+                match opening {
+                    Opening::Login { .. } => {
+                        self.connection = None;
+                        let _ = self.events.send(NetEvent::ConnectFailed(format!("Failed to open circuit: {}", e)));
+                    }
+                    Opening::Teleport(_) => {
+                        let reason = format!("Failed to open circuit to {}: {}", sim_addr, e);
+                        let _ = self.events.send(NetEvent::TeleportFailed { reason });
+                    }
+                    Opening::Crossing { .. } => return Err(format!("Failed to open circuit to {}: {}", sim_addr, e)),
+                    Opening::Child => return Err(format!("Failed to open child circuit to {}: {}", sim_addr, e)),
+                }
+                return Ok(());
+            }
+        };
+        conn.circuits.insert(region_handle, sim_addr, circuit);
+        match opening {
+            Opening::Login { seed_capability } => {
+                if let Some(region) = conn.circuits.get_mut(region_handle) {
+                    region.seed_capability = Some(seed_capability.clone());
+                }
+                conn.follow_event_queue(region_handle, seed_capability, &self.events, &self.commands);
+                let _ = self.events.send(NetEvent::Connected { sim_addr });
+            }
+            Opening::Teleport(finish) => self.complete_hand_over(finish).await?,
+            Opening::Crossing { crossing, old_handle } => self.complete_crossing(crossing, old_handle).await?,
+            Opening::Child => {
+                let _ = self.events.send(NetEvent::RegionEnabled { region_handle, sim_addr });
+            }
+        }
+        Ok(())
+    }
+
+    async fn teleport_update(&mut self, update: TeleportUpdate) -> Result<(), String> {
+        let conn = self.connection.as_mut().ok_or_else(|| "Not connected".to_string())?;
+        match update {
+            TeleportUpdate::Started { .. } => {
+                let _ = self.events.send(NetEvent::TeleportStarted);
+            }
+            TeleportUpdate::Progress { message } => {
+                let _ = self.events.send(NetEvent::TeleportProgress { message });
+            }
+            TeleportUpdate::Local { position, .. } => {
+                conn.teleport = None;
+                conn.circuits.agent_state().lock().await.position = position;
+                if let Some(region) = conn.circuits.primary() {
+                    let _ = self.events.send(NetEvent::TeleportFinished { region_handle: region.region_handle, sim_addr: region.sim_addr });
+                }
+            }
+            TeleportUpdate::Failed { reason } => {
+                conn.teleport = None;
+                let _ = self.events.send(NetEvent::TeleportFailed { reason });
+            }
+            TeleportUpdate::Finished(finish) => {
+                conn.teleport = None;
+                if let Err(reason) = self.hand_over(finish).await {
+                    let _ = self.events.send(NetEvent::TeleportFailed { reason });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Move the primary circuit to the teleport destination and drop the circuits around the
+    /// old region; the destination announces its own neighbours with EnableSimulator.
+    async fn hand_over(&mut self, finish: TeleportFinish) -> Result<(), String> {
+        let conn = self.connection.as_mut().ok_or_else(|| "Not connected".to_string())?;
+        let TeleportFinish { region_handle, sim_addr, .. } = finish;
+        if conn.circuits.primary_handle() == Some(region_handle) || conn.circuits.is_pending(sim_addr) {
+            // Already handed over; TeleportFinish came over both UDP and the event queue.
+            return Ok(());
+        }
+        // A child circuit skipped CompleteAgentMovement, so the destination gets a fresh one.
+        if let Some(existing) = conn.circuits.region_for_addr(sim_addr) {
+            conn.circuits.close(existing).await;
+        }
+        conn.circuits.close(region_handle).await;
+        let params = CircuitParams::new(conn.agent_id, conn.session_id, conn.circuit_code);
+        let circuit = conn
+            .circuits
+            .start(region_handle, sim_addr, params, |circuit| {
+                attach_region(circuit, region_handle, &self.objects, &self.events, &self.commands);
+            })
+            .await
+            .map_err(|e| format!("Failed to open circuit to {}: {}", sim_addr, e))?;
+        self.spawn_handshake(region_handle, sim_addr, circuit, HANDSHAKE_TIMEOUT_SECS, Opening::Teleport(finish));
+        Ok(())
+    }
+
+    /// The teleport destination's circuit is up: make it the primary and close the rest.
+    async fn complete_hand_over(&mut self, finish: TeleportFinish) -> Result<(), String> {
+        let conn = self.connection.as_mut().ok_or_else(|| "Not connected".to_string())?;
+        let TeleportFinish { region_handle, sim_addr, .. } = finish;
+        if let Some(region) = conn.circuits.get_mut(region_handle) {
+            region.seed_capability = Some(finish.seed_capability.clone());
+        }
+        conn.circuits.set_primary(region_handle);
+        conn.follow_event_queue(region_handle, finish.seed_capability, &self.events, &self.commands);
+
+        let stale: Vec<u64> = conn.circuits.regions().map(|r| r.region_handle).filter(|&h| h != region_handle).collect();
+        for handle in stale {
+            conn.circuits.close(handle).await;
+            conn.stop_event_queue(handle);
+            self.objects.lock().unwrap().remove_region(handle);
+        }
+        flush_cache(&self.objects);
+        let _ = self.events.send(NetEvent::TeleportFinished { region_handle, sim_addr });
+        Ok(())
+    }
+
+    /// Make the region the agent walked into the primary one. The region left behind stays
+    /// open as a child until it sends DisableSimulator.
+    async fn cross_region(&mut self, crossing: RegionCrossing) -> Result<(), String> {
+        let conn = self.connection.as_mut().ok_or_else(|| "Not connected".to_string())?;
+        let region_handle = crossing.region_handle;
+        let Some(old_handle) = conn.circuits.primary_handle() else {
+            return Err("No primary circuit".to_string());
+        };
+        if old_handle == region_handle {
+            // Already crossed; CrossedRegion came over both UDP and the event queue.
+            return Ok(());
+        }
+
+        if conn.circuits.is_pending(crossing.sim_addr) {
+            return Ok(());
+        }
+
+        let child = conn.circuits.get_mut(region_handle).filter(|region| region.sim_addr == crossing.sim_addr);
+        if let Some(region) = child {
+            region.circuit.promote().await.map_err(|e| format!("Failed to move into {}: {}", crossing.sim_addr, e))?;
+            return self.complete_crossing(crossing, old_handle).await;
+        }
+        conn.circuits.close(region_handle).await;
+        if let Some(existing) = conn.circuits.region_for_addr(crossing.sim_addr) {
+            conn.circuits.close(existing).await;
+        }
+        let params = CircuitParams::new(conn.agent_id, conn.session_id, conn.circuit_code);
+        let sim_addr = crossing.sim_addr;
+        let circuit = conn
+            .circuits
+            .start(region_handle, sim_addr, params, |circuit| {
+                attach_region(circuit, region_handle, &self.objects, &self.events, &self.commands);
+            })
+            .await
+            .map_err(|e| format!("Failed to open circuit to {}: {}", sim_addr, e))?;
+        self.spawn_handshake(region_handle, sim_addr, circuit, HANDSHAKE_TIMEOUT_SECS, Opening::Crossing { crossing, old_handle });
+        Ok(())
+    }
+
+    /// The agent is in the new region: make it the primary and demote the one it left.
+    async fn complete_crossing(&mut self, crossing: RegionCrossing, old_handle: u64) -> Result<(), String> {
+        let conn = self.connection.as_mut().ok_or_else(|| "Not connected".to_string())?;
+        let region_handle = crossing.region_handle;
+        conn.circuits.set_primary(region_handle);
+        if let Some(region) = conn.circuits.get_mut(region_handle) {
+            region.seed_capability = Some(crossing.seed_capability.clone());
+        }
+        if let Some(old) = conn.circuits.get_mut(old_handle) {
+            old.circuit.demote();
+        }
+        rebase_agent(&mut *conn.circuits.agent_state().lock().await, old_handle, &crossing);
+        conn.follow_event_queue(region_handle, crossing.seed_capability.clone(), &self.events, &self.commands);
+
+        let _ = self.events.send(NetEvent::RegionCrossed { region_handle, sim_addr: crossing.sim_addr });
+        Ok(())
+    }
+
+    /// A region's event queue gave up. The agent's region gets a new poller; TeleportFinish and
+    /// CrossedRegion arrive on it, so a teleport in flight is failed rather than left waiting. A
+    /// region the agent left is closed.
+    async fn event_queue_failed(&mut self, region_handle: u64, error: String) -> Result<(), String> {
+        let Some(conn) = self.connection.as_mut() else {
+            return Ok(());
+        };
+        conn.event_queues.remove(&region_handle);
+        if conn.circuits.primary_handle() == Some(region_handle) {
+            if conn.teleport.take().is_some() {
+                let _ = self.events.send(NetEvent::TeleportFailed { reason: format!("Lost the region's event queue: {}", error) });
+            }
+            if let Some(seed_capability) = conn.circuits.get(region_handle).and_then(|region| region.seed_capability.clone()) {
+                conn.follow_event_queue(region_handle, seed_capability, &self.events, &self.commands);
+            }
+            return Err(format!("Event queue for region {} failed: {}", region_handle, error));
+        }
+        if conn.circuits.close(region_handle).await {
+            self.objects.lock().unwrap().remove_region(region_handle);
+            flush_cache(&self.objects);
+            let _ = self.events.send(NetEvent::RegionDisabled { region_handle });
+        }
+        Ok(())
+    }
+
+    /// Open a child circuit to a neighbouring region. Repeated announcements are ignored.
+    async fn enable_simulator(&mut self, region_handle: u64, sim_addr: SocketAddr) -> Result<(), String> {
+        let conn = self.connection.as_mut().ok_or_else(|| "Not connected".to_string())?;
+        let circuits = &conn.circuits;
+        if circuits.get(region_handle).is_some() || circuits.region_for_addr(sim_addr).is_some() || circuits.is_pending(sim_addr) {
+            return Ok(());
+        }
+        let mut params = CircuitParams::new(conn.agent_id, conn.session_id, conn.circuit_code);
+        params.child = true;
+        let circuit = conn
+            .circuits
+            .start(region_handle, sim_addr, params, |circuit| {
+                attach_region(circuit, region_handle, &self.objects, &self.events, &self.commands);
+            })
+            .await
+            .map_err(|e| format!("Failed to open child circuit to {}: {}", sim_addr, e))?;
+        self.spawn_handshake(region_handle, sim_addr, circuit, CHILD_HANDSHAKE_TIMEOUT_SECS, Opening::Child);
+        Ok(())
+    }
+}
+
+/// Route an event from a region's event queue: circuit changes go back through the actor,
+/// the rest to the UI.
+fn dispatch_event_queue(
+    event: EventQueueEvent,
+    region_handle: u64,
+    events: &Sender<NetEvent>,
+    commands: &mpsc::WeakUnboundedSender<NetCommand>,
+) {
+    let command = match event {
+        EventQueueEvent::EnableSimulator { region_handle, sim_addr } => NetCommand::EnableSimulator { region_handle, sim_addr },
+        EventQueueEvent::DisableSimulator => NetCommand::DisableSimulator { region_handle },
+        EventQueueEvent::TeleportFinish(finish) => NetCommand::TeleportUpdate(TeleportUpdate::Finished(finish)),
+        EventQueueEvent::TeleportFailed { reason } => NetCommand::TeleportUpdate(TeleportUpdate::Failed { reason }),
+        EventQueueEvent::CrossedRegion(crossing) => NetCommand::CrossedRegion(crossing),
+        EventQueueEvent::ChatterBoxInvitation { session_id, from_name, message, .. } => {
+            let _ = events.send(NetEvent::ChatSessionInvitation { session_id, from_name, message });
+            return;
+        }
+        EventQueueEvent::AgentStateUpdate(body) => {
+            let _ = events.send(NetEvent::AgentStateUpdate(body));
+            return;
+        }
+        EventQueueEvent::EstablishAgentCommunication { .. } | EventQueueEvent::Other { .. } => return,
+    };
+    if let Some(commands) = commands.upgrade() {
+        let _ = commands.send(command);
+    }
+}
+
+/// Hook a circuit up to the UI, the actor and the object store, once per circuit. Its handlers
+/// check the circuit's role as each message arrives, so a border crossing only has to promote or
+/// demote it: the region the agent is in reports to the UI, and a child leaves when its neighbour
+/// sends DisableSimulator or stops answering.
+fn attach_region(
+    circuit: &Circuit,
+    region_handle: u64,
+    objects: &SharedObjectStore,
+    events: &Sender<NetEvent>,
+    commands: &mpsc::WeakUnboundedSender<NetCommand>,
+) {
+    let role = circuit.role();
+    attach_handlers(&circuit.bus(), &role, region_handle, objects, events, commands);
+    let (events, commands) = (events.clone(), commands.clone());
+    circuit.set_circuit_dead_callback(move |sim_addr| {
+        if !role.is_child() {
+            let _ = events.send(NetEvent::CircuitDead { sim_addr });
+        } else if let Some(commands) = commands.upgrade() {
+            let _ = commands.send(NetCommand::DisableSimulator { region_handle });
+        }
+    });
+}
+
+/// The message handlers `attach_region` installs, on a bare bus. `slv-replay` uses this to run
+/// a capture through the same pipeline as a live circuit.
+pub fn attach_handlers(
+    bus: &MessageBus,
+    role: &CircuitRole,
+    region_handle: u64,
+    objects: &SharedObjectStore,
+    events: &Sender<NetEvent>,
+    commands: &mpsc::WeakUnboundedSender<NetCommand>,
+) {
+    forward_events(bus, role, events, commands);
+    let disable = commands.clone();
+    let child = role.clone();
+    bus.on::<generated::DisableSimulator, _>(move |_, _| {
+        if !child.is_child() {
+            return;
+        }
+        if let Some(commands) = disable.upgrade() {
+            let _ = commands.send(NetCommand::DisableSimulator { region_handle });
+        }
+    });
+    attach_objects(bus, region_handle, objects, commands);
+}
+
+/// Feed a circuit's object updates into the store, once per circuit. A new circuit to a region
+/// gets every object resent, so whatever the store had for the region is dropped first. The
+/// region's object cache is loaded when its RegionHandshake names the cache id.
+fn attach_objects(bus: &MessageBus, region_handle: u64, objects: &SharedObjectStore, commands: &mpsc::WeakUnboundedSender<NetCommand>) {
+    objects.lock().unwrap().remove_region(region_handle);
+    flush_cache(objects);
+    let store = Arc::clone(objects);
+    let tx = commands.clone();
+    bus.on::<generated::RegionHandshake, _>(move |_, msg| {
+        let (region_id, cache_id) = (msg.region_info2.region_id, msg.region_info.cache_id);
+        let tx = tx.clone();
+        load_region_cache(&store, region_handle, region_id, cache_id, move |missing| request_missing(&tx, region_handle, missing));
+    });
+    forward_objects::<generated::ObjectUpdate, _>(bus, region_handle, objects, commands, object_update::from_object_update);
+    forward_objects::<generated::ObjectUpdateCompressed, _>(
+        bus,
+        region_handle,
+        objects,
+        commands,
+        object_update::from_object_update_compressed,
+    );
+    forward_objects::<generated::ImprovedTerseObjectUpdate, _>(
+        bus,
+        region_handle,
+        objects,
+        commands,
+        object_update::from_improved_terse_object_update,
+    );
+    forward_objects::<generated::ObjectUpdateCached, _>(bus, region_handle, objects, commands, object_update::from_object_update_cached);
+    forward_objects::<generated::KillObject, _>(bus, region_handle, objects, commands, object_update::from_kill_object);
+}
+
+/// Apply each `T` to the store and ask the region for whatever it could not place.
+fn forward_objects<T, F>(
+    bus: &MessageBus,
+    region_handle: u64,
+    objects: &SharedObjectStore,
+    commands: &mpsc::WeakUnboundedSender<NetCommand>,
+    decode: F,
+) where
+    T: generated::TemplateMessage,
+    F: Fn(&T) -> Vec<ObjectUpdate> + Send + Sync + 'static,
+{
+    let objects = Arc::clone(objects);
+    let tx = commands.clone();
+    bus.on::<T, _>(move |_, msg| {
+        let missing = objects.lock().unwrap().apply(region_handle, decode(msg));
+        request_missing(&tx, region_handle, missing);
+    });
+}
+
+/// Ask the region for full updates of what the store could not place.
+fn request_missing(commands: &mpsc::WeakUnboundedSender<NetCommand>, region_handle: u64, missing: MissingObjects) {
+    let Some(commands) = commands.upgrade() else {
+        return;
+    };
+    let requests = [(missing.unknown, builders::CACHE_MISS_FULL), (missing.cache_misses, builders::CACHE_MISS_CRC)];
+    for (local_ids, cache_miss_type) in requests {
+        if !local_ids.is_empty() {
+            let _ = commands.send(NetCommand::RequestObjects { region_handle, local_ids, cache_miss_type });
+        }
+    }
+}
+
+/// Turn the messages the UI cares about into `NetEvent`s as they arrive, while `role` is the
+/// agent's own region.
+fn forward_events(bus: &MessageBus, role: &CircuitRole, events: &Sender<NetEvent>, commands: &mpsc::WeakUnboundedSender<NetCommand>) {
+    let tx = events.clone();
+    on_primary::<generated::ChatFromSimulator, _>(bus, role, move |_, msg| {
+        let chat = &msg.chat_data;
+        let _ = tx.send(NetEvent::ChatReceived {
+            from_name: variable_to_string(&chat.from_name),
+            source_id: chat.source_id,
+            chat_type: chat.chat_type,
+            message: variable_to_string(&chat.message),
+        });
+    });
+    let tx = events.clone();
+    on_primary::<generated::AgentMovementComplete, _>(bus, role, move |_, msg| {
+        let p = msg.data.position;
+        let _ = tx.send(NetEvent::AgentMovementComplete { region_handle: msg.data.region_handle, position: (p.x, p.y, p.z) });
+    });
+    let tx = commands.clone();
+    on_primary::<generated::CrossedRegion, _>(bus, role, move |_, msg| {
+        if let Some(commands) = tx.upgrade() {
+            let _ = commands.send(NetCommand::CrossedRegion(RegionCrossing::from_message(msg)));
+        }
+    });
+    forward_teleport::<generated::TeleportStart, _>(bus, role, commands, |msg| TeleportUpdate::Started {
+        teleport_flags: msg.info.teleport_flags,
+    });
+    forward_teleport::<generated::TeleportProgress, _>(bus, role, commands, |msg| TeleportUpdate::Progress {
+        message: variable_to_string(&msg.info.message),
+    });
+    forward_teleport::<generated::TeleportLocal, _>(bus, role, commands, |msg| TeleportUpdate::Local {
+        position: (msg.info.position.x, msg.info.position.y, msg.info.position.z),
+        look_at: (msg.info.look_at.x, msg.info.look_at.y, msg.info.look_at.z),
+    });
+    forward_teleport::<generated::TeleportFinish, _>(bus, role, commands, |msg| {
+        TeleportUpdate::Finished(TeleportFinish::from_message(msg))
+    });
+    forward_teleport::<generated::TeleportFailed, _>(bus, role, commands, |msg| TeleportUpdate::Failed {
+        reason: variable_to_string(&msg.info.reason),
+    });
+    let tx = commands.clone();
+    on_primary::<generated::EnableSimulator, _>(bus, role, move |_, msg| {
+        let info = &msg.simulator_info;
+        if let Some(commands) = tx.upgrade() {
+            let sim_addr = SocketAddr::new(info.ip.into(), info.port);
+            let _ = commands.send(NetCommand::EnableSimulator { region_handle: info.handle, sim_addr });
+        }
+    });
+}
+
+/// Teleport messages change circuits, so they go back through the actor rather than to the UI.
+fn forward_teleport<T, F>(bus: &MessageBus, role: &CircuitRole, commands: &mpsc::WeakUnboundedSender<NetCommand>, update: F)
+where
+    T: generated::TemplateMessage,
+    F: Fn(&T) -> TeleportUpdate + Send + Sync + 'static,
+{
+    let tx = commands.clone();
+    on_primary::<T, _>(bus, role, move |_, msg| {
+        if let Some(commands) = tx.upgrade() {
+            let _ = commands.send(NetCommand::TeleportUpdate(update(msg)));
+        }
+    });
+}
+
+/// Register a handler that only runs while the circuit is not a child.
+fn on_primary<T, F>(bus: &MessageBus, role: &CircuitRole, handler: F)
+where
+    T: generated::TemplateMessage,
+    F: Fn(SocketAddr, &T) + Send + Sync + 'static,
+{
+    let role = role.clone();
+    bus.on::<T, _>(move |from, msg| {
+        if !role.is_child() {
+            handler(from, msg);
+        }
+    });
+}
+
+/// Variable fields carrying text are NUL-terminated.
+fn variable_to_string(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).trim_end_matches('\0').to_string()
+}
@@ -0,0 +1,326 @@
+//! Capabilities: the per-region HTTP endpoints handed out by a simulator's seed capability.
+//!
+//! [`CapsClient`] posts the list of capabilities we want to the seed, keeps the returned
+//! name -> URL map, and talks LLSD to the individual capabilities. Capability URLs are tied to
+//! the region and the agent's presence there; a 404 from one means the map is stale, so the
+//! client re-fetches it from the seed once and retries. A client belongs to one region: when the
+//! agent moves on, the actor's `follow_event_queue` connects a fresh one to the new region's seed.
+
+use reqwest::header::{HeaderValue, ACCEPT, CONTENT_TYPE, COOKIE, USER_AGENT};
+use reqwest::{Method, StatusCode};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::networking::llsd::{self, Llsd, LlsdError};
+use crate::networking::proxy::{self, ProxyError};
+use crate::ui::proxy::ProxySettings;
+use crate::utils::platform;
+
+/// Capabilities requested from the seed by default.
+pub const REQUESTED_CAPS: &[&str] = &[
+    "AbuseCategories", "AcceptFriendship", "AcceptGroupInvite", "AgentPreferences", "AgentProfile",
+    "AgentState", "AttachmentResources", "AvatarPickerSearch", "AvatarRenderInfo",
+    "CharacterProperties", "ChatSessionRequest", "CopyInventoryFromNotecard",
+    "CreateInventoryCategory", "DeclineFriendship", "DeclineGroupInvite", "DispatchRegionInfo",
+    "DirectDelivery", "EnvironmentSettings", "EstateAccess", "EstateChangeInfo", "EventQueueGet",
+    "ExtEnvironment", "FetchLib2", "FetchLibDescendents2", "FetchInventory2",
+    "FetchInventoryDescendents2", "IncrementCOFVersion", "RequestTaskInventory", "InventoryAPIv3",
+    "LibraryAPIv3", "InterestList", "InventoryThumbnailUpload", "GetDisplayNames", "GetExperiences",
+    "AgentExperiences", "FindExperienceByName", "GetExperienceInfo", "GetAdminExperiences",
+    "GetCreatorExperiences", "ExperiencePreferences", "GroupExperiences", "UpdateExperience",
+    "IsExperienceAdmin", "IsExperienceContributor", "RegionExperiences", "ExperienceQuery",
+    "GetMetadata", "GetObjectCost", "GetObjectPhysicsData", "GetTexture", "GroupAPIv1",
+    "GroupMemberData", "GroupProposalBallot", "HomeLocation", "LandResources", "LSLSyntax",
+    "MapLayer", "MapLayerGod", "MeshUploadFlag", "ModifyMaterialParams", "ModifyRegion",
+    "NavMeshGenerationStatus", "NewFileAgentInventory", "ObjectAnimation", "ObjectMedia",
+    "ObjectMediaNavigate", "ObjectNavMeshProperties", "ParcelPropertiesUpdate",
+    "ParcelVoiceInfoRequest", "ProductInfoRequest", "ProvisionVoiceAccountRequest",
+    "VoiceSignalingRequest", "ReadOfflineMsgs", "RegionObjects", "RegionSchedule",
+    "RemoteParcelRequest", "RenderMaterials", "RequestTextureDownload", "ResourceCostSelected",
+    "RetrieveNavMeshSrc", "SearchStatRequest", "SearchStatTracking", "SendPostcard",
+    "SendUserReport", "SendUserReportWithScreenshot", "ServerReleaseNotes", "SetDisplayName",
+    "SimConsoleAsync", "SimulatorFeatures", "StartGroupProposal", "TerrainNavMeshProperties",
+    "TextureStats", "UntrustedSimulatorMessage", "UpdateAgentInformation", "UpdateAgentLanguage",
+    "UpdateAvatarAppearance", "UpdateGestureAgentInventory", "UpdateGestureTaskInventory",
+    "UpdateNotecardAgentInventory", "UpdateNotecardTaskInventory", "UpdateScriptAgent",
+    "UpdateScriptTask", "UpdateSettingsAgentInventory", "UpdateSettingsTaskInventory",
+    "UploadAgentProfileImage", "UpdateMaterialAgentInventory", "UpdateMaterialTaskInventory",
+    "UploadBakedTexture", "UserInfo", "ViewerAsset", "ViewerBenefits", "ViewerMetrics",
+    "ViewerStartAuction", "ViewerStats",
+];
+
+const LLSD_XML: &str = "application/llsd+xml";
+
+#[derive(Debug, thiserror::Error)]
+pub enum CapsError {
+    #[error("capability request failed: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("{cap} failed: HTTP {status}")]
+    Status { cap: String, status: StatusCode },
+    #[error("region did not grant the {0} capability")]
+    Missing(String),
+    #[error(transparent)]
+    Llsd(#[from] LlsdError),
+    #[error(transparent)]
+    Proxy(#[from] ProxyError),
+}
+
+/// User-Agent in the viewer's `product/version (channel; skin)` form, from the same channel and
+/// version the login request reports.
+fn user_agent() -> String {
+    format!("{}/{} ({}; default skin)", platform::CHANNEL, platform::viewer_version(), platform::CHANNEL)
+}
+
+/// The capability name -> URL map returned from the seed capability.
+#[derive(Debug, Clone, Default)]
+pub struct Capabilities {
+    pub map: HashMap<String, String>,
+}
+
+impl Capabilities {
+    /// Parse the seed capability's response.
+    pub fn from_llsd(value: &Llsd) -> Result<Self, LlsdError> {
+        let map = value
+            .as_map()
+            .ok_or_else(|| LlsdError::Serde("capabilities response is not an LLSD map".to_string()))?
+            .iter()
+            .filter_map(|(name, url)| Some((name.clone(), url.as_string()?)))
+            .collect();
+        Ok(Self { map })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.map.get(name).map(String::as_str)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.map.contains_key(name)
+    }
+}
+
+/// Decode an LLSD response body by its Content-Type, sniffing the format when the header is
+/// missing or generic. An empty body is `Undefined`.
+pub fn decode_llsd(content_type: Option<&str>, body: &[u8]) -> Result<Llsd, LlsdError> {
+    if body.iter().all(u8::is_ascii_whitespace) {
+        return Ok(Llsd::Undefined);
+    }
+    let text = || std::str::from_utf8(body).map_err(|_| LlsdError::Serde("LLSD body is not UTF-8".to_string()));
+    let mime = content_type.and_then(|ct| ct.split(';').next()).map(str::trim).unwrap_or("");
+    match mime {
+        "application/llsd+xml" | "application/xml" | "text/xml" => llsd::xml::from_xml(text()?),
+        "application/llsd+binary" => llsd::binary::from_binary(body),
+        "application/llsd+notation" => llsd::notation::from_notation(text()?),
+        _ if body.starts_with(llsd::binary::HEADER) => llsd::binary::from_binary(body),
+        _ if body.trim_ascii_start().starts_with(b"<") => llsd::xml::from_xml(text()?),
+        _ => llsd::notation::from_notation(text()?),
+    }
+}
+
+/// One folder for FetchInventoryDescendents2.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FolderRequest {
+    pub folder_id: Uuid,
+    pub owner_id: Uuid,
+    pub fetch_folders: bool,
+    pub fetch_items: bool,
+    /// Bit 0: sort by date, bit 1: folders by name.
+    pub sort_order: i32,
+}
+
+impl FolderRequest {
+    fn to_llsd(&self) -> Llsd {
+        Llsd::from_iter([
+            ("folder_id", Llsd::Uuid(self.folder_id)),
+            ("owner_id", Llsd::Uuid(self.owner_id)),
+            ("fetch_folders", Llsd::Boolean(self.fetch_folders)),
+            ("fetch_items", Llsd::Boolean(self.fetch_items)),
+            ("sort_order", Llsd::Integer(self.sort_order)),
+        ])
+    }
+}
+
+/// LLSD client for one region's capabilities.
+pub struct CapsClient {
+    http: reqwest::Client,
+    udp_port: u16,
+    session_cookie: Option<String>,
+    requested: Vec<String>,
+    seed_capability: String,
+    capabilities: Capabilities,
+}
+
+impl CapsClient {
+    /// A client for `seed_capability` that has not fetched anything yet; see [`CapsClient::connect`].
+    pub fn new(seed_capability: impl Into<String>, udp_port: u16, proxy_settings: Option<&ProxySettings>, session_cookie: Option<String>) -> Result<Self, CapsError> {
+        Ok(Self {
+            http: proxy::http_client(proxy_settings)?,
+            udp_port,
+            session_cookie,
+            requested: REQUESTED_CAPS.iter().map(|name| name.to_string()).collect(),
+            seed_capability: seed_capability.into(),
+            capabilities: Capabilities::default(),
+        })
+    }
+
+    /// Fetch the capabilities granted by `seed_capability`.
+    pub async fn connect(
+        seed_capability: impl Into<String>,
+        udp_port: u16,
+        proxy_settings: Option<&ProxySettings>,
+        session_cookie: Option<String>,
+    ) -> Result<Self, CapsError> {
+        let mut client = Self::new(seed_capability, udp_port, proxy_settings, session_cookie)?;
+        client.refresh().await?;
+        Ok(client)
+    }
+
+    /// Ask the seed for these capabilities instead of [`REQUESTED_CAPS`] on the next refresh.
+    pub fn with_requested_caps<I, S>(mut self, names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.requested = names.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn seed_capability(&self) -> &str {
+        &self.seed_capability
+    }
+
+    pub fn capabilities(&self) -> &Capabilities {
+        &self.capabilities
+    }
+
+    /// URL of a granted capability.
+    pub fn url(&self, cap: &str) -> Result<&str, CapsError> {
+        self.capabilities.get(cap).ok_or_else(|| CapsError::Missing(cap.to_string()))
+    }
+
+    /// Re-fetch the capability map from the seed.
+    pub async fn refresh(&mut self) -> Result<(), CapsError> {
+        let requested = Llsd::Array(self.requested.iter().map(|name| Llsd::from(name.as_str())).collect());
+        let resp = self.request(Method::POST, &self.seed_capability).body(llsd::xml::to_xml(&requested)).send().await?;
+        let value = self.read("seed capability", resp).await?;
+        self.capabilities = Capabilities::from_llsd(&value)?;
+        Ok(())
+    }
+
+    fn request(&self, method: Method, url: &str) -> reqwest::RequestBuilder {
+        let mut builder = self
+            .http
+            .request(method, url)
+            .header(ACCEPT, LLSD_XML)
+            .header(CONTENT_TYPE, LLSD_XML)
+            .header(USER_AGENT, user_agent())
+            .header("X-SecondLife-UDP-Listen-Port", self.udp_port.to_string());
+        if let Some(cookie) = self.session_cookie.as_deref().and_then(|c| HeaderValue::from_str(c).ok()) {
+            builder = builder.header(COOKIE, cookie);
+        }
+        builder
+    }
+
+    async fn read(&self, cap: &str, resp: reqwest::Response) -> Result<Llsd, CapsError> {
+        let status = resp.status();
+        if !status.is_success() {
+            return Err(CapsError::Status { cap: cap.to_string(), status });
+        }
+        let content_type = resp.headers().get(CONTENT_TYPE).and_then(|v| v.to_str().ok()).map(str::to_string);
+        let body = resp.bytes().await?;
+        Ok(decode_llsd(content_type.as_deref(), &body)?)
+    }
+
+    /// Send a request to a capability (plus `suffix`, e.g. a query string), refreshing the
+    /// capability map and retrying once if the URL has expired.
+    async fn call(&mut self, method: Method, cap: &str, suffix: &str, body: Option<&Llsd>) -> Result<Llsd, CapsError> {
+        let mut refreshed = false;
+        loop {
+            let url = format!("{}{}", self.url(cap)?, suffix);
+            let mut builder = self.request(method.clone(), &url);
+            if let Some(body) = body {
+                builder = builder.body(llsd::xml::to_xml(body));
+            }
+            let resp = builder.send().await?;
+            if resp.status() == StatusCode::NOT_FOUND && !refreshed {
+                refreshed = true;
+                self.refresh().await?;
+                continue;
+            }
+            return self.read(cap, resp).await;
+        }
+    }
+
+    pub async fn get(&mut self, cap: &str) -> Result<Llsd, CapsError> {
+        self.call(Method::GET, cap, "", None).await
+    }
+
+    pub async fn post(&mut self, cap: &str, body: &Llsd) -> Result<Llsd, CapsError> {
+        self.call(Method::POST, cap, "", Some(body)).await
+    }
+
+    /// Region features: mesh and physics limits, OpenSim extras, etc.
+    pub async fn simulator_features(&mut self) -> Result<Llsd, CapsError> {
+        self.get("SimulatorFeatures").await
+    }
+
+    pub async fn navmesh_generation_status(&mut self) -> Result<Llsd, CapsError> {
+        self.get("NavMeshGenerationStatus").await
+    }
+
+    /// Environment settings of the region, or of one parcel in it.
+    pub async fn ext_environment(&mut self, parcel_id: Option<i32>) -> Result<Llsd, CapsError> {
+        let suffix = parcel_id.map(|id| format!("?parcelid={}", id)).unwrap_or_default();
+        self.call(Method::GET, "ExtEnvironment", &suffix, None).await
+    }
+
+    /// Store agent preferences (god level, hover height, access, default permissions).
+    pub async fn agent_preferences(&mut self, preferences: &Llsd) -> Result<Llsd, CapsError> {
+        self.post("AgentPreferences", preferences).await
+    }
+
+    pub async fn update_agent_language(&mut self, language: &str, language_is_public: bool) -> Result<(), CapsError> {
+        let body = Llsd::from_iter([("language", Llsd::from(language)), ("language_is_public", Llsd::Boolean(language_is_public))]);
+        self.post("UpdateAgentLanguage", &body).await.map(|_| ())
+    }
+
+    /// Folder contents for each request; the response carries a `folders` array.
+    pub async fn fetch_inventory_descendents(&mut self, folders: &[FolderRequest]) -> Result<Llsd, CapsError> {
+        let body = Llsd::from_iter([("folders", Llsd::Array(folders.iter().map(FolderRequest::to_llsd).collect()))]);
+        self.post("FetchInventoryDescendents2", &body).await
+    }
+
+    /// Where to GET a texture: ViewerAsset on current grids, GetTexture on older ones.
+    pub fn texture_url(&self, texture_id: Uuid) -> Result<String, CapsError> {
+        let base = self.capabilities.get("ViewerAsset").or_else(|| self.capabilities.get("GetTexture"));
+        let base = base.ok_or_else(|| CapsError::Missing("ViewerAsset".to_string()))?;
+        Ok(format!("{}/?texture_id={}", base.trim_end_matches('/'), texture_id))
+    }
+
+    /// Download a texture's JPEG2000 data.
+    pub async fn fetch_texture(&self, texture_id: Uuid) -> Result<Vec<u8>, CapsError> {
+        let resp = self.request(Method::GET, &self.texture_url(texture_id)?).header(ACCEPT, "image/x-j2c").send().await?;
+        let status = resp.status();
+        if !status.is_success() {
+            return Err(CapsError::Status { cap: "ViewerAsset".to_string(), status });
+        }
+        Ok(resp.bytes().await?.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_llsd_by_content_type_and_sniffing() {
+        let xml = br#"<?xml version="1.0" ?><llsd><map><key>EventQueueGet</key><string>https://sim/cap/eq</string></map></llsd>"#;
+        let caps = Capabilities::from_llsd(&decode_llsd(Some("application/llsd+xml; charset=utf-8"), xml).unwrap()).unwrap();
+        assert_eq!(caps.get("EventQueueGet"), Some("https://sim/cap/eq"));
+        assert_eq!(decode_llsd(None, xml).unwrap(), decode_llsd(Some("text/html"), xml).unwrap());
+
+        let binary = [llsd::binary::HEADER, &llsd::binary::to_binary(&Llsd::Integer(7))[..]].concat();
+        assert_eq!(decode_llsd(Some("application/octet-stream"), &binary).unwrap(), Llsd::Integer(7));
+        assert_eq!(decode_llsd(Some("application/llsd+notation"), b"[i1,'a']").unwrap()[1].as_str(), Some("a"));
+        assert_eq!(decode_llsd(Some(LLSD_XML), b"").unwrap(), Llsd::Undefined);
+    }
+}
@@ -0,0 +1,312 @@
+//! LLUDP packet captures, for reproducing parser bugs offline.
+//!
+//! Set `SLV_CAPTURE=<path>` before starting the client and every datagram sent or received on
+//! the UDP socket is appended to that file. `slv-replay <path>` feeds a capture back through
+//! the packet framing, `MessageCodec` and a [`MessageBus`] carrying the networking actor's handlers.
+//!
+//! File layout: the 8-byte [`MAGIC`], then one record per datagram:
+//!
+//! | bytes  | field                                                |
+//! |--------|------------------------------------------------------|
+//! | 8      | timestamp, microseconds since the Unix epoch (LE)    |
+//! | 1      | direction: 0 inbound, 1 outbound                     |
+//! | 1      | address family: 4 or 6                               |
+//! | 4 / 16 | simulator IP                                         |
+//! | 2      | simulator port (BE)                                  |
+//! | 4      | datagram length (LE)                                 |
+//! | n      | datagram, exactly as on the wire                     |
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::Path;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::networking::handlers::{Incoming, MessageBus};
+use crate::networking::protocol::codecs::MessageCodec;
+use crate::networking::protocol::generated::Message;
+use crate::networking::protocol::packet::Packet;
+use crate::networking::protocol::wire::{CodecError, MessageNumber};
+
+pub const MAGIC: &[u8; 8] = b"SLVCAP01";
+
+/// Environment variable naming the capture file.
+pub const CAPTURE_ENV: &str = "SLV_CAPTURE";
+
+/// Larger than any LLUDP datagram; a longer length means the file is damaged.
+const MAX_DATAGRAM: u32 = 65536;
+
+#[derive(Debug, thiserror::Error)]
+pub enum CaptureError {
+    #[error("capture I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("not a packet capture (bad magic)")]
+    BadMagic,
+    #[error("capture is truncated or damaged: {0}")]
+    Corrupt(String),
+}
+
+/// Why a captured datagram did not replay.
+#[derive(Debug, thiserror::Error)]
+pub enum ReplayError {
+    #[error("bad packet framing: {0}")]
+    Framing(CodecError),
+    /// A well-formed packet carrying a message the template does not define. The circuit acks
+    /// and drops these, so they are not parser failures.
+    #[error("message {0} is not in the template")]
+    UnknownMessage(MessageNumber),
+    #[error("{name} failed to decode: {error}")]
+    Decode { name: &'static str, error: CodecError },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Inbound,
+    Outbound,
+}
+
+/// One captured datagram.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaptureRecord {
+    /// Time since the Unix epoch.
+    pub timestamp: Duration,
+    pub direction: Direction,
+    /// The simulator end of the circuit: the sender of inbound and the target of outbound datagrams.
+    pub circuit: SocketAddr,
+    pub data: Vec<u8>,
+}
+
+impl CaptureRecord {
+    pub fn now(direction: Direction, circuit: SocketAddr, data: &[u8]) -> Self {
+        // Captures store microseconds
+        let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let timestamp = Duration::from_micros(since_epoch.as_micros() as u64);
+        Self { timestamp, direction, circuit, data: data.to_vec() }
+    }
+
+    pub fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        out.write_all(&(self.timestamp.as_micros() as u64).to_le_bytes())?;
+        out.write_all(&[match self.direction {
+            Direction::Inbound => 0,
+            Direction::Outbound => 1,
+        }])?;
+        match self.circuit.ip() {
+            IpAddr::V4(ip) => {
+                out.write_all(&[4])?;
+                out.write_all(&ip.octets())?;
+            }
+            IpAddr::V6(ip) => {
+                out.write_all(&[6])?;
+                out.write_all(&ip.octets())?;
+            }
+        }
+        out.write_all(&self.circuit.port().to_be_bytes())?;
+        out.write_all(&(self.data.len() as u32).to_le_bytes())?;
+        out.write_all(&self.data)
+    }
+
+    /// Read the next record; `None` at a clean end of file.
+    pub fn read_from<R: Read>(input: &mut R) -> Result<Option<Self>, CaptureError> {
+        let mut timestamp = [0u8; 8];
+        match input.read(&mut timestamp[..1])? {
+            0 => return Ok(None),
+            _ => read_exact(input, &mut timestamp[1..])?,
+        }
+        let mut head = [0u8; 2];
+        read_exact(input, &mut head)?;
+        let direction = match head[0] {
+            0 => Direction::Inbound,
+            1 => Direction::Outbound,
+            other => return Err(CaptureError::Corrupt(format!("unknown direction {}", other))),
+        };
+        let ip = match head[1] {
+            4 => {
+                let mut octets = [0u8; 4];
+                read_exact(input, &mut octets)?;
+                IpAddr::V4(Ipv4Addr::from(octets))
+            }
+            6 => {
+                let mut octets = [0u8; 16];
+                read_exact(input, &mut octets)?;
+                IpAddr::V6(Ipv6Addr::from(octets))
+            }
+            other => return Err(CaptureError::Corrupt(format!("unknown address family {}", other))),
+        };
+        let mut port = [0u8; 2];
+        read_exact(input, &mut port)?;
+        let mut len = [0u8; 4];
+        read_exact(input, &mut len)?;
+        let len = u32::from_le_bytes(len);
+        if len > MAX_DATAGRAM {
+            return Err(CaptureError::Corrupt(format!("datagram length {}", len)));
+        }
+        let mut data = vec![0u8; len as usize];
+        read_exact(input, &mut data)?;
+        Ok(Some(Self {
+            timestamp: Duration::from_micros(u64::from_le_bytes(timestamp)),
+            direction,
+            circuit: SocketAddr::new(ip, u16::from_be_bytes(port)),
+            data,
+        }))
+    }
+}
+
+fn read_exact<R: Read>(input: &mut R, buf: &mut [u8]) -> Result<(), CaptureError> {
+    input.read_exact(buf).map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof => CaptureError::Corrupt("record cut short".to_string()),
+        _ => CaptureError::Io(e),
+    })
+}
+
+/// Appends records to a capture file. Shared by the socket's send and receive paths.
+pub struct CaptureWriter {
+    out: Mutex<Box<dyn Write + Send>>,
+}
+
+impl CaptureWriter {
+    pub fn new(mut out: impl Write + Send + 'static) -> io::Result<Self> {
+        out.write_all(MAGIC)?;
+        out.flush()?;
+        Ok(Self { out: Mutex::new(Box::new(out)) })
+    }
+
+    /// Create (or truncate) a capture file.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?))
+    }
+
+    /// The writer named by `SLV_CAPTURE`, if it is set and the file can be created. Opened
+    /// once per process, so every socket bound afterwards appends to the same capture.
+    pub fn from_env() -> Option<Arc<Self>> {
+        static WRITER: OnceLock<Option<Arc<CaptureWriter>>> = OnceLock::new();
+        WRITER
+            .get_or_init(|| {
+                let path = std::env::var_os(CAPTURE_ENV).filter(|path| !path.is_empty())?;
+                match Self::create(&path) {
+                    Ok(writer) => {
+                        tracing::info!("Capturing LLUDP traffic to {}", Path::new(&path).display());
+                        Some(Arc::new(writer))
+                    }
+                    Err(e) => {
+                        tracing::warn!("Cannot create capture file {}: {}", Path::new(&path).display(), e);
+                        None
+                    }
+                }
+            })
+            .clone()
+    }
+
+    /// Append a datagram. Each record is flushed so a crash keeps everything up to it.
+    pub fn record(&self, direction: Direction, circuit: SocketAddr, data: &[u8]) {
+        let record = CaptureRecord::now(direction, circuit, data);
+        let mut out = self.out.lock().unwrap();
+        if let Err(e) = record.write_to(&mut *out).and_then(|_| out.flush()) {
+            tracing::warn!("Failed to write packet capture: {}", e);
+        }
+    }
+}
+
+/// Reads the records of a capture in order.
+pub struct CaptureReader<R> {
+    input: R,
+    failed: bool,
+}
+
+impl CaptureReader<BufReader<File>> {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, CaptureError> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> CaptureReader<R> {
+    pub fn new(mut input: R) -> Result<Self, CaptureError> {
+        let mut magic = [0u8; 8];
+        input.read_exact(&mut magic).map_err(|_| CaptureError::BadMagic)?;
+        if &magic != MAGIC {
+            return Err(CaptureError::BadMagic);
+        }
+        Ok(Self { input, failed: false })
+    }
+}
+
+impl<R: Read> Iterator for CaptureReader<R> {
+    type Item = Result<CaptureRecord, CaptureError>;
+
+    /// Stops after the first error, since record boundaries are lost.
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let next = CaptureRecord::read_from(&mut self.input).transpose();
+        self.failed = matches!(next, Some(Err(_)));
+        next
+    }
+}
+
+/// A datagram that decoded.
+#[derive(Debug, Clone)]
+pub struct Replayed {
+    pub sequence: u32,
+    pub message: Message,
+    /// Whether the legacy `MessageCodec` also understood it; it only handles a few messages.
+    pub legacy_decoded: bool,
+}
+
+/// Run one captured datagram through the receive path: framing, template decode and
+/// `MessageCodec`. Inbound messages are then dispatched on `bus` as the circuit would.
+pub fn replay_record(record: &CaptureRecord, bus: &MessageBus) -> Result<Replayed, ReplayError> {
+    let packet = Packet::decode(&record.data).map_err(ReplayError::Framing)?;
+    let number = packet.message_number().map_err(ReplayError::Framing)?;
+    let name = Message::name_of(number).ok_or(ReplayError::UnknownMessage(number))?;
+    let message = packet.message().map_err(|error| ReplayError::Decode { name, error })?;
+    let legacy_decoded = MessageCodec::decode_packet(&packet).is_ok();
+    if record.direction == Direction::Inbound {
+        bus.dispatch(Incoming { from: record.circuit, sequence: packet.sequence, message: message.clone() });
+    }
+    Ok(Replayed { sequence: packet.sequence, message, legacy_decoded })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networking::protocol::builders;
+    use crate::networking::protocol::generated;
+
+    #[test]
+    fn test_capture_roundtrip_and_replay() {
+        let sim: SocketAddr = "127.0.0.1:13000".parse().unwrap();
+        let ping = Packet::from_message(&builders::start_ping_check(5, 1), 42, false).unwrap().encode().unwrap();
+        let records = vec![
+            CaptureRecord::now(Direction::Inbound, sim, &ping),
+            CaptureRecord::now(Direction::Outbound, "[::1]:9000".parse().unwrap(), &[0x40, 0, 0, 0, 1, 0, 0xFF]),
+        ];
+        let mut file = MAGIC.to_vec();
+        for record in &records {
+            record.write_to(&mut file).unwrap();
+        }
+        let read: Vec<_> = CaptureReader::new(file.as_slice()).unwrap().collect::<Result<_, _>>().unwrap();
+        assert_eq!(read, records);
+
+        file.truncate(file.len() - 3);
+        let mut reader = CaptureReader::new(file.as_slice()).unwrap();
+        assert!(reader.next().unwrap().is_ok());
+        assert!(matches!(reader.next(), Some(Err(CaptureError::Corrupt(_)))));
+        assert!(reader.next().is_none());
+        assert!(matches!(CaptureReader::new(&b"garbage!"[..]), Err(CaptureError::BadMagic)));
+
+        let bus = MessageBus::default();
+        let seen = Arc::new(Mutex::new(None));
+        let seen_by = Arc::clone(&seen);
+        bus.on::<generated::StartPingCheck, _>(move |from, msg| {
+            *seen_by.lock().unwrap() = Some((from, msg.ping_id.ping_id));
+        });
+        let replayed = replay_record(&read[0], &bus).unwrap();
+        assert_eq!(replayed.sequence, 42);
+        assert_eq!(replayed.message.name(), "StartPingCheck");
+        assert_eq!(*seen.lock().unwrap(), Some((sim, 5)));
+        assert!(matches!(replay_record(&read[1], &bus), Err(ReplayError::Framing(_))));
+        let unknown = CaptureRecord::now(Direction::Inbound, sim, &[0, 0, 0, 0, 2, 0, 0xFF, 0xFF, 0xFF, 0x00]);
+        assert!(matches!(replay_record(&unknown, &bus), Err(ReplayError::UnknownMessage(MessageNumber::Fixed(0x00)))));
+    }
+}
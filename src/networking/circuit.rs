@@ -1,11 +1,16 @@
-use crate::networking::protocol::messages::{PacketHeader, Message};
-use crate::networking::protocol::codecs::MessageCodec;
+use crate::networking::protocol::messages::Message;
+use crate::networking::protocol::{builders, generated};
+use crate::networking::protocol::packet::{Packet, MAX_APPENDED_ACKS, MAX_PACKET_SIZE, PACKET_HEADER_SIZE};
+use crate::networking::reliability::{ReliabilityConfig, ReliabilityTracker};
+use crate::networking::stats::{CircuitStats, StatsRecorder};
+use crate::networking::handlers::{Incoming, MessageBus};
 use std::net::SocketAddr;
 use std::io;
-use std::collections::HashMap;
 use tokio::time::{self, Instant, Duration};
 use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use tokio::sync::Mutex;
 use crate::networking::transport::UdpTransport;
 
@@ -25,6 +30,32 @@ pub enum HandshakeState {
     HandshakeComplete,
 }
 
+/// Identity and settings the handshake needs, fixed for the life of the circuit.
+#[derive(Debug, Clone)]
+pub struct CircuitParams {
+    pub agent_id: uuid::Uuid,
+    pub session_id: uuid::Uuid,
+    pub circuit_code: u32,
+    pub throttle: [f32; 7],
+    /// RegionHandshakeReply flags.
+    pub region_handshake_flags: u32,
+    /// Child agent in a neighbouring region: no CompleteAgentMovement and no AgentUpdate stream.
+    pub child: bool,
+}
+
+impl CircuitParams {
+    pub fn new(agent_id: uuid::Uuid, session_id: uuid::Uuid, circuit_code: u32) -> Self {
+        Self {
+            agent_id,
+            session_id,
+            circuit_code,
+            throttle: builders::DEFAULT_THROTTLE,
+            region_handshake_flags: 0,
+            child: false,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct AgentState {
     pub position: (f32, f32, f32),
@@ -33,22 +64,123 @@ pub struct AgentState {
     pub controls: u32,
 }
 
+/// Minimum resend timeout; the actual timeout adapts to the measured RTT.
 const RETRANSMISSION_TIMEOUT_MS: u64 = 200;
 const MAX_RETRANSMISSIONS: u32 = 5;
+/// How often owed acks are flushed as PacketAck and resend timers are checked.
+const ACK_FLUSH_INTERVAL_MS: u64 = 100;
+/// How often we send our own StartPingCheck to measure RTT and loss.
+const PING_INTERVAL_SECS: u64 = 5;
+
+/// Datagrams queued for a circuit's receive task before the reader waits.
+const INBOUND_QUEUE_LEN: usize = 256;
+
+/// A received UDP payload and the address it came from.
+pub type Datagram = (Vec<u8>, SocketAddr);
+
+/// Whether a circuit is currently a child agent's, readable from its message handlers. Border
+/// crossings flip it with `Circuit::promote` and `Circuit::demote`.
+#[derive(Clone)]
+pub struct CircuitRole(Arc<AtomicBool>);
+
+impl CircuitRole {
+    pub fn new(child: bool) -> Self {
+        Self(Arc::new(AtomicBool::new(child)))
+    }
+
+    pub fn is_child(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    pub fn set_child(&self, child: bool) {
+        self.0.store(child, Ordering::Relaxed);
+    }
+}
+
+/// The reply a circuit sends on its own to an incoming message, before handing it to the bus.
+pub fn automatic_reply(message: &generated::Message) -> Option<generated::Message> {
+    match message {
+        generated::Message::StartPingCheck(ping) => Some(builders::complete_ping_check(ping.ping_id.ping_id)),
+        _ => None,
+    }
+}
+
+/// Called with the simulator address when a reliable packet runs out of resends.
+pub type CircuitDeadCallback = Arc<dyn Fn(SocketAddr) + Send + Sync>;
+
+/// Frame and send one message: takes the next sequence number, appends any acks we owe
+/// and starts the resend timer for reliable packets. Returns the sequence number and bytes sent.
+async fn send_on_transport(
+    transport: &mut UdpTransport,
+    reliability: &Mutex<ReliabilityTracker>,
+    stats: &std::sync::Mutex<StatsRecorder>,
+    message: &generated::Message,
+    reliable: bool,
+    target: &SocketAddr,
+) -> io::Result<(u32, usize)> {
+    let sequence = transport.next_sequence();
+    let mut packet = Packet::from_message(message, sequence, reliable)?;
+    {
+        let mut reliability = reliability.lock().await;
+        let room = MAX_PACKET_SIZE.saturating_sub(PACKET_HEADER_SIZE + packet.body.len() + 1) / 4;
+        packet.acks = reliability.take_pending_acks(room.min(MAX_APPENDED_ACKS));
+        if reliable {
+            reliability.track(&packet, Instant::now());
+        }
+    }
+    let encoded = packet.encode()?;
+    let sent = transport.send_to(&encoded, target).await?;
+    stats.lock().unwrap().record_sent(sent, Instant::now());
+    Ok((sequence, sent))
+}
+
+/// Map the legacy `Message` enum onto a generated message and whether it is sent reliably.
+/// Messages that do not carry ids of their own (chat, logout) use the circuit's.
+fn to_wire_message(message: &Message, params: &CircuitParams) -> io::Result<(generated::Message, bool)> {
+    let uuid = |s: &str| uuid::Uuid::parse_str(s).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e));
+    Ok(match message {
+        Message::PacketAck { packets } => (builders::packet_ack(packets), false),
+        Message::UseCircuitCode { agent_id, session_id, circuit_code } => {
+            (builders::use_circuit_code(*circuit_code, uuid(session_id)?, uuid(agent_id)?), true)
+        }
+        Message::CompleteAgentMovement { agent_id, session_id, circuit_code, .. } => {
+            (builders::complete_agent_movement(uuid(agent_id)?, uuid(session_id)?, *circuit_code), true)
+        }
+        Message::RegionHandshakeReply { agent_id, session_id, flags } => {
+            (builders::region_handshake_reply(uuid(agent_id)?, uuid(session_id)?, *flags), true)
+        }
+        Message::AgentThrottle { agent_id, session_id, circuit_code, throttle } => {
+            (builders::agent_throttle(uuid(agent_id)?, uuid(session_id)?, *circuit_code, *throttle), true)
+        }
+        Message::AgentUpdate { agent_id, session_id, camera_at, camera_eye, controls, .. } => {
+            (builders::agent_update(uuid(agent_id)?, uuid(session_id)?, *camera_at, *camera_eye, *controls), false)
+        }
+        Message::ChatFromViewer { message, channel } => {
+            // "local" and other named channels are public chat on channel 0.
+            let channel = channel.parse().unwrap_or(0);
+            (builders::chat_from_viewer(params.agent_id, params.session_id, message, builders::CHAT_TYPE_NORMAL, channel), true)
+        }
+        Message::Logout => (builders::logout_request(params.agent_id, params.session_id), true),
+        // TODO: Add encoding for other message types as needed
+        _ => {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "send_message: unsupported message type for manual encoding"));
+        }
+    })
+}
 
 // TODO: Refactor for proxy support. UdpSocketExt removed.
 pub struct Circuit {
     transport: Arc<Mutex<UdpTransport>>,
-    next_sequence_number: u32,
-    next_expected_sequence_number: Arc<Mutex<u32>>,
-    unacked_messages: Arc<Mutex<HashMap<u32, (Message, Instant, u32, SocketAddr, Vec<u8>)>>>, // sequence_id -> (message, sent_time, retransmission_count, target_addr, encoded_message)
-    receiver_channel: mpsc::Receiver<(PacketHeader, Message, SocketAddr)>, // Channel for receiving messages from the processing task
-    out_of_order_buffer: Arc<Mutex<HashMap<u32, (PacketHeader, Message, SocketAddr)>>>, // sequence_id -> (header, message, sender_addr)
+    /// Unacked outgoing packets, acks we owe and the duplicate window. Shared with the receive task.
+    reliability: Arc<Mutex<ReliabilityTracker>>,
+    /// RTT, jitter, loss and bandwidth counters. Shared with the receive task.
+    stats: Arc<std::sync::Mutex<StatsRecorder>>,
+    /// Every decoded incoming message is dispatched here; see `networking::handlers`.
+    bus: Arc<MessageBus>,
+    params: CircuitParams,
+    role: CircuitRole,
+    on_circuit_dead: Arc<std::sync::Mutex<Option<CircuitDeadCallback>>>,
     pub handshake_state: HandshakeState,
-    pub eq_polling_started: bool,
-    pub capabilities: Option<Arc<crate::networking::session::Capabilities>>,
-    pub udp_port: u16,
-    pub proxy_settings: Option<crate::ui::proxy::ProxySettings>,
     /// Shared agent state for dynamic updates.
     /// Update this from UI/game logic to change position, camera, controls, etc.:
     /// {
@@ -59,138 +191,191 @@ pub struct Circuit {
     ///     state.controls = new_controls;
     /// }
     pub agent_state: Arc<Mutex<AgentState>>,
+    /// Background tasks owned by this circuit; aborted when it is dropped.
+    tasks: Vec<JoinHandle<()>>,
+    /// Periodic AgentUpdate task, while this is the agent's own region.
+    agent_updates: Option<JoinHandle<()>>,
+}
+
+impl Drop for Circuit {
+    fn drop(&mut self) {
+        for task in self.tasks.iter().chain(&self.agent_updates) {
+            task.abort();
+        }
+    }
 }
 
 impl Circuit {
-    pub async fn new_with_transport(transport: Arc<Mutex<UdpTransport>>, agent_state: Arc<Mutex<AgentState>>) -> std::io::Result<Self> {
-        let (sender_channel_for_task, receiver_channel) = mpsc::channel(100);
-
-        let unacked_messages_arc = Arc::new(Mutex::new(HashMap::<u32, (Message, Instant, u32, SocketAddr, Vec<u8>)>::new()));
-        let unacked_messages_arc_clone = Arc::clone(&unacked_messages_arc);
-        let next_expected_sequence_number_arc = Arc::new(Mutex::new(1));
-        let next_expected_sequence_number_arc_clone = Arc::clone(&next_expected_sequence_number_arc);
-        let out_of_order_buffer_arc = Arc::new(Mutex::new(HashMap::<u32, (PacketHeader, Message, SocketAddr)>::new()));
-        let out_of_order_buffer_arc_clone = Arc::clone(&out_of_order_buffer_arc);
+    /// Circuit that reads its transport's socket directly. Use `new_with_inbound` when several
+    /// circuits share one socket (see `CircuitManager`).
+    pub async fn new_with_transport(
+        transport: Arc<Mutex<UdpTransport>>,
+        params: CircuitParams,
+        agent_state: Arc<Mutex<AgentState>>,
+    ) -> std::io::Result<Self> {
+        let socket = transport.lock().await.socket();
+        let (inbound_tx, inbound_rx) = mpsc::channel(INBOUND_QUEUE_LEN);
+        let reader = tokio::spawn(async move {
+            let mut buf = vec![0; 4096];
+            loop {
+                match socket.recv_from(&mut buf).await {
+                    Ok((len, addr)) => {
+                        if inbound_tx.send((buf[..len].to_vec(), addr)).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        eprintln!("[UDP RX] Socket receive error: {}", e);
+                        break;
+                    }
+                }
+            }
+        });
+        let mut circuit = Self::new_with_inbound(transport, inbound_rx, params, agent_state).await?;
+        circuit.tasks.push(reader);
+        Ok(circuit)
+    }
+
+    /// Circuit fed with datagrams from `inbound`, already filtered to this simulator.
+    pub async fn new_with_inbound(
+        transport: Arc<Mutex<UdpTransport>>,
+        mut inbound: mpsc::Receiver<Datagram>,
+        params: CircuitParams,
+        agent_state: Arc<Mutex<AgentState>>,
+    ) -> std::io::Result<Self> {
+        let bus = Arc::new(MessageBus::default());
+        let bus_bg = Arc::clone(&bus);
+
+        let reliability = Arc::new(Mutex::new(ReliabilityTracker::new(ReliabilityConfig {
+            min_rto: Duration::from_millis(RETRANSMISSION_TIMEOUT_MS),
+            max_resends: MAX_RETRANSMISSIONS,
+            ..Default::default()
+        })));
+        let reliability_bg = Arc::clone(&reliability);
+        let stats = Arc::new(std::sync::Mutex::new(StatsRecorder::new(Instant::now())));
+        let stats_bg = Arc::clone(&stats);
+        let on_circuit_dead: Arc<std::sync::Mutex<Option<CircuitDeadCallback>>> = Arc::new(std::sync::Mutex::new(None));
+        let on_circuit_dead_bg = Arc::clone(&on_circuit_dead);
         let transport_bg = Arc::clone(&transport);
 
         // Spawn the UDP receive/retransmit task
-        tokio::spawn(async move {
-            // Use the trait object for send/recv
-            let mut buf = vec![0; 1024];
+        let receive_task = tokio::spawn(async move {
+            let mut tick = time::interval(Duration::from_millis(ACK_FLUSH_INTERVAL_MS));
+            let mut circuit_dead = false;
+            let mut last_ping = Instant::now();
             loop {
-                let mut transport_locked = transport_bg.lock().await;
                 tokio::select! {
-                    Ok((len, addr)) = transport_locked.recv_from(&mut buf) => {
-                        println!("[UDP RX] Received {} bytes from {}: {:02X?}", len, addr, &buf[..len]);
-                        if let Ok((header, message)) = MessageCodec::decode(&buf[..len]) {
-                            println!("[UDP RX] Decoded message: {:?} (seq: {}) from {}", message, header.sequence_id, addr);
-                            match &message {
-                                Message::UseCircuitCodeReply(success) => {
-                                    println!("[HANDSHAKE] Received UseCircuitCodeReply: success={}", success);
-                                }
-                                Message::AgentMovementComplete { .. } => {
-                                    println!("[HANDSHAKE] Received AgentMovementComplete!");
-                                }
-                                _ => {}
+                    datagram = inbound.recv() => {
+                        let Some((data, addr)) = datagram else {
+                            break;
+                        };
+                        let len = data.len();
+                        tracing::trace!("[UDP RX] {} bytes from {}", len, addr);
+                        let packet = match Packet::decode(&data) {
+                            Ok(packet) => packet,
+                            Err(e) => {
+                                tracing::debug!("[UDP RX] Failed to frame UDP packet from {}: {}", addr, e);
+                                continue;
                             }
-                            match message {
-                                Message::Ack { sequence_id } => {
-                                    let mut unacked_messages = unacked_messages_arc_clone.lock().await;
-                                    unacked_messages.remove(&sequence_id);
-                                }
-                                Message::KeepAlive => {
-                                    // Do not send an ACK for KeepAlive!
-                                    // Just log or handle as needed.
+                        };
+
+                        stats_bg.lock().unwrap().record_received(packet.sequence, len, Instant::now());
+
+                        // Ack bookkeeping only needs the framing, so reliable messages we cannot
+                        // decode yet are still acked instead of being resent to us until the sim gives up.
+                        let is_new = {
+                            let mut reliability = reliability_bg.lock().await;
+                            let now = Instant::now();
+                            for ack in &packet.acks {
+                                reliability.ack(*ack, now);
+                            }
+                            reliability.receive(packet.sequence, packet.is_reliable())
+                        };
+                        if !is_new {
+                            stats_bg.lock().unwrap().record_duplicate();
+                            tracing::debug!("Discarding duplicate packet {} from {}", packet.sequence, addr);
+                            continue;
+                        }
+
+                        let message = match packet.message() {
+                            Ok(message) => message,
+                            Err(e) => {
+                                tracing::debug!("No template decode for packet {} from {}: {}", packet.sequence, addr, e);
+                                continue;
+                            }
+                        };
+                        match &message {
+                            generated::Message::PacketAck(acks) => {
+                                let mut reliability = reliability_bg.lock().await;
+                                let now = Instant::now();
+                                for ack in &acks.packets {
+                                    reliability.ack(ack.id, now);
                                 }
-                                received_message => {
-                                    let mut messages_to_send = Vec::new();
-                                    {
-                                        let mut current_expected_seq = next_expected_sequence_number_arc_clone.lock().await;
-                                        let mut out_of_order_buffer = out_of_order_buffer_arc_clone.lock().await;
-
-                                        if header.sequence_id == *current_expected_seq {
-                                            messages_to_send.push((header.clone(), received_message.clone(), addr));
-                                            *current_expected_seq += 1;
-                                            while let Some((h, m, a)) = out_of_order_buffer.remove(&*current_expected_seq) {
-                                                messages_to_send.push((h, m, a));
-                                                *current_expected_seq += 1;
-                                            }
-                                        } else if header.sequence_id > *current_expected_seq {
-                                            out_of_order_buffer.insert(header.sequence_id, (header.clone(), received_message.clone(), addr));
-                                        } else {
-                                            tracing::debug!("Discarding duplicate or old packet: {:?}", header);
-                                        }
-                                    }
-                                    // Only send ACK if not KeepAlive
-                                    if !matches!(received_message, Message::KeepAlive) {
-                                        let ack_message = Message::Ack { sequence_id: header.sequence_id };
-                                        let ack_header = PacketHeader { sequence_id: 0, flags: 0 };
-                                        // Manual encoding for ACK message
-                                        let mut ack_packet = Vec::new();
-                                        let flags: u8 = 0x00; // Not reliable, not zerocoded
-                                        ack_packet.push(flags);
-                                        ack_packet.extend_from_slice(&ack_header.sequence_id.to_be_bytes());
-                                        ack_packet.push(0x00); // offset
-                                        ack_packet.extend_from_slice(&[0x00, 0x00, 0x00, 0x01]); // message number for ACK
-                                        ack_packet.extend_from_slice(&header.sequence_id.to_be_bytes()); // ACKed sequence id
-                                        if ack_packet.len() < 7 {
-                                            tracing::warn!("[BUG] Would send ACK packet < 7 bytes ({} bytes): {:02X?}. Skipping.", ack_packet.len(), ack_packet);
-                                        } else {
-                                            let _ = transport_locked.send_to(&ack_packet, &addr).await;
-                                        }
-                                    }
-                                    for (h, m, a) in messages_to_send {
-                                        let _ = sender_channel_for_task.send((h, m, a)).await;
-                                    }
+                            }
+                            generated::Message::CompletePingCheck(ping) => {
+                                let ping_id = ping.ping_id.ping_id;
+                                if let Some(rtt) = stats_bg.lock().unwrap().complete_ping(ping_id, Instant::now()) {
+                                    tracing::debug!("Ping {} to {} completed in {:?}", ping_id, addr, rtt);
                                 }
                             }
-                        } else {
-                            // Try legacy UseCircuitCode parsing
-                            let pkt = &buf[..len];
-                            // Legacy UseCircuitCode: flags(1) + packet_id(4) + offset(1) + msgnum(4) + circuit_code(4) + session_id(16) + agent_id(16) = 46 bytes
-                            if pkt.len() == 46 && pkt[0] & 0x40 != 0 && pkt[6..10] == [0xFF, 0xFF, 0x00, 0x03] {
-                                let packet_id = u32::from_le_bytes([pkt[1], pkt[2], pkt[3], pkt[4]]);
-                                let circuit_code = u32::from_le_bytes([pkt[10], pkt[11], pkt[12], pkt[13]]);
-                                let session_id = uuid::Uuid::from_bytes([pkt[14], pkt[15], pkt[16], pkt[17], pkt[18], pkt[19], pkt[20], pkt[21], pkt[22], pkt[23], pkt[24], pkt[25], pkt[26], pkt[27], pkt[28], pkt[29]]);
-                                let agent_id = uuid::Uuid::from_bytes([pkt[30], pkt[31], pkt[32], pkt[33], pkt[34], pkt[35], pkt[36], pkt[37], pkt[38], pkt[39], pkt[40], pkt[41], pkt[42], pkt[43], pkt[44], pkt[45]]);
-                                let message = Message::UseCircuitCode {
-                                    agent_id: agent_id.to_string(),
-                                    session_id: session_id.to_string(),
-                                    circuit_code,
-                                };
-                                let header = PacketHeader { sequence_id: packet_id, flags: pkt[0] };
-                                println!("[UDP RX] Parsed legacy UseCircuitCode: circuit_code={}, session_id={}, agent_id={}, seq={}", circuit_code, session_id, agent_id, packet_id);
-                                let _ = sender_channel_for_task.send((header, message, addr)).await;
-                            } else {
-                                println!("[UDP RX] Failed to decode UDP packet from {}: {:02X?}", addr, &buf[..len]);
+                            _ => {}
+                        }
+                        if let Some(reply) = automatic_reply(&message) {
+                            if let Err(e) = send_on_transport(&mut *transport_bg.lock().await, &reliability_bg, &stats_bg, &reply, false, &addr).await {
+                                tracing::warn!("Failed to send {}: {}", reply.name(), e);
                             }
                         }
+                        bus_bg.dispatch(Incoming { from: addr, sequence: packet.sequence, message });
                     },
-                    _ = time::sleep(Duration::from_millis(RETRANSMISSION_TIMEOUT_MS)) => {
-                        let mut messages_to_retransmit = Vec::new();
-                        let mut lost_messages = Vec::new();
-                        let mut unacked_messages = unacked_messages_arc_clone.lock().await;
-                        unacked_messages.retain(|&seq_id, (_message, sent_time, retransmission_count, target_addr, encoded_message)| {
-                            if sent_time.elapsed() > Duration::from_millis(RETRANSMISSION_TIMEOUT_MS) {
-                                if *retransmission_count < MAX_RETRANSMISSIONS {
-                                    messages_to_retransmit.push((seq_id, target_addr.clone(), encoded_message.clone()));
-                                    *sent_time = Instant::now();
-                                    *retransmission_count += 1;
-                                    true
-                                } else {
-                                    lost_messages.push(seq_id);
-                                    false
+                    _ = tick.tick() => {
+                        let mut transport_locked = transport_bg.lock().await;
+                        let target = transport_locked.sim_addr();
+
+                        // Coalesce owed acks into as few PacketAck messages as possible.
+                        let ack_batches = reliability_bg.lock().await.take_ack_batches();
+                        for batch in ack_batches {
+                            if let Err(e) = send_on_transport(&mut transport_locked, &reliability_bg, &stats_bg, &builders::packet_ack(&batch), false, &target).await {
+                                tracing::warn!("Failed to send PacketAck: {}", e);
+                            }
+                        }
+
+                        let resends = reliability_bg.lock().await.poll_resends(Instant::now());
+                        for packet in resends.resend {
+                            match packet.encode() {
+                                Ok(encoded) => {
+                                    if let Ok(sent) = transport_locked.send_to(&encoded, &target).await {
+                                        stats_bg.lock().unwrap().record_resent(sent, Instant::now());
+                                    }
+                                    tracing::debug!("Retransmitting message {} to {}.", packet.sequence, target);
                                 }
-                            } else {
-                                true
+                                Err(e) => tracing::warn!("Failed to re-encode packet {}: {}", packet.sequence, e),
                             }
-                        });
-                        for (seq_id, target_addr, encoded_message) in messages_to_retransmit {
-                            let _ = transport_locked.send_to(&encoded_message, &target_addr).await;
-                            tracing::debug!("Retransmitting message {} to {}.", seq_id, target_addr);
                         }
-                        for seq_id in lost_messages {
-                            tracing::warn!("Message {} lost after {} retransmissions.", seq_id, MAX_RETRANSMISSIONS);
+                        stats_bg.lock().unwrap().record_expired(resends.expired.len());
+                        if !resends.expired.is_empty() && !circuit_dead {
+                            for seq_id in &resends.expired {
+                                tracing::warn!("Message {} lost after {} retransmissions.", seq_id, MAX_RETRANSMISSIONS);
+                            }
+                            circuit_dead = true;
+                            let callback = on_circuit_dead_bg.lock().unwrap().clone();
+                            if let Some(callback) = callback {
+                                callback(target);
+                            }
+                        }
+
+                        let now = Instant::now();
+                        if now.saturating_duration_since(last_ping) >= Duration::from_secs(PING_INTERVAL_SECS) {
+                            last_ping = now;
+                            let oldest_unacked = reliability_bg.lock().await.oldest_unacked().unwrap_or(0);
+                            let (ping_id, snapshot) = {
+                                let mut stats = stats_bg.lock().unwrap();
+                                (stats.start_ping(now), stats.snapshot(now))
+                            };
+                            tracing::debug!("[NET] {}: {}", target, snapshot);
+                            let ping = builders::start_ping_check(ping_id, oldest_unacked);
+                            if let Err(e) = send_on_transport(&mut transport_locked, &reliability_bg, &stats_bg, &ping, false, &target).await {
+                                tracing::warn!("Failed to send StartPingCheck: {}", e);
+                            }
                         }
                     }
                 }
@@ -199,255 +384,223 @@ impl Circuit {
 
         Ok(Self {
             transport,
-            next_sequence_number: 1,
-            next_expected_sequence_number: Arc::new(Mutex::new(1)),
-            unacked_messages: unacked_messages_arc,
-            receiver_channel,
-            out_of_order_buffer: Arc::new(Mutex::new(HashMap::new())),
+            reliability,
+            stats,
+            bus,
+            role: CircuitRole::new(params.child),
+            params,
+            on_circuit_dead,
             handshake_state: HandshakeState::NotStarted,
-            eq_polling_started: false,
-            capabilities: None,
-            udp_port: 0,
-            proxy_settings: None,
             agent_state,
+            tasks: vec![receive_task],
+            agent_updates: None,
         })
     }
 
-    pub async fn send_message(&mut self, message: &Message, target: &SocketAddr) -> io::Result<usize> {
-        let header = PacketHeader {
-            sequence_id: self.next_sequence_number,
-            flags: 0, // TODO: Define flags for ACKs, etc.
-        };
-        self.next_sequence_number += 1;
-        // Manual encoding for each message type
-        let encoded = match message {
-            Message::Ack { sequence_id } => {
-                let mut ack_packet = Vec::new();
-                let flags: u8 = 0x00;
-                ack_packet.push(flags);
-                ack_packet.extend_from_slice(&header.sequence_id.to_be_bytes());
-                ack_packet.push(0x00);
-                ack_packet.extend_from_slice(&[0x00, 0x00, 0x00, 0x01]);
-                ack_packet.extend_from_slice(&sequence_id.to_be_bytes());
-                ack_packet
-            },
-            // TODO: Add manual encoding for other message types as needed
-            _ => {
-                return Err(io::Error::new(io::ErrorKind::InvalidInput, "send_message: unsupported message type for manual encoding"));
-            }
+    /// Handler registry and per-message-type broadcast channels for this circuit.
+    pub fn bus(&self) -> Arc<MessageBus> {
+        Arc::clone(&self.bus)
+    }
+
+    pub fn params(&self) -> &CircuitParams {
+        &self.params
+    }
+
+    pub fn is_child(&self) -> bool {
+        self.role.is_child()
+    }
+
+    /// Shared view of `is_child`, for handlers that outlive a promotion or demotion.
+    pub fn role(&self) -> CircuitRole {
+        self.role.clone()
+    }
+
+    /// Register a callback that runs once when a reliable packet exhausts its resends.
+    pub fn set_circuit_dead_callback<F>(&self, callback: F)
+    where
+        F: Fn(SocketAddr) + Send + Sync + 'static,
+    {
+        *self.on_circuit_dead.lock().unwrap() = Some(Arc::new(callback));
+    }
+
+    /// Smoothed round-trip time measured from acked reliable packets.
+    pub async fn smoothed_rtt(&self) -> Option<Duration> {
+        self.reliability.lock().await.srtt()
+    }
+
+    pub async fn unacked_count(&self) -> usize {
+        self.reliability.lock().await.unacked_count()
+    }
+
+    /// Snapshot of this circuit's RTT, jitter, loss, resend and bandwidth statistics.
+    pub async fn stats(&self) -> CircuitStats {
+        let (smoothed_rtt, unacked) = {
+            let reliability = self.reliability.lock().await;
+            (reliability.srtt(), reliability.unacked_count())
         };
-        // Store message for retransmission
-        let mut unacked_messages = self.unacked_messages.lock().await;
-        unacked_messages.insert(
-            header.sequence_id,
-            (message.clone(), Instant::now(), 0, target.clone(), encoded.clone()),
-        );
+        let mut stats = self.stats.lock().unwrap().snapshot(Instant::now());
+        stats.smoothed_rtt = smoothed_rtt;
+        stats.unacked = unacked;
+        stats
+    }
+
+    /// Send a generated message to the circuit's simulator. Returns the sequence number used.
+    pub async fn send(&self, message: &generated::Message, reliable: bool) -> io::Result<u32> {
         let mut transport = self.transport.lock().await;
-        transport.send_to(&encoded, target).await
+        let target = transport.sim_addr();
+        let (sequence, _) = send_on_transport(&mut transport, &self.reliability, &self.stats, message, reliable, &target).await?;
+        Ok(sequence)
     }
 
-    pub async fn recv_message(&mut self) -> io::Result<(PacketHeader, Message, SocketAddr)> {
-        self.receiver_channel.recv().await.ok_or_else(|| io::Error::new(io::ErrorKind::BrokenPipe, "Circuit receive channel closed"))
+    pub async fn send_message(&mut self, message: &Message, target: &SocketAddr) -> io::Result<usize> {
+        let (wire, reliable) = to_wire_message(message, &self.params)?;
+        let mut transport = self.transport.lock().await;
+        let (_, sent) = send_on_transport(&mut transport, &self.reliability, &self.stats, &wire, reliable, target).await?;
+        Ok(sent)
     }
 
-    /// Main receive loop for processing incoming UDP messages and enforcing handshake state machine
-    pub async fn run_receive_loop(&mut self,
-        agent_id: uuid::Uuid,
-        session_id: uuid::Uuid,
-        circuit_code: u32,
-        position: (f32, f32, f32),
-        look_at: (f32, f32, f32),
-        throttle: [f32; 7],
-        flags: u32,
-        controls: u32,
-        camera_at: (f32, f32, f32),
-        camera_eye: (f32, f32, f32),
-    ) {
-        loop {
-            match self.recv_message().await {
-                Ok((header, message, addr)) => {
-                    self.handle_incoming_message(
-                        header,
-                        message,
-                        addr,
-                        agent_id,
-                        session_id,
-                        circuit_code,
-                        position,
-                        look_at,
-                        throttle,
-                        flags,
-                        controls,
-                        camera_at,
-                        camera_eye
-                    ).await;
-                    // ... handle other message types as needed ...
-                }
-                Err(e) => {
-                    eprintln!("[UDP RX] Error receiving message: {}", e);
-                    break;
+    /// Run the handshake to completion. RegionHandshake is taken from the message bus, so this
+    /// subscribes before sending anything to avoid missing a fast reply.
+    pub async fn run_handshake(&mut self) -> io::Result<()> {
+        let mut region_handshakes = self.bus.subscribe::<generated::RegionHandshake>();
+        while self.handshake_state != HandshakeState::HandshakeComplete {
+            if self.handshake_state == HandshakeState::SentCompleteAgentMovement {
+                match region_handshakes.recv().await {
+                    Some(incoming) => {
+                        tracing::info!("[HANDSHAKE] Received RegionHandshake from {}", incoming.from);
+                        self.handshake_state = HandshakeState::ReceivedRegionHandshake;
+                    }
+                    None => {
+                        return Err(io::Error::new(io::ErrorKind::BrokenPipe, "Circuit closed while waiting for RegionHandshake"));
+                    }
                 }
+            } else {
+                self.advance_handshake().await?;
             }
         }
+        Ok(())
     }
 
-    pub async fn advance_handshake(
-        &mut self,
-        agent_id: uuid::Uuid,
-        session_id: uuid::Uuid,
-        circuit_code: u32,
-        position: (f32, f32, f32),
-        look_at: (f32, f32, f32),
-        throttle: [f32; 7],
-        flags: u32,
-        controls: u32,
-        camera_at: (f32, f32, f32),
-        camera_eye: (f32, f32, f32),
-    ) {
+    pub async fn advance_handshake(&mut self) -> io::Result<()> {
+        let CircuitParams { agent_id, session_id, circuit_code, throttle, .. } = self.params.clone();
+        let (camera_at, camera_eye, controls) = {
+            let state = self.agent_state.lock().await;
+            (state.camera_at, state.camera_eye, state.controls)
+        };
         use tracing::{info, warn};
         match self.handshake_state {
             HandshakeState::NotStarted => {
                 info!("[HANDSHAKE] Sending UseCircuitCode");
-                let mut transport = self.transport.lock().await;
-                let _ = transport.send_usecircuitcode_packet_lludp(circuit_code, session_id, agent_id).await;
+                self.send(&builders::use_circuit_code(circuit_code, session_id, agent_id), true).await?;
                 self.handshake_state = HandshakeState::SentUseCircuitCode;
             }
+            HandshakeState::SentUseCircuitCode if self.params.child => {
+                // Child agents are not moved into the region; the sim just sends RegionHandshake.
+                info!("[HANDSHAKE] Child agent, waiting for RegionHandshake");
+                self.handshake_state = HandshakeState::SentCompleteAgentMovement;
+            }
             HandshakeState::SentUseCircuitCode => {
                 info!("[HANDSHAKE] Sending CompleteAgentMovement");
-                let mut transport = self.transport.lock().await;
-                let _ = transport.send_complete_agent_movement_packet(agent_id, session_id, circuit_code, position, look_at).await;
+                self.send(&builders::complete_agent_movement(agent_id, session_id, circuit_code), true).await?;
                 self.handshake_state = HandshakeState::SentCompleteAgentMovement;
             }
             HandshakeState::SentCompleteAgentMovement => {
                 warn!("advance_handshake called in SentCompleteAgentMovement; waiting for RegionHandshake (IN)");
                 // Wait for RegionHandshake (IN)
-                return;
             }
             HandshakeState::ReceivedRegionHandshake => {
-                // This state is now handled directly in handle_incoming_message
-                return;
+                info!("[HANDSHAKE] Sending RegionHandshakeReply");
+                self.send_region_handshake_reply().await?;
             }
             HandshakeState::SentRegionHandshakeReply => {
                 info!("[HANDSHAKE] Sending AgentThrottle");
-                let mut transport = self.transport.lock().await;
-                let _ = transport.send_agent_throttle_packet(agent_id, session_id, circuit_code, throttle).await;
+                self.send(&builders::agent_throttle(agent_id, session_id, circuit_code, throttle), true).await?;
                 self.handshake_state = HandshakeState::SentAgentThrottle;
             }
+            HandshakeState::SentAgentThrottle if self.params.child => {
+                info!("[HANDSHAKE] Child agent handshake complete");
+                self.handshake_state = HandshakeState::HandshakeComplete;
+            }
             HandshakeState::SentAgentThrottle => {
                 info!("[HANDSHAKE] Sending first AgentUpdate");
-                let mut transport = self.transport.lock().await;
-                let _ = transport.send_agent_update_packet(agent_id, session_id, position, camera_at, camera_eye, controls).await;
+                self.send(&builders::agent_update(agent_id, session_id, camera_at, camera_eye, controls), false).await?;
                 self.handshake_state = HandshakeState::SentFirstAgentUpdate;
             }
             HandshakeState::SentFirstAgentUpdate => {
-                info!("[HANDSHAKE] Handshake complete. Starting EQ polling and periodic AgentUpdate.");
+                info!("[HANDSHAKE] Handshake complete. Starting periodic AgentUpdate.");
                 self.handshake_state = HandshakeState::HandshakeComplete;
-                if !self.eq_polling_started {
-                    self.eq_polling_started = true;
-                    // EQ polling
-                    if let Some(ref caps) = self.capabilities {
-                        let caps = caps.clone();
-                        let udp_port = self.udp_port;
-                        let proxy_settings = self.proxy_settings.clone();
-                        tokio::spawn(async move {
-                            let _ = crate::networking::session::poll_event_queue(
-                                &caps,
-                                udp_port,
-                                proxy_settings.as_ref(),
-                                |event_xml| {
-                                    println!("[EQ] Event: {}", event_xml);
-                                    // TODO: Forward to UI or state handler
-                                }
-                            ).await;
-                        });
-                    }
-                    // Periodic AgentUpdate
-                    let transport = self.transport.clone();
-                    let agent_id = agent_id;
-                    let session_id = session_id;
-                    let agent_state = self.agent_state.clone();
-                    tokio::spawn(async move {
-                        let interval = tokio::time::Duration::from_millis(100);
-                        loop {
-                            let (position, camera_at, camera_eye, controls) = {
-                                let state = agent_state.lock().await;
-                                (state.position, state.camera_at, state.camera_eye, state.controls)
-                            };
-                            let mut transport = transport.lock().await;
-                            let _ = transport.send_agent_update_packet(
-                                agent_id,
-                                session_id,
-                                position,
-                                camera_at,
-                                camera_eye,
-                                controls
-                            ).await;
-                            tokio::time::sleep(interval).await;
-                        }
-                    });
-                }
+                self.start_agent_updates();
             }
             HandshakeState::HandshakeComplete => {
                 warn!("advance_handshake called after handshake is already complete");
-                return;
             }
         }
+        Ok(())
     }
 
-    pub async fn disconnect_and_logout(&mut self, sim_addr: &SocketAddr) {
-        // Send Logout message
-        let _ = self.send_message(&Message::Logout, sim_addr).await;
-        // TODO: Add any additional cleanup if needed
+    /// Send AgentUpdate every 100ms from the shared agent state. Only the circuit to the
+    /// region the agent is in does this.
+    pub fn start_agent_updates(&mut self) {
+        let transport = self.transport.clone();
+        let reliability = self.reliability.clone();
+        let stats = self.stats.clone();
+        let agent_id = self.params.agent_id;
+        let session_id = self.params.session_id;
+        let agent_state = self.agent_state.clone();
+        self.stop_agent_updates();
+        self.agent_updates = Some(tokio::spawn(async move {
+            let interval = tokio::time::Duration::from_millis(100);
+            loop {
+                let (camera_at, camera_eye, controls) = {
+                    let state = agent_state.lock().await;
+                    (state.camera_at, state.camera_eye, state.controls)
+                };
+                let update = builders::agent_update(agent_id, session_id, camera_at, camera_eye, controls);
+                {
+                    let mut transport = transport.lock().await;
+                    let target = transport.sim_addr();
+                    let _ = send_on_transport(&mut transport, &reliability, &stats, &update, false, &target).await;
+                }
+                tokio::time::sleep(interval).await;
+            }
+        }));
     }
 
-    pub async fn send_region_handshake_reply_with_seq(&mut self, agent_id: uuid::Uuid, session_id: uuid::Uuid, flags: u32, sequence_id: u32, addr: &SocketAddr) {
-        let mut transport = self.transport.lock().await;
-        let packet = crate::utils::lludp::build_region_handshake_reply_packet(
-            agent_id,
-            session_id,
-            flags,
-            sequence_id,
-        );
-        let _ = transport.send_to(&packet, addr).await;
-        self.handshake_state = HandshakeState::SentRegionHandshakeReply;
+    pub fn stop_agent_updates(&mut self) {
+        if let Some(task) = self.agent_updates.take() {
+            task.abort();
+        }
     }
 
-    pub async fn handle_incoming_message(&mut self, header: PacketHeader, message: Message, addr: SocketAddr,
-        agent_id: uuid::Uuid,
-        session_id: uuid::Uuid,
-        circuit_code: u32,
-        position: (f32, f32, f32),
-        look_at: (f32, f32, f32),
-        throttle: [f32; 7],
-        flags: u32,
-        controls: u32,
-        camera_at: (f32, f32, f32),
-        camera_eye: (f32, f32, f32),
-    ) {
-        use tracing::info;
-        match &message {
-            Message::RegionHandshake { .. } => {
-                info!("[HANDSHAKE] Received RegionHandshake");
-                // Send reply with the same sequence number as incoming packet
-                self.send_region_handshake_reply_with_seq(agent_id, session_id, flags, header.sequence_id, &addr).await;
-                // Continue handshake progression
-                self.advance_handshake(agent_id, session_id, circuit_code, position, look_at, throttle, flags, controls, camera_at, camera_eye).await;
-            }
-            Message::KeepAlive => {
-                // Per SL protocol and Hippolyzer, no response is required for KeepAlive packets.
-                // Only log receipt for debugging.
-                info!("[UDP] Received KeepAlive from {} (no response sent)", addr);
-            }
-            Message::AgentDataUpdate { .. } => {
-                info!("[UDP] Received AgentDataUpdate from {}", addr);
-                // ACK would be handled by the main loop, but specific logic could go here.
-            }
-            Message::HealthMessage { .. } => {
-                info!("[UDP] Received HealthMessage from {}", addr);
-                // ACK would be handled by the main loop.
-            }
-            _ => {}
-        }
+    /// Turn a child circuit into the agent's own region after a border crossing: move the agent
+    /// in with CompleteAgentMovement and take over the AgentUpdate stream.
+    pub async fn promote(&mut self) -> io::Result<()> {
+        let (agent_id, session_id, circuit_code) = (self.params.agent_id, self.params.session_id, self.params.circuit_code);
+        self.send(&builders::complete_agent_movement(agent_id, session_id, circuit_code), true).await?;
+        self.params.child = false;
+        self.role.set_child(false);
+        self.start_agent_updates();
+        Ok(())
+    }
+
+    /// The agent left this region but can still see into it.
+    pub fn demote(&mut self) {
+        self.params.child = true;
+        self.role.set_child(true);
+        self.stop_agent_updates();
+    }
+
+    pub async fn disconnect_and_logout(&mut self, sim_addr: &SocketAddr) -> io::Result<()> {
+        self.send_message(&Message::Logout, sim_addr).await?;
+        // TODO: Add any additional cleanup if needed
+        Ok(())
+    }
+
+    pub async fn send_region_handshake_reply(&mut self) -> io::Result<()> {
+        let reply = builders::region_handshake_reply(self.params.agent_id, self.params.session_id, self.params.region_handshake_flags);
+        self.send(&reply, true).await?;
+        self.handshake_state = HandshakeState::SentRegionHandshakeReply;
+        Ok(())
     }
 }
 
@@ -470,7 +623,8 @@ mod tests {
             camera_eye: (0.0, 0.0, 0.0),
             controls: 0,
         }));
-        let mut circuit = Circuit::new_with_transport(transport_arc, agent_state).await.unwrap();
-        circuit.disconnect_and_logout(&addr).await;
+        let params = CircuitParams::new(uuid::Uuid::nil(), uuid::Uuid::nil(), 0);
+        let mut circuit = Circuit::new_with_transport(transport_arc, params, agent_state).await.unwrap();
+        circuit.disconnect_and_logout(&addr).await.unwrap();
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,279 @@
+//! Circuits to several simulators multiplexed over one UDP socket.
+//!
+//! The agent's own region has the primary circuit; neighbouring regions announced through
+//! EnableSimulator get child-agent circuits so their objects and avatars are visible across
+//! borders. Circuits are keyed by region handle, and a single reader task routes each datagram
+//! to the circuit for its source address.
+
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::sync::{mpsc, Mutex};
+use tokio::task::JoinHandle;
+use tokio::time::Duration;
+
+use crate::networking::circuit::{AgentState, Circuit, CircuitParams, Datagram};
+use crate::networking::protocol::builders;
+use crate::networking::transport::{UdpSocketExt, UdpTransport};
+use crate::ui::proxy::ProxySettings;
+
+/// Datagrams queued per circuit; beyond this the reader drops them and the sim resends.
+const ROUTE_QUEUE_LEN: usize = 256;
+
+/// Region handle for the region whose south-west corner is at the given global coordinates (meters).
+pub fn region_handle(global_x: u32, global_y: u32) -> u64 {
+    ((global_x as u64) << 32) | global_y as u64
+}
+
+/// Global coordinates (meters) of a region's south-west corner.
+pub fn region_origin(handle: u64) -> (u32, u32) {
+    ((handle >> 32) as u32, handle as u32)
+}
+
+type Routes = Arc<std::sync::Mutex<HashMap<SocketAddr, mpsc::Sender<Datagram>>>>;
+
+pub struct RegionCircuit {
+    pub region_handle: u64,
+    pub sim_addr: SocketAddr,
+    pub circuit: Circuit,
+    /// Seed capability from EstablishAgentCommunication, if the region sent one.
+    pub seed_capability: Option<String>,
+}
+
+pub struct CircuitManager {
+    socket: Arc<dyn UdpSocketExt>,
+    routes: Routes,
+    regions: HashMap<u64, RegionCircuit>,
+    primary: Option<u64>,
+    /// Agent state shared by every circuit; only the primary sends AgentUpdate from it.
+    agent_state: Arc<Mutex<AgentState>>,
+    reader: JoinHandle<()>,
+}
+
+impl Drop for CircuitManager {
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
+}
+
+impl CircuitManager {
+    /// Bind the shared socket (directly or through the SOCKS5 proxy).
+    pub async fn bind(local_port: u16, proxy_settings: Option<&ProxySettings>, agent_state: Arc<Mutex<AgentState>>) -> io::Result<Self> {
+        let socket = UdpTransport::bind(local_port, proxy_settings).await?;
+        Ok(Self::with_socket(socket, agent_state))
+    }
+
+    pub fn with_socket(socket: Arc<dyn UdpSocketExt>, agent_state: Arc<Mutex<AgentState>>) -> Self {
+        let routes: Routes = Arc::new(std::sync::Mutex::new(HashMap::new()));
+        let reader = tokio::spawn(demultiplex(Arc::clone(&socket), Arc::clone(&routes)));
+        Self { socket, routes, regions: HashMap::new(), primary: None, agent_state, reader }
+    }
+
+    pub fn agent_state(&self) -> Arc<Mutex<AgentState>> {
+        Arc::clone(&self.agent_state)
+    }
+
+    /// Open a circuit to `sim_addr` for `region_handle` and run its handshake. The first circuit
+    /// opened becomes the primary. `prepare` runs before the handshake, e.g. to register handlers.
+    pub async fn open<F>(
+        &mut self,
+        region_handle: u64,
+        sim_addr: SocketAddr,
+        params: CircuitParams,
+        handshake_timeout: Duration,
+        prepare: F,
+    ) -> io::Result<&mut RegionCircuit>
+    where
+        F: FnOnce(&Circuit),
+    {
+        let circuit = self.start(region_handle, sim_addr, params, prepare).await?;
+        match handshake(circuit, sim_addr, handshake_timeout).await {
+            Ok(circuit) => Ok(self.insert(region_handle, sim_addr, circuit)),
+            Err(e) => {
+                self.abandon(sim_addr);
+                Err(e)
+            }
+        }
+    }
+
+    /// Route `sim_addr` to a new circuit without running its handshake, so the caller can run
+    /// [`handshake`] on another task and hand the circuit back with `insert` (or `abandon` it).
+    pub async fn start<F>(&mut self, region_handle: u64, sim_addr: SocketAddr, params: CircuitParams, prepare: F) -> io::Result<Circuit>
+    where
+        F: FnOnce(&Circuit),
+    {
+        if self.regions.contains_key(&region_handle) {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("Region {} already has a circuit", region_handle)));
+        }
+        if self.is_pending(sim_addr) {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("Handshake with {} already in progress", sim_addr)));
+        }
+        let (route_tx, route_rx) = mpsc::channel(ROUTE_QUEUE_LEN);
+        self.routes.lock().unwrap().insert(sim_addr, route_tx);
+
+        let result = async {
+            let transport = UdpTransport::new_with_socket(Arc::clone(&self.socket), sim_addr, 1).await?;
+            Circuit::new_with_inbound(Arc::new(Mutex::new(transport)), route_rx, params, self.agent_state()).await
+        }
+        .await;
+        match result {
+            Ok(circuit) => {
+                prepare(&circuit);
+                Ok(circuit)
+            }
+            Err(e) => {
+                self.abandon(sim_addr);
+                Err(e)
+            }
+        }
+    }
+
+    /// Add a circuit whose handshake finished. The first circuit added becomes the primary.
+    pub fn insert(&mut self, region_handle: u64, sim_addr: SocketAddr, circuit: Circuit) -> &mut RegionCircuit {
+        if self.primary.is_none() {
+            self.primary = Some(region_handle);
+        }
+        self.regions.insert(region_handle, RegionCircuit { region_handle, sim_addr, circuit, seed_capability: None });
+        self.regions.get_mut(&region_handle).expect("just inserted")
+    }
+
+    /// Stop routing datagrams to a circuit whose handshake failed.
+    pub fn abandon(&mut self, sim_addr: SocketAddr) {
+        if self.region_for_addr(sim_addr).is_none() {
+            self.routes.lock().unwrap().remove(&sim_addr);
+        }
+    }
+
+    /// Whether a circuit to `sim_addr` has been started but not inserted yet.
+    pub fn is_pending(&self, sim_addr: SocketAddr) -> bool {
+        self.region_for_addr(sim_addr).is_none() && self.routes.lock().unwrap().contains_key(&sim_addr)
+    }
+
+    /// Tear down a region's circuit. Children get CloseCircuit; the primary should be left via `logout`.
+    pub async fn close(&mut self, region_handle: u64) -> bool {
+        let Some(region) = self.regions.remove(&region_handle) else {
+            return false;
+        };
+        self.routes.lock().unwrap().remove(&region.sim_addr);
+        if self.primary == Some(region_handle) {
+            self.primary = None;
+        } else {
+            let _ = region.circuit.send(&builders::close_circuit(), false).await;
+        }
+        true
+    }
+
+    /// Send LogoutRequest on the primary circuit and drop every circuit.
+    pub async fn logout(&mut self) {
+        if let Some(region) = self.primary.and_then(|handle| self.regions.get_mut(&handle)) {
+            let sim_addr = region.sim_addr;
+            if let Err(e) = region.circuit.disconnect_and_logout(&sim_addr).await {
+                tracing::warn!("Failed to send LogoutRequest to {}: {}", sim_addr, e);
+            }
+        }
+        self.primary = None;
+        self.regions.clear();
+        self.routes.lock().unwrap().clear();
+    }
+
+    pub fn primary(&self) -> Option<&RegionCircuit> {
+        self.primary.and_then(|handle| self.regions.get(&handle))
+    }
+
+    pub fn primary_handle(&self) -> Option<u64> {
+        self.primary
+    }
+
+    /// Make an open circuit the primary one. Returns false if there is no circuit for the region.
+    pub fn set_primary(&mut self, region_handle: u64) -> bool {
+        if self.regions.contains_key(&region_handle) {
+            self.primary = Some(region_handle);
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn get(&self, region_handle: u64) -> Option<&RegionCircuit> {
+        self.regions.get(&region_handle)
+    }
+
+    pub fn get_mut(&mut self, region_handle: u64) -> Option<&mut RegionCircuit> {
+        self.regions.get_mut(&region_handle)
+    }
+
+    pub fn region_for_addr(&self, sim_addr: SocketAddr) -> Option<u64> {
+        self.regions.values().find(|r| r.sim_addr == sim_addr).map(|r| r.region_handle)
+    }
+
+    pub fn regions(&self) -> impl Iterator<Item = &RegionCircuit> {
+        self.regions.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.regions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+}
+
+/// Run a started circuit's handshake, giving up after `handshake_timeout`.
+pub async fn handshake(mut circuit: Circuit, sim_addr: SocketAddr, handshake_timeout: Duration) -> io::Result<Circuit> {
+    tokio::time::timeout(handshake_timeout, circuit.run_handshake())
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, format!("Handshake with {} timed out", sim_addr)))??;
+    Ok(circuit)
+}
+
+/// Read the shared socket and hand each datagram to the circuit for its source address.
+async fn demultiplex(socket: Arc<dyn UdpSocketExt>, routes: Routes) {
+    let mut buf = vec![0; 4096];
+    loop {
+        match socket.recv_from(&mut buf).await {
+            Ok((len, addr)) => {
+                let route = routes.lock().unwrap().get(&addr).cloned();
+                match route {
+                    Some(route) => {
+                        if route.try_send((buf[..len].to_vec(), addr)).is_err() {
+                            tracing::debug!("Dropping datagram from {}: circuit queue full or closed", addr);
+                        }
+                    }
+                    None => tracing::debug!("Dropping datagram from {} with no circuit", addr),
+                }
+            }
+            Err(e) if is_transient(&e) => tracing::debug!("Ignoring socket receive error: {}", e),
+            Err(e) => {
+                tracing::error!("Socket receive error, no longer receiving: {}", e);
+                break;
+            }
+        }
+    }
+}
+
+/// Receive errors about one datagram or one peer rather than the socket. Windows reports an ICMP
+/// port unreachable from any simulator, e.g. a neighbour that just closed, as ConnectionReset.
+fn is_transient(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::Interrupted
+            | io::ErrorKind::WouldBlock
+            | io::ErrorKind::TimedOut
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_region_handle_roundtrip() {
+        let handle = region_handle(256_000, 256_256);
+        assert_eq!(handle, 0x0003_E800_0003_E900);
+        assert_eq!(region_origin(handle), (256_000, 256_256));
+    }
+}
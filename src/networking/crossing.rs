@@ -0,0 +1,70 @@
+//! Walking (or flying) over a region border.
+//!
+//! The region the agent leaves sends CrossedRegion, over the event queue on current grids and
+//! over UDP on older ones. The networking actor then promotes the child circuit to the new
+//! region (opening one if there is none), swaps the primary region and rebases the agent's
+//! region-local coordinates.
+
+use std::net::SocketAddr;
+
+use crate::networking::circuit::AgentState;
+use crate::networking::circuit_manager::region_origin;
+use crate::networking::protocol::generated;
+
+/// Destination of a border crossing, from CrossedRegion.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegionCrossing {
+    pub region_handle: u64,
+    pub sim_addr: SocketAddr,
+    pub seed_capability: String,
+    /// Agent position in the new region's local frame.
+    pub position: (f32, f32, f32),
+    pub look_at: (f32, f32, f32),
+}
+
+impl RegionCrossing {
+    pub fn from_message(msg: &generated::CrossedRegion) -> Self {
+        let region = &msg.region_data;
+        let (p, l) = (msg.info.position, msg.info.look_at);
+        Self {
+            region_handle: region.region_handle,
+            sim_addr: SocketAddr::new(region.sim_ip.into(), region.sim_port),
+            seed_capability: String::from_utf8_lossy(&region.seed_capability).trim_end_matches('\0').to_string(),
+            position: (p.x, p.y, p.z),
+            look_at: (l.x, l.y, l.z),
+        }
+    }
+}
+
+/// Move a region-local point from one region's frame into another's.
+pub fn rebase(point: (f32, f32, f32), from_region: u64, to_region: u64) -> (f32, f32, f32) {
+    let (from_x, from_y) = region_origin(from_region);
+    let (to_x, to_y) = region_origin(to_region);
+    (
+        point.0 + (from_x as f64 - to_x as f64) as f32,
+        point.1 + (from_y as f64 - to_y as f64) as f32,
+        point.2,
+    )
+}
+
+/// Rebase the agent and camera into the new region. The simulator's idea of the agent position
+/// wins over our own, which may be a frame behind.
+pub fn rebase_agent(state: &mut AgentState, from_region: u64, crossing: &RegionCrossing) {
+    state.position = crossing.position;
+    state.camera_at = rebase(state.camera_at, from_region, crossing.region_handle);
+    state.camera_eye = rebase(state.camera_eye, from_region, crossing.region_handle);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networking::circuit_manager::region_handle;
+
+    #[test]
+    fn test_rebase_across_eastern_border() {
+        let west = region_handle(256_000, 256_000);
+        let east = region_handle(256_256, 256_000);
+        assert_eq!(rebase((255.5, 10.0, 22.0), west, east), (-0.5, 10.0, 22.0));
+        assert_eq!(rebase((-0.5, 10.0, 22.0), east, west), (255.5, 10.0, 22.0));
+    }
+}
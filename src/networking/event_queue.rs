@@ -0,0 +1,412 @@
+//! EventQueueGet: the long-poll capability simulators use for messages too large or too
+//! important for UDP (TeleportFinish, EnableSimulator, CrossedRegion, group chat invitations).
+//!
+//! An [`EventQueueClient`] posts `{ack, done}` and gets back `{id, events}`; the next request
+//! acknowledges that `id`. The simulator holds the request open until it has something to say
+//! and answers 502 (or 499 through some proxies) when the poll times out, so those statuses are
+//! part of the normal cycle. [`spawn`] runs the loop for one region in a task, retrying other
+//! failures with exponential backoff and reporting when it gives up, and
+//! [`EventQueueHandle::shutdown`] ends it with `done: true` so the simulator can drop the queue.
+
+use serde::Deserialize;
+use std::net::SocketAddr;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tokio::time::Duration;
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use crate::networking::caps::CapsClient;
+use crate::networking::crossing::RegionCrossing;
+use crate::networking::llsd::{self, Llsd, LlsdError};
+use crate::networking::proxy::{self, ProxyError};
+use crate::networking::teleport::TeleportFinish;
+use crate::ui::proxy::ProxySettings;
+
+/// First delay after a failed poll; doubled for every further failure in a row.
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(1);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Consecutive failures after which the queue is given up on.
+const MAX_CONSECUTIVE_FAILURES: u32 = 10;
+
+/// How long `shutdown` waits for the final `done: true` request.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, thiserror::Error)]
+pub enum EventQueueError {
+    #[error("EventQueueGet request failed: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("EventQueueGet failed: HTTP {0}")]
+    Status(reqwest::StatusCode),
+    /// The simulator no longer has a queue for us (404), e.g. after we left the region.
+    #[error("event queue closed by the simulator")]
+    Closed,
+    #[error(transparent)]
+    Llsd(#[from] LlsdError),
+    #[error(transparent)]
+    Proxy(#[from] ProxyError),
+}
+
+/// A decoded event queue message.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EventQueueEvent {
+    /// The simulator is ready for the agent; carries its address and seed capability.
+    EstablishAgentCommunication { agent_id: Uuid, sim_addr: SocketAddr, seed_capability: String },
+    /// Open a child circuit to a neighbouring region.
+    EnableSimulator { region_handle: u64, sim_addr: SocketAddr },
+    /// This region is done with us as a child agent.
+    DisableSimulator,
+    TeleportFinish(TeleportFinish),
+    TeleportFailed { reason: String },
+    CrossedRegion(RegionCrossing),
+    /// Invitation to a group or conference chat session.
+    ChatterBoxInvitation { session_id: Uuid, from_id: Uuid, from_name: String, message: String },
+    /// Agent preferences and navmesh permissions; the body is passed through as is.
+    AgentStateUpdate(Llsd),
+    /// Any message without a typed form.
+    Other { message: String, body: Llsd },
+}
+
+impl EventQueueEvent {
+    /// Decode one `{message, body}` entry. EnableSimulator may announce several regions at once;
+    /// known messages with missing fields come back as `Other`.
+    pub fn decode(message: &str, body: Llsd) -> Vec<EventQueueEvent> {
+        let decoded = match message {
+            "EstablishAgentCommunication" => establish_agent_communication(&body).map(|event| vec![event]),
+            "EnableSimulator" => Some(enable_simulator(&body)),
+            "DisableSimulator" => Some(vec![EventQueueEvent::DisableSimulator]),
+            "TeleportFinish" => teleport_finish(block(&body, "Info")).map(|finish| vec![EventQueueEvent::TeleportFinish(finish)]),
+            "TeleportFailed" => Some(vec![EventQueueEvent::TeleportFailed { reason: text(&block(&body, "Info")["Reason"]) }]),
+            "CrossedRegion" => crossed_region(&body).map(|crossing| vec![EventQueueEvent::CrossedRegion(crossing)]),
+            "ChatterBoxInvitation" => Some(vec![chatterbox_invitation(&body)]),
+            "AgentStateUpdate" => return vec![EventQueueEvent::AgentStateUpdate(body)],
+            _ => {
+                debug!("[EQ] Unhandled event {}", message);
+                return vec![EventQueueEvent::Other { message: message.to_string(), body }];
+            }
+        };
+        decoded.unwrap_or_else(|| {
+            warn!("[EQ] Could not decode {}", message);
+            vec![EventQueueEvent::Other { message: message.to_string(), body }]
+        })
+    }
+}
+
+/// One EventQueueGet response: its id (the next ack) and the decoded events.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EventQueueResponse {
+    pub id: i32,
+    pub events: Vec<EventQueueEvent>,
+}
+
+#[derive(Deserialize)]
+struct RawResponse {
+    id: i32,
+    #[serde(default)]
+    events: Vec<RawEvent>,
+}
+
+#[derive(Deserialize)]
+struct RawEvent {
+    message: String,
+    #[serde(default)]
+    body: Llsd,
+}
+
+/// Parse the LLSD XML body of an EventQueueGet response.
+pub fn parse_response(xml: &str) -> Result<EventQueueResponse, LlsdError> {
+    let raw: RawResponse = llsd::from_xml_str(xml)?;
+    let events = raw.events.into_iter().flat_map(|event| EventQueueEvent::decode(&event.message, event.body)).collect();
+    Ok(EventQueueResponse { id: raw.id, events })
+}
+
+// Message blocks are arrays with one map, but some simulators send the map directly
+fn block<'a>(body: &'a Llsd, key: &str) -> &'a Llsd {
+    match &body[key] {
+        Llsd::Array(_) => &body[key][0],
+        block => block,
+    }
+}
+
+fn text(value: &Llsd) -> String {
+    value.as_string().unwrap_or_default().trim().to_string()
+}
+
+fn sim_addr(ip: &Llsd, port: &Llsd) -> Option<SocketAddr> {
+    Some(SocketAddr::new(ip.as_ipv4()?.into(), port.as_u64()? as u16))
+}
+
+fn establish_agent_communication(body: &Llsd) -> Option<EventQueueEvent> {
+    Some(EventQueueEvent::EstablishAgentCommunication {
+        agent_id: body["agent-id"].as_uuid().unwrap_or_default(),
+        sim_addr: body["sim-ip-and-port"].as_str()?.trim().parse().ok()?,
+        seed_capability: text(&body["seed-capability"]),
+    })
+}
+
+fn enable_simulator(body: &Llsd) -> Vec<EventQueueEvent> {
+    body["SimulatorInfo"]
+        .as_array()
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|info| {
+            Some(EventQueueEvent::EnableSimulator {
+                region_handle: info["Handle"].as_u64()?,
+                sim_addr: sim_addr(&info["IP"], &info["Port"])?,
+            })
+        })
+        .collect()
+}
+
+fn teleport_finish(info: &Llsd) -> Option<TeleportFinish> {
+    Some(TeleportFinish {
+        region_handle: info["RegionHandle"].as_u64()?,
+        sim_addr: sim_addr(&info["SimIP"], &info["SimPort"])?,
+        seed_capability: text(&info["SeedCapability"]),
+        location_id: info["LocationID"].as_u64().unwrap_or(0) as u32,
+        sim_access: info["SimAccess"].as_u64().unwrap_or(0) as u8,
+        teleport_flags: info["TeleportFlags"].as_u64().unwrap_or(0) as u32,
+    })
+}
+
+fn crossed_region(body: &Llsd) -> Option<RegionCrossing> {
+    let (region, info) = (block(body, "RegionData"), block(body, "Info"));
+    let vector = |key: &str| info[key].as_vector3().unwrap_or((0.0, 0.0, 0.0));
+    Some(RegionCrossing {
+        region_handle: region["RegionHandle"].as_u64()?,
+        sim_addr: sim_addr(&region["SimIP"], &region["SimPort"])?,
+        seed_capability: text(&region["SeedCapability"]),
+        position: vector("Position"),
+        look_at: vector("LookAt"),
+    })
+}
+
+fn chatterbox_invitation(body: &Llsd) -> EventQueueEvent {
+    let params = &body["instantmessage"]["message_params"];
+    let uuid = |value: &Llsd| value.as_uuid().unwrap_or_default();
+    let from_name = match text(&body["from_name"]) {
+        name if name.is_empty() => text(&params["from_name"]),
+        name => name,
+    };
+    EventQueueEvent::ChatterBoxInvitation {
+        session_id: body["session_id"].as_uuid().unwrap_or_else(|| uuid(&params["id"])),
+        from_id: uuid(&params["from_id"]),
+        from_name,
+        message: text(&params["message"]),
+    }
+}
+
+/// Delay before retrying after `failures` failed polls in a row.
+fn retry_delay(failures: u32) -> Duration {
+    INITIAL_RETRY_DELAY.saturating_mul(1 << failures.saturating_sub(1).min(16)).min(MAX_RETRY_DELAY)
+}
+
+/// Talks to one region's EventQueueGet capability.
+pub struct EventQueueClient {
+    url: String,
+    udp_port: u16,
+    client: reqwest::Client,
+    /// Id of the last response, acknowledged by the next request.
+    ack: Option<i32>,
+}
+
+impl EventQueueClient {
+    pub fn new(url: impl Into<String>, udp_port: u16, proxy_settings: Option<&ProxySettings>) -> Result<Self, EventQueueError> {
+        // Always send ack 0 in the first request for Hippolyzer compatibility
+        Ok(Self { url: url.into(), udp_port, client: proxy::http_client(proxy_settings)?, ack: Some(0) })
+    }
+
+    async fn post(&self, done: bool) -> Result<reqwest::Response, EventQueueError> {
+        let payload = llsd::xml::to_xml(&Llsd::from_iter([
+            ("ack", self.ack.map(Llsd::Integer).unwrap_or_default()),
+            ("done", Llsd::Boolean(done)),
+        ]));
+        Ok(self
+            .client
+            .post(&self.url)
+            .header("Accept", "application/llsd+xml")
+            .header("Content-Type", "application/llsd+xml")
+            .header("X-SecondLife-UDP-Listen-Port", self.udp_port.to_string())
+            .body(payload)
+            .send()
+            .await?)
+    }
+
+    /// One long poll. A poll that timed out on the simulator's side returns no events.
+    pub async fn poll(&mut self) -> Result<Vec<EventQueueEvent>, EventQueueError> {
+        let resp = self.post(false).await?;
+        match resp.status().as_u16() {
+            200..=299 => {}
+            // Long-poll timeout, from the simulator (502) or a proxy (499)
+            499 | 502 => return Ok(Vec::new()),
+            404 => return Err(EventQueueError::Closed),
+            _ => return Err(EventQueueError::Status(resp.status())),
+        }
+        let text = resp.text().await?;
+        let response = parse_response(&text)?;
+        self.ack = Some(response.id);
+        Ok(response.events)
+    }
+
+    /// Tell the simulator we are done with the queue. Events it answers with are dropped.
+    pub async fn close(&mut self) -> Result<(), EventQueueError> {
+        self.post(true).await?;
+        Ok(())
+    }
+
+    /// Poll until stopped or the simulator closes the queue, handing each non-empty batch of
+    /// events to `on_events`. Returns the last error if it gave up.
+    async fn run<F>(mut self, stop: &mut oneshot::Receiver<()>, on_events: &mut F) -> Result<(), EventQueueError>
+    where
+        F: FnMut(Vec<EventQueueEvent>),
+    {
+        let mut failures = 0;
+        loop {
+            let result = tokio::select! {
+                _ = &mut *stop => break,
+                result = self.poll() => result,
+            };
+            match result {
+                Ok(events) => {
+                    failures = 0;
+                    if !events.is_empty() {
+                        on_events(events);
+                    }
+                }
+                Err(EventQueueError::Closed) => {
+                    debug!("[EQ] {} closed", self.url);
+                    return Ok(());
+                }
+                Err(e) => {
+                    failures += 1;
+                    if failures >= MAX_CONSECUTIVE_FAILURES {
+                        warn!("[EQ] Giving up on {} after {} failures: {}", self.url, failures, e);
+                        return Err(e);
+                    }
+                    let delay = retry_delay(failures);
+                    warn!("[EQ] {} (retrying in {:?})", e, delay);
+                    tokio::select! {
+                        _ = &mut *stop => break,
+                        _ = tokio::time::sleep(delay) => {}
+                    }
+                }
+            }
+        }
+        if let Err(e) = self.close().await {
+            debug!("[EQ] Final done request to {} failed: {}", self.url, e);
+        }
+        Ok(())
+    }
+}
+
+/// A region's event queue polling in the background.
+pub struct EventQueueHandle {
+    stop: Option<oneshot::Sender<()>>,
+    task: JoinHandle<()>,
+}
+
+impl EventQueueHandle {
+    /// Stop polling and send `done: true`, waiting a few seconds for it to go out.
+    pub async fn shutdown(mut self) {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+        let _ = tokio::time::timeout(SHUTDOWN_TIMEOUT, &mut self.task).await;
+    }
+}
+
+impl Drop for EventQueueHandle {
+    /// Dropping the handle cancels polling without telling the simulator.
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Fetch the EventQueueGet capability from a region's seed capability and poll it until the
+/// returned handle is shut down or dropped. If the capability cannot be fetched or the queue
+/// keeps failing, `on_failed` gets the reason; a queue the simulator closes is not a failure.
+pub fn spawn<F, G>(
+    seed_capability: String,
+    udp_port: u16,
+    proxy_settings: Option<ProxySettings>,
+    session_cookie: Option<String>,
+    mut on_events: F,
+    on_failed: G,
+) -> EventQueueHandle
+where
+    F: FnMut(Vec<EventQueueEvent>) + Send + 'static,
+    G: FnOnce(String) + Send + 'static,
+{
+    let (stop_tx, mut stop) = oneshot::channel();
+    let task = tokio::spawn(async move {
+        let caps = tokio::select! {
+            _ = &mut stop => return,
+            caps = CapsClient::connect(seed_capability.as_str(), udp_port, proxy_settings.as_ref(), session_cookie) => caps,
+        };
+        let client = caps
+            .and_then(|caps| caps.url("EventQueueGet").map(str::to_string))
+            .map_err(|e| e.to_string())
+            .and_then(|url| EventQueueClient::new(url, udp_port, proxy_settings.as_ref()).map_err(|e| e.to_string()));
+        let result = match client {
+            Ok(client) => client.run(&mut stop, &mut on_events).await.map_err(|e| e.to_string()),
+            Err(e) => {
+                warn!("[EQ] {}: {}", seed_capability, e);
+                Err(e)
+            }
+        };
+        if let Err(e) = result {
+            on_failed(e);
+        }
+    });
+    EventQueueHandle { stop: Some(stop_tx), task }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn test_decode_events() {
+        let info: Llsd = [
+            ("Handle", Llsd::Binary(vec![0, 3, 0xE8, 0, 0, 3, 0xE9, 0])),
+            ("IP", Llsd::Binary(vec![10, 0, 0, 7])),
+            ("Port", Llsd::Integer(13005)),
+        ]
+        .into_iter()
+        .collect();
+        let body: Llsd = [("SimulatorInfo", Llsd::Array(vec![info]))].into_iter().collect();
+        assert_eq!(
+            EventQueueEvent::decode("EnableSimulator", body),
+            vec![EventQueueEvent::EnableSimulator {
+                region_handle: 0x0003_E800_0003_E900,
+                sim_addr: SocketAddr::new(Ipv4Addr::new(10, 0, 0, 7).into(), 13005),
+            }]
+        );
+
+        let failed: Llsd = [("Info", Llsd::Array(vec![[("Reason", "no such region")].into_iter().collect()]))].into_iter().collect();
+        assert_eq!(EventQueueEvent::decode("TeleportFailed", failed), vec![EventQueueEvent::TeleportFailed { reason: "no such region".to_string() }]);
+
+        let incomplete: Llsd = [("Info", Llsd::Map(Default::default()))].into_iter().collect();
+        assert!(matches!(&EventQueueEvent::decode("TeleportFinish", incomplete)[..], [EventQueueEvent::Other { message, .. }] if message == "TeleportFinish"));
+    }
+
+    #[tokio::test]
+    async fn test_spawn_reports_an_unreachable_seed_capability() {
+        let (failed_tx, failed) = oneshot::channel();
+        let _queue = spawn("http://127.0.0.1:1/seed".to_string(), 0, None, None, |_| {}, move |error| {
+            let _ = failed_tx.send(error);
+        });
+        let error = tokio::time::timeout(Duration::from_secs(10), failed).await.unwrap().unwrap();
+        assert!(!error.is_empty());
+    }
+
+    #[test]
+    fn test_retry_delay_backs_off_to_cap() {
+        assert_eq!(retry_delay(1), Duration::from_secs(1));
+        assert_eq!(retry_delay(3), Duration::from_secs(4));
+        assert_eq!(retry_delay(6), MAX_RETRY_DELAY);
+        assert_eq!(retry_delay(100), MAX_RETRY_DELAY);
+    }
+}
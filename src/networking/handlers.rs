@@ -0,0 +1,185 @@
+//! Typed dispatch of incoming LLUDP messages.
+//!
+//! The circuit's receive task decodes every packet into a generated [`Message`] and hands it to
+//! its [`MessageBus`]. Subsystems either register a handler, which runs synchronously on the
+//! receive task and should be cheap, or subscribe to a broadcast channel for one message type:
+//!
+//! ```ignore
+//! let mut chat = circuit.bus().subscribe::<generated::ChatFromSimulator>();
+//! while let Some(incoming) = chat.recv().await {
+//!     println!("{:?}", incoming.message.chat_data.message);
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, RwLock};
+use tokio::sync::broadcast;
+
+use crate::networking::protocol::generated::{Message, TemplateMessage};
+use crate::networking::protocol::wire::MessageNumber;
+
+/// Messages buffered per channel before slow subscribers start missing them.
+pub const DEFAULT_CHANNEL_CAPACITY: usize = 256;
+
+/// A message received on a circuit.
+#[derive(Debug, Clone)]
+pub struct Incoming<T> {
+    /// Simulator the packet came from.
+    pub from: SocketAddr,
+    pub sequence: u32,
+    pub message: T,
+}
+
+type Handler = Box<dyn Fn(&Incoming<Message>) + Send + Sync>;
+
+/// Per-circuit registry of message handlers and broadcast channels, keyed by message number.
+pub struct MessageBus {
+    capacity: usize,
+    channels: Mutex<HashMap<MessageNumber, broadcast::Sender<Arc<Incoming<Message>>>>>,
+    all: broadcast::Sender<Arc<Incoming<Message>>>,
+    handlers: RwLock<HashMap<MessageNumber, Vec<Handler>>>,
+}
+
+impl Default for MessageBus {
+    fn default() -> Self {
+        Self::new(DEFAULT_CHANNEL_CAPACITY)
+    }
+}
+
+impl MessageBus {
+    pub fn new(capacity: usize) -> Self {
+        let (all, _) = broadcast::channel(capacity);
+        Self {
+            capacity,
+            channels: Mutex::new(HashMap::new()),
+            all,
+            handlers: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Receive every message of type `T` from now on.
+    pub fn subscribe<T: TemplateMessage>(&self) -> Subscription<T> {
+        Subscription { rx: self.subscribe_number(T::NUMBER), _marker: PhantomData }
+    }
+
+    /// Receive every message with the given number, untyped.
+    pub fn subscribe_number(&self, number: MessageNumber) -> broadcast::Receiver<Arc<Incoming<Message>>> {
+        let mut channels = self.channels.lock().unwrap();
+        channels.entry(number).or_insert_with(|| broadcast::channel(self.capacity).0).subscribe()
+    }
+
+    /// Receive every decoded message, e.g. for logging or packet capture.
+    pub fn subscribe_all(&self) -> broadcast::Receiver<Arc<Incoming<Message>>> {
+        self.all.subscribe()
+    }
+
+    /// Run `handler` on the receive task for every message of type `T`.
+    pub fn on<T, F>(&self, handler: F)
+    where
+        T: TemplateMessage,
+        F: Fn(SocketAddr, &T) + Send + Sync + 'static,
+    {
+        let handler: Handler = Box::new(move |incoming| {
+            if let Some(message) = T::from_message(&incoming.message) {
+                handler(incoming.from, message);
+            }
+        });
+        self.handlers.write().unwrap().entry(T::NUMBER).or_default().push(handler);
+    }
+
+    /// Deliver a message to its handlers and subscribers. Returns false if nobody was interested.
+    pub fn dispatch(&self, incoming: Incoming<Message>) -> bool {
+        let number = incoming.message.number();
+        let mut delivered = false;
+        if let Some(handlers) = self.handlers.read().unwrap().get(&number) {
+            for handler in handlers {
+                handler(&incoming);
+            }
+            delivered = !handlers.is_empty();
+        }
+
+        let incoming = Arc::new(incoming);
+        {
+            let mut channels = self.channels.lock().unwrap();
+            if let Some(sender) = channels.get(&number) {
+                if sender.send(Arc::clone(&incoming)).is_ok() {
+                    delivered = true;
+                } else {
+                    // Every subscriber has been dropped.
+                    channels.remove(&number);
+                }
+            }
+        }
+        if self.all.send(incoming).is_ok() {
+            delivered = true;
+        }
+        delivered
+    }
+}
+
+/// Typed receiver returned by [`MessageBus::subscribe`].
+pub struct Subscription<T> {
+    rx: broadcast::Receiver<Arc<Incoming<Message>>>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T: TemplateMessage> Subscription<T> {
+    /// Wait for the next message. Returns None once the circuit is gone. A subscriber that falls
+    /// more than the channel capacity behind skips the messages it missed.
+    pub async fn recv(&mut self) -> Option<Incoming<T>> {
+        loop {
+            match self.rx.recv().await {
+                Ok(incoming) => {
+                    if let Some(message) = T::from_message(&incoming.message) {
+                        return Some(Incoming { from: incoming.from, sequence: incoming.sequence, message: message.clone() });
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    tracing::warn!("{} subscriber lagged, skipped {} messages", T::NAME, skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networking::protocol::builders;
+    use crate::networking::protocol::generated;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn incoming(message: Message) -> Incoming<Message> {
+        Incoming { from: "127.0.0.1:13000".parse().unwrap(), sequence: 1, message }
+    }
+
+    #[tokio::test]
+    async fn test_typed_subscription_and_handlers() {
+        let bus = MessageBus::default();
+        let mut pings = bus.subscribe::<generated::StartPingCheck>();
+        let handled = Arc::new(AtomicU32::new(0));
+        let handled_by = Arc::clone(&handled);
+        bus.on::<generated::CompletePingCheck, _>(move |_, msg| {
+            handled_by.store(msg.ping_id.ping_id as u32, Ordering::SeqCst);
+        });
+
+        assert!(bus.dispatch(incoming(builders::complete_ping_check(9))));
+        assert!(bus.dispatch(incoming(builders::start_ping_check(3, 77))));
+        assert_eq!(handled.load(Ordering::SeqCst), 9);
+
+        let ping = pings.recv().await.unwrap();
+        assert_eq!(ping.message.ping_id.ping_id, 3);
+        assert_eq!(ping.message.ping_id.oldest_unacked, 77);
+    }
+
+    #[test]
+    fn test_dispatch_without_listeners() {
+        let bus = MessageBus::default();
+        assert!(!bus.dispatch(incoming(builders::packet_ack(&[1]))));
+        drop(bus.subscribe::<generated::PacketAck>());
+        assert!(!bus.dispatch(incoming(builders::packet_ack(&[2]))));
+    }
+}
@@ -0,0 +1,180 @@
+//! Binary LLSD: one marker byte per value, big-endian lengths and numbers.
+//!
+//! Dates are the exception and are little-endian f64, as in the viewer's serializer.
+
+use uuid::Uuid;
+
+use super::{Llsd, LlsdError, Map};
+
+/// Optional header in front of binary LLSD documents.
+pub const HEADER: &[u8] = b"<? LLSD/Binary ?>\n";
+
+/// Parse binary LLSD, with or without the header.
+pub fn from_binary(bytes: &[u8]) -> Result<Llsd, LlsdError> {
+    let bytes = bytes.strip_prefix(HEADER).unwrap_or(bytes);
+    let mut reader = Reader { bytes, pos: 0 };
+    let value = reader.value()?;
+    Ok(value)
+}
+
+/// Serialize as binary LLSD without the header.
+pub fn to_binary(value: &Llsd) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_value(&mut out, value);
+    out
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn error(&self, message: impl Into<String>) -> LlsdError {
+        LlsdError::Binary { offset: self.pos, message: message.into() }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], LlsdError> {
+        let end = self.pos.checked_add(len).filter(|&end| end <= self.bytes.len()).ok_or_else(|| self.error(format!("needed {} more bytes", len)))?;
+        let slice = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], LlsdError> {
+        Ok(self.take(N)?.try_into().expect("take returns exactly N bytes"))
+    }
+
+    fn u32(&mut self) -> Result<u32, LlsdError> {
+        Ok(u32::from_be_bytes(self.array()?))
+    }
+
+    fn sized(&mut self) -> Result<&'a [u8], LlsdError> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+
+    fn string(&mut self) -> Result<String, LlsdError> {
+        let bytes = self.sized()?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| self.error("string is not UTF-8"))
+    }
+
+    fn value(&mut self) -> Result<Llsd, LlsdError> {
+        let marker = self.take(1)?[0];
+        Ok(match marker {
+            b'!' => Llsd::Undefined,
+            b'1' => Llsd::Boolean(true),
+            b'0' => Llsd::Boolean(false),
+            b'i' => Llsd::Integer(i32::from_be_bytes(self.array()?)),
+            b'r' => Llsd::Real(f64::from_be_bytes(self.array()?)),
+            b'u' => Llsd::Uuid(Uuid::from_bytes(self.array()?)),
+            b's' => Llsd::String(self.string()?),
+            b'l' => Llsd::Uri(self.string()?),
+            b'd' => Llsd::Date(f64::from_le_bytes(self.array()?)),
+            b'b' => Llsd::Binary(self.sized()?.to_vec()),
+            b'[' => {
+                let count = self.u32()? as usize;
+                let mut items = Vec::with_capacity(count.min(1024));
+                for _ in 0..count {
+                    items.push(self.value()?);
+                }
+                self.expect(b']')?;
+                Llsd::Array(items)
+            }
+            b'{' => {
+                let count = self.u32()?;
+                let mut map = Map::new();
+                for _ in 0..count {
+                    self.expect(b'k')?;
+                    let key = self.string()?;
+                    map.insert(key, self.value()?);
+                }
+                self.expect(b'}')?;
+                Llsd::Map(map)
+            }
+            other => return Err(self.error(format!("unknown marker {:?}", other as char))),
+        })
+    }
+
+    fn expect(&mut self, marker: u8) -> Result<(), LlsdError> {
+        let found = self.take(1)?[0];
+        if found == marker {
+            Ok(())
+        } else {
+            Err(self.error(format!("expected {:?}, found {:?}", marker as char, found as char)))
+        }
+    }
+}
+
+fn write_sized(out: &mut Vec<u8>, marker: u8, bytes: &[u8]) {
+    out.push(marker);
+    out.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+    out.extend_from_slice(bytes);
+}
+
+fn write_value(out: &mut Vec<u8>, value: &Llsd) {
+    match value {
+        Llsd::Undefined => out.push(b'!'),
+        Llsd::Boolean(true) => out.push(b'1'),
+        Llsd::Boolean(false) => out.push(b'0'),
+        Llsd::Integer(i) => {
+            out.push(b'i');
+            out.extend_from_slice(&i.to_be_bytes());
+        }
+        Llsd::Real(r) => {
+            out.push(b'r');
+            out.extend_from_slice(&r.to_be_bytes());
+        }
+        Llsd::Uuid(u) => {
+            out.push(b'u');
+            out.extend_from_slice(u.as_bytes());
+        }
+        Llsd::String(s) => write_sized(out, b's', s.as_bytes()),
+        Llsd::Uri(s) => write_sized(out, b'l', s.as_bytes()),
+        Llsd::Date(d) => {
+            out.push(b'd');
+            out.extend_from_slice(&d.to_le_bytes());
+        }
+        Llsd::Binary(bytes) => write_sized(out, b'b', bytes),
+        Llsd::Array(items) => {
+            out.push(b'[');
+            out.extend_from_slice(&(items.len() as u32).to_be_bytes());
+            for item in items {
+                write_value(out, item);
+            }
+            out.push(b']');
+        }
+        Llsd::Map(map) => {
+            out.push(b'{');
+            out.extend_from_slice(&(map.len() as u32).to_be_bytes());
+            for (key, item) in map {
+                write_sized(out, b'k', key.as_bytes());
+                write_value(out, item);
+            }
+            out.push(b'}');
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_binary_roundtrip_and_header() {
+        let value: Llsd = [
+            ("seq", Llsd::Array(vec![Llsd::Integer(1), Llsd::Real(-2.5), Llsd::Boolean(false), Llsd::Undefined])),
+            ("id", Llsd::Uuid(Uuid::from_u128(42))),
+            ("uri", Llsd::Uri("http://example.com/".to_string())),
+            ("data", Llsd::Binary(vec![0, 1, 2])),
+            ("when", Llsd::Date(86_400.5)),
+        ]
+        .into_iter()
+        .collect();
+        let bytes = to_binary(&value);
+        assert_eq!(from_binary(&bytes).unwrap(), value);
+        assert_eq!(from_binary(&[HEADER, &bytes[..]].concat()).unwrap(), value);
+        assert_eq!(to_binary(&Llsd::Integer(258)), b"i\x00\x00\x01\x02");
+        assert!(from_binary(b"[\x00\x00\x00\x02i").is_err());
+    }
+}
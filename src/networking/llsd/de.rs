@@ -0,0 +1,392 @@
+//! serde `Deserializer` over [`Llsd`] values, and `Deserialize` for `Llsd` itself.
+//!
+//! Conversions are as lenient as the viewer's `asInteger()`/`asString()`: a missing value reads
+//! as zero, false or empty, reals truncate into integer fields, and binary fields carry wide
+//! integers. Deserializing into `Llsd` through other formats cannot tell UUIDs, dates and URIs
+//! from strings and reals; use the `as_*` accessors on those.
+
+use serde::de::value::{MapDeserializer, SeqDeserializer, StringDeserializer};
+use serde::de::{self, Deserialize, DeserializeOwned, IntoDeserializer, Visitor};
+use std::fmt;
+
+use super::{Llsd, LlsdError, Map};
+
+/// Convert LLSD into any deserializable type.
+pub fn from_llsd<T: DeserializeOwned>(value: Llsd) -> Result<T, LlsdError> {
+    T::deserialize(value)
+}
+
+impl<'de> Deserialize<'de> for Llsd {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> Result<Llsd, D::Error> {
+        deserializer.deserialize_any(LlsdVisitor)
+    }
+}
+
+struct LlsdVisitor;
+
+impl<'de> Visitor<'de> for LlsdVisitor {
+    type Value = Llsd;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an LLSD value")
+    }
+
+    fn visit_bool<E>(self, v: bool) -> Result<Llsd, E> {
+        Ok(Llsd::Boolean(v))
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<Llsd, E> {
+        Ok(i32::try_from(v).map(Llsd::Integer).unwrap_or(Llsd::Real(v as f64)))
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<Llsd, E> {
+        Ok(i32::try_from(v).map(Llsd::Integer).unwrap_or(Llsd::Real(v as f64)))
+    }
+
+    fn visit_f64<E>(self, v: f64) -> Result<Llsd, E> {
+        Ok(Llsd::Real(v))
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Llsd, E> {
+        Ok(Llsd::String(v.to_string()))
+    }
+
+    fn visit_string<E>(self, v: String) -> Result<Llsd, E> {
+        Ok(Llsd::String(v))
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<Llsd, E> {
+        Ok(Llsd::Binary(v.to_vec()))
+    }
+
+    fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<Llsd, E> {
+        Ok(Llsd::Binary(v))
+    }
+
+    fn visit_none<E>(self) -> Result<Llsd, E> {
+        Ok(Llsd::Undefined)
+    }
+
+    fn visit_unit<E>(self) -> Result<Llsd, E> {
+        Ok(Llsd::Undefined)
+    }
+
+    fn visit_some<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<Llsd, D::Error> {
+        Llsd::deserialize(deserializer)
+    }
+
+    fn visit_newtype_struct<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<Llsd, D::Error> {
+        Llsd::deserialize(deserializer)
+    }
+
+    fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<Llsd, A::Error> {
+        let mut items = Vec::new();
+        while let Some(item) = seq.next_element()? {
+            items.push(item);
+        }
+        Ok(Llsd::Array(items))
+    }
+
+    fn visit_map<A: de::MapAccess<'de>>(self, mut access: A) -> Result<Llsd, A::Error> {
+        let mut map = Map::new();
+        while let Some((key, value)) = access.next_entry::<String, Llsd>()? {
+            map.insert(key, value);
+        }
+        Ok(Llsd::Map(map))
+    }
+}
+
+impl<'de> IntoDeserializer<'de, LlsdError> for Llsd {
+    type Deserializer = Llsd;
+
+    fn into_deserializer(self) -> Llsd {
+        self
+    }
+}
+
+impl Llsd {
+    fn unexpected(&self, expected: &str) -> LlsdError {
+        LlsdError::Serde(format!("expected {}, found {:?}", expected, self))
+    }
+
+    /// Integer view for the numeric `deserialize_*` methods; wide integers come as binary.
+    fn visit_integer<'de, V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, LlsdError> {
+        match self {
+            Llsd::Undefined => visitor.visit_i64(0),
+            Llsd::Binary(_) => match self.as_u64() {
+                Some(v) => visitor.visit_u64(v),
+                None => Err(self.unexpected("an integer")),
+            },
+            Llsd::Real(r) => visitor.visit_i64(r as i64),
+            Llsd::String(ref s) => match s.trim().parse::<i64>() {
+                Ok(v) => visitor.visit_i64(v),
+                Err(_) => Err(self.unexpected("an integer")),
+            },
+            other => match other.as_integer() {
+                Some(v) => visitor.visit_i64(v as i64),
+                None => Err(other.unexpected("an integer")),
+            },
+        }
+    }
+}
+
+macro_rules! deserialize_integer {
+    ($($method:ident)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, LlsdError> {
+                self.visit_integer(visitor)
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for Llsd {
+    type Error = LlsdError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, LlsdError> {
+        match self {
+            Llsd::Undefined => visitor.visit_unit(),
+            Llsd::Boolean(b) => visitor.visit_bool(b),
+            Llsd::Integer(i) => visitor.visit_i32(i),
+            Llsd::Real(r) => visitor.visit_f64(r),
+            Llsd::String(s) | Llsd::Uri(s) => visitor.visit_string(s),
+            Llsd::Uuid(u) => visitor.visit_string(u.to_string()),
+            Llsd::Date(d) => visitor.visit_f64(d),
+            Llsd::Binary(bytes) => visitor.visit_byte_buf(bytes),
+            Llsd::Array(items) => visit_array(items, visitor),
+            Llsd::Map(map) => visit_map(map, visitor),
+        }
+    }
+
+    deserialize_integer!(deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64 deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64);
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, LlsdError> {
+        match self {
+            Llsd::Undefined => visitor.visit_bool(false),
+            other => match other.as_bool() {
+                Some(b) => visitor.visit_bool(b),
+                None => Err(other.unexpected("a boolean")),
+            },
+        }
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, LlsdError> {
+        self.deserialize_f64(visitor)
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, LlsdError> {
+        match self {
+            Llsd::Undefined => visitor.visit_f64(0.0),
+            Llsd::Date(d) => visitor.visit_f64(d),
+            other => match other.as_real() {
+                Some(r) => visitor.visit_f64(r),
+                None => Err(other.unexpected("a real")),
+            },
+        }
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, LlsdError> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, LlsdError> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, LlsdError> {
+        match self {
+            Llsd::Undefined => visitor.visit_string(String::new()),
+            Llsd::String(s) | Llsd::Uri(s) => visitor.visit_string(s),
+            other => match other.as_string() {
+                Some(s) => visitor.visit_string(s),
+                None => Err(other.unexpected("a string")),
+            },
+        }
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, LlsdError> {
+        self.deserialize_byte_buf(visitor)
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, LlsdError> {
+        match self {
+            Llsd::Undefined => visitor.visit_byte_buf(Vec::new()),
+            Llsd::Binary(bytes) => visitor.visit_byte_buf(bytes),
+            Llsd::String(s) => visitor.visit_byte_buf(s.into_bytes()),
+            other => Err(other.unexpected("binary")),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, LlsdError> {
+        match self {
+            Llsd::Undefined => visitor.visit_none(),
+            other => visitor.visit_some(other),
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, LlsdError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, LlsdError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, LlsdError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, LlsdError> {
+        match self {
+            Llsd::Undefined => visit_array(Vec::new(), visitor),
+            Llsd::Array(items) => visit_array(items, visitor),
+            other => Err(other.unexpected("an array")),
+        }
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, LlsdError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(self, _name: &'static str, _len: usize, visitor: V) -> Result<V::Value, LlsdError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, LlsdError> {
+        match self {
+            Llsd::Undefined => visit_map(Map::new(), visitor),
+            Llsd::Map(map) => visit_map(map, visitor),
+            other => Err(other.unexpected("a map")),
+        }
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, LlsdError> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, LlsdError> {
+        match self {
+            Llsd::String(variant) => {
+                let name: StringDeserializer<LlsdError> = variant.into_deserializer();
+                visitor.visit_enum(name)
+            }
+            Llsd::Map(map) if map.len() == 1 => {
+                let (variant, value) = map.into_iter().next().expect("map has one entry");
+                visitor.visit_enum(Variant { variant, value })
+            }
+            other => Err(other.unexpected("an enum variant name or single-key map")),
+        }
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, LlsdError> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, LlsdError> {
+        visitor.visit_unit()
+    }
+}
+
+fn visit_array<'de, V: Visitor<'de>>(items: Vec<Llsd>, visitor: V) -> Result<V::Value, LlsdError> {
+    let mut seq = SeqDeserializer::<_, LlsdError>::new(items.into_iter());
+    let value = visitor.visit_seq(&mut seq)?;
+    seq.end()?;
+    Ok(value)
+}
+
+fn visit_map<'de, V: Visitor<'de>>(map: Map, visitor: V) -> Result<V::Value, LlsdError> {
+    let mut access = MapDeserializer::<_, LlsdError>::new(map.into_iter());
+    let value = visitor.visit_map(&mut access)?;
+    access.end()?;
+    Ok(value)
+}
+
+/// Externally tagged enum variant with data: `{variant: value}`.
+struct Variant {
+    variant: String,
+    value: Llsd,
+}
+
+impl<'de> de::EnumAccess<'de> for Variant {
+    type Error = LlsdError;
+    type Variant = Llsd;
+
+    fn variant_seed<S: de::DeserializeSeed<'de>>(self, seed: S) -> Result<(S::Value, Llsd), LlsdError> {
+        let name: StringDeserializer<LlsdError> = self.variant.into_deserializer();
+        Ok((seed.deserialize(name)?, self.value))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for Llsd {
+    type Error = LlsdError;
+
+    fn unit_variant(self) -> Result<(), LlsdError> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<S: de::DeserializeSeed<'de>>(self, seed: S) -> Result<S::Value, LlsdError> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, LlsdError> {
+        de::Deserializer::deserialize_seq(self, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(self, _fields: &'static [&'static str], visitor: V) -> Result<V::Value, LlsdError> {
+        de::Deserializer::deserialize_map(self, visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{from_xml_str, to_llsd, to_xml_string};
+    use super::*;
+    use serde::Serialize;
+
+    #[derive(Debug, PartialEq, Serialize, serde::Deserialize)]
+    struct SimulatorInfo {
+        #[serde(rename = "Handle")]
+        handle: u64,
+        #[serde(rename = "Port")]
+        port: u16,
+        #[serde(rename = "Name", default)]
+        name: Option<String>,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, serde::Deserialize)]
+    enum Shape {
+        Point,
+        Circle { radius: f32 },
+    }
+
+    #[test]
+    fn test_typed_roundtrip_through_xml() {
+        let info = SimulatorInfo { handle: 0x0003_E800_0003_E900, port: 13005, name: None };
+        let llsd = to_llsd(&info).unwrap();
+        assert_eq!(llsd["Handle"].as_binary().map(|b| b.len()), Some(8));
+        assert_eq!(llsd["Port"], Llsd::Integer(13005));
+        assert_eq!(from_xml_str::<SimulatorInfo>(&to_xml_string(&info).unwrap()).unwrap(), info);
+
+        for shape in [Shape::Point, Shape::Circle { radius: 2.5 }] {
+            assert_eq!(from_llsd::<Shape>(to_llsd(&shape).unwrap()).unwrap(), shape);
+        }
+    }
+
+    #[test]
+    fn test_lenient_fields() {
+        let value: Llsd = [("Port", Llsd::Real(13005.0)), ("Handle", Llsd::from("42"))].into_iter().collect();
+        let info: SimulatorInfo = from_llsd(value).unwrap();
+        assert_eq!((info.handle, info.port, info.name), (42, 13005, None));
+        assert_eq!(from_llsd::<Llsd>(Llsd::Array(vec![Llsd::Integer(1)])).unwrap(), Llsd::Array(vec![Llsd::Integer(1)]));
+    }
+}
@@ -0,0 +1,426 @@
+//! LLSD (Linden Lab Structured Data), the format of capability and event queue payloads.
+//!
+//! [`Llsd`] is the dynamic value; `xml`, `binary` and `notation` convert it to and from the
+//! three wire serializations, and [`to_llsd`]/[`from_llsd`] map typed Rust structs onto it with
+//! serde:
+//!
+//! ```ignore
+//! #[derive(Deserialize)]
+//! struct EventQueueResponse { id: i32, events: Vec<Llsd> }
+//!
+//! let response: EventQueueResponse = llsd::from_xml_str(&body)?;
+//! ```
+//!
+//! Lookups are forgiving the way the viewer's LLSD is: indexing a missing key or a non-map
+//! yields `Llsd::Undefined`, and the `as_*` accessors convert between scalar types.
+
+pub mod binary;
+mod de;
+pub mod notation;
+mod ser;
+pub mod xml;
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::Ipv4Addr;
+use std::ops::Index;
+use uuid::Uuid;
+
+pub use de::from_llsd;
+pub use ser::to_llsd;
+
+#[derive(Debug, thiserror::Error)]
+pub enum LlsdError {
+    #[error("malformed LLSD XML: {0}")]
+    Xml(String),
+    #[error("malformed binary LLSD at offset {offset}: {message}")]
+    Binary { offset: usize, message: String },
+    #[error("malformed LLSD notation at offset {offset}: {message}")]
+    Notation { offset: usize, message: String },
+    #[error("{0}")]
+    Serde(String),
+}
+
+impl serde::ser::Error for LlsdError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        LlsdError::Serde(msg.to_string())
+    }
+}
+
+impl serde::de::Error for LlsdError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        LlsdError::Serde(msg.to_string())
+    }
+}
+
+pub type Map = BTreeMap<String, Llsd>;
+
+/// An LLSD value.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Llsd {
+    #[default]
+    Undefined,
+    Boolean(bool),
+    Integer(i32),
+    Real(f64),
+    String(String),
+    Uuid(Uuid),
+    /// Seconds since the Unix epoch.
+    Date(f64),
+    Uri(String),
+    Binary(Vec<u8>),
+    Array(Vec<Llsd>),
+    Map(Map),
+}
+
+static UNDEFINED: Llsd = Llsd::Undefined;
+
+impl Llsd {
+    pub fn is_undefined(&self) -> bool {
+        matches!(self, Llsd::Undefined)
+    }
+
+    /// Value for `key` if this is a map containing it.
+    pub fn get(&self, key: &str) -> Option<&Llsd> {
+        match self {
+            Llsd::Map(map) => map.get(key),
+            _ => None,
+        }
+    }
+
+    pub fn as_map(&self) -> Option<&Map> {
+        match self {
+            Llsd::Map(map) => Some(map),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Llsd]> {
+        match self {
+            Llsd::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    /// String, URI, or the text form of a scalar.
+    pub fn as_string(&self) -> Option<String> {
+        match self {
+            Llsd::String(s) | Llsd::Uri(s) => Some(s.clone()),
+            Llsd::Boolean(b) => Some(b.to_string()),
+            Llsd::Integer(i) => Some(i.to_string()),
+            Llsd::Real(r) => Some(r.to_string()),
+            Llsd::Uuid(u) => Some(u.to_string()),
+            Llsd::Date(d) => Some(format_date(*d)),
+            _ => None,
+        }
+    }
+
+    /// Borrowed string or URI, without conversion.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Llsd::String(s) | Llsd::Uri(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Llsd::Boolean(b) => Some(*b),
+            Llsd::Integer(i) => Some(*i != 0),
+            Llsd::Real(r) => Some(*r != 0.0),
+            Llsd::String(s) => Some(!s.is_empty()),
+            _ => None,
+        }
+    }
+
+    pub fn as_integer(&self) -> Option<i32> {
+        match self {
+            Llsd::Integer(i) => Some(*i),
+            Llsd::Boolean(b) => Some(*b as i32),
+            Llsd::Real(r) => Some(*r as i32),
+            Llsd::String(s) => s.trim().parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// Unsigned 64-bit value; the viewer sends these (region handles, flags) as big-endian binary.
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Llsd::Binary(bytes) if bytes.len() <= 8 => Some(bytes.iter().fold(0u64, |acc, &b| (acc << 8) | b as u64)),
+            Llsd::Integer(i) => Some(*i as u32 as u64),
+            Llsd::Real(r) => Some(*r as u64),
+            Llsd::String(s) => s.trim().parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_real(&self) -> Option<f64> {
+        match self {
+            Llsd::Real(r) => Some(*r),
+            Llsd::Integer(i) => Some(*i as f64),
+            Llsd::Boolean(b) => Some(*b as i32 as f64),
+            Llsd::String(s) => s.trim().parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_uuid(&self) -> Option<Uuid> {
+        match self {
+            Llsd::Uuid(u) => Some(*u),
+            Llsd::String(s) => Uuid::parse_str(s.trim()).ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_binary(&self) -> Option<&[u8]> {
+        match self {
+            Llsd::Binary(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    /// A vector sent as an array of three reals.
+    pub fn as_vector3(&self) -> Option<(f32, f32, f32)> {
+        match self.as_array()? {
+            [x, y, z] => Some((x.as_real()? as f32, y.as_real()? as f32, z.as_real()? as f32)),
+            _ => None,
+        }
+    }
+
+    /// An IPv4 address sent as four bytes of binary in network order, or as dotted text.
+    pub fn as_ipv4(&self) -> Option<Ipv4Addr> {
+        match self {
+            Llsd::Binary(bytes) => <[u8; 4]>::try_from(bytes.as_slice()).ok().map(Ipv4Addr::from),
+            Llsd::String(s) => s.trim().parse().ok(),
+            _ => None,
+        }
+    }
+}
+
+impl Index<&str> for Llsd {
+    type Output = Llsd;
+
+    fn index(&self, key: &str) -> &Llsd {
+        self.get(key).unwrap_or(&UNDEFINED)
+    }
+}
+
+impl Index<usize> for Llsd {
+    type Output = Llsd;
+
+    fn index(&self, index: usize) -> &Llsd {
+        match self {
+            Llsd::Array(items) => items.get(index).unwrap_or(&UNDEFINED),
+            _ => &UNDEFINED,
+        }
+    }
+}
+
+impl From<bool> for Llsd {
+    fn from(b: bool) -> Self {
+        Llsd::Boolean(b)
+    }
+}
+
+impl From<i32> for Llsd {
+    fn from(i: i32) -> Self {
+        Llsd::Integer(i)
+    }
+}
+
+impl From<f64> for Llsd {
+    fn from(r: f64) -> Self {
+        Llsd::Real(r)
+    }
+}
+
+impl From<&str> for Llsd {
+    fn from(s: &str) -> Self {
+        Llsd::String(s.to_string())
+    }
+}
+
+impl From<String> for Llsd {
+    fn from(s: String) -> Self {
+        Llsd::String(s)
+    }
+}
+
+impl From<Uuid> for Llsd {
+    fn from(u: Uuid) -> Self {
+        Llsd::Uuid(u)
+    }
+}
+
+impl From<Vec<Llsd>> for Llsd {
+    fn from(items: Vec<Llsd>) -> Self {
+        Llsd::Array(items)
+    }
+}
+
+impl From<Map> for Llsd {
+    fn from(map: Map) -> Self {
+        Llsd::Map(map)
+    }
+}
+
+impl<K: Into<String>, V: Into<Llsd>> FromIterator<(K, V)> for Llsd {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        Llsd::Map(iter.into_iter().map(|(k, v)| (k.into(), v.into())).collect())
+    }
+}
+
+/// Parse an LLSD XML document into a typed value.
+pub fn from_xml_str<T: serde::de::DeserializeOwned>(text: &str) -> Result<T, LlsdError> {
+    from_llsd(xml::from_xml(text)?)
+}
+
+/// Serialize a typed value as an LLSD XML document.
+pub fn to_xml_string<T: serde::Serialize + ?Sized>(value: &T) -> Result<String, LlsdError> {
+    Ok(xml::to_xml(&to_llsd(value)?))
+}
+
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub(crate) fn base64_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |acc, (i, &b)| acc | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64_ALPHABET[(n >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+pub(crate) fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len() * 3 / 4);
+    let (mut acc, mut bits) = (0u32, 0);
+    for c in text.bytes().filter(|c| !c.is_ascii_whitespace()) {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            b'=' => break,
+            _ => return None,
+        };
+        acc = (acc << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+            acc &= (1 << bits) - 1;
+        }
+    }
+    Some(out)
+}
+
+pub(crate) fn base16_decode(text: &str) -> Option<Vec<u8>> {
+    let digits: Vec<u8> = text.bytes().filter(|c| !c.is_ascii_whitespace()).collect();
+    if !digits.len().is_multiple_of(2) {
+        return None;
+    }
+    digits
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+        .collect()
+}
+
+/// ISO 8601 UTC, e.g. `2006-02-01T14:29:53.460Z`; fractional seconds only when present.
+pub(crate) fn format_date(seconds: f64) -> String {
+    // Round to the millisecond before splitting, so 1.9996 s prints as 00:00:02, not 00:00:01.1000.
+    let total_millis = (seconds * 1000.0).round() as i64;
+    let whole = total_millis.div_euclid(1000);
+    let millis = total_millis.rem_euclid(1000) as u32;
+    let days = whole.div_euclid(86_400);
+    let secs_of_day = whole.rem_euclid(86_400) as u32;
+    let (year, month, day) = civil_from_days(days);
+    let (h, m, s) = (secs_of_day / 3600, secs_of_day / 60 % 60, secs_of_day % 60);
+    if millis == 0 {
+        format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, h, m, s)
+    } else {
+        format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z", year, month, day, h, m, s, millis)
+    }
+}
+
+/// Parse an ISO 8601 UTC date (`YYYY-MM-DDTHH:MM:SS[.fff]Z`). An empty string is the epoch.
+pub(crate) fn parse_date(text: &str) -> Option<f64> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Some(0.0);
+    }
+    let text = text.strip_suffix('Z').unwrap_or(text);
+    let (date, time) = text.split_once('T').unwrap_or((text, "00:00:00"));
+    let mut date_parts = date.splitn(3, '-');
+    let year: i64 = date_parts.next()?.parse().ok()?;
+    let month: u32 = date_parts.next()?.parse().ok()?;
+    let day: u32 = date_parts.next()?.parse().ok()?;
+    let mut time_parts = time.splitn(3, ':');
+    let hour: f64 = time_parts.next()?.parse().ok()?;
+    let minute: f64 = time_parts.next()?.parse().ok()?;
+    let second: f64 = time_parts.next().unwrap_or("0").parse().ok()?;
+    let days = days_from_civil(year, month, day);
+    Some(days as f64 * 86_400.0 + hour * 3600.0 + minute * 60.0 + second)
+}
+
+// Howard Hinnant's days_from_civil / civil_from_days.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let mp = (month as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lenient_lookup_and_conversions() {
+        let value: Llsd = [("handle", Llsd::Binary(vec![0, 3, 0xE8, 0, 0, 3, 0xE9, 0])), ("port", Llsd::Integer(13005))]
+            .into_iter()
+            .collect();
+        assert_eq!(value["handle"].as_u64(), Some(0x0003_E800_0003_E900));
+        assert_eq!(value["port"].as_string().as_deref(), Some("13005"));
+        assert!(value["missing"][3]["deeper"].is_undefined());
+    }
+
+    #[test]
+    fn test_base64_and_dates_roundtrip() {
+        for bytes in [&b""[..], b"f", b"fo", b"foo", b"foob", b"\x00\xff\x10"] {
+            assert_eq!(base64_decode(&base64_encode(bytes)).unwrap(), bytes);
+        }
+        assert_eq!(base64_encode(b"foob"), "Zm9vYg==");
+        assert_eq!(parse_date("2006-02-01T14:29:53Z"), Some(1_138_804_193.0));
+        assert_eq!(format_date(1_138_804_193.25), "2006-02-01T14:29:53.250Z");
+    }
+
+    #[test]
+    fn test_format_date_rounds_into_the_next_second() {
+        assert_eq!(format_date(1.9996), "1970-01-01T00:00:02Z");
+        assert_eq!(format_date(86_399.9999), "1970-01-02T00:00:00Z");
+        assert_eq!(format_date(-0.25), "1969-12-31T23:59:59.750Z");
+    }
+}
@@ -0,0 +1,327 @@
+//! LLSD notation: the compact text form, e.g. `{'region_id':u67153d5b-3659-afb4-8510-adda2c034649,'scale':r1.5}`.
+
+use std::fmt::Write;
+use uuid::Uuid;
+
+use super::{base16_decode, base64_decode, base64_encode, format_date, parse_date, Llsd, LlsdError, Map};
+
+/// Parse an LLSD notation document.
+pub fn from_notation(text: &str) -> Result<Llsd, LlsdError> {
+    let mut parser = Parser { bytes: text.as_bytes(), pos: 0 };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.pos != parser.bytes.len() {
+        return Err(parser.error("trailing data after value"));
+    }
+    Ok(value)
+}
+
+/// Serialize as LLSD notation.
+pub fn to_notation(value: &Llsd) -> String {
+    let mut out = String::new();
+    write_value(&mut out, value);
+    out
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, message: impl Into<String>) -> LlsdError {
+        LlsdError::Notation { offset: self.pos, message: message.into() }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Result<u8, LlsdError> {
+        let byte = self.peek().ok_or_else(|| self.error("unexpected end of input"))?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn expect(&mut self, expected: u8) -> Result<(), LlsdError> {
+        let found = self.next()?;
+        if found == expected {
+            Ok(())
+        } else {
+            self.pos -= 1;
+            Err(self.error(format!("expected {:?}, found {:?}", expected as char, found as char)))
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(|b| b.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    /// Consume `word` if the input continues with it.
+    fn eat(&mut self, word: &str) -> bool {
+        if self.bytes[self.pos..].starts_with(word.as_bytes()) {
+            self.pos += word.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Characters that may make up a number.
+    fn number_text(&mut self) -> &'a str {
+        let start = self.pos;
+        while self.peek().is_some_and(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'+' | b'.')) {
+            self.pos += 1;
+        }
+        std::str::from_utf8(&self.bytes[start..self.pos]).unwrap_or("")
+    }
+
+    fn value(&mut self) -> Result<Llsd, LlsdError> {
+        self.skip_whitespace();
+        let marker = self.next()?;
+        Ok(match marker {
+            b'!' => Llsd::Undefined,
+            b'1' => Llsd::Boolean(true),
+            b'0' => Llsd::Boolean(false),
+            b't' | b'T' => {
+                let _ = self.eat("rue") || self.eat("RUE");
+                Llsd::Boolean(true)
+            }
+            b'f' | b'F' => {
+                let _ = self.eat("alse") || self.eat("ALSE");
+                Llsd::Boolean(false)
+            }
+            b'i' => {
+                let text = self.number_text();
+                Llsd::Integer(text.parse().map_err(|_| self.error(format!("invalid integer {:?}", text)))?)
+            }
+            b'r' => {
+                let text = self.number_text();
+                Llsd::Real(text.parse().map_err(|_| self.error(format!("invalid real {:?}", text)))?)
+            }
+            b'u' => {
+                let end = self.pos + 36;
+                let text = self.bytes.get(self.pos..end).and_then(|b| std::str::from_utf8(b).ok()).ok_or_else(|| self.error("truncated uuid"))?;
+                let uuid = Uuid::parse_str(text).map_err(|_| self.error(format!("invalid uuid {:?}", text)))?;
+                self.pos = end;
+                Llsd::Uuid(uuid)
+            }
+            b's' | b'\'' | b'"' => {
+                self.pos -= 1;
+                Llsd::String(self.string()?)
+            }
+            b'l' => Llsd::Uri(self.quoted()?),
+            b'd' => {
+                let text = self.quoted()?;
+                Llsd::Date(parse_date(&text).ok_or_else(|| self.error(format!("invalid date {:?}", text)))?)
+            }
+            b'b' => Llsd::Binary(self.binary()?),
+            b'[' => {
+                let mut items = Vec::new();
+                loop {
+                    self.skip_whitespace();
+                    if self.peek() == Some(b']') {
+                        self.pos += 1;
+                        break;
+                    }
+                    if !items.is_empty() {
+                        self.expect(b',')?;
+                    }
+                    items.push(self.value()?);
+                }
+                Llsd::Array(items)
+            }
+            b'{' => {
+                let mut map = Map::new();
+                loop {
+                    self.skip_whitespace();
+                    if self.peek() == Some(b'}') {
+                        self.pos += 1;
+                        break;
+                    }
+                    if !map.is_empty() {
+                        self.expect(b',')?;
+                        self.skip_whitespace();
+                    }
+                    let key = self.string()?;
+                    self.skip_whitespace();
+                    self.expect(b':')?;
+                    let value = self.value()?;
+                    map.insert(key, value);
+                }
+                Llsd::Map(map)
+            }
+            other => {
+                self.pos -= 1;
+                return Err(self.error(format!("unexpected {:?}", other as char)));
+            }
+        })
+    }
+
+    /// `'escaped'`, `"escaped"` or `s(len)"raw"`.
+    fn string(&mut self) -> Result<String, LlsdError> {
+        if self.peek() == Some(b's') {
+            self.pos += 1;
+            let raw = self.sized_raw()?;
+            return String::from_utf8(raw.to_vec()).map_err(|_| self.error("string is not UTF-8"));
+        }
+        self.quoted()
+    }
+
+    /// `(len)"raw bytes"`
+    fn sized_raw(&mut self) -> Result<&'a [u8], LlsdError> {
+        self.expect(b'(')?;
+        let text = self.number_text();
+        let len: usize = text.parse().map_err(|_| self.error(format!("invalid length {:?}", text)))?;
+        self.expect(b')')?;
+        let quote = self.next()?;
+        let raw = self.bytes.get(self.pos..self.pos + len).ok_or_else(|| self.error("truncated raw string"))?;
+        self.pos += len;
+        self.expect(quote)?;
+        Ok(raw)
+    }
+
+    /// A single- or double-quoted string with backslash escapes.
+    fn quoted(&mut self) -> Result<String, LlsdError> {
+        let quote = self.next()?;
+        if quote != b'\'' && quote != b'"' {
+            self.pos -= 1;
+            return Err(self.error("expected a quoted string"));
+        }
+        let mut bytes = Vec::new();
+        loop {
+            match self.next()? {
+                b if b == quote => break,
+                b'\\' => bytes.push(match self.next()? {
+                    b'n' => b'\n',
+                    b't' => b'\t',
+                    b'r' => b'\r',
+                    b'a' => 0x07,
+                    b'b' => 0x08,
+                    b'f' => 0x0C,
+                    b'v' => 0x0B,
+                    b'x' => {
+                        let hex = self.bytes.get(self.pos..self.pos + 2).and_then(|h| std::str::from_utf8(h).ok()).unwrap_or("");
+                        let byte = u8::from_str_radix(hex, 16).map_err(|_| self.error("invalid \\x escape"))?;
+                        self.pos += 2;
+                        byte
+                    }
+                    other => other,
+                }),
+                b => bytes.push(b),
+            }
+        }
+        String::from_utf8(bytes).map_err(|_| self.error("string is not UTF-8"))
+    }
+
+    /// After `b`: `64"…"`, `16"…"` or `(len)"raw"`.
+    fn binary(&mut self) -> Result<Vec<u8>, LlsdError> {
+        if self.peek() == Some(b'(') {
+            return Ok(self.sized_raw()?.to_vec());
+        }
+        let decoded = if self.eat("64") {
+            base64_decode(&self.quoted()?)
+        } else if self.eat("16") {
+            base16_decode(&self.quoted()?)
+        } else {
+            return Err(self.error("expected b64, b16 or b(len)"));
+        };
+        decoded.ok_or_else(|| self.error("invalid binary encoding"))
+    }
+}
+
+fn write_quoted(out: &mut String, text: &str) {
+    out.push('\'');
+    for c in text.chars() {
+        match c {
+            '\'' => out.push_str("\\'"),
+            '\\' => out.push_str("\\\\"),
+            c => out.push(c),
+        }
+    }
+    out.push('\'');
+}
+
+fn write_value(out: &mut String, value: &Llsd) {
+    match value {
+        Llsd::Undefined => out.push('!'),
+        Llsd::Boolean(b) => out.push_str(if *b { "true" } else { "false" }),
+        Llsd::Integer(i) => {
+            let _ = write!(out, "i{}", i);
+        }
+        Llsd::Real(r) => {
+            let _ = write!(out, "r{:?}", r);
+        }
+        Llsd::Uuid(u) => {
+            let _ = write!(out, "u{}", u);
+        }
+        Llsd::String(s) => write_quoted(out, s),
+        Llsd::Uri(s) => {
+            out.push('l');
+            write_quoted(out, s);
+        }
+        Llsd::Date(d) => {
+            let _ = write!(out, "d\"{}\"", format_date(*d));
+        }
+        Llsd::Binary(bytes) => {
+            let _ = write!(out, "b64\"{}\"", base64_encode(bytes));
+        }
+        Llsd::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_value(out, item);
+            }
+            out.push(']');
+        }
+        Llsd::Map(map) => {
+            out.push('{');
+            for (i, (key, item)) in map.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_quoted(out, key);
+                out.push(':');
+                write_value(out, item);
+            }
+            out.push('}');
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_notation_roundtrip() {
+        let value: Llsd = [
+            ("name", Llsd::from("it's \\ here")),
+            ("list", Llsd::Array(vec![Llsd::Integer(-3), Llsd::Real(1.0), Llsd::Undefined, Llsd::Boolean(false)])),
+            ("id", Llsd::Uuid(Uuid::from_u128(0xabc))),
+            ("blob", Llsd::Binary(vec![1, 2, 3, 250])),
+            ("uri", Llsd::Uri("http://example.com/a?b=c".to_string())),
+            ("when", Llsd::Date(1_138_804_193.0)),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(from_notation(&to_notation(&value)).unwrap(), value);
+    }
+
+    #[test]
+    fn test_notation_variants() {
+        let text = r#"[ s(5)"a"b,c", "tab\there", b16"0AFF", b(2)"xy", TRUE, f, i42, {"k" : 'v'} ]"#;
+        let value = from_notation(text).unwrap();
+        assert_eq!(value[0].as_str(), Some("a\"b,c"));
+        assert_eq!(value[1].as_str(), Some("tab\there"));
+        assert_eq!(value[2].as_binary(), Some(&[0x0A, 0xFF][..]));
+        assert_eq!(value[3].as_binary(), Some(&b"xy"[..]));
+        assert_eq!((value[4].as_bool(), value[5].as_bool(), value[6].as_integer()), (Some(true), Some(false), Some(42)));
+        assert_eq!(value[7]["k"].as_str(), Some("v"));
+        assert!(from_notation("[i1,").is_err());
+    }
+}
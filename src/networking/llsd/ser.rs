@@ -0,0 +1,350 @@
+//! serde `Serializer` producing [`Llsd`] values, and `Serialize` for `Llsd` itself.
+//!
+//! Integers that do not fit an LLSD integer (i32) become big-endian binary, the way the viewer
+//! sends U32/U64 fields such as region handles.
+
+use serde::ser::{self, Serialize, SerializeMap, SerializeSeq};
+use uuid::Uuid;
+
+use super::{Llsd, LlsdError, Map};
+
+/// Newtype names that carry LLSD types serde has no notion of through other serializers.
+pub(super) const UUID_TOKEN: &str = "$llsd::Uuid";
+pub(super) const DATE_TOKEN: &str = "$llsd::Date";
+pub(super) const URI_TOKEN: &str = "$llsd::Uri";
+
+/// Convert any serializable value to LLSD.
+pub fn to_llsd<T: Serialize + ?Sized>(value: &T) -> Result<Llsd, LlsdError> {
+    value.serialize(Serializer)
+}
+
+impl Serialize for Llsd {
+    fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Llsd::Undefined => serializer.serialize_unit(),
+            Llsd::Boolean(b) => serializer.serialize_bool(*b),
+            Llsd::Integer(i) => serializer.serialize_i32(*i),
+            Llsd::Real(r) => serializer.serialize_f64(*r),
+            Llsd::String(s) => serializer.serialize_str(s),
+            Llsd::Uuid(u) => serializer.serialize_newtype_struct(UUID_TOKEN, &u.to_string()),
+            Llsd::Date(d) => serializer.serialize_newtype_struct(DATE_TOKEN, d),
+            Llsd::Uri(s) => serializer.serialize_newtype_struct(URI_TOKEN, s),
+            Llsd::Binary(bytes) => serializer.serialize_bytes(bytes),
+            Llsd::Array(items) => {
+                let mut seq = serializer.serialize_seq(Some(items.len()))?;
+                for item in items {
+                    seq.serialize_element(item)?;
+                }
+                seq.end()
+            }
+            Llsd::Map(map) => {
+                let mut out = serializer.serialize_map(Some(map.len()))?;
+                for (key, item) in map {
+                    out.serialize_entry(key, item)?;
+                }
+                out.end()
+            }
+        }
+    }
+}
+
+struct Serializer;
+
+fn wide_integer(bytes: &[u8], narrow: Option<i32>) -> Llsd {
+    match narrow {
+        Some(i) => Llsd::Integer(i),
+        None => Llsd::Binary(bytes.to_vec()),
+    }
+}
+
+/// `{variant: value}`, serde's externally tagged enum layout.
+fn tagged(variant: &'static str, value: Llsd) -> Llsd {
+    Llsd::Map(Map::from([(variant.to_string(), value)]))
+}
+
+impl ser::Serializer for Serializer {
+    type Ok = Llsd;
+    type Error = LlsdError;
+    type SerializeSeq = SeqBuilder;
+    type SerializeTuple = SeqBuilder;
+    type SerializeTupleStruct = SeqBuilder;
+    type SerializeTupleVariant = SeqBuilder;
+    type SerializeMap = MapBuilder;
+    type SerializeStruct = MapBuilder;
+    type SerializeStructVariant = MapBuilder;
+
+    fn serialize_bool(self, v: bool) -> Result<Llsd, LlsdError> {
+        Ok(Llsd::Boolean(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Llsd, LlsdError> {
+        Ok(Llsd::Integer(v as i32))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Llsd, LlsdError> {
+        Ok(Llsd::Integer(v as i32))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Llsd, LlsdError> {
+        Ok(Llsd::Integer(v))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Llsd, LlsdError> {
+        Ok(wide_integer(&v.to_be_bytes(), i32::try_from(v).ok()))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Llsd, LlsdError> {
+        Ok(Llsd::Integer(v as i32))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Llsd, LlsdError> {
+        Ok(Llsd::Integer(v as i32))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Llsd, LlsdError> {
+        Ok(wide_integer(&v.to_be_bytes(), i32::try_from(v).ok()))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Llsd, LlsdError> {
+        Ok(wide_integer(&v.to_be_bytes(), i32::try_from(v).ok()))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Llsd, LlsdError> {
+        Ok(Llsd::Real(v as f64))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Llsd, LlsdError> {
+        Ok(Llsd::Real(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Llsd, LlsdError> {
+        Ok(Llsd::String(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Llsd, LlsdError> {
+        Ok(Llsd::String(v.to_string()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Llsd, LlsdError> {
+        Ok(Llsd::Binary(v.to_vec()))
+    }
+
+    fn serialize_none(self) -> Result<Llsd, LlsdError> {
+        Ok(Llsd::Undefined)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Llsd, LlsdError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Llsd, LlsdError> {
+        Ok(Llsd::Undefined)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Llsd, LlsdError> {
+        Ok(Llsd::Undefined)
+    }
+
+    fn serialize_unit_variant(self, _name: &'static str, _index: u32, variant: &'static str) -> Result<Llsd, LlsdError> {
+        Ok(Llsd::String(variant.to_string()))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, name: &'static str, value: &T) -> Result<Llsd, LlsdError> {
+        let inner = value.serialize(self)?;
+        let invalid = || LlsdError::Serde(format!("invalid {} value {:?}", name, inner));
+        Ok(match name {
+            UUID_TOKEN => Llsd::Uuid(inner.as_str().and_then(|s| Uuid::parse_str(s).ok()).ok_or_else(invalid)?),
+            DATE_TOKEN => Llsd::Date(inner.as_real().ok_or_else(invalid)?),
+            URI_TOKEN => Llsd::Uri(inner.as_str().ok_or_else(invalid)?.to_string()),
+            _ => inner,
+        })
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Llsd, LlsdError> {
+        Ok(tagged(variant, value.serialize(self)?))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqBuilder, LlsdError> {
+        Ok(SeqBuilder { items: Vec::with_capacity(len.unwrap_or(0)), variant: None })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SeqBuilder, LlsdError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<SeqBuilder, LlsdError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SeqBuilder, LlsdError> {
+        Ok(SeqBuilder { items: Vec::with_capacity(len), variant: Some(variant) })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<MapBuilder, LlsdError> {
+        Ok(MapBuilder { map: Map::new(), next_key: None, variant: None })
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<MapBuilder, LlsdError> {
+        self.serialize_map(None)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<MapBuilder, LlsdError> {
+        Ok(MapBuilder { map: Map::new(), next_key: None, variant: Some(variant) })
+    }
+}
+
+struct SeqBuilder {
+    items: Vec<Llsd>,
+    variant: Option<&'static str>,
+}
+
+impl SeqBuilder {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), LlsdError> {
+        self.items.push(to_llsd(value)?);
+        Ok(())
+    }
+
+    fn finish(self) -> Llsd {
+        let array = Llsd::Array(self.items);
+        match self.variant {
+            Some(variant) => tagged(variant, array),
+            None => array,
+        }
+    }
+}
+
+impl ser::SerializeSeq for SeqBuilder {
+    type Ok = Llsd;
+    type Error = LlsdError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), LlsdError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Llsd, LlsdError> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeTuple for SeqBuilder {
+    type Ok = Llsd;
+    type Error = LlsdError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), LlsdError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Llsd, LlsdError> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqBuilder {
+    type Ok = Llsd;
+    type Error = LlsdError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), LlsdError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Llsd, LlsdError> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeTupleVariant for SeqBuilder {
+    type Ok = Llsd;
+    type Error = LlsdError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), LlsdError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Llsd, LlsdError> {
+        Ok(self.finish())
+    }
+}
+
+struct MapBuilder {
+    map: Map,
+    next_key: Option<String>,
+    variant: Option<&'static str>,
+}
+
+impl MapBuilder {
+    fn finish(self) -> Llsd {
+        let map = Llsd::Map(self.map);
+        match self.variant {
+            Some(variant) => tagged(variant, map),
+            None => map,
+        }
+    }
+}
+
+impl ser::SerializeMap for MapBuilder {
+    type Ok = Llsd;
+    type Error = LlsdError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), LlsdError> {
+        let key = to_llsd(key)?;
+        let key = key.as_string().ok_or_else(|| LlsdError::Serde(format!("map key {:?} is not a string", key)))?;
+        self.next_key = Some(key);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), LlsdError> {
+        let key = self.next_key.take().ok_or_else(|| LlsdError::Serde("map value without a key".to_string()))?;
+        self.map.insert(key, to_llsd(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Llsd, LlsdError> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeStruct for MapBuilder {
+    type Ok = Llsd;
+    type Error = LlsdError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), LlsdError> {
+        self.map.insert(key.to_string(), to_llsd(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Llsd, LlsdError> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeStructVariant for MapBuilder {
+    type Ok = Llsd;
+    type Error = LlsdError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), LlsdError> {
+        self.map.insert(key.to_string(), to_llsd(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Llsd, LlsdError> {
+        Ok(self.finish())
+    }
+}
@@ -0,0 +1,176 @@
+//! LLSD XML: `<llsd><map><key>…</key><string>…</string></map></llsd>`.
+
+use std::fmt::Write;
+
+use super::{base16_decode, base64_decode, base64_encode, format_date, parse_date, Llsd, LlsdError, Map};
+
+/// Parse an LLSD XML document. A document with an empty `<llsd/>` is `Undefined`.
+pub fn from_xml(text: &str) -> Result<Llsd, LlsdError> {
+    let doc = roxmltree::Document::parse(text).map_err(|e| LlsdError::Xml(e.to_string()))?;
+    let root = doc.root_element();
+    if !root.has_tag_name("llsd") {
+        return Err(LlsdError::Xml(format!("root element is <{}>, expected <llsd>", root.tag_name().name())));
+    }
+    match root.children().find(|n| n.is_element()) {
+        Some(node) => parse_node(node),
+        None => Ok(Llsd::Undefined),
+    }
+}
+
+fn parse_node(node: roxmltree::Node) -> Result<Llsd, LlsdError> {
+    let text = node.text().unwrap_or("");
+    let invalid = |what: &str| LlsdError::Xml(format!("invalid {} {:?}", what, text));
+    Ok(match node.tag_name().name() {
+        "undef" => Llsd::Undefined,
+        "boolean" => Llsd::Boolean(matches!(text.trim(), "1" | "true")),
+        "integer" => Llsd::Integer(if text.trim().is_empty() { 0 } else { text.trim().parse().map_err(|_| invalid("integer"))? }),
+        "real" => Llsd::Real(match text.trim() {
+            "" => 0.0,
+            "nan" => f64::NAN,
+            t => t.parse().map_err(|_| invalid("real"))?,
+        }),
+        "string" => Llsd::String(text.to_string()),
+        "uuid" => Llsd::Uuid(if text.trim().is_empty() { uuid::Uuid::nil() } else { uuid::Uuid::parse_str(text.trim()).map_err(|_| invalid("uuid"))? }),
+        "date" => Llsd::Date(parse_date(text).ok_or_else(|| invalid("date"))?),
+        "uri" => Llsd::Uri(text.to_string()),
+        "binary" => {
+            let bytes = match node.attribute("encoding").unwrap_or("base64") {
+                "base64" => base64_decode(text),
+                "base16" => base16_decode(text),
+                other => return Err(LlsdError::Xml(format!("unsupported binary encoding {}", other))),
+            };
+            Llsd::Binary(bytes.ok_or_else(|| invalid("binary"))?)
+        }
+        "array" => Llsd::Array(node.children().filter(|n| n.is_element()).map(parse_node).collect::<Result<_, _>>()?),
+        "map" => {
+            let mut map = Map::new();
+            let mut children = node.children().filter(|n| n.is_element());
+            while let Some(key) = children.next() {
+                if !key.has_tag_name("key") {
+                    return Err(LlsdError::Xml(format!("expected <key> in map, found <{}>", key.tag_name().name())));
+                }
+                let value = children.next().ok_or_else(|| LlsdError::Xml("map key without a value".to_string()))?;
+                map.insert(key.text().unwrap_or("").to_string(), parse_node(value)?);
+            }
+            Llsd::Map(map)
+        }
+        other => return Err(LlsdError::Xml(format!("unknown element <{}>", other))),
+    })
+}
+
+/// Serialize as an LLSD XML document.
+pub fn to_xml(value: &Llsd) -> String {
+    let mut out = String::from(r#"<?xml version="1.0" ?><llsd>"#);
+    write_node(&mut out, value);
+    out.push_str("</llsd>");
+    out
+}
+
+fn write_node(out: &mut String, value: &Llsd) {
+    match value {
+        Llsd::Undefined => out.push_str("<undef />"),
+        Llsd::Boolean(b) => {
+            let _ = write!(out, "<boolean>{}</boolean>", b);
+        }
+        Llsd::Integer(i) => {
+            let _ = write!(out, "<integer>{}</integer>", i);
+        }
+        Llsd::Real(r) if r.is_nan() => out.push_str("<real>nan</real>"),
+        Llsd::Real(r) => {
+            let _ = write!(out, "<real>{:?}</real>", r);
+        }
+        Llsd::String(s) => {
+            let _ = write!(out, "<string>{}</string>", escape(s));
+        }
+        Llsd::Uuid(u) => {
+            let _ = write!(out, "<uuid>{}</uuid>", u);
+        }
+        Llsd::Date(d) => {
+            let _ = write!(out, "<date>{}</date>", format_date(*d));
+        }
+        Llsd::Uri(s) => {
+            let _ = write!(out, "<uri>{}</uri>", escape(s));
+        }
+        Llsd::Binary(bytes) => {
+            let _ = write!(out, r#"<binary encoding="base64">{}</binary>"#, base64_encode(bytes));
+        }
+        Llsd::Array(items) => {
+            out.push_str("<array>");
+            for item in items {
+                write_node(out, item);
+            }
+            out.push_str("</array>");
+        }
+        Llsd::Map(map) => {
+            out.push_str("<map>");
+            for (key, item) in map {
+                let _ = write!(out, "<key>{}</key>", escape(key));
+                write_node(out, item);
+            }
+            out.push_str("</map>");
+        }
+    }
+}
+
+pub(crate) fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_xml_roundtrip() {
+        let value: Llsd = [
+            ("message", Llsd::from("EnableSimulator")),
+            ("ip", Llsd::Binary(vec![10, 0, 0, 7])),
+            ("body", Llsd::Array(vec![Llsd::Integer(-4), Llsd::Real(0.5), Llsd::Undefined, Llsd::Boolean(true)])),
+            ("name", Llsd::from("a < b & \"c\"")),
+            ("agent", Llsd::Uuid(uuid::Uuid::from_u128(0x1234))),
+            ("when", Llsd::Date(1_138_804_193.0)),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(from_xml(&to_xml(&value)).unwrap(), value);
+    }
+
+    #[test]
+    fn test_xml_from_simulator() {
+        let text = r#"<?xml version="1.0" ?>
+<llsd>
+<map>
+  <key>events</key>
+  <array>
+    <map>
+      <key>message</key><string>EnableSimulator</string>
+      <key>body</key>
+      <map><key>SimulatorInfo</key><array><map>
+        <key>Handle</key><binary encoding="base64">AAPoAAAD6QA=</binary>
+        <key>Port</key><integer>13005</integer>
+        <key>Flag</key><boolean />
+      </map></array></map>
+    </map>
+  </array>
+  <key>id</key><integer>7</integer>
+</map>
+</llsd>"#;
+        let value = from_xml(text).unwrap();
+        let info = &value["events"][0]["body"]["SimulatorInfo"][0];
+        assert_eq!(info["Handle"].as_u64(), Some(0x0003_E800_0003_E900));
+        assert_eq!(info["Port"].as_integer(), Some(13005));
+        assert_eq!(info["Flag"], Llsd::Boolean(false));
+        assert_eq!(value["id"].as_integer(), Some(7));
+    }
+}
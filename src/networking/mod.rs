@@ -1,5 +1,18 @@
+pub mod actor;
+pub mod capture;
+pub mod caps;
 pub mod transport;
 pub mod circuit;
+pub mod circuit_manager;
+pub mod crossing;
+pub mod event_queue;
+pub mod handlers;
+pub mod llsd;
+pub mod reliability;
+pub mod stats;
 pub mod protocol;
+pub mod proxy;
 pub mod session;
+pub mod teleport;
+pub mod xmlrpc;
 pub mod socks5_udp;
@@ -0,0 +1,165 @@
+//! Constructors for the generated messages the viewer sends.
+
+use glam::{Quat, Vec3};
+use uuid::Uuid;
+
+use crate::networking::protocol::generated::{self, Message};
+
+/// Throttle values (bits per second) for resend, land, wind, cloud, task, texture, asset.
+pub const DEFAULT_THROTTLE: [f32; 7] = [207360.0, 165376.0, 33075.2, 33075.2, 682700.75, 682700.75, 269312.0];
+
+/// Draw distance reported in AgentUpdate.
+pub const DEFAULT_DRAW_DISTANCE: f32 = 128.0;
+
+pub fn use_circuit_code(code: u32, session_id: Uuid, agent_id: Uuid) -> Message {
+    generated::UseCircuitCode {
+        circuit_code: generated::use_circuit_code::CircuitCode { code, session_id, id: agent_id },
+    }
+    .into()
+}
+
+pub fn complete_agent_movement(agent_id: Uuid, session_id: Uuid, circuit_code: u32) -> Message {
+    generated::CompleteAgentMovement {
+        agent_data: generated::complete_agent_movement::AgentData { agent_id, session_id, circuit_code },
+    }
+    .into()
+}
+
+pub fn region_handshake_reply(agent_id: Uuid, session_id: Uuid, flags: u32) -> Message {
+    generated::RegionHandshakeReply {
+        agent_data: generated::region_handshake_reply::AgentData { agent_id, session_id },
+        region_info: generated::region_handshake_reply::RegionInfo { flags },
+    }
+    .into()
+}
+
+pub fn agent_throttle(agent_id: Uuid, session_id: Uuid, circuit_code: u32, throttle: [f32; 7]) -> Message {
+    let throttles = throttle.iter().flat_map(|v| v.to_le_bytes()).collect();
+    generated::AgentThrottle {
+        agent_data: generated::agent_throttle::AgentData { agent_id, session_id, circuit_code },
+        throttle: generated::agent_throttle::Throttle { gen_counter: 0, throttles },
+    }
+    .into()
+}
+
+/// AgentUpdate with the camera at `camera_eye` looking towards `camera_at`.
+pub fn agent_update(
+    agent_id: Uuid,
+    session_id: Uuid,
+    camera_at: (f32, f32, f32),
+    camera_eye: (f32, f32, f32),
+    controls: u32,
+) -> Message {
+    let eye = Vec3::new(camera_eye.0, camera_eye.1, camera_eye.2);
+    let target = Vec3::new(camera_at.0, camera_at.1, camera_at.2);
+    let at_axis = (target - eye).try_normalize().unwrap_or(Vec3::X);
+    let left_axis = Vec3::Z.cross(at_axis).try_normalize().unwrap_or(Vec3::Y);
+    let up_axis = at_axis.cross(left_axis);
+    generated::AgentUpdate {
+        agent_data: generated::agent_update::AgentData {
+            agent_id,
+            session_id,
+            body_rotation: Quat::IDENTITY,
+            head_rotation: Quat::IDENTITY,
+            state: 0,
+            camera_center: eye,
+            camera_at_axis: at_axis,
+            camera_left_axis: left_axis,
+            camera_up_axis: up_axis,
+            far: DEFAULT_DRAW_DISTANCE,
+            control_flags: controls,
+            flags: 0,
+        },
+    }
+    .into()
+}
+
+pub fn packet_ack(acked: &[u32]) -> Message {
+    generated::PacketAck {
+        packets: acked.iter().map(|&id| generated::packet_ack::Packets { id }).collect(),
+    }
+    .into()
+}
+
+pub fn start_ping_check(ping_id: u8, oldest_unacked: u32) -> Message {
+    generated::StartPingCheck {
+        ping_id: generated::start_ping_check::PingID { ping_id, oldest_unacked },
+    }
+    .into()
+}
+
+pub fn complete_ping_check(ping_id: u8) -> Message {
+    generated::CompletePingCheck {
+        ping_id: generated::complete_ping_check::PingID { ping_id },
+    }
+    .into()
+}
+
+pub fn teleport_location_request(
+    agent_id: Uuid,
+    session_id: Uuid,
+    region_handle: u64,
+    position: (f32, f32, f32),
+    look_at: (f32, f32, f32),
+) -> Message {
+    generated::TeleportLocationRequest {
+        agent_data: generated::teleport_location_request::AgentData { agent_id, session_id },
+        info: generated::teleport_location_request::Info {
+            region_handle,
+            position: Vec3::new(position.0, position.1, position.2),
+            look_at: Vec3::new(look_at.0, look_at.1, look_at.2),
+        },
+    }
+    .into()
+}
+
+pub fn teleport_landmark_request(agent_id: Uuid, session_id: Uuid, landmark_id: Uuid) -> Message {
+    generated::TeleportLandmarkRequest {
+        info: generated::teleport_landmark_request::Info { agent_id, session_id, landmark_id },
+    }
+    .into()
+}
+
+/// RequestMultipleObjects cache miss type for objects the viewer knows nothing about.
+pub const CACHE_MISS_FULL: u8 = 0;
+
+/// RequestMultipleObjects cache miss type for ObjectUpdateCached entries the cache could not answer.
+pub const CACHE_MISS_CRC: u8 = 1;
+
+/// Ask for full updates of objects by local id, all with the same `CACHE_MISS_*` type.
+pub fn request_multiple_objects(agent_id: Uuid, session_id: Uuid, local_ids: &[u32], cache_miss_type: u8) -> Message {
+    generated::RequestMultipleObjects {
+        agent_data: generated::request_multiple_objects::AgentData { agent_id, session_id },
+        object_data: local_ids
+            .iter()
+            .map(|&id| generated::request_multiple_objects::ObjectData { cache_miss_type, id })
+            .collect(),
+    }
+    .into()
+}
+
+/// Sent to a child simulator when we stop listening to its region.
+pub fn close_circuit() -> Message {
+    generated::CloseCircuit {}.into()
+}
+
+pub fn logout_request(agent_id: Uuid, session_id: Uuid) -> Message {
+    generated::LogoutRequest {
+        agent_data: generated::logout_request::AgentData { agent_id, session_id },
+    }
+    .into()
+}
+
+/// ChatFromViewer type for normal (say) chat.
+pub const CHAT_TYPE_NORMAL: u8 = 1;
+
+/// Chat text is sent NUL-terminated.
+pub fn chat_from_viewer(agent_id: Uuid, session_id: Uuid, message: &str, chat_type: u8, channel: i32) -> Message {
+    let mut text = message.as_bytes().to_vec();
+    text.push(0);
+    generated::ChatFromViewer {
+        agent_data: generated::chat_from_viewer::AgentData { agent_id, session_id },
+        chat_data: generated::chat_from_viewer::ChatData { message: text, r#type: chat_type, channel },
+    }
+    .into()
+}
@@ -1,123 +1,88 @@
+use crate::networking::protocol::generated;
 use crate::networking::protocol::region_handshake::parse_region_handshake;
-use crate::networking::protocol::messages::{PacketHeader, Message, RegionHandshakeData};
+use crate::networking::protocol::messages::{PacketHeader, Message};
+use crate::networking::protocol::packet::Packet;
+use crate::networking::protocol::wire::MessageNumber;
 use std::io::{self, ErrorKind};
-use uuid::Uuid;
 
 pub struct MessageCodec;
 
 impl MessageCodec {
-    /// Manually decode LLUDP messages. Supports RegionHandshake, ACK, AgentMovementComplete, KeepAlive.
+    /// Decode an LLUDP datagram. Framing (extra header bytes, zero-coding, appended acks) is
+    /// handled by `Packet`; the decoded body is then dispatched on its message number.
+    /// Supports RegionHandshake, PacketAck, StartPingCheck, CompletePingCheck, AgentMovementComplete,
+    /// AgentDataUpdate, HealthMessage.
     pub fn decode(data: &[u8]) -> io::Result<(PacketHeader, Message)> {
-        if data.len() < 7 {
-            println!("[CODEC] Packet too short: {} bytes", data.len());
-            return Err(io::Error::new(ErrorKind::InvalidData, "Packet too short for message ID"));
-        }
+        let packet = Packet::decode(data).map_err(|e| {
+            println!("[CODEC] Bad packet framing ({} bytes): {}", data.len(), e);
+            io::Error::from(e)
+        })?;
+        Self::decode_packet(&packet)
+    }
 
+    /// Decode the message carried by an already-framed packet.
+    pub fn decode_packet(packet: &Packet) -> io::Result<(PacketHeader, Message)> {
         let header = PacketHeader {
-            sequence_id: u32::from_be_bytes(data[1..5].try_into().unwrap_or_default()),
-            flags: data[0],
+            sequence_id: packet.sequence,
+            flags: packet.flags.bits(),
+            acks: packet.acks.clone(),
         };
 
-        // Message IDs can be 1, 2, or 4 bytes.
-        // High frequency = 1 byte. Medium = 2 bytes. Low = 4 bytes.
-        // The first two bytes of 2 and 4-byte IDs are 0xFF.
-        
-        let id_byte1 = data[6];
-        
-        // --- High Frequency Messages ---
-        if id_byte1 < 0xFF {
-            match id_byte1 {
-                5 => { // RegionHandshake
-                    println!("[CODEC] Parsed RegionHandshake");
-                    let payload = &data[7..];
-                    return if let Some(rh) = parse_region_handshake(payload) {
-                        Ok((header, Message::RegionHandshake(rh)))
-                    } else {
-                        Err(io::Error::new(ErrorKind::InvalidData, "Failed to parse RegionHandshake"))
-                    };
-                },
-                _ => {
-                    // Other high-frequency messages can be added here.
+        let mut reader = packet.body_reader();
+        let number = MessageNumber::read(&mut reader)?;
+
+        match number {
+            generated::RegionHandshake::NUMBER => {
+                println!("[CODEC] Parsed RegionHandshake");
+                match parse_region_handshake(reader.rest()) {
+                    Some(rh) => Ok((header, Message::RegionHandshake(rh))),
+                    None => Err(io::Error::new(ErrorKind::InvalidData, "Failed to parse RegionHandshake")),
                 }
             }
-        }
-
-        // --- Medium and Low Frequency Messages ---
-        if data.len() >= 10 {
-            let full_id = &data[6..10];
-            match full_id {
-                [0xFF, 0xFF, 0xFF, 0xFB] => { // KeepAlive
-                    println!("[CODEC] Parsed KeepAlive");
-                    return Ok((header, Message::KeepAlive));
-                },
-                // IMPORTANT NOTE: AgentMovementComplete Packet Structure
-                // This decoding logic for AgentMovementComplete is a critical source of truth.
-                // It defines the exact byte offsets and data types for agent_id (16 bytes, Uuid)
-                // and session_id (16 bytes, Uuid) within the packet payload.
-                // Any future modifications or new message additions that relate to agent movement
-                // or session management MUST refer to this structure to ensure compatibility and correctness.
-                // The packet is expected to be at least 42 bytes long to contain these UUIDs.
-                [0xFF, 0xFF, 0x00, 0xF9] => { // AgentMovementComplete
-                    println!("[CODEC] Parsed AgentMovementComplete");
-                    if data.len() >= 42 {
-                        let agent_id = Uuid::from_slice(&data[10..26]).map(|u| u.to_string()).unwrap_or_default();
-                        let session_id = Uuid::from_slice(&data[26..42]).map(|u| u.to_string()).unwrap_or_default();
-                        return Ok((header, Message::AgentMovementComplete { agent_id, session_id }));
-                    } else {
-                        return Err(io::Error::new(ErrorKind::InvalidData, "Packet too short for AgentMovementComplete"));
-                    }
-                },
-                [0xFF, 0xFF, 0x00, 0xFA] => { // ImprovedAvatarPowers
-                     println!("[CODEC] Parsed ImprovedAvatarPowers");
-                     if data.len() >= 34 {
-                        let agent_id = Uuid::from_slice(&data[10..26]).map(|u| u.to_string()).unwrap_or_default();
-                        let powers = u64::from_le_bytes(data[26..34].try_into().unwrap_or_default());
-                        // Message::ImprovedAvatarPowers variant would be needed
-                        return Err(io::Error::new(ErrorKind::InvalidData, "ImprovedAvatarPowers not fully handled"));
-                     } else {
-                        return Err(io::Error::new(ErrorKind::InvalidData, "Packet too short for ImprovedAvatarPowers"));
-                     }
-                },
-                [0xFF, 0xFF, 0x00, 0x01] => { // StartPingCheck
-                    println!("[CODEC] Parsed StartPingCheck");
-                    if data.len() >= 14 {
-                        let ping_id = u32::from_le_bytes(data[10..14].try_into().unwrap_or_default());
-                        // Message::StartPingCheck variant would be needed
-                        return Err(io::Error::new(ErrorKind::InvalidData, "StartPingCheck not fully handled"));
-                    } else {
-                        return Err(io::Error::new(ErrorKind::InvalidData, "Packet too short for StartPingCheck"));
-                    }
-                },
-                [0xFF, 0xFF, 0x01, 0x83] => { // AgentDataUpdate
-                    println!("[CODEC] Parsed AgentDataUpdate");
-                    if data.len() >= 26 {
-                        let agent_id = Uuid::from_slice(&data[10..26]).map(|u| u.to_string()).unwrap_or_default();
-                        return Ok((header, Message::AgentDataUpdate{ agent_id }));
-                    } else {
-                        return Err(io::Error::new(ErrorKind::InvalidData, "Packet too short for AgentDataUpdate"));
-                    }
-                },
-                [0xFF, 0xFF, 0x00, 0x8A] => { // HealthMessage
-                    println!("[CODEC] Parsed HealthMessage");
-                    // Placeholder for actual parsing
-                    return Ok((header, Message::HealthMessage{}));
-                },
-                _ => {} // Fall through to unknown
+            generated::PacketAck::NUMBER => {
+                let ack = generated::PacketAck::decode_body(&mut reader)?;
+                let packets = ack.packets.iter().map(|p| p.id).collect();
+                Ok((header, Message::PacketAck { packets }))
             }
-        }
-        
-        // --- ACK ---
-        // This is a special case. It's identified by a flag in the header, not a message ID in the body.
-        // The body of an ACK-only packet contains a list of acknowledged sequence numbers.
-        if header.flags & 0x10 != 0 { // ACK_FLAG
-            println!("[CODEC] Parsed ACK");
-            if data.len() >= 10 { // At least one ACK
-                let acked_seq = u32::from_be_bytes(data[6..10].try_into().unwrap_or_default());
-                return Ok((header, Message::Ack { sequence_id: acked_seq }));
+            // AgentData block carries AgentID then SessionID; the Data/SimData blocks are not surfaced yet.
+            generated::AgentMovementComplete::NUMBER => {
+                println!("[CODEC] Parsed AgentMovementComplete");
+                let msg = generated::AgentMovementComplete::decode_body(&mut reader)?;
+                Ok((header, Message::AgentMovementComplete {
+                    agent_id: msg.agent_data.agent_id.to_string(),
+                    session_id: msg.agent_data.session_id.to_string(),
+                }))
+            }
+            generated::StartPingCheck::NUMBER => {
+                let msg = generated::StartPingCheck::decode_body(&mut reader)?;
+                Ok((header, Message::StartPingCheck {
+                    ping_id: msg.ping_id.ping_id,
+                    oldest_unacked: msg.ping_id.oldest_unacked,
+                }))
+            }
+            generated::CompletePingCheck::NUMBER => {
+                let msg = generated::CompletePingCheck::decode_body(&mut reader)?;
+                Ok((header, Message::CompletePingCheck { ping_id: msg.ping_id.ping_id }))
+            }
+            generated::AgentDataUpdate::NUMBER => {
+                println!("[CODEC] Parsed AgentDataUpdate");
+                let msg = generated::AgentDataUpdate::decode_body(&mut reader)?;
+                Ok((header, Message::AgentDataUpdate { agent_id: msg.agent_data.agent_id.to_string() }))
+            }
+            generated::HealthMessage::NUMBER => {
+                println!("[CODEC] Parsed HealthMessage");
+                // Placeholder for actual parsing
+                Ok((header, Message::HealthMessage {}))
+            }
+            _ => {
+                println!(
+                    "[CODEC] Unknown or unsupported message {} ({}): {:02X?}",
+                    number,
+                    generated::Message::name_of(number).unwrap_or("not in template"),
+                    &packet.body[..std::cmp::min(packet.body.len(), 32)]
+                );
+                Err(io::Error::new(ErrorKind::InvalidData, "Unsupported or unknown message type"))
             }
         }
-
-        println!("[CODEC] Unknown or unsupported message: {:02X?}", &data[..std::cmp::min(data.len(), 32)]);
-        Err(io::Error::new(ErrorKind::InvalidData, "Unsupported or unknown message type"))
     }
 }
@@ -6,6 +6,8 @@ use uuid::Uuid;
 pub struct PacketHeader {
     pub sequence_id: u32,
     pub flags: u8,
+    /// Acks appended to the packet trailer (see `protocol::packet`).
+    pub acks: Vec<u32>,
 }
 
 #[derive(Debug, Clone)]
@@ -30,7 +32,9 @@ pub enum Message {
     // Placeholder for various Second Life messages
     KeepAlive,
     Logout,
-    Ack { sequence_id: u32 },
+    PacketAck { packets: Vec<u32> },
+    StartPingCheck { ping_id: u8, oldest_unacked: u32 },
+    CompletePingCheck { ping_id: u8 },
     // IMPORTANT NOTE: UseCircuitCode Packet Structure
     // This message variant defines the canonical structure for the UseCircuitCode packet.
     // It includes agent_id (String, derived from Uuid), session_id (String, derived from Uuid),
@@ -2,3 +2,8 @@ pub mod messages;
 pub mod codecs;
 pub mod region_handshake;
 pub mod template_parser;
+pub mod template_codec;
+pub mod wire;
+pub mod generated;
+pub mod packet;
+pub mod builders;
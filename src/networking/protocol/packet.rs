@@ -0,0 +1,212 @@
+//! LLUDP packet framing.
+//!
+//! On the wire a packet is:
+//!
+//! ```text
+//! flags (1) | sequence (4, big-endian) | extra length (1) | extra header | body | [acks (4 each, big-endian) | ack count (1)]
+//! ```
+//!
+//! The body starts with the message number and is zero-coded when `ZEROCODED` is set.
+//! Appended acks are never zero-coded and are only present when `ACK` is set.
+
+use crate::networking::protocol::generated;
+use crate::networking::protocol::wire::{CodecError, MessageNumber, WireReader, WireWriter};
+use crate::utils::lludp::{zerocode, zerodecode};
+
+bitflags::bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+    pub struct PacketFlags: u8 {
+        const ZEROCODED = 0x80;
+        const RELIABLE = 0x40;
+        const RESENT = 0x20;
+        const ACK = 0x10;
+    }
+}
+
+/// flags + sequence + extra length.
+pub const PACKET_HEADER_SIZE: usize = 6;
+/// Datagram size the simulator expects us to stay under (the viewer's MTU).
+pub const MAX_PACKET_SIZE: usize = 1200;
+/// The ack count is a single byte.
+pub const MAX_APPENDED_ACKS: usize = u8::MAX as usize;
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Packet {
+    pub flags: PacketFlags,
+    pub sequence: u32,
+    /// Extra header bytes; unused by current simulators but must be skipped.
+    pub extra: Vec<u8>,
+    /// Message body (message number onwards), always stored zero-decoded.
+    pub body: Vec<u8>,
+    /// Sequence numbers acknowledged by this packet's trailer.
+    pub acks: Vec<u32>,
+}
+
+impl Packet {
+    pub fn new(flags: PacketFlags, sequence: u32, body: Vec<u8>) -> Self {
+        Self { flags, sequence, extra: Vec::new(), body, acks: Vec::new() }
+    }
+
+    /// Frame a generated message, zero-coding it if the template says so.
+    pub fn from_message(message: &generated::Message, sequence: u32, reliable: bool) -> Result<Self, CodecError> {
+        let mut w = WireWriter::new();
+        message.encode(&mut w)?;
+        let mut flags = PacketFlags::empty();
+        if reliable {
+            flags |= PacketFlags::RELIABLE;
+        }
+        if message.is_zerocoded() {
+            flags |= PacketFlags::ZEROCODED;
+        }
+        Ok(Self::new(flags, sequence, w.into_inner()))
+    }
+
+    pub fn is_reliable(&self) -> bool {
+        self.flags.contains(PacketFlags::RELIABLE)
+    }
+
+    pub fn is_resent(&self) -> bool {
+        self.flags.contains(PacketFlags::RESENT)
+    }
+
+    pub fn body_reader(&self) -> WireReader<'_> {
+        WireReader::new(&self.body)
+    }
+
+    pub fn message_number(&self) -> Result<MessageNumber, CodecError> {
+        MessageNumber::read(&mut self.body_reader())
+    }
+
+    /// Decode the body as one of the generated message types.
+    pub fn message(&self) -> Result<generated::Message, CodecError> {
+        let mut r = self.body_reader();
+        let number = MessageNumber::read(&mut r)?;
+        generated::Message::decode_body(number, &mut r)
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self, CodecError> {
+        if data.len() < PACKET_HEADER_SIZE {
+            return Err(CodecError::UnexpectedEof { offset: 0, needed: PACKET_HEADER_SIZE });
+        }
+        let flags = PacketFlags::from_bits_truncate(data[0]);
+        let sequence = u32::from_be_bytes([data[1], data[2], data[3], data[4]]);
+        let body_start = PACKET_HEADER_SIZE + data[5] as usize;
+        if data.len() < body_start {
+            return Err(CodecError::UnexpectedEof { offset: PACKET_HEADER_SIZE, needed: data[5] as usize });
+        }
+        let extra = data[PACKET_HEADER_SIZE..body_start].to_vec();
+
+        // Appended acks are read from the end of the datagram, before zero-decoding.
+        let mut body_end = data.len();
+        let mut acks = Vec::new();
+        if flags.contains(PacketFlags::ACK) {
+            if body_end <= body_start {
+                return Err(CodecError::Malformed("ACK flag set but no ack count".to_string()));
+            }
+            let count = data[body_end - 1] as usize;
+            let trailer = 1 + count * 4;
+            if body_end - body_start < trailer {
+                return Err(CodecError::Malformed(format!("{} appended acks do not fit in packet", count)));
+            }
+            body_end -= trailer;
+            acks = data[body_end..body_end + count * 4]
+                .chunks_exact(4)
+                .map(|c| u32::from_be_bytes([c[0], c[1], c[2], c[3]]))
+                .collect();
+        }
+
+        let raw_body = &data[body_start..body_end];
+        let body = if flags.contains(PacketFlags::ZEROCODED) { zerodecode(raw_body) } else { raw_body.to_vec() };
+
+        Ok(Self { flags, sequence, extra, body, acks })
+    }
+
+    /// Encode for sending. `ACK` is set from `acks`; zero-coding is skipped when it would not
+    /// make the body smaller, as the official viewer does.
+    pub fn encode(&self) -> Result<Vec<u8>, CodecError> {
+        if self.extra.len() > u8::MAX as usize {
+            return Err(CodecError::FieldTooLong { field: "extra header".to_string(), len: self.extra.len(), max: u8::MAX as usize });
+        }
+        if self.acks.len() > MAX_APPENDED_ACKS {
+            return Err(CodecError::FieldTooLong { field: "appended acks".to_string(), len: self.acks.len(), max: MAX_APPENDED_ACKS });
+        }
+
+        let mut flags = self.flags;
+        let zerocoded = if flags.contains(PacketFlags::ZEROCODED) {
+            let encoded = zerocode(&self.body);
+            if encoded.len() < self.body.len() {
+                Some(encoded)
+            } else {
+                flags.remove(PacketFlags::ZEROCODED);
+                None
+            }
+        } else {
+            None
+        };
+        flags.set(PacketFlags::ACK, !self.acks.is_empty());
+
+        let body = zerocoded.as_deref().unwrap_or(&self.body);
+        let mut out = Vec::with_capacity(PACKET_HEADER_SIZE + self.extra.len() + body.len() + self.acks.len() * 4 + 1);
+        out.push(flags.bits());
+        out.extend_from_slice(&self.sequence.to_be_bytes());
+        out.push(self.extra.len() as u8);
+        out.extend_from_slice(&self.extra);
+        out.extend_from_slice(body);
+        if !self.acks.is_empty() {
+            for ack in &self.acks {
+                out.extend_from_slice(&ack.to_be_bytes());
+            }
+            out.push(self.acks.len() as u8);
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zerocoded_packet_with_acks_roundtrip() {
+        let mut body = vec![0xFF, 0xFF, 0x00, 0x94];
+        body.extend_from_slice(&[0u8; 40]);
+        body.extend_from_slice(&[1, 2, 3]);
+        let mut packet = Packet::new(PacketFlags::RELIABLE | PacketFlags::ZEROCODED, 0x01020304, body.clone());
+        packet.acks = vec![7, 0xDEADBEEF];
+
+        let bytes = packet.encode().unwrap();
+        assert_eq!(bytes[0], 0x80 | 0x40 | 0x10);
+        assert_eq!(&bytes[1..5], &[1, 2, 3, 4]);
+        assert_eq!(*bytes.last().unwrap(), 2);
+        assert!(bytes.len() < PACKET_HEADER_SIZE + body.len());
+
+        let decoded = Packet::decode(&bytes).unwrap();
+        assert_eq!(decoded.body, body);
+        assert_eq!(decoded.acks, vec![7, 0xDEADBEEF]);
+        assert_eq!(decoded.sequence, 0x01020304);
+        assert_eq!(decoded.message_number().unwrap(), MessageNumber::Low(148));
+    }
+
+    #[test]
+    fn test_extra_header_is_skipped() {
+        let data = [0x00, 0, 0, 0, 9, 2, 0xAA, 0xBB, 0x01, 7, 0, 0, 0, 0];
+        let packet = Packet::decode(&data).unwrap();
+        assert_eq!(packet.extra, vec![0xAA, 0xBB]);
+        assert_eq!(packet.message_number().unwrap(), MessageNumber::High(1));
+        assert_eq!(packet.encode().unwrap(), data);
+    }
+
+    #[test]
+    fn test_zerocode_dropped_when_not_smaller() {
+        let packet = Packet::new(PacketFlags::ZEROCODED, 1, vec![0x01, 0x02, 0x03]);
+        let bytes = packet.encode().unwrap();
+        assert_eq!(bytes[0] & PacketFlags::ZEROCODED.bits(), 0);
+        assert_eq!(&bytes[6..], &[0x01, 0x02, 0x03]);
+    }
+
+    #[test]
+    fn test_truncated_ack_trailer_rejected() {
+        let data = [0x10, 0, 0, 0, 1, 0, 0x01, 0x00, 5];
+        assert!(Packet::decode(&data).is_err());
+    }
+}
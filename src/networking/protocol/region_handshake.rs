@@ -1,70 +1,42 @@
-//! Manual parser for Second Life LLUDP RegionHandshake packets.
+//! RegionHandshake decoding into `RegionHandshakeData`.
 
-use uuid::Uuid;
-use std::io::{Cursor, Read};
-use byteorder::{LittleEndian, ReadBytesExt};
+use crate::networking::protocol::generated::RegionHandshake;
 use crate::networking::protocol::messages::RegionHandshakeData;
+use crate::networking::protocol::wire::WireReader;
 
-// Correctly parse RegionHandshake according to message_template.msg
-pub fn parse_region_handshake(mut payload: &[u8]) -> Option<RegionHandshakeData> {
-    let mut cursor = Cursor::new(&mut payload);
-
-    // RegionInfo block
-    let region_flags = cursor.read_u32::<LittleEndian>().ok()?;
-    let sim_access = cursor.read_u8().ok()?;
-    let sim_owner = read_uuid(&mut cursor).ok()?;
-    let is_estate_manager = cursor.read_u8().ok()?;
-    let water_height = cursor.read_f32::<LittleEndian>().ok()?;
-    let billable_factor = cursor.read_f32::<LittleEndian>().ok()?;
-    let cache_id = read_uuid(&mut cursor).ok()?;
-    let region_id = read_uuid(&mut cursor).ok()?;
+/// Parse a RegionHandshake body (everything after the message number, already zero-decoded).
+pub fn parse_region_handshake(payload: &[u8]) -> Option<RegionHandshakeData> {
+    let mut reader = WireReader::new(payload);
+    RegionHandshake::decode_body(&mut reader).ok().map(RegionHandshakeData::from)
+}
 
-    // Terrain block (simplified for now, as the template is complex)
-    let mut terrain_base = [Uuid::nil(); 4];
-    for i in 0..4 {
-        terrain_base[i] = read_uuid(&mut cursor).ok()?;
-    }
-    let mut terrain_detail = [Uuid::nil(); 4];
-    for i in 0..4 {
-        terrain_detail[i] = read_uuid(&mut cursor).ok()?;
-    }
-    let mut terrain_start_height = [0.0f32; 4];
-    for i in 0..4 {
-        terrain_start_height[i] = cursor.read_f32::<LittleEndian>().ok()?;
+impl From<RegionHandshake> for RegionHandshakeData {
+    fn from(msg: RegionHandshake) -> Self {
+        let info = msg.region_info;
+        RegionHandshakeData {
+            region_flags: info.region_flags,
+            sim_access: info.sim_access,
+            region_name: String::from_utf8_lossy(&info.sim_name).trim_end_matches('\0').to_string(),
+            sim_owner: info.sim_owner,
+            is_estate_manager: info.is_estate_manager as u8,
+            water_height: info.water_height,
+            billable_factor: info.billable_factor,
+            cache_id: info.cache_id,
+            terrain_base: [info.terrain_base0, info.terrain_base1, info.terrain_base2, info.terrain_base3],
+            terrain_detail: [info.terrain_detail0, info.terrain_detail1, info.terrain_detail2, info.terrain_detail3],
+            terrain_start_height: [
+                info.terrain_start_height00,
+                info.terrain_start_height01,
+                info.terrain_start_height10,
+                info.terrain_start_height11,
+            ],
+            terrain_height_range: [
+                info.terrain_height_range00,
+                info.terrain_height_range01,
+                info.terrain_height_range10,
+                info.terrain_height_range11,
+            ],
+            region_id: msg.region_info2.region_id,
+        }
     }
-    let mut terrain_height_range = [0.0f32; 4];
-    for i in 0..4 {
-        terrain_height_range[i] = cursor.read_f32::<LittleEndian>().ok()?;
-    }
-    
-    // SimulatorVersion block
-    let version_len = cursor.read_u8().ok()? as usize;
-    let mut version_buf = vec![0u8; version_len];
-    cursor.read_exact(&mut version_buf).ok()?;
-    let _simulator_version = String::from_utf8_lossy(&version_buf).to_string();
-
-    // RegionName is part of a different message (RegionInfo), so we'll use a placeholder
-    let region_name = "Unknown".to_string();
-
-    Some(RegionHandshakeData {
-        region_flags,
-        sim_access,
-        region_name, // Placeholder
-        sim_owner,
-        is_estate_manager,
-        water_height,
-        billable_factor,
-        cache_id,
-        terrain_base,
-        terrain_detail,
-        terrain_start_height,
-        terrain_height_range,
-        region_id,
-    })
 }
-
-fn read_uuid<R: Read>(reader: &mut R) -> std::io::Result<Uuid> {
-    let mut buf = [0u8; 16];
-    reader.read_exact(&mut buf)?;
-    Ok(Uuid::from_bytes(buf))
-} 
\ No newline at end of file
@@ -0,0 +1,427 @@
+//! Runtime, template-driven LLUDP codec.
+//!
+//! Unlike the generated types in [`super::generated`], this works from a `MessageTemplate` loaded
+//! at runtime and decodes any message it describes into a generic tree of blocks and typed values.
+//! Debugging and proxy tools use it to inspect and re-encode traffic we have no typed handling for.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::net::Ipv4Addr;
+use uuid::Uuid;
+
+use crate::networking::protocol::template_parser::{
+    self, BlockDefinition, Cardinality, Frequency, MessageDefinition, MessageTemplate,
+};
+use crate::networking::protocol::wire::{CodecError, MessageNumber, WireReader, WireWriter};
+
+/// The template `build.rs` generated the typed messages from.
+pub const BUNDLED_TEMPLATE: &str = include_str!(concat!(env!("OUT_DIR"), "/message_template.msg"));
+
+/// Field type as declared in the template, e.g. `U32`, `Variable 1`, `Fixed 4`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldType {
+    U8,
+    U16,
+    U32,
+    U64,
+    S8,
+    S16,
+    S32,
+    S64,
+    F32,
+    F64,
+    Bool,
+    Uuid,
+    Vector3,
+    Vector3d,
+    Vector4,
+    Quaternion,
+    IpAddr,
+    IpPort,
+    Variable1,
+    Variable2,
+    Fixed(usize),
+}
+
+impl FieldType {
+    pub fn parse(type_name: &str) -> Result<Self, CodecError> {
+        let parts: Vec<&str> = type_name.split_whitespace().collect();
+        Ok(match parts.as_slice() {
+            ["U8"] => FieldType::U8,
+            ["U16"] => FieldType::U16,
+            ["U32"] => FieldType::U32,
+            ["U64"] => FieldType::U64,
+            ["S8"] => FieldType::S8,
+            ["S16"] => FieldType::S16,
+            ["S32"] => FieldType::S32,
+            ["S64"] => FieldType::S64,
+            ["F32"] => FieldType::F32,
+            ["F64"] => FieldType::F64,
+            ["BOOL"] => FieldType::Bool,
+            ["LLUUID"] => FieldType::Uuid,
+            ["LLVector3"] => FieldType::Vector3,
+            ["LLVector3d"] => FieldType::Vector3d,
+            ["LLVector4"] => FieldType::Vector4,
+            ["LLQuaternion"] => FieldType::Quaternion,
+            ["IPADDR"] => FieldType::IpAddr,
+            ["IPPORT"] => FieldType::IpPort,
+            ["Variable", "1"] => FieldType::Variable1,
+            ["Variable", "2"] => FieldType::Variable2,
+            ["Fixed", n] => FieldType::Fixed(
+                n.parse().map_err(|_| CodecError::Malformed(format!("bad Fixed size: {}", type_name)))?,
+            ),
+            _ => return Err(CodecError::Malformed(format!("unsupported field type: {}", type_name))),
+        })
+    }
+}
+
+/// A decoded field value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldValue {
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    S8(i8),
+    S16(i16),
+    S32(i32),
+    S64(i64),
+    F32(f32),
+    F64(f64),
+    Bool(bool),
+    Uuid(Uuid),
+    Vector3(glam::Vec3),
+    Vector3d(glam::DVec3),
+    Vector4(glam::Vec4),
+    Quaternion(glam::Quat),
+    IpAddr(Ipv4Addr),
+    IpPort(u16),
+    /// `Variable 1` / `Variable 2` payload, without the length prefix.
+    Variable(Vec<u8>),
+    Fixed(Vec<u8>),
+}
+
+impl FieldValue {
+    fn read(ty: FieldType, r: &mut WireReader<'_>) -> Result<Self, CodecError> {
+        Ok(match ty {
+            FieldType::U8 => FieldValue::U8(r.read_u8()?),
+            FieldType::U16 => FieldValue::U16(r.read_u16()?),
+            FieldType::U32 => FieldValue::U32(r.read_u32()?),
+            FieldType::U64 => FieldValue::U64(r.read_u64()?),
+            FieldType::S8 => FieldValue::S8(r.read_i8()?),
+            FieldType::S16 => FieldValue::S16(r.read_i16()?),
+            FieldType::S32 => FieldValue::S32(r.read_i32()?),
+            FieldType::S64 => FieldValue::S64(r.read_i64()?),
+            FieldType::F32 => FieldValue::F32(r.read_f32()?),
+            FieldType::F64 => FieldValue::F64(r.read_f64()?),
+            FieldType::Bool => FieldValue::Bool(r.read_bool()?),
+            FieldType::Uuid => FieldValue::Uuid(r.read_uuid()?),
+            FieldType::Vector3 => FieldValue::Vector3(r.read_vec3()?),
+            FieldType::Vector3d => FieldValue::Vector3d(r.read_vec3d()?),
+            FieldType::Vector4 => FieldValue::Vector4(r.read_vec4()?),
+            FieldType::Quaternion => FieldValue::Quaternion(r.read_quat()?),
+            FieldType::IpAddr => FieldValue::IpAddr(r.read_ipaddr()?),
+            FieldType::IpPort => FieldValue::IpPort(r.read_ipport()?),
+            FieldType::Variable1 => FieldValue::Variable(r.read_variable1()?),
+            FieldType::Variable2 => FieldValue::Variable(r.read_variable2()?),
+            FieldType::Fixed(n) => FieldValue::Fixed(r.read_bytes(n)?.to_vec()),
+        })
+    }
+
+    fn write(&self, ty: FieldType, field: &str, w: &mut WireWriter) -> Result<(), CodecError> {
+        match (ty, self) {
+            (FieldType::U8, FieldValue::U8(v)) => w.put_u8(*v),
+            (FieldType::U16, FieldValue::U16(v)) => w.put_u16(*v),
+            (FieldType::U32, FieldValue::U32(v)) => w.put_u32(*v),
+            (FieldType::U64, FieldValue::U64(v)) => w.put_u64(*v),
+            (FieldType::S8, FieldValue::S8(v)) => w.put_i8(*v),
+            (FieldType::S16, FieldValue::S16(v)) => w.put_i16(*v),
+            (FieldType::S32, FieldValue::S32(v)) => w.put_i32(*v),
+            (FieldType::S64, FieldValue::S64(v)) => w.put_i64(*v),
+            (FieldType::F32, FieldValue::F32(v)) => w.put_f32(*v),
+            (FieldType::F64, FieldValue::F64(v)) => w.put_f64(*v),
+            (FieldType::Bool, FieldValue::Bool(v)) => w.put_bool(*v),
+            (FieldType::Uuid, FieldValue::Uuid(v)) => w.put_uuid(v),
+            (FieldType::Vector3, FieldValue::Vector3(v)) => w.put_vec3(*v),
+            (FieldType::Vector3d, FieldValue::Vector3d(v)) => w.put_vec3d(*v),
+            (FieldType::Vector4, FieldValue::Vector4(v)) => w.put_vec4(*v),
+            (FieldType::Quaternion, FieldValue::Quaternion(v)) => w.put_quat(*v),
+            (FieldType::IpAddr, FieldValue::IpAddr(v)) => w.put_ipaddr(*v),
+            (FieldType::IpPort, FieldValue::IpPort(v)) => w.put_ipport(*v),
+            (FieldType::Variable1, FieldValue::Variable(v)) => w.put_variable1(field, v)?,
+            (FieldType::Variable2, FieldValue::Variable(v)) => w.put_variable2(field, v)?,
+            (FieldType::Fixed(n), FieldValue::Fixed(v)) => {
+                if v.len() != n {
+                    return Err(CodecError::Malformed(format!("field {} must be {} bytes, got {}", field, n, v.len())));
+                }
+                w.put_bytes(v);
+            }
+            (ty, value) => {
+                return Err(CodecError::Malformed(format!("field {} is {:?}, got value {:?}", field, ty, value)));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for FieldValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldValue::U8(v) => write!(f, "{}", v),
+            FieldValue::U16(v) => write!(f, "{}", v),
+            FieldValue::U32(v) => write!(f, "{}", v),
+            FieldValue::U64(v) => write!(f, "{}", v),
+            FieldValue::S8(v) => write!(f, "{}", v),
+            FieldValue::S16(v) => write!(f, "{}", v),
+            FieldValue::S32(v) => write!(f, "{}", v),
+            FieldValue::S64(v) => write!(f, "{}", v),
+            FieldValue::F32(v) => write!(f, "{}", v),
+            FieldValue::F64(v) => write!(f, "{}", v),
+            FieldValue::Bool(v) => write!(f, "{}", v),
+            FieldValue::Uuid(v) => write!(f, "{}", v),
+            FieldValue::Vector3(v) => write!(f, "<{}, {}, {}>", v.x, v.y, v.z),
+            FieldValue::Vector3d(v) => write!(f, "<{}, {}, {}>", v.x, v.y, v.z),
+            FieldValue::Vector4(v) => write!(f, "<{}, {}, {}, {}>", v.x, v.y, v.z, v.w),
+            FieldValue::Quaternion(v) => write!(f, "<{}, {}, {}, {}>", v.x, v.y, v.z, v.w),
+            FieldValue::IpAddr(v) => write!(f, "{}", v),
+            FieldValue::IpPort(v) => write!(f, "{}", v),
+            // Most variable fields are NUL-terminated strings; fall back to hex for binary data.
+            FieldValue::Variable(v) => match std::str::from_utf8(v) {
+                Ok(s) if !s.trim_end_matches('\0').contains(|c: char| c.is_control() && c != '\n') => {
+                    write!(f, "{:?}", s.trim_end_matches('\0'))
+                }
+                _ => write!(f, "{:02X?}", v),
+            },
+            FieldValue::Fixed(v) => write!(f, "{:02X?}", v),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DynamicField {
+    pub name: String,
+    pub value: FieldValue,
+}
+
+/// One instance of a block; Multiple/Variable blocks appear once per repetition.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DynamicBlock {
+    pub name: String,
+    pub fields: Vec<DynamicField>,
+}
+
+impl DynamicBlock {
+    pub fn field(&self, name: &str) -> Option<&FieldValue> {
+        self.fields.iter().find(|f| f.name == name).map(|f| &f.value)
+    }
+}
+
+/// A message decoded against the template. Blocks are kept in template order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DynamicMessage {
+    pub name: String,
+    pub number: MessageNumber,
+    pub blocks: Vec<DynamicBlock>,
+}
+
+impl DynamicMessage {
+    /// All instances of the named block.
+    pub fn blocks_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a DynamicBlock> + 'a {
+        self.blocks.iter().filter(move |b| b.name == name)
+    }
+
+    /// Convenience lookup for `Block.Field` on the first instance of a block.
+    pub fn field(&self, block: &str, field: &str) -> Option<&FieldValue> {
+        self.blocks.iter().find(|b| b.name == block).and_then(|b| b.field(field))
+    }
+}
+
+impl fmt::Display for DynamicMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} ({})", self.name, self.number)?;
+        for block in &self.blocks {
+            writeln!(f, "  [{}]", block.name)?;
+            for field in &block.fields {
+                writeln!(f, "    {}: {}", field.name, field.value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Wire message number for a template definition.
+pub fn message_number(def: &MessageDefinition) -> MessageNumber {
+    match def.frequency {
+        Frequency::High => MessageNumber::High(def.id as u8),
+        Frequency::Medium => MessageNumber::Medium(def.id as u8),
+        Frequency::Low => MessageNumber::Low(def.id as u16),
+        Frequency::Fixed => MessageNumber::Fixed((def.id & 0xFF) as u8),
+    }
+}
+
+/// Decodes and encodes message bodies (message number onwards, already zero-decoded)
+/// using a parsed `MessageTemplate`.
+pub struct TemplateCodec {
+    template: MessageTemplate,
+    by_number: HashMap<MessageNumber, usize>,
+    by_name: HashMap<String, usize>,
+}
+
+impl TemplateCodec {
+    pub fn new(template: MessageTemplate) -> Result<Self, CodecError> {
+        let mut by_number = HashMap::new();
+        let mut by_name = HashMap::new();
+        for (i, def) in template.messages.iter().enumerate() {
+            // Reject unknown field types up front rather than on first use.
+            for block in &def.blocks {
+                for field in &block.fields {
+                    FieldType::parse(&field.type_name)?;
+                }
+            }
+            by_number.insert(message_number(def), i);
+            by_name.insert(def.name.clone(), i);
+        }
+        Ok(Self { template, by_number, by_name })
+    }
+
+    /// Parse template text (the contents of a message_template.msg).
+    pub fn from_template_str(content: &str) -> Result<Self, CodecError> {
+        let template = template_parser::parse(content).map_err(CodecError::Malformed)?;
+        Self::new(template)
+    }
+
+    /// Codec for the template bundled with the crate.
+    pub fn bundled() -> Result<Self, CodecError> {
+        Self::from_template_str(BUNDLED_TEMPLATE)
+    }
+
+    pub fn template(&self) -> &MessageTemplate {
+        &self.template
+    }
+
+    pub fn definition(&self, number: MessageNumber) -> Option<&MessageDefinition> {
+        self.by_number.get(&number).map(|&i| &self.template.messages[i])
+    }
+
+    pub fn definition_by_name(&self, name: &str) -> Option<&MessageDefinition> {
+        self.by_name.get(name).map(|&i| &self.template.messages[i])
+    }
+
+    /// Decode a message body starting at the message number.
+    /// Trailing bytes after the last block are ignored, matching the simulator's tolerance.
+    pub fn decode(&self, body: &[u8]) -> Result<DynamicMessage, CodecError> {
+        let mut r = WireReader::new(body);
+        let number = MessageNumber::read(&mut r)?;
+        let def = self.definition(number).ok_or(CodecError::UnknownMessage(number))?;
+
+        let mut blocks = Vec::new();
+        for block in &def.blocks {
+            let count = match block.cardinality {
+                Cardinality::Single => 1,
+                Cardinality::Multiple => block.count.unwrap_or(1) as usize,
+                Cardinality::Variable => r.read_block_count()?,
+            };
+            for _ in 0..count {
+                blocks.push(Self::decode_block(block, &mut r)?);
+            }
+        }
+
+        Ok(DynamicMessage { name: def.name.clone(), number, blocks })
+    }
+
+    fn decode_block(block: &BlockDefinition, r: &mut WireReader<'_>) -> Result<DynamicBlock, CodecError> {
+        let mut fields = Vec::with_capacity(block.fields.len());
+        for field in &block.fields {
+            let ty = FieldType::parse(&field.type_name)?;
+            fields.push(DynamicField { name: field.name.clone(), value: FieldValue::read(ty, r)? });
+        }
+        Ok(DynamicBlock { name: block.name.clone(), fields })
+    }
+
+    /// Encode a message (message number followed by its blocks).
+    /// The message is looked up by name; fields are written in template order.
+    pub fn encode(&self, msg: &DynamicMessage) -> Result<Vec<u8>, CodecError> {
+        let def = self
+            .definition_by_name(&msg.name)
+            .ok_or_else(|| CodecError::Malformed(format!("unknown message {}", msg.name)))?;
+
+        let mut w = WireWriter::new();
+        message_number(def).write(&mut w);
+
+        for block in &def.blocks {
+            let instances: Vec<&DynamicBlock> = msg.blocks_named(&block.name).collect();
+            match block.cardinality {
+                Cardinality::Single => w.check_multiple(&block.name, 1, instances.len())?,
+                Cardinality::Multiple => {
+                    w.check_multiple(&block.name, block.count.unwrap_or(1) as usize, instances.len())?
+                }
+                Cardinality::Variable => w.put_block_count(&block.name, instances.len())?,
+            }
+            for instance in instances {
+                for field in &block.fields {
+                    let ty = FieldType::parse(&field.type_name)?;
+                    let value = instance.field(&field.name).ok_or_else(|| {
+                        CodecError::Malformed(format!("missing field {}.{}", block.name, field.name))
+                    })?;
+                    value.write(ty, &field.name, &mut w)?;
+                }
+            }
+        }
+
+        Ok(w.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networking::protocol::generated::{self, Message};
+
+    #[test]
+    fn test_decode_matches_generated_encoding() {
+        let codec = TemplateCodec::bundled().unwrap();
+        let agent_id = Uuid::new_v4();
+        let typed = Message::from(generated::ChatFromViewer {
+            agent_data: generated::chat_from_viewer::AgentData { agent_id, session_id: Uuid::nil() },
+            chat_data: generated::chat_from_viewer::ChatData {
+                message: b"hello\0".to_vec(),
+                r#type: 1,
+                channel: 0,
+            },
+        });
+        let mut w = WireWriter::new();
+        typed.encode(&mut w).unwrap();
+
+        let msg = codec.decode(w.as_slice()).unwrap();
+        assert_eq!(msg.name, "ChatFromViewer");
+        assert_eq!(msg.field("AgentData", "AgentID"), Some(&FieldValue::Uuid(agent_id)));
+        assert_eq!(msg.field("ChatData", "Message"), Some(&FieldValue::Variable(b"hello\0".to_vec())));
+        assert_eq!(codec.encode(&msg).unwrap(), w.as_slice());
+    }
+
+    #[test]
+    fn test_variable_block_roundtrip() {
+        let codec = TemplateCodec::bundled().unwrap();
+        let body = [0xFF, 0xFF, 0xFF, 0xFB, 2, 1, 0, 0, 0, 2, 0, 0, 0];
+        let msg = codec.decode(&body).unwrap();
+        assert_eq!(msg.name, "PacketAck");
+        assert_eq!(msg.blocks_named("Packets").count(), 2);
+        assert_eq!(codec.encode(&msg).unwrap(), body);
+    }
+
+    #[test]
+    fn test_unknown_message_and_type_mismatch() {
+        let codec = TemplateCodec::bundled().unwrap();
+        assert!(matches!(codec.decode(&[0xFF, 0xFF, 0x7F, 0x7F]), Err(CodecError::UnknownMessage(_))));
+
+        let bad = DynamicMessage {
+            name: "CompletePingCheck".to_string(),
+            number: MessageNumber::High(2),
+            blocks: vec![DynamicBlock {
+                name: "PingID".to_string(),
+                fields: vec![DynamicField { name: "PingID".to_string(), value: FieldValue::U32(1) }],
+            }],
+        };
+        assert!(codec.encode(&bad).is_err());
+    }
+}
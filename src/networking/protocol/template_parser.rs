@@ -16,6 +16,21 @@ pub struct MessageDefinition {
     pub blocks: Vec<BlockDefinition>,
 }
 
+/// Tags the upstream template puts after a message's encoding.
+pub const MESSAGE_FLAGS: &[&str] = &["Deprecated", "UDPDeprecated", "UDPBlackListed"];
+
+impl MessageDefinition {
+    /// Deprecated outright, or deprecated for UDP in favour of a capability.
+    pub fn is_deprecated(&self) -> bool {
+        self.flags.iter().any(|f| f == "Deprecated" || f == "UDPDeprecated")
+    }
+
+    /// Must not be accepted over UDP; simulators only send it over the event queue.
+    pub fn is_udp_blacklisted(&self) -> bool {
+        self.flags.iter().any(|f| f == "UDPBlackListed")
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct BlockDefinition {
     pub name: String,
@@ -133,9 +148,13 @@ pub fn parse(content: &str) -> Result<MessageTemplate, String> {
     let mut line_num = 0;
     
     while line_num < lines.len() {
-        let line = lines[line_num].trim();
+        // Drop trailing comments, e.g. "{ CRC U32 } // TEMPORARY HACK FOR JAMES"
+        let line = match lines[line_num].find("//") {
+            Some(idx) => lines[line_num][..idx].trim(),
+            None => lines[line_num].trim(),
+        };
         line_num += 1;
-        
+
         // Skip empty lines and comments
         if line.is_empty() || line.starts_with("//") || line.starts_with("version") {
             continue;
@@ -190,12 +209,22 @@ pub fn parse(content: &str) -> Result<MessageTemplate, String> {
                     let encoding = parts[4].parse::<Encoding>()
                         .map_err(|e| format!("Error parsing encoding at line {}: {}", line_num, e))?;
                     
-                    // Collect any additional flags (like "UDPBlackListed")
-                    let flags = if parts.len() > 5 {
-                        parts[5..].iter().map(|s| s.to_string()).collect()
-                    } else {
-                        Vec::new()
+                    // Fixed numbers are written out in full (0xFFFFFFFB); the others must fit
+                    // below the escape bytes that introduce the next frequency.
+                    let valid = match frequency {
+                        Frequency::High | Frequency::Medium => (1..0xFF).contains(&id),
+                        Frequency::Low => (1..0xFF00).contains(&id),
+                        Frequency::Fixed => id >= 0xFFFF_FF00,
                     };
+                    if !valid {
+                        return Err(format!("Message number {:#X} out of range for {:?} at line {}", id, frequency, line_num));
+                    }
+
+                    // Collect any additional flags (like "UDPBlackListed")
+                    let flags: Vec<String> = parts[5..].iter().map(|s| s.to_string()).collect();
+                    if let Some(flag) = flags.iter().find(|f| !MESSAGE_FLAGS.contains(&f.as_str())) {
+                        return Err(format!("Unknown message flag at line {}: {}", line_num, flag));
+                    }
                     
                     current_message = Some(MessageDefinition {
                         name,
@@ -396,6 +425,48 @@ mod tests {
         assert_eq!(message.flags, vec!["UDPBlackListed".to_string()]);
     }
 
+    #[test]
+    fn test_parse_upstream_tags_and_number_ranges() {
+        let input = r#"
+{
+	CloseCircuit Fixed 0xFFFFFFFD NotTrusted Unencoded
+}
+{
+	ParcelRename Low 359 Trusted Unencoded UDPDeprecated
+	{
+		ParcelData Variable
+		{	ParcelID	LLUUID	}
+		{	NewName		Variable	1	}	// string
+	}
+}
+{
+	GroupNoticeAdd Low 341 NotTrusted Unencoded Deprecated
+	{
+		AgentData Single
+		{	AgentID		LLUUID	}
+	}
+}
+"#;
+
+        let template = parse(input).unwrap();
+        let [close, rename, notice] = [0, 1, 2].map(|i| &template.messages[i]);
+        assert_eq!((close.id, close.blocks.len()), (0xFFFFFFFD, 0));
+        assert!(rename.is_deprecated() && !rename.is_udp_blacklisted());
+        assert_eq!(rename.blocks[0].fields[1].type_name, "Variable 1");
+        assert_eq!(notice.flags, vec!["Deprecated".to_string()]);
+
+        let bad_flag = "{\n TestMessage Low 1 NotTrusted Zerocoded Obsolete\n}";
+        assert!(parse(bad_flag).unwrap_err().contains("Unknown message flag"));
+        let out_of_range = [
+            "PacketAck Fixed 251 NotTrusted Unencoded",
+            "TestMessage High 255 NotTrusted Unencoded",
+            "TestMessage Low 65281 NotTrusted Unencoded",
+        ];
+        for header in out_of_range {
+            assert!(parse(&format!("{{\n {}\n}}", header)).unwrap_err().contains("out of range"), "{}", header);
+        }
+    }
+
     #[test]
     fn test_parse_comments_ignored() {
         let input = r#"
@@ -416,6 +487,28 @@ version 2.0
         assert_eq!(result.messages.len(), 1);
     }
 
+    #[test]
+    fn test_parse_trailing_comments_ignored() {
+        let input = r#"
+{
+    ObjectUpdateCached High 14 Trusted Unencoded
+    {
+        ObjectData Variable
+        {   ID          U32 }
+        {   CRC         U32 }   // CRC of the cached object
+        {   UpdateFlags U32 }   // see object_flags.h
+    }
+}
+"#;
+
+        let result = parse(input).unwrap();
+        let block = &result.messages[0].blocks[0];
+        assert_eq!(block.fields.len(), 3);
+        assert_eq!(block.fields[1].name, "CRC");
+        assert_eq!(block.fields[1].type_name, "U32");
+        assert_eq!(block.fields[2].type_name, "U32");
+    }
+
     #[test]
     fn test_parse_actual_message_template() {
         let content = std::fs::read_to_string("message_template.msg");
@@ -0,0 +1,384 @@
+//! Primitive LLUDP field encoding shared by the generated and template-driven codecs.
+//!
+//! Everything is little-endian except IPPORT, which goes over the wire in network order.
+//! LLQuaternion is packed as x/y/z of a normalized quaternion; w is reconstructed on read.
+
+use std::fmt;
+use std::net::Ipv4Addr;
+use bytes::BufMut;
+use uuid::Uuid;
+
+#[derive(Debug, thiserror::Error)]
+pub enum CodecError {
+    #[error("unexpected end of message: needed {needed} bytes at offset {offset}")]
+    UnexpectedEof { offset: usize, needed: usize },
+    #[error("unknown message number {0}")]
+    UnknownMessage(MessageNumber),
+    #[error("block {block} expects {expected} entries, got {actual}")]
+    BlockCount { block: String, expected: usize, actual: usize },
+    #[error("field {field} is {len} bytes, limit is {max}")]
+    FieldTooLong { field: String, len: usize, max: usize },
+    #[error("{0}")]
+    Malformed(String),
+}
+
+impl From<CodecError> for std::io::Error {
+    fn from(e: CodecError) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, e)
+    }
+}
+
+/// Message number as it appears on the wire.
+/// High = 1 byte, Medium = 0xFF + 1 byte, Low = 0xFF 0xFF + u16 (big-endian), Fixed = 0xFF 0xFF 0xFF + 1 byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MessageNumber {
+    High(u8),
+    Medium(u8),
+    Low(u16),
+    Fixed(u8),
+}
+
+impl MessageNumber {
+    /// Read a message number from the start of a message body.
+    pub fn read(r: &mut WireReader<'_>) -> Result<Self, CodecError> {
+        let b0 = r.read_u8()?;
+        if b0 != 0xFF {
+            return Ok(MessageNumber::High(b0));
+        }
+        let b1 = r.read_u8()?;
+        if b1 != 0xFF {
+            return Ok(MessageNumber::Medium(b1));
+        }
+        let b2 = r.read_u8()?;
+        let b3 = r.read_u8()?;
+        if b2 == 0xFF {
+            Ok(MessageNumber::Fixed(b3))
+        } else {
+            Ok(MessageNumber::Low(u16::from_be_bytes([b2, b3])))
+        }
+    }
+
+    pub fn write(&self, w: &mut WireWriter) {
+        match *self {
+            MessageNumber::High(id) => w.put_u8(id),
+            MessageNumber::Medium(id) => {
+                w.put_u8(0xFF);
+                w.put_u8(id);
+            }
+            MessageNumber::Low(id) => {
+                w.put_u8(0xFF);
+                w.put_u8(0xFF);
+                w.put_bytes(&id.to_be_bytes());
+            }
+            MessageNumber::Fixed(id) => {
+                w.put_bytes(&[0xFF, 0xFF, 0xFF, id]);
+            }
+        }
+    }
+
+    /// Number of bytes the message number occupies on the wire.
+    pub fn encoded_len(&self) -> usize {
+        match self {
+            MessageNumber::High(_) => 1,
+            MessageNumber::Medium(_) => 2,
+            MessageNumber::Low(_) | MessageNumber::Fixed(_) => 4,
+        }
+    }
+}
+
+impl fmt::Display for MessageNumber {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MessageNumber::High(id) => write!(f, "High {}", id),
+            MessageNumber::Medium(id) => write!(f, "Medium {}", id),
+            MessageNumber::Low(id) => write!(f, "Low {}", id),
+            MessageNumber::Fixed(id) => write!(f, "Fixed 0xFFFFFF{:02X}", id),
+        }
+    }
+}
+
+/// Cursor over a decoded (non-zerocoded) message body.
+pub struct WireReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> WireReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.data.len() - self.pos
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    pub fn rest(&self) -> &'a [u8] {
+        &self.data[self.pos..]
+    }
+
+    pub fn read_bytes(&mut self, n: usize) -> Result<&'a [u8], CodecError> {
+        if self.remaining() < n {
+            return Err(CodecError::UnexpectedEof { offset: self.pos, needed: n });
+        }
+        let out = &self.data[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(out)
+    }
+
+    pub fn read_fixed<const N: usize>(&mut self) -> Result<[u8; N], CodecError> {
+        let mut out = [0u8; N];
+        out.copy_from_slice(self.read_bytes(N)?);
+        Ok(out)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, CodecError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, CodecError> {
+        Ok(u16::from_le_bytes(self.read_fixed()?))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, CodecError> {
+        Ok(u32::from_le_bytes(self.read_fixed()?))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, CodecError> {
+        Ok(u64::from_le_bytes(self.read_fixed()?))
+    }
+
+    pub fn read_i8(&mut self) -> Result<i8, CodecError> {
+        Ok(self.read_u8()? as i8)
+    }
+
+    pub fn read_i16(&mut self) -> Result<i16, CodecError> {
+        Ok(i16::from_le_bytes(self.read_fixed()?))
+    }
+
+    pub fn read_i32(&mut self) -> Result<i32, CodecError> {
+        Ok(i32::from_le_bytes(self.read_fixed()?))
+    }
+
+    pub fn read_i64(&mut self) -> Result<i64, CodecError> {
+        Ok(i64::from_le_bytes(self.read_fixed()?))
+    }
+
+    pub fn read_f32(&mut self) -> Result<f32, CodecError> {
+        Ok(f32::from_le_bytes(self.read_fixed()?))
+    }
+
+    pub fn read_f64(&mut self) -> Result<f64, CodecError> {
+        Ok(f64::from_le_bytes(self.read_fixed()?))
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, CodecError> {
+        Ok(self.read_u8()? != 0)
+    }
+
+    pub fn read_uuid(&mut self) -> Result<Uuid, CodecError> {
+        Ok(Uuid::from_bytes(self.read_fixed()?))
+    }
+
+    pub fn read_vec3(&mut self) -> Result<glam::Vec3, CodecError> {
+        Ok(glam::Vec3::new(self.read_f32()?, self.read_f32()?, self.read_f32()?))
+    }
+
+    pub fn read_vec3d(&mut self) -> Result<glam::DVec3, CodecError> {
+        Ok(glam::DVec3::new(self.read_f64()?, self.read_f64()?, self.read_f64()?))
+    }
+
+    pub fn read_vec4(&mut self) -> Result<glam::Vec4, CodecError> {
+        Ok(glam::Vec4::new(self.read_f32()?, self.read_f32()?, self.read_f32()?, self.read_f32()?))
+    }
+
+    pub fn read_quat(&mut self) -> Result<glam::Quat, CodecError> {
+        let x = self.read_f32()?;
+        let y = self.read_f32()?;
+        let z = self.read_f32()?;
+        let w = (1.0 - (x * x + y * y + z * z)).max(0.0).sqrt();
+        Ok(glam::Quat::from_xyzw(x, y, z, w))
+    }
+
+    /// IPADDR is stored in network order, so the octets read straight off the wire.
+    pub fn read_ipaddr(&mut self) -> Result<Ipv4Addr, CodecError> {
+        Ok(Ipv4Addr::from(self.read_fixed::<4>()?))
+    }
+
+    pub fn read_ipport(&mut self) -> Result<u16, CodecError> {
+        Ok(u16::from_be_bytes(self.read_fixed()?))
+    }
+
+    pub fn read_variable1(&mut self) -> Result<Vec<u8>, CodecError> {
+        let len = self.read_u8()? as usize;
+        Ok(self.read_bytes(len)?.to_vec())
+    }
+
+    pub fn read_variable2(&mut self) -> Result<Vec<u8>, CodecError> {
+        let len = self.read_u16()? as usize;
+        Ok(self.read_bytes(len)?.to_vec())
+    }
+
+    /// Repetition count prefix of a Variable block.
+    pub fn read_block_count(&mut self) -> Result<usize, CodecError> {
+        Ok(self.read_u8()? as usize)
+    }
+}
+
+/// Growable buffer for encoding message bodies.
+#[derive(Debug, Default)]
+pub struct WireWriter {
+    buf: Vec<u8>,
+}
+
+impl WireWriter {
+    pub fn new() -> Self {
+        Self { buf: Vec::new() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.buf
+    }
+
+    pub fn into_inner(self) -> Vec<u8> {
+        self.buf
+    }
+
+    pub fn put_bytes(&mut self, data: &[u8]) {
+        self.buf.put_slice(data);
+    }
+
+    pub fn put_u8(&mut self, v: u8) {
+        self.buf.put_u8(v);
+    }
+
+    pub fn put_u16(&mut self, v: u16) {
+        self.buf.put_u16_le(v);
+    }
+
+    pub fn put_u32(&mut self, v: u32) {
+        self.buf.put_u32_le(v);
+    }
+
+    pub fn put_u64(&mut self, v: u64) {
+        self.buf.put_u64_le(v);
+    }
+
+    pub fn put_i8(&mut self, v: i8) {
+        self.buf.put_i8(v);
+    }
+
+    pub fn put_i16(&mut self, v: i16) {
+        self.buf.put_i16_le(v);
+    }
+
+    pub fn put_i32(&mut self, v: i32) {
+        self.buf.put_i32_le(v);
+    }
+
+    pub fn put_i64(&mut self, v: i64) {
+        self.buf.put_i64_le(v);
+    }
+
+    pub fn put_f32(&mut self, v: f32) {
+        self.buf.put_f32_le(v);
+    }
+
+    pub fn put_f64(&mut self, v: f64) {
+        self.buf.put_f64_le(v);
+    }
+
+    pub fn put_bool(&mut self, v: bool) {
+        self.buf.put_u8(v as u8);
+    }
+
+    pub fn put_uuid(&mut self, v: &Uuid) {
+        self.buf.put_slice(v.as_bytes());
+    }
+
+    pub fn put_vec3(&mut self, v: glam::Vec3) {
+        self.put_f32(v.x);
+        self.put_f32(v.y);
+        self.put_f32(v.z);
+    }
+
+    pub fn put_vec3d(&mut self, v: glam::DVec3) {
+        self.put_f64(v.x);
+        self.put_f64(v.y);
+        self.put_f64(v.z);
+    }
+
+    pub fn put_vec4(&mut self, v: glam::Vec4) {
+        self.put_f32(v.x);
+        self.put_f32(v.y);
+        self.put_f32(v.z);
+        self.put_f32(v.w);
+    }
+
+    pub fn put_quat(&mut self, q: glam::Quat) {
+        // Only x/y/z are sent; the receiver assumes a normalized quaternion with w >= 0.
+        let q = if q.length_squared() > 0.0 { q.normalize() } else { glam::Quat::IDENTITY };
+        let q = if q.w < 0.0 { -q } else { q };
+        self.put_f32(q.x);
+        self.put_f32(q.y);
+        self.put_f32(q.z);
+    }
+
+    pub fn put_ipaddr(&mut self, v: Ipv4Addr) {
+        self.buf.put_slice(&v.octets());
+    }
+
+    pub fn put_ipport(&mut self, v: u16) {
+        self.buf.put_u16(v);
+    }
+
+    pub fn put_variable1(&mut self, field: &str, data: &[u8]) -> Result<(), CodecError> {
+        if data.len() > u8::MAX as usize {
+            return Err(CodecError::FieldTooLong { field: field.to_string(), len: data.len(), max: u8::MAX as usize });
+        }
+        self.put_u8(data.len() as u8);
+        self.put_bytes(data);
+        Ok(())
+    }
+
+    pub fn put_variable2(&mut self, field: &str, data: &[u8]) -> Result<(), CodecError> {
+        if data.len() > u16::MAX as usize {
+            return Err(CodecError::FieldTooLong { field: field.to_string(), len: data.len(), max: u16::MAX as usize });
+        }
+        self.put_u16(data.len() as u16);
+        self.put_bytes(data);
+        Ok(())
+    }
+
+    /// Write the repetition count of a Variable block.
+    pub fn put_block_count(&mut self, block: &str, count: usize) -> Result<(), CodecError> {
+        if count > u8::MAX as usize {
+            return Err(CodecError::BlockCount { block: block.to_string(), expected: u8::MAX as usize, actual: count });
+        }
+        self.put_u8(count as u8);
+        Ok(())
+    }
+
+    /// Multiple blocks have no count on the wire, so the number of entries must match the template exactly.
+    pub fn check_multiple(&self, block: &str, expected: usize, actual: usize) -> Result<(), CodecError> {
+        if expected != actual {
+            return Err(CodecError::BlockCount { block: block.to_string(), expected, actual });
+        }
+        Ok(())
+    }
+}
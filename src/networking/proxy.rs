@@ -0,0 +1,112 @@
+//! HTTP(S) and UDP proxying driven by [`ProxySettings`].
+//!
+//! HTTP requests (login, capabilities, event queue) go through `http_host:http_port` and UDP
+//! goes through the SOCKS5 proxy at `socks5_host:socks5_port`, each only when proxying is
+//! enabled and the host is set. Intercepting proxies such as Hippolyzer re-sign TLS with their
+//! own CA; point `ca_bundle_path` at its PEM file, or turn off certificate validation.
+
+use std::io;
+use std::path::PathBuf;
+
+use crate::networking::socks5_udp::Socks5Credentials;
+use crate::ui::proxy::ProxySettings;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ProxyError {
+    #[error("invalid HTTP proxy {url}: {source}")]
+    InvalidProxy { url: String, source: reqwest::Error },
+    #[error("cannot read CA bundle {}: {source}", path.display())]
+    CaBundle { path: PathBuf, source: io::Error },
+    #[error("invalid certificate in CA bundle {}: {message}", path.display())]
+    Certificate { path: PathBuf, message: String },
+    #[error("failed to build HTTP client: {0}")]
+    Client(reqwest::Error),
+}
+
+impl ProxySettings {
+    /// Proxy URL for HTTP(S) traffic, if HTTP proxying is on.
+    pub fn http_proxy_url(&self) -> Option<String> {
+        if !self.enabled || self.http_host.trim().is_empty() {
+            return None;
+        }
+        let host = self.http_host.trim();
+        Some(if host.contains("://") { format!("{}:{}", host, self.http_port) } else { format!("http://{}:{}", host, self.http_port) })
+    }
+
+    /// SOCKS5 proxy for UDP, if UDP proxying is on.
+    pub fn socks5_proxy(&self) -> Option<(&str, u16)> {
+        let host = self.socks5_host.trim();
+        (self.enabled && !host.is_empty()).then_some((host, self.socks5_port))
+    }
+
+    /// SOCKS5 credentials, if a username is set.
+    pub fn socks5_credentials(&self) -> Option<Socks5Credentials> {
+        (!self.socks5_username.is_empty())
+            .then(|| Socks5Credentials { username: self.socks5_username.clone(), password: self.socks5_password.clone() })
+    }
+}
+
+/// Certificates in a PEM bundle, in file order.
+fn pem_certificates(pem: &str) -> impl Iterator<Item = &str> {
+    const END: &str = "-----END CERTIFICATE-----";
+    pem.match_indices("-----BEGIN CERTIFICATE-----").filter_map(move |(start, _)| {
+        let end = pem[start..].find(END)? + start + END.len();
+        Some(&pem[start..end])
+    })
+}
+
+/// An HTTP client configured from the proxy settings; without settings, a direct client.
+pub fn http_client(settings: Option<&ProxySettings>) -> Result<reqwest::Client, ProxyError> {
+    let mut builder = reqwest::Client::builder();
+    let Some(settings) = settings else {
+        return builder.build().map_err(ProxyError::Client);
+    };
+    if let Some(url) = settings.http_proxy_url() {
+        let proxy = reqwest::Proxy::all(&url).map_err(|source| ProxyError::InvalidProxy { url, source })?;
+        builder = builder.proxy(proxy);
+    }
+    let ca_bundle = settings.ca_bundle_path.trim();
+    if settings.enabled && !ca_bundle.is_empty() {
+        let path = PathBuf::from(ca_bundle);
+        let pem = std::fs::read_to_string(&path).map_err(|source| ProxyError::CaBundle { path: path.clone(), source })?;
+        let mut found = false;
+        for cert in pem_certificates(&pem) {
+            let cert = reqwest::Certificate::from_pem(cert.as_bytes())
+                .map_err(|e| ProxyError::Certificate { path: path.clone(), message: e.to_string() })?;
+            builder = builder.add_root_certificate(cert);
+            found = true;
+        }
+        if !found {
+            return Err(ProxyError::Certificate { path, message: "no PEM certificates found".to_string() });
+        }
+    }
+    if settings.enabled && settings.disable_cert_validation {
+        builder = builder.danger_accept_invalid_certs(true);
+    }
+    builder.build().map_err(ProxyError::Client)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_proxy_selection_and_pem_split() {
+        let mut settings = ProxySettings { http_host: "127.0.0.1".to_string(), http_port: 9062, ..Default::default() };
+        assert_eq!(settings.http_proxy_url(), None);
+        settings.enabled = true;
+        assert_eq!(settings.http_proxy_url().as_deref(), Some("http://127.0.0.1:9062"));
+        assert_eq!(settings.socks5_proxy(), None);
+        settings.socks5_host = "proxy.lan".to_string();
+        settings.socks5_port = 1080;
+        assert_eq!(settings.socks5_proxy(), Some(("proxy.lan", 1080)));
+        settings.socks5_password = "hunter22".to_string();
+        assert!(!format!("{:?}", settings).contains("hunter22"));
+        assert!(!toml::to_string(&settings).unwrap().contains("hunter22"));
+
+        let pem = "junk\n-----BEGIN CERTIFICATE-----\nAAA\n-----END CERTIFICATE-----\n-----BEGIN CERTIFICATE-----\nBBB\n-----END CERTIFICATE-----\n";
+        let certs: Vec<&str> = pem_certificates(pem).collect();
+        assert_eq!(certs.len(), 2);
+        assert!(certs[1].contains("BBB") && certs[1].ends_with("-----END CERTIFICATE-----"));
+    }
+}
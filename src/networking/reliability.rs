@@ -0,0 +1,266 @@
+//! LLUDP reliability bookkeeping for a single circuit.
+//!
+//! Tracks our unacked reliable packets (resent with the RESENT flag on an RTT-adaptive timer),
+//! the acks we owe the simulator (sent appended to outgoing packets or coalesced into PacketAck),
+//! and a window of recently received reliable sequence numbers used to drop duplicates.
+//! Time is passed in explicitly so the state machine can be tested without a socket.
+
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use tokio::time::{Duration, Instant};
+
+use crate::networking::protocol::packet::{Packet, PacketFlags, MAX_APPENDED_ACKS};
+
+#[derive(Debug, Clone)]
+pub struct ReliabilityConfig {
+    /// Lower bound for the resend timeout, also used before the first RTT sample.
+    pub min_rto: Duration,
+    /// Upper bound for the resend timeout, including backoff.
+    pub max_rto: Duration,
+    /// Resends allowed per packet before the circuit is considered dead.
+    pub max_resends: u32,
+    /// Number of recent reliable sequence numbers remembered for duplicate suppression.
+    pub duplicate_window: usize,
+}
+
+impl Default for ReliabilityConfig {
+    fn default() -> Self {
+        Self {
+            min_rto: Duration::from_millis(200),
+            max_rto: Duration::from_secs(5),
+            max_resends: 5,
+            duplicate_window: 1024,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Unacked {
+    packet: Packet,
+    last_sent: Instant,
+    resends: u32,
+}
+
+/// Result of polling the resend timers.
+#[derive(Debug, Default)]
+pub struct ResendBatch {
+    /// Packets to send again, already flagged RESENT.
+    pub resend: Vec<Packet>,
+    /// Sequence numbers that ran out of resends; non-empty means the circuit is dead.
+    pub expired: Vec<u32>,
+}
+
+#[derive(Debug)]
+pub struct ReliabilityTracker {
+    config: ReliabilityConfig,
+    unacked: BTreeMap<u32, Unacked>,
+    pending_acks: Vec<u32>,
+    seen: VecDeque<u32>,
+    seen_set: HashSet<u32>,
+    srtt: Option<Duration>,
+    rttvar: Duration,
+}
+
+impl ReliabilityTracker {
+    pub fn new(config: ReliabilityConfig) -> Self {
+        Self {
+            config,
+            unacked: BTreeMap::new(),
+            pending_acks: Vec::new(),
+            seen: VecDeque::new(),
+            seen_set: HashSet::new(),
+            srtt: None,
+            rttvar: Duration::ZERO,
+        }
+    }
+
+    pub fn config(&self) -> &ReliabilityConfig {
+        &self.config
+    }
+
+    /// Remember a reliable packet we just sent. Appended acks are not kept; a resend carries only the body.
+    pub fn track(&mut self, packet: &Packet, now: Instant) {
+        let mut stored = packet.clone();
+        stored.acks.clear();
+        self.unacked.insert(packet.sequence, Unacked { packet: stored, last_sent: now, resends: 0 });
+    }
+
+    /// Handle an ack from the simulator. Returns false if the sequence was not outstanding.
+    pub fn ack(&mut self, sequence: u32, now: Instant) -> bool {
+        match self.unacked.remove(&sequence) {
+            Some(entry) => {
+                // Karn's algorithm: only packets that were never resent give a usable sample.
+                if entry.resends == 0 {
+                    self.sample_rtt(now.saturating_duration_since(entry.last_sent));
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Record an incoming packet. Reliable packets are queued for acking even when they are
+    /// duplicates (our previous ack may have been lost). Returns false for a duplicate.
+    pub fn receive(&mut self, sequence: u32, reliable: bool) -> bool {
+        if !reliable {
+            return true;
+        }
+        self.pending_acks.push(sequence);
+        if self.seen_set.contains(&sequence) {
+            return false;
+        }
+        self.seen.push_back(sequence);
+        self.seen_set.insert(sequence);
+        while self.seen.len() > self.config.duplicate_window {
+            if let Some(old) = self.seen.pop_front() {
+                self.seen_set.remove(&old);
+            }
+        }
+        true
+    }
+
+    pub fn has_pending_acks(&self) -> bool {
+        !self.pending_acks.is_empty()
+    }
+
+    /// Take up to `max` owed acks, e.g. to append to an outgoing packet.
+    pub fn take_pending_acks(&mut self, max: usize) -> Vec<u32> {
+        let n = max.min(self.pending_acks.len());
+        self.pending_acks.drain(..n).collect()
+    }
+
+    /// Take all owed acks, split into PacketAck-sized batches.
+    pub fn take_ack_batches(&mut self) -> Vec<Vec<u32>> {
+        let acks = std::mem::take(&mut self.pending_acks);
+        acks.chunks(MAX_APPENDED_ACKS).map(|c| c.to_vec()).collect()
+    }
+
+    /// Current resend timeout (RFC 6298 style), before per-packet backoff.
+    pub fn rto(&self) -> Duration {
+        let rto = match self.srtt {
+            Some(srtt) => srtt + (self.rttvar * 4).max(Duration::from_millis(10)),
+            None => self.config.min_rto,
+        };
+        rto.clamp(self.config.min_rto, self.config.max_rto)
+    }
+
+    pub fn srtt(&self) -> Option<Duration> {
+        self.srtt
+    }
+
+    pub fn unacked_count(&self) -> usize {
+        self.unacked.len()
+    }
+
+    /// Lowest outstanding reliable sequence number, reported in StartPingCheck.
+    pub fn oldest_unacked(&self) -> Option<u32> {
+        self.unacked.keys().next().copied()
+    }
+
+    /// Collect packets whose timer has expired. Each resend doubles that packet's timeout.
+    pub fn poll_resends(&mut self, now: Instant) -> ResendBatch {
+        let rto = self.rto();
+        let mut batch = ResendBatch::default();
+        for (&sequence, entry) in self.unacked.iter_mut() {
+            let timeout = (rto * 2u32.saturating_pow(entry.resends)).min(self.config.max_rto);
+            if now.saturating_duration_since(entry.last_sent) < timeout {
+                continue;
+            }
+            if entry.resends >= self.config.max_resends {
+                batch.expired.push(sequence);
+                continue;
+            }
+            entry.resends += 1;
+            entry.last_sent = now;
+            entry.packet.flags |= PacketFlags::RESENT;
+            batch.resend.push(entry.packet.clone());
+        }
+        for sequence in &batch.expired {
+            self.unacked.remove(sequence);
+        }
+        batch
+    }
+
+    fn sample_rtt(&mut self, rtt: Duration) {
+        match self.srtt {
+            None => {
+                self.srtt = Some(rtt);
+                self.rttvar = rtt / 2;
+            }
+            Some(srtt) => {
+                let delta = srtt.abs_diff(rtt);
+                self.rttvar = (self.rttvar * 3 + delta) / 4;
+                self.srtt = Some((srtt * 7 + rtt) / 8);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reliable(sequence: u32) -> Packet {
+        Packet::new(PacketFlags::RELIABLE, sequence, vec![0x01, 0x00, 0x00, 0x00, 0x00, 0x00])
+    }
+
+    #[test]
+    fn test_duplicates_dropped_but_reacked() {
+        let mut tracker = ReliabilityTracker::new(ReliabilityConfig::default());
+        assert!(tracker.receive(10, true));
+        assert!(!tracker.receive(10, true));
+        assert!(tracker.receive(11, false));
+        assert_eq!(tracker.take_pending_acks(255), vec![10, 10]);
+        assert!(!tracker.has_pending_acks());
+    }
+
+    #[test]
+    fn test_duplicate_window_forgets_old_sequences() {
+        let config = ReliabilityConfig { duplicate_window: 2, ..Default::default() };
+        let mut tracker = ReliabilityTracker::new(config);
+        tracker.receive(1, true);
+        tracker.receive(2, true);
+        tracker.receive(3, true);
+        assert!(tracker.receive(1, true));
+        assert!(!tracker.receive(3, true));
+    }
+
+    #[test]
+    fn test_resend_sets_flag_and_expires() {
+        let config = ReliabilityConfig { max_resends: 2, ..Default::default() };
+        let mut tracker = ReliabilityTracker::new(config);
+        let start = Instant::now();
+        tracker.track(&reliable(5), start);
+
+        assert!(tracker.poll_resends(start + Duration::from_millis(50)).resend.is_empty());
+        let first = tracker.poll_resends(start + Duration::from_millis(200));
+        assert_eq!(first.resend.len(), 1);
+        assert!(first.resend[0].is_resent());
+
+        // Backoff: the second resend waits twice as long.
+        assert!(tracker.poll_resends(start + Duration::from_millis(500)).resend.is_empty());
+        assert_eq!(tracker.poll_resends(start + Duration::from_millis(600)).resend.len(), 1);
+
+        let dead = tracker.poll_resends(start + Duration::from_secs(5));
+        assert_eq!(dead.expired, vec![5]);
+        assert_eq!(tracker.unacked_count(), 0);
+    }
+
+    #[test]
+    fn test_ack_updates_rtt_and_ack_batches_split() {
+        let mut tracker = ReliabilityTracker::new(ReliabilityConfig::default());
+        let start = Instant::now();
+        tracker.track(&reliable(1), start);
+        assert!(tracker.ack(1, start + Duration::from_millis(80)));
+        assert!(!tracker.ack(1, start + Duration::from_millis(90)));
+        assert_eq!(tracker.srtt(), Some(Duration::from_millis(80)));
+        assert_eq!(tracker.rto(), Duration::from_millis(240));
+
+        for seq in 0..300 {
+            tracker.receive(seq, true);
+        }
+        let batches = tracker.take_ack_batches();
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[0].len(), MAX_APPENDED_ACKS);
+        assert_eq!(batches[1].len(), 300 - MAX_APPENDED_ACKS);
+    }
+}
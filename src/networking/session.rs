@@ -1,22 +1,23 @@
 use reqwest::Client;
-use reqwest::Url;
 use serde::{Serialize, Deserialize};
 use quick_xml::de::from_str;
 use quick_xml::events::Event;
 use quick_xml::Reader;
 use quick_xml::name::QName;
 use crate::ui::proxy::ProxySettings;
+use crate::config::grids::Grid;
+use crate::utils::platform::{self, MachineIds, PlatformInfo};
+pub use crate::networking::caps::Capabilities;
+use crate::networking::caps::CapsClient;
+use crate::networking::proxy;
+use crate::networking::xmlrpc::{self, Value};
 use tracing::{info, warn};
 use regex::Regex;
-use roxmltree::Document;
 use std::str::FromStr;
-use std::collections::HashMap;
 use md5;
-use std::fs;
-use reqwest::Certificate;
-use std::sync::Arc;
+use uuid::Uuid;
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, Clone)]
 pub struct LoginRequest {
     pub first: String,
     pub last: String,
@@ -43,6 +44,36 @@ pub struct LoginRequest {
 }
 
 impl LoginRequest {
+    /// A request for `first last` reporting this build, OS and the given machine ids, starting
+    /// at the last location with the default options.
+    pub fn new(first: impl Into<String>, last: impl Into<String>, password: impl Into<String>, ids: &MachineIds) -> Self {
+        let platform = PlatformInfo::detect();
+        Self {
+            first: first.into(),
+            last: last.into(),
+            password: password.into(),
+            start: "last".to_string(),
+            channel: platform::CHANNEL.to_string(),
+            version: platform::viewer_version(),
+            platform: platform.platform,
+            platform_string: platform.platform_string,
+            platform_version: platform.platform_version,
+            mac: ids.mac.clone(),
+            id0: ids.id0.clone(),
+            agree_to_tos: 0,
+            address_size: 64,
+            extended_errors: 1,
+            host_id: String::new(),
+            last_exec_duration: 0,
+            last_exec_event: 0,
+            last_exec_session_id: Uuid::nil().to_string(),
+            mfa_hash: String::new(),
+            token: String::new(),
+            read_critical: 0,
+            options: Self::default_options(),
+        }
+    }
+
     pub fn default_options() -> Vec<String> {
         vec![
             "inventory-root", "inventory-skeleton", "inventory-lib-root", "inventory-lib-owner",
@@ -52,13 +83,71 @@ impl LoginRequest {
             "voice-config", "tutorial_setting", "login-flags", "global-textures"
         ].into_iter().map(String::from).collect()
     }
+
+    /// The `login_to_simulator` parameter struct. The password is sent as `$1$` + its MD5.
+    pub fn to_xmlrpc(&self) -> Value {
+        [
+            ("address_size", Value::from(self.address_size)),
+            ("agree_to_tos", Value::from(self.agree_to_tos)),
+            ("channel", Value::from(self.channel.as_str())),
+            ("extended_errors", Value::from(self.extended_errors)),
+            ("first", Value::from(self.first.as_str())),
+            ("host_id", Value::from(self.host_id.as_str())),
+            ("id0", Value::from(self.id0.as_str())),
+            ("last", Value::from(self.last.as_str())),
+            ("last_exec_duration", Value::from(self.last_exec_duration)),
+            ("last_exec_event", Value::from(self.last_exec_event)),
+            ("last_exec_session_id", Value::from(self.last_exec_session_id.as_str())),
+            ("mac", Value::from(self.mac.as_str())),
+            ("mfa_hash", Value::from(self.mfa_hash.as_str())),
+            ("options", Value::Array(self.options.iter().map(|opt| Value::from(opt.as_str())).collect())),
+            ("passwd", Value::from(hash_password(&self.password))),
+            ("platform", Value::from(self.platform.as_str())),
+            ("platform_string", Value::from(self.platform_string.as_str())),
+            ("platform_version", Value::from(self.platform_version.as_str())),
+            ("read_critical", Value::from(self.read_critical)),
+            ("start", Value::from(self.start.as_str())),
+            ("token", Value::from(self.token.as_str())),
+            ("version", Value::from(self.version.as_str())),
+        ]
+        .into_iter()
+        .collect()
+    }
 }
 
-#[derive(Debug, Clone)]
-// Stores the capability name->URL map returned from the capabilities POST.
-// TODO: If we use these capabilities a lot, consider adding helper methods for common lookups.
-pub struct Capabilities {
-    pub map: HashMap<String, String>,
+impl LoginRequest {
+    /// Retry after [`LoginOutcome::TosRequired`] with the terms accepted.
+    pub fn with_tos_accepted(mut self) -> Self {
+        self.agree_to_tos = 1;
+        self
+    }
+
+    /// Retry after [`LoginOutcome::CriticalMessage`] with the message acknowledged.
+    pub fn with_critical_read(mut self) -> Self {
+        self.read_critical = 1;
+        self
+    }
+
+    /// Retry after [`LoginOutcome::MfaChallenge`] with the one-time code the user entered.
+    pub fn with_mfa_token(mut self, token: impl Into<String>) -> Self {
+        self.token = token.into();
+        self
+    }
+
+    /// Copy safe to log: the password hash, MFA token and MFA hash are blanked out.
+    pub fn redacted(&self) -> Self {
+        let redact = |value: &str| if value.is_empty() { String::new() } else { "<redacted>".to_string() };
+        Self { password: redact(&self.password), token: redact(&self.token), mfa_hash: redact(&self.mfa_hash), ..self.clone() }
+    }
+}
+
+/// `$1$` + the hex MD5 of the password; an already hashed password is passed through.
+pub fn hash_password(password: &str) -> String {
+    if password.starts_with("$1$") {
+        password.to_string()
+    } else {
+        format!("$1${:x}", md5::compute(password))
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -89,12 +178,152 @@ pub struct LoginSessionInfo {
     pub max_god_level: Option<i32>,
     pub god_level: Option<i32>,
     pub inventory_root: Option<String>,
-    pub buddy_list: Option<Vec<String>>,
+    // One field per `LoginRequest::default_options` entry that has a payload; `display_names`,
+    // `adult_compliant`, `newuser-config` and `advanced-mode` only switch server behaviour on.
+    pub inventory_skeleton: Vec<InventoryFolder>,
+    pub inventory_lib_root: Option<Uuid>,
+    pub inventory_lib_owner: Option<Uuid>,
+    pub inventory_skel_lib: Vec<InventoryFolder>,
+    pub initial_outfit: Option<InitialOutfit>,
+    pub gestures: Vec<Gesture>,
+    pub event_categories: Vec<Category>,
+    pub event_notifications: Vec<EventNotification>,
+    pub classified_categories: Vec<Category>,
+    pub buddy_list: Vec<Buddy>,
+    pub ui_config: Option<UiConfig>,
+    pub max_agent_groups: Option<i32>,
+    pub map_server_url: Option<String>,
+    pub voice_config: Option<VoiceConfig>,
+    pub tutorial_setting: Option<TutorialSetting>,
+    pub login_flags: Option<LoginFlags>,
+    pub global_textures: Option<GlobalTextures>,
+    pub openid_token: Option<String>,
+    pub openid_url: Option<String>,
+    /// Returned after a successful MFA login; send it as `mfa_hash` next time to skip the challenge.
+    pub mfa_hash: Option<String>,
     pub capabilities: Option<Capabilities>,
-    // TODO: Add more fields as needed (inventory-skeleton, gestures, event_categories, etc.)
     pub session_cookie: Option<String>, // Stores agni_sl_session_id for later use
 }
 
+/// A folder from `inventory-skeleton` or `inventory-skel-lib`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InventoryFolder {
+    pub folder_id: Uuid,
+    pub parent_id: Uuid,
+    pub name: String,
+    /// Preferred asset type of the folder, -1 for none.
+    pub type_default: i32,
+    pub version: i32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InitialOutfit {
+    pub folder_name: String,
+    pub gender: String,
+}
+
+/// An active gesture: the inventory item and the gesture asset it points at.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gesture {
+    pub item_id: Uuid,
+    pub asset_id: Uuid,
+}
+
+/// An `event_categories` or `classified_categories` entry.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Category {
+    pub id: i32,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EventNotification {
+    pub event_id: i32,
+    pub event_name: String,
+    pub event_date: String,
+}
+
+/// A friend and the rights (`FriendRights` bits) granted in each direction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Buddy {
+    pub buddy_id: Uuid,
+    pub rights_given: i32,
+    pub rights_has: i32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UiConfig {
+    pub allow_first_life: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VoiceConfig {
+    pub voice_server_type: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TutorialSetting {
+    pub tutorial_url: String,
+    pub use_tutorial: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoginFlags {
+    pub stipend_since_login: bool,
+    pub ever_logged_in: bool,
+    pub daylight_savings: bool,
+    pub gendered: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GlobalTextures {
+    pub sun_texture_id: Uuid,
+    pub moon_texture_id: Uuid,
+    pub cloud_texture_id: Uuid,
+}
+
+/// How a login attempt ended. Every outcome except `Success` comes from a `login: false`
+/// response; the resumable ones are retried with the amended request (see the `LoginRequest::with_*` helpers).
+#[derive(Debug, Clone)]
+pub enum LoginOutcome {
+    Success(Box<LoginSessionInfo>),
+    /// `reason: tos`; retry with [`LoginRequest::with_tos_accepted`].
+    TosRequired { message: String },
+    /// `reason: critical`; show the message and retry with [`LoginRequest::with_critical_read`].
+    CriticalMessage { message: String },
+    /// `reason: mfa_challenge`; retry with [`LoginRequest::with_mfa_token`].
+    MfaChallenge { message: String },
+    /// `reason: presence`; the grid is still logging out the previous session.
+    AlreadyLoggedIn { message: String },
+    /// `reason: key`; wrong name or password.
+    InvalidCredentials { message: String },
+    /// `reason: update` or `optional`; the grid wants a newer viewer.
+    UpdateRequired { message: String, update_url: Option<String>, optional: bool },
+    /// Any other `login: false` reason.
+    Failed { reason: String, message: String },
+}
+
+impl LoginOutcome {
+    /// Whether retrying with the user's answer can get past this outcome.
+    pub fn is_resumable(&self) -> bool {
+        matches!(self, LoginOutcome::TosRequired { .. } | LoginOutcome::CriticalMessage { .. } | LoginOutcome::MfaChallenge { .. })
+    }
+
+    /// The server's message for a failed login.
+    pub fn message(&self) -> Option<&str> {
+        match self {
+            LoginOutcome::Success(_) => None,
+            LoginOutcome::TosRequired { message }
+            | LoginOutcome::CriticalMessage { message }
+            | LoginOutcome::MfaChallenge { message }
+            | LoginOutcome::AlreadyLoggedIn { message }
+            | LoginOutcome::InvalidCredentials { message }
+            | LoginOutcome::UpdateRequired { message, .. }
+            | LoginOutcome::Failed { message, .. } => Some(message),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum HandshakeState {
     NotStarted,
@@ -108,87 +337,7 @@ pub enum HandshakeState {
 }
 
 fn build_login_xml(req: &LoginRequest) -> String {
-    let options_xml: String = req.options.iter()
-        .map(|opt| format!("<value><string>{}</string></value>", opt))
-        .collect::<Vec<_>>()
-        .join("\n");
-
-    format!(r#"<?xml version="1.0" ?>
-<methodCall>
-  <methodName>login_to_simulator</methodName>
-  <params>
-    <param>
-      <value>
-        <struct>
-          <member><name>address_size</name><value><int>{address_size}</int></value></member>
-          <member><name>agree_to_tos</name><value><int>{agree_to_tos}</int></value></member>
-          <member><name>channel</name><value><string>{channel}</string></value></member>
-          <member><name>extended_errors</name><value><int>{extended_errors}</int></value></member>
-          <member><name>first</name><value><string>{first}</string></value></member>
-          <member><name>host_id</name><value><string>{host_id}</string></value></member>
-          <member><name>id0</name><value><string>{id0}</string></value></member>
-          <member><name>last</name><value><string>{last}</string></value></member>
-          <member><name>last_exec_duration</name><value><int>{last_exec_duration}</int></value></member>
-          <member><name>last_exec_event</name><value><int>{last_exec_event}</int></value></member>
-          <member><name>last_exec_session_id</name><value><string>{last_exec_session_id}</string></value></member>
-          <member><name>mac</name><value><string>{mac}</string></value></member>
-          <member><name>mfa_hash</name><value><string>{mfa_hash}</string></value></member>
-          <member><name>options</name>
-            <value>
-              <array>
-                <data>
-                  {options_xml}
-                </data>
-              </array>
-            </value>
-          </member>
-          <member><name>passwd</name><value><string>{password}</string></value></member>
-          <member><name>platform</name><value><string>{platform}</string></value></member>
-          <member><name>platform_string</name><value><string>{platform_string}</string></value></member>
-          <member><name>platform_version</name><value><string>{platform_version}</string></value></member>
-          <member><name>read_critical</name><value><int>{read_critical}</int></value></member>
-          <member><name>start</name><value><string>{start}</string></value></member>
-          <member><name>token</name><value><string>{token}</string></value></member>
-          <member><name>version</name><value><string>{version}</string></value></member>
-        </struct>
-      </value>
-    </param>
-  </params>
-</methodCall>"#,
-        address_size = req.address_size,
-        agree_to_tos = req.agree_to_tos,
-        channel = req.channel,
-        extended_errors = req.extended_errors,
-        first = req.first,
-        host_id = req.host_id,
-        id0 = req.id0,
-        last = req.last,
-        last_exec_duration = req.last_exec_duration,
-        last_exec_event = req.last_exec_event,
-        last_exec_session_id = req.last_exec_session_id,
-        mac = req.mac,
-        mfa_hash = req.mfa_hash,
-        options_xml = options_xml,
-        password = req.password,
-        platform = req.platform,
-        platform_string = req.platform_string,
-        platform_version = req.platform_version,
-        read_critical = req.read_critical,
-        start = req.start,
-        token = req.token,
-        version = req.version,
-    )
-}
-
-// Helper to always build a reqwest::Client with proxy settings if enabled
-fn build_proxied_client(_proxy_settings: Option<&crate::ui::proxy::ProxySettings>) -> reqwest::Client {
-    let mut builder = reqwest::Client::builder();
-    // Always use the proxy for this test
-    builder = builder.proxy(reqwest::Proxy::all("http://127.0.0.1:9062").unwrap());
-    let ca_cert = fs::read("src/assets/CA.pem").expect("Failed to read Hippolyzer CA cert");
-    let ca_cert = Certificate::from_pem(&ca_cert).expect("Invalid CA cert");
-    builder = builder.add_root_certificate(ca_cert);
-    builder.build().unwrap()
+    xmlrpc::method_call("login_to_simulator", &[req.to_xmlrpc()])
 }
 
 // Add this helper for logging
@@ -208,101 +357,28 @@ fn log_http_request(method: &str, url: &str, proxy_settings: Option<&crate::ui::
     }
 }
 
+/// The body is left out: a login response carries the secure session id and the MFA hash.
 fn log_http_response(status: reqwest::StatusCode, headers: &reqwest::header::HeaderMap, body: &str) {
     println!("[HTTP DEBUG] Response status: {}", status);
     println!("[HTTP DEBUG] Response headers:");
     for (k, v) in headers.iter() {
         println!("  {}: {:?}", k, v);
     }
-    println!("[HTTP DEBUG] Response body (first 512 chars): {}", &body.chars().take(512).collect::<String>());
+    println!("[HTTP DEBUG] Response body: {} bytes", body.len());
 }
 
-pub async fn login_to_secondlife(grid_uri: &str, req: &LoginRequest, proxy_settings: Option<&ProxySettings>, udp_port: u16) -> Result<LoginSessionInfo, String> {
-    info!("[LOGIN] login_to_secondlife called. proxy_settings: {:?}", proxy_settings);
-    // --- Build official viewer-matching fields ---
-    let channel = "Second Life Release".to_string();
-    let version = "7.1.15.15596336374".to_string();
-    let platform = "mac".to_string();
-    let platform_string = "macOS 12.7.4".to_string();
-    let platform_version = "12.7.4".to_string();
-    // Use dummy id0/mac for now (should be real hardware IDs)
-    let id0 = "2cbb24b76a6a40fea1bff24b0ab32d08".to_string();
-    let mac = "b91746d84dada8ffd383e1e9ce32649b".to_string();
-    // Hash password as $1$md5(password)
-    let md5 = format!("{:x}", md5::compute(&req.password));
-    let passwd = format!("$1${}", md5);
-    // Build XML body with these fields
-    let options_xml: String = LoginRequest::default_options().iter()
-        .map(|opt| format!("<value><string>{}</string></value>", opt))
-        .collect::<Vec<_>>()
-        .join("\n");
-    let xml_body = format!(r#"<?xml version="1.0" ?>
-<methodCall>
-  <methodName>login_to_simulator</methodName>
-  <params>
-    <param>
-      <value>
-        <struct>
-          <member><name>address_size</name><value><int>{address_size}</int></value></member>
-          <member><name>agree_to_tos</name><value><int>{agree_to_tos}</int></value></member>
-          <member><name>channel</name><value><string>{channel}</string></value></member>
-          <member><name>extended_errors</name><value><int>{extended_errors}</int></value></member>
-          <member><name>first</name><value><string>{first}</string></value></member>
-          <member><name>host_id</name><value><string>{host_id}</string></value></member>
-          <member><name>id0</name><value><string>{id0}</string></value></member>
-          <member><name>last</name><value><string>{last}</string></value></member>
-          <member><name>last_exec_duration</name><value><int>{last_exec_duration}</int></value></member>
-          <member><name>last_exec_event</name><value><int>{last_exec_event}</int></value></member>
-          <member><name>last_exec_session_id</name><value><string>{last_exec_session_id}</string></value></member>
-          <member><name>mac</name><value><string>{mac}</string></value></member>
-          <member><name>mfa_hash</name><value><string>{mfa_hash}</string></value></member>
-          <member><name>options</name><value><array><data>{options_xml}</data></array></value></member>
-          <member><name>passwd</name><value><string>{passwd}</string></value></member>
-          <member><name>platform</name><value><string>{platform}</string></value></member>
-          <member><name>platform_string</name><value><string>{platform_string}</string></value></member>
-          <member><name>platform_version</name><value><string>{platform_version}</string></value></member>
-          <member><name>read_critical</name><value><int>{read_critical}</int></value></member>
-          <member><name>start</name><value><string>{start}</string></value></member>
-          <member><name>token</name><value><string>{token}</string></value></member>
-          <member><name>version</name><value><string>{version}</string></value></member>
-        </struct>
-      </value>
-    </param>
-  </params>
-</methodCall>"#,
-        address_size = req.address_size,
-        agree_to_tos = req.agree_to_tos,
-        channel = channel,
-        extended_errors = req.extended_errors,
-        first = req.first,
-        host_id = req.host_id,
-        id0 = id0,
-        last = req.last,
-        last_exec_duration = req.last_exec_duration,
-        last_exec_event = req.last_exec_event,
-        last_exec_session_id = req.last_exec_session_id,
-        mac = mac,
-        mfa_hash = req.mfa_hash,
-        options_xml = options_xml,
-        passwd = passwd,
-        platform = platform,
-        platform_string = platform_string,
-        platform_version = platform_version,
-        read_critical = req.read_critical,
-        start = req.start,
-        token = req.token,
-        version = version,
-    );
-    eprintln!("[LOGIN XML BODY]\n{}", xml_body);
-    let client = build_proxied_client(proxy_settings);
+pub async fn login_to_secondlife(grid: &Grid, req: &LoginRequest, proxy_settings: Option<&ProxySettings>, udp_port: u16) -> Result<LoginOutcome, String> {
+    info!("[LOGIN] login_to_secondlife called. grid: {} proxy_settings: {:?}", grid.nick, proxy_settings);
+    let grid_uri = grid.login_uri.as_str();
+    let xml_body = build_login_xml(req);
+    let client = proxy::http_client(proxy_settings).map_err(|e| e.to_string())?;
     println!("[DEBUG] Login POST will use proxy: {:?}", proxy_settings);
     println!("[DEBUG] Login POST URL: {}", grid_uri);
     // --- Actual login POST ---
     let mut req_builder = client
         .post(grid_uri)
         .header("Content-Type", "text/xml")
-        // .header("User-Agent", "Second Life Release 7.1.15 (15596336374)")
-        .header("User-Agent", "SecondLife/7.1.15.15596336374 (Second Life Release; default skin)")
+        .header("User-Agent", format!("SecondLife/{} ({}; default skin)", req.version, req.channel))
         .header("Accept", "*/*")
         .header("Accept-Encoding", "deflate, gzip")
         .header("Connection", "keep-alive")
@@ -312,7 +388,7 @@ pub async fn login_to_secondlife(grid_uri: &str, req: &LoginRequest, proxy_setti
     for (k, v) in request.headers().iter() {
         println!("  {}: {:?}", k, v);
     }
-    log_http_request("POST", grid_uri, proxy_settings, request.headers(), Some(&xml_body));
+    log_http_request("POST", grid_uri, proxy_settings, request.headers(), Some(&build_login_xml(&req.redacted())));
     let res = client.execute(request).await.map_err(|e| format!("HTTP error: {e}"))?;
     let status = res.status();
     let headers = res.headers().clone();
@@ -330,14 +406,13 @@ pub async fn login_to_secondlife(grid_uri: &str, req: &LoginRequest, proxy_setti
     eprintln!("[LOGIN RESPONSE] HTTP status: {}", status);
     // eprintln!("[LOGIN RESPONSE] Raw body:\n{}", filtered_text);
     // eprintln!("[DEBUG] Full login response XML:\n{}", text);
-    match parse_login_response(&text) {
-        Ok(info) => {
+    match parse_login_response(&text)? {
+        LoginOutcome::Success(info) => {
             // --- OpenID/capabilities step: MUST complete OpenID POST before UDP handshake ---
-            if let Some(openid_token) = extract_openid_token(&text) {
-                eprintln!("[DEBUG] Found openid_token: {}", openid_token);
-                let openid_token = openid_token.replace("&amp;", "&");
-                let openid_url = extract_openid_url(&text).unwrap_or_else(|| "https://id.secondlife.com/openid/webkit".to_string());
-                let client = build_proxied_client(proxy_settings);
+            let openid_url = info.openid_url.clone().or_else(|| Some(grid.openid_url.clone()).filter(|url| !url.is_empty()));
+            if let (Some(openid_token), Some(openid_url)) = (info.openid_token.clone(), openid_url) {
+                eprintln!("[DEBUG] Found openid_token");
+                let client = proxy::http_client(proxy_settings).map_err(|e| e.to_string())?;
                 let res = client
                     .post(&openid_url)
                     .header("Content-Type", "application/x-www-form-urlencoded")
@@ -364,8 +439,8 @@ pub async fn login_to_secondlife(grid_uri: &str, req: &LoginRequest, proxy_setti
             }
             // After parsing login response and extracting seed_capability
             // Fetch seed capabilities if not present
-            let mut capabilities = info.capabilities.clone();
-            if capabilities.is_none() {
+            let mut info = info;
+            if info.capabilities.is_none() {
                 match fetch_seed_capabilities(
                     &info.seed_capability,
                     udp_port,
@@ -373,7 +448,7 @@ pub async fn login_to_secondlife(grid_uri: &str, req: &LoginRequest, proxy_setti
                     info.session_cookie.as_deref(),
                 ).await {
                     Ok(caps) => {
-                        capabilities = Some(caps);
+                        info.capabilities = Some(caps);
                     }
                     Err(e) => {
                         eprintln!("[CAPS] Failed to fetch seed capabilities: {}", e);
@@ -381,169 +456,130 @@ pub async fn login_to_secondlife(grid_uri: &str, req: &LoginRequest, proxy_setti
                 }
             }
             // (Event queue polling should be started from the UI code after login succeeds)
-            // --- Now start UDP handshake (after OpenID POST is done) ---
-            let sim_addr = format!("{}:{}", info.sim_ip, info.sim_port).parse().unwrap();
-            let session_id = uuid::Uuid::parse_str(&info.session_id).unwrap();
-            let agent_id = uuid::Uuid::parse_str(&info.agent_id).unwrap();
-            let circuit_code = info.circuit_code;
-            // Use a default local port (0 = OS assigns)
-            let udp_port = 0;
-            let mut udp_transport = crate::networking::transport::UdpTransport::new(udp_port, sim_addr, proxy_settings).await.map_err(|e| format!("Failed to create UDP transport: {}", e))?;
-
-            // Create agent_state for dynamic updates
-            let agent_state = Arc::new(tokio::sync::Mutex::new(crate::networking::circuit::AgentState {
-                position: (0.0, 0.0, 0.0),
-                camera_at: (0.0, 0.0, 0.0),
-                camera_eye: (0.0, 0.0, 0.0),
-                controls: 0,
-            }));
-            // Create a Circuit for handshake management
-            let mut circuit = crate::networking::circuit::Circuit::new_with_transport(
-                Arc::new(tokio::sync::Mutex::new(udp_transport)),
-                agent_state
-            ).await.map_err(|e| e.to_string())?;
-            let position = (0.0, 0.0, 0.0);
-            let look_at = (0.0, 0.0, 0.0);
-            let throttle = [207360.0, 165376.0, 33075.19921875, 33075.19921875, 682700.75, 682700.75, 269312.0];
-            let flags = 0; // Default flags
-            let controls = 0; // Default controls
-            let camera_at = (0.0, 0.0, 0.0);
-            let camera_eye = (0.0, 0.0, 0.0);
-
-            tokio::spawn(async move {
-                // Progress through handshake states
-                circuit.advance_handshake(agent_id, session_id, circuit_code, position, look_at, throttle, flags, controls, camera_at, camera_eye).await;
-                circuit.advance_handshake(agent_id, session_id, circuit_code, position, look_at, throttle, flags, controls, camera_at, camera_eye).await;
-                // Start the receive loop to handle all incoming messages and handshake progression
-                circuit.run_receive_loop(
-                    agent_id,
-                    session_id,
-                    circuit_code,
-                    position,
-                    look_at,
-                    throttle,
-                    flags,
-                    controls,
-                    camera_at,
-                    camera_eye
-                ).await;
-            });
-            Ok(info)
+            // The UDP circuit and handshake are owned by the networking actor (see `networking::actor`),
+            // which the UI asks to connect once the sim address is known.
+            Ok(LoginOutcome::Success(info))
         }
-        Err(e) => Err(e),
+        outcome => Ok(outcome),
     }
 }
 
-fn parse_login_response(xml: &str) -> Result<LoginSessionInfo, String> {
-    use roxmltree::Document;
-    use std::str::FromStr;
-    let doc = Document::parse(xml).map_err(|e| format!("XML parse error: {e}"))?;
-    // Find the first <struct> node under <methodResponse>
-    let struct_node = doc.descendants().find(|n| n.has_tag_name("struct")).ok_or("No <struct> found in login response")?;
-    let mut get_field = |field: &str| -> Option<String> {
-        for member in struct_node.children().filter(|n| n.has_tag_name("member")) {
-            let name = member.children().find(|n| n.has_tag_name("name")).and_then(|n| n.text()).unwrap_or("");
-            if name == field {
-                // Try to get the value as text from <string>, <int>, or directly from <value>
-                let value_node = member.children().find(|n| n.has_tag_name("value"))?;
-                if let Some(s) = value_node.children().find(|n| n.has_tag_name("string")).and_then(|n| n.text()) {
-                    return Some(s.trim_matches('"').to_string());
-                } else if let Some(i) = value_node.children().find(|n| n.has_tag_name("int")).and_then(|n| n.text()) {
-                    return Some(i.to_string());
-                } else if let Some(t) = value_node.text() {
-                    return Some(t.trim_matches('"').to_string());
-                }
-            }
-        }
-        None
+fn parse_login_response(xml: &str) -> Result<LoginOutcome, String> {
+    let resp = xmlrpc::parse_method_response(xml).map_err(|e| e.to_string())?;
+    if resp["login"].as_str() == Some("false") {
+        let message = resp["message"].as_str().unwrap_or_default().to_string();
+        return Ok(match resp["reason"].as_str().unwrap_or_default() {
+            "tos" => LoginOutcome::TosRequired { message },
+            "critical" => LoginOutcome::CriticalMessage { message },
+            "mfa_challenge" => LoginOutcome::MfaChallenge { message },
+            "presence" => LoginOutcome::AlreadyLoggedIn { message },
+            "key" => LoginOutcome::InvalidCredentials { message },
+            reason @ ("update" | "optional") => LoginOutcome::UpdateRequired {
+                message,
+                update_url: resp["update_url"].as_str().map(str::to_string),
+                optional: reason == "optional",
+            },
+            reason => LoginOutcome::Failed { reason: reason.to_string(), message },
+        });
+    }
+    let string = |key: &str| resp[key].as_str().map(str::to_string);
+    let int = |key: &str| resp[key].as_i64();
+    // Option payloads are arrays; single-valued ones hold one struct.
+    let list = |key: &str| resp[key].as_array().unwrap_or_default();
+    let first = |key: &str| list(key).first();
+    let uuid = |value: &Value| value.as_uuid().unwrap_or_default();
+    let text = |value: &Value| value.as_str().unwrap_or_default().to_string();
+    let flag = |value: &Value| value.as_bool().unwrap_or_default();
+    let folders = |key: &str| {
+        list(key)
+            .iter()
+            .map(|f| InventoryFolder {
+                folder_id: uuid(&f["folder_id"]),
+                parent_id: uuid(&f["parent_id"]),
+                name: text(&f["name"]),
+                type_default: f["type_default"].as_i32().unwrap_or(-1),
+                version: f["version"].as_i32().unwrap_or_default(),
+            })
+            .collect::<Vec<_>>()
+    };
+    let categories = |key: &str| {
+        list(key)
+            .iter()
+            .map(|c| Category { id: c["category_id"].as_i32().unwrap_or_default(), name: text(&c["category_name"]) })
+            .collect::<Vec<_>>()
     };
-    Ok(LoginSessionInfo {
-        last_name: get_field("last_name").or_else(|| get_field("last")).ok_or("missing last_name")?,
-        first_name: get_field("first_name").or_else(|| get_field("first")).ok_or("missing first_name")?,
-        agent_id: get_field("agent_id").ok_or("missing agent_id")?,
-        session_id: get_field("session_id").ok_or("missing session_id")?,
-        secure_session_id: get_field("secure_session_id").ok_or("missing secure_session_id")?,
-        sim_ip: get_field("sim_ip").ok_or("missing sim_ip")?,
-        sim_port: get_field("sim_port").and_then(|v| v.trim().parse::<u16>().ok()).ok_or("missing sim_port")?,
-        circuit_code: get_field("circuit_code").and_then(|v| v.trim().parse::<u32>().ok()).ok_or("missing circuit_code")?,
-        region_x: get_field("region_x").and_then(|v| v.trim().parse::<i32>().ok()).ok_or("missing region_x")?,
-        region_y: get_field("region_y").and_then(|v| v.trim().parse::<i32>().ok()).ok_or("missing region_y")?,
-        look_at: get_field("look_at").ok_or("missing look_at")?,
-        start_location: get_field("start_location").or_else(|| get_field("start")).ok_or("missing start_location")?,
-        seconds_since_epoch: get_field("seconds_since_epoch").and_then(|v| v.trim().parse::<i64>().ok()).ok_or("missing seconds_since_epoch")?,
-        message: get_field("message").unwrap_or_default(),
-        inventory_host: get_field("inventory_host").unwrap_or_default(),
-        seed_capability: get_field("seed_capability").unwrap_or_default(),
-        agent_access: get_field("agent_access").unwrap_or_default(),
-        login: get_field("login").unwrap_or_default(),
-        account_type: get_field("account_type"),
-        linden_status_code: get_field("Linden_Status_Code"),
-        agent_flags: get_field("agent_flags").and_then(|v| v.trim().parse::<i32>().ok()),
-        max_god_level: get_field("max_god_level").and_then(|v| v.trim().parse::<i32>().ok()),
-        god_level: get_field("god_level").and_then(|v| v.trim().parse::<i32>().ok()),
-        inventory_root: get_field("inventory-root"),
-        buddy_list: None, // TODO: parse buddy-list if needed
+    Ok(LoginOutcome::Success(Box::new(LoginSessionInfo {
+        last_name: string("last_name").or_else(|| string("last")).ok_or("missing last_name")?.trim_matches('"').to_string(),
+        first_name: string("first_name").or_else(|| string("first")).ok_or("missing first_name")?.trim_matches('"').to_string(),
+        agent_id: string("agent_id").ok_or("missing agent_id")?,
+        session_id: string("session_id").ok_or("missing session_id")?,
+        secure_session_id: string("secure_session_id").ok_or("missing secure_session_id")?,
+        sim_ip: string("sim_ip").ok_or("missing sim_ip")?,
+        sim_port: int("sim_port").and_then(|v| u16::try_from(v).ok()).ok_or("missing sim_port")?,
+        circuit_code: int("circuit_code").and_then(|v| u32::try_from(v).ok()).ok_or("missing circuit_code")?,
+        region_x: int("region_x").and_then(|v| i32::try_from(v).ok()).ok_or("missing region_x")?,
+        region_y: int("region_y").and_then(|v| i32::try_from(v).ok()).ok_or("missing region_y")?,
+        look_at: string("look_at").ok_or("missing look_at")?,
+        start_location: string("start_location").or_else(|| string("start")).ok_or("missing start_location")?,
+        seconds_since_epoch: int("seconds_since_epoch").ok_or("missing seconds_since_epoch")?,
+        message: string("message").unwrap_or_default(),
+        inventory_host: string("inventory_host").unwrap_or_default(),
+        seed_capability: string("seed_capability").unwrap_or_default(),
+        agent_access: string("agent_access").unwrap_or_default(),
+        login: string("login").unwrap_or_default(),
+        account_type: string("account_type"),
+        linden_status_code: string("Linden_Status_Code"),
+        agent_flags: resp["agent_flags"].as_i32(),
+        max_god_level: resp["max_god_level"].as_i32(),
+        god_level: resp["god_level"].as_i32(),
+        inventory_root: first("inventory-root").and_then(|f| f["folder_id"].as_str()).map(str::to_string),
+        inventory_skeleton: folders("inventory-skeleton"),
+        inventory_lib_root: first("inventory-lib-root").and_then(|f| f["folder_id"].as_uuid()),
+        inventory_lib_owner: first("inventory-lib-owner").and_then(|o| o["agent_id"].as_uuid()),
+        inventory_skel_lib: folders("inventory-skel-lib"),
+        initial_outfit: first("initial-outfit").map(|o| InitialOutfit { folder_name: text(&o["folder_name"]), gender: text(&o["gender"]) }),
+        gestures: list("gestures").iter().map(|g| Gesture { item_id: uuid(&g["item_id"]), asset_id: uuid(&g["asset_id"]) }).collect(),
+        event_categories: categories("event_categories"),
+        event_notifications: list("event_notifications")
+            .iter()
+            .map(|e| EventNotification {
+                event_id: e["event_id"].as_i32().unwrap_or_default(),
+                event_name: text(&e["event_name"]),
+                event_date: text(&e["event_date"]),
+            })
+            .collect(),
+        classified_categories: categories("classified_categories"),
+        buddy_list: list("buddy-list")
+            .iter()
+            .map(|b| Buddy {
+                buddy_id: uuid(&b["buddy_id"]),
+                rights_given: b["buddy_rights_given"].as_i32().unwrap_or_default(),
+                rights_has: b["buddy_rights_has"].as_i32().unwrap_or_default(),
+            })
+            .collect(),
+        ui_config: first("ui-config").map(|c| UiConfig { allow_first_life: flag(&c["allow_first_life"]) }),
+        max_agent_groups: resp["max-agent-groups"].as_i32().or_else(|| resp["max_groups"].as_i32()),
+        map_server_url: string("map-server-url"),
+        voice_config: first("voice-config").map(|c| VoiceConfig { voice_server_type: text(&c["VoiceServerType"]) }),
+        tutorial_setting: first("tutorial_setting")
+            .map(|t| TutorialSetting { tutorial_url: text(&t["tutorial_url"]), use_tutorial: flag(&t["use_tutorial"]) }),
+        login_flags: first("login-flags").map(|f| LoginFlags {
+            stipend_since_login: flag(&f["stipend_since_login"]),
+            ever_logged_in: flag(&f["ever_logged_in"]),
+            daylight_savings: flag(&f["daylight_savings"]),
+            gendered: flag(&f["gendered"]),
+        }),
+        global_textures: first("global-textures").map(|t| GlobalTextures {
+            sun_texture_id: uuid(&t["sun_texture_id"]),
+            moon_texture_id: uuid(&t["moon_texture_id"]),
+            cloud_texture_id: uuid(&t["cloud_texture_id"]),
+        }),
+        openid_token: string("openid_token"),
+        openid_url: string("openid_url"),
+        mfa_hash: string("mfa_hash").filter(|hash| !hash.is_empty()),
         capabilities: None, // Initialize capabilities to None
         session_cookie: None, // Initialize session_cookie to None
-    })
-}
-
-// Improved helper to extract openid_token from the login response XML using roxmltree robustly
-fn extract_openid_token(xml: &str) -> Option<String> {
-    let doc = roxmltree::Document::parse(xml).ok()?;
-    for member in doc.descendants().filter(|n| n.has_tag_name("member")) {
-        let mut name = None;
-        let mut value = None;
-        for child in member.children() {
-            if child.has_tag_name("name") {
-                name = child.text();
-            }
-            if child.has_tag_name("value") {
-                // Look for <string> child or direct text
-                for vchild in child.children() {
-                    if vchild.has_tag_name("string") {
-                        value = vchild.text();
-                    }
-                }
-                if value.is_none() {
-                    value = child.text();
-                }
-            }
-        }
-        if name == Some("openid_token") {
-            return value.map(|s| s.to_string());
-        }
-    }
-    None
-}
-
-// New: Extract openid_url from the login response XML
-fn extract_openid_url(xml: &str) -> Option<String> {
-    let doc = roxmltree::Document::parse(xml).ok()?;
-    for member in doc.descendants().filter(|n| n.has_tag_name("member")) {
-        let mut name = None;
-        let mut value = None;
-        for child in member.children() {
-            if child.has_tag_name("name") {
-                name = child.text();
-            }
-            if child.has_tag_name("value") {
-                // Look for <string> child or direct text
-                for vchild in child.children() {
-                    if vchild.has_tag_name("string") {
-                        value = vchild.text();
-                    }
-                }
-                if value.is_none() {
-                    value = child.text();
-                }
-            }
-        }
-        if name == Some("openid_url") {
-            return value.map(|s| s.to_string());
-        }
-    }
-    None
+    })))
 }
 
 // Helper: Extract only the cookie name and value from a Set-Cookie header
@@ -551,87 +587,17 @@ fn extract_cookie_kv(set_cookie: &str) -> String {
     set_cookie.split(';').next().unwrap_or("").trim().to_string()
 }
 
-// Parse LLSD XML capabilities response into Capabilities struct
-fn parse_capabilities_response(xml: &str) -> Result<Capabilities, String> {
-    let doc = roxmltree::Document::parse(xml).map_err(|e| e.to_string())?;
-    let mut map = HashMap::new();
-    for node in doc.descendants().filter(|n| n.has_tag_name("map")) {
-        let mut key = None;
-        for child in node.children() {
-            if child.has_tag_name("key") {
-                key = child.text();
-            }
-            if child.has_tag_name("string") {
-                if let Some(k) = key {
-                    if let Some(v) = child.text() {
-                        map.insert(k.to_string(), v.to_string());
-                    }
-                }
-                key = None;
-            }
-        }
-    }
-    Ok(Capabilities { map })
-}
-
-/// Polls the EventQueueGet capability repeatedly and delivers events via a callback.
-pub async fn poll_event_queue<F>(
-    capabilities: &Capabilities,
-    udp_port: u16,
-    proxy_settings: Option<&ProxySettings>,
-    mut on_event: F,
-) -> Result<(), String>
-where
-    F: FnMut(String) + Send + 'static,
-{
-    let eq_url = match capabilities.map.get("EventQueueGet") {
-        Some(url) => url,
-        None => return Err("EventQueueGet capability not found".to_string()),
-    };
-    let client = build_proxied_client(proxy_settings);
-    let mut ack: Option<i32> = Some(0); // Always send <ack>0> in first request for Hippolyzer compatibility
-    loop {
-        let payload = if let Some(ack_val) = ack {
-            format!(r#"<?xml version="1.0" ?><llsd><map><key>ack</key><integer>{}</integer><key>done</key><boolean>false</boolean></map></llsd>"#, ack_val)
-        } else {
-            r#"<?xml version="1.0" ?><llsd><map><key>done</key><boolean>false</boolean></map></llsd>"#.to_string()
-        };
-        let resp = client
-            .post(eq_url)
-            .header("Accept", "application/llsd+xml")
-            .header("Content-Type", "application/llsd+xml")
-            .header("X-SecondLife-UDP-Listen-Port", udp_port.to_string())
-            .body(payload)
-            .send()
-            .await
-            .map_err(|e| format!("EventQueueGet POST error: {e}"))?;
-        let status = resp.status();
-        let text = resp.text().await.map_err(|e| format!("EventQueueGet read error: {e}"))?;
-        if !status.is_success() {
-            return Err(format!("EventQueueGet failed: HTTP {}", status));
-        }
-        // Parse <ack> from the response XML, if present
-        let mut new_ack: Option<i32> = None;
-        if let Ok(doc) = roxmltree::Document::parse(&text) {
-            for node in doc.descendants() {
-                if node.has_tag_name("key") && node.text() == Some("ack") {
-                    if let Some(val_node) = node.next_sibling_element() {
-                        if val_node.has_tag_name("integer") {
-                            if let Some(val_text) = val_node.text() {
-                                if let Ok(val) = val_text.parse::<i32>() {
-                                    new_ack = Some(val);
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-        }
-        ack = new_ack;
-        on_event(text.clone());
-        // Simulate long-polling delay
-        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+/// Fetches an OpenSim login server's `get_grid_info` document and builds a grid from it.
+pub async fn fetch_grid_info(login_uri: &str, proxy_settings: Option<&ProxySettings>) -> Result<Grid, String> {
+    let url = Grid::grid_info_url(login_uri);
+    let client = proxy::http_client(proxy_settings).map_err(|e| e.to_string())?;
+    let resp = client.get(&url).send().await.map_err(|e| format!("get_grid_info GET error: {e}"))?;
+    let status = resp.status();
+    let text = resp.text().await.map_err(|e| format!("get_grid_info GET error: {e}"))?;
+    if !status.is_success() {
+        return Err(format!("get_grid_info GET failed: HTTP {}", status));
     }
+    Grid::from_grid_info(&text, login_uri).map_err(|e| e.to_string())
 }
 
 /// Posts to the seed_capability URL to fetch the capabilities map.
@@ -641,165 +607,21 @@ pub async fn fetch_seed_capabilities(
     proxy_settings: Option<&ProxySettings>,
     openid_cookie: Option<&str>,
 ) -> Result<Capabilities, String> {
-    let llsd_body = r#"<?xml version="1.0" ?>
-<llsd>
-  <array>
-    <string>AbuseCategories</string>
-    <string>AcceptFriendship</string>
-    <string>AcceptGroupInvite</string>
-    <string>AgentPreferences</string>
-    <string>AgentProfile</string>
-    <string>AgentState</string>
-    <string>AttachmentResources</string>
-    <string>AvatarPickerSearch</string>
-    <string>AvatarRenderInfo</string>
-    <string>CharacterProperties</string>
-    <string>ChatSessionRequest</string>
-    <string>CopyInventoryFromNotecard</string>
-    <string>CreateInventoryCategory</string>
-    <string>DeclineFriendship</string>
-    <string>DeclineGroupInvite</string>
-    <string>DispatchRegionInfo</string>
-    <string>DirectDelivery</string>
-    <string>EnvironmentSettings</string>
-    <string>EstateAccess</string>
-    <string>EstateChangeInfo</string>
-    <string>EventQueueGet</string>
-    <string>ExtEnvironment</string>
-    <string>FetchLib2</string>
-    <string>FetchLibDescendents2</string>
-    <string>FetchInventory2</string>
-    <string>FetchInventoryDescendents2</string>
-    <string>IncrementCOFVersion</string>
-    <string>RequestTaskInventory</string>
-    <string>InventoryAPIv3</string>
-    <string>LibraryAPIv3</string>
-    <string>InterestList</string>
-    <string>InventoryThumbnailUpload</string>
-    <string>GetDisplayNames</string>
-    <string>GetExperiences</string>
-    <string>AgentExperiences</string>
-    <string>FindExperienceByName</string>
-    <string>GetExperienceInfo</string>
-    <string>GetAdminExperiences</string>
-    <string>GetCreatorExperiences</string>
-    <string>ExperiencePreferences</string>
-    <string>GroupExperiences</string>
-    <string>UpdateExperience</string>
-    <string>IsExperienceAdmin</string>
-    <string>IsExperienceContributor</string>
-    <string>RegionExperiences</string>
-    <string>ExperienceQuery</string>
-    <string>GetMetadata</string>
-    <string>GetObjectCost</string>
-    <string>GetObjectPhysicsData</string>
-    <string>GroupAPIv1</string>
-    <string>GroupMemberData</string>
-    <string>GroupProposalBallot</string>
-    <string>HomeLocation</string>
-    <string>LandResources</string>
-    <string>LSLSyntax</string>
-    <string>MapLayer</string>
-    <string>MapLayerGod</string>
-    <string>MeshUploadFlag</string>
-    <string>ModifyMaterialParams</string>
-    <string>ModifyRegion</string>
-    <string>NavMeshGenerationStatus</string>
-    <string>NewFileAgentInventory</string>
-    <string>ObjectAnimation</string>
-    <string>ObjectMedia</string>
-    <string>ObjectMediaNavigate</string>
-    <string>ObjectNavMeshProperties</string>
-    <string>ParcelPropertiesUpdate</string>
-    <string>ParcelVoiceInfoRequest</string>
-    <string>ProductInfoRequest</string>
-    <string>ProvisionVoiceAccountRequest</string>
-    <string>VoiceSignalingRequest</string>
-    <string>ReadOfflineMsgs</string>
-    <string>RegionObjects</string>
-    <string>RegionSchedule</string>
-    <string>RemoteParcelRequest</string>
-    <string>RenderMaterials</string>
-    <string>RequestTextureDownload</string>
-    <string>ResourceCostSelected</string>
-    <string>RetrieveNavMeshSrc</string>
-    <string>SearchStatRequest</string>
-    <string>SearchStatTracking</string>
-    <string>SendPostcard</string>
-    <string>SendUserReport</string>
-    <string>SendUserReportWithScreenshot</string>
-    <string>ServerReleaseNotes</string>
-    <string>SetDisplayName</string>
-    <string>SimConsoleAsync</string>
-    <string>SimulatorFeatures</string>
-    <string>StartGroupProposal</string>
-    <string>TerrainNavMeshProperties</string>
-    <string>TextureStats</string>
-    <string>UntrustedSimulatorMessage</string>
-    <string>UpdateAgentInformation</string>
-    <string>UpdateAgentLanguage</string>
-    <string>UpdateAvatarAppearance</string>
-    <string>UpdateGestureAgentInventory</string>
-    <string>UpdateGestureTaskInventory</string>
-    <string>UpdateNotecardAgentInventory</string>
-    <string>UpdateNotecardTaskInventory</string>
-    <string>UpdateScriptAgent</string>
-    <string>UpdateScriptTask</string>
-    <string>UpdateSettingsAgentInventory</string>
-    <string>UpdateSettingsTaskInventory</string>
-    <string>UploadAgentProfileImage</string>
-    <string>UpdateMaterialAgentInventory</string>
-    <string>UpdateMaterialTaskInventory</string>
-    <string>UploadBakedTexture</string>
-    <string>UserInfo</string>
-    <string>ViewerAsset</string>
-    <string>ViewerBenefits</string>
-    <string>ViewerMetrics</string>
-    <string>ViewerStartAuction</string>
-    <string>ViewerStats</string>
-  </array>
-</llsd>
-"#;
-    let client = build_proxied_client(proxy_settings);
-    let url = Url::parse(seed_capability).ok();
-    let host_header = url.as_ref().and_then(|u| {
-        if let Some(port) = u.port() {
-            Some(format!("{}:{}", u.host_str().unwrap_or(""), port))
-        } else {
-            Some(u.host_str().unwrap_or("").to_string())
-        }
-    });
-    let mut req_builder = client
-        .post(seed_capability)
-        .header("Accept", "application/llsd+xml")
-        .header("Content-Type", "application/llsd+xml")
-        .header("X-SecondLife-UDP-Listen-Port", udp_port.to_string())
-        .header("User-Agent", "SecondLife/7.1.15.15596336374 (Second Life Release; default skin)")
-        .header("Accept-Encoding", "deflate, gzip")
-        .header("Connection", "keep-alive")
-        .header("Keep-alive", "300");
-    if let Some(ref host) = host_header {
-        req_builder = req_builder.header("Host", host);
-    }
-    if let Some(cookie) = openid_cookie {
-        req_builder = req_builder.header("Cookie", cookie);
-    }
-    let resp = req_builder.body(llsd_body).send().await.map_err(|e| format!("Seed capabilities POST error: {e}"))?;
-    let status = resp.status();
-    let text = resp.text().await.map_err(|e| format!("Seed capabilities read error: {e}"))?;
-    if !status.is_success() {
-        return Err(format!("Seed capabilities POST failed: HTTP {}", status));
-    }
-    parse_capabilities_response(&text)
+    let client = CapsClient::connect(seed_capability, udp_port, proxy_settings, openid_cookie.map(str::to_string))
+        .await
+        .map_err(|e| format!("Seed capabilities POST error: {e}"))?;
+    Ok(client.capabilities().clone())
 }
 
+/// Fetches the grid's Terms of Service page for `tos_id`.
 pub async fn fetch_tos_html(
+    grid: &Grid,
     tos_id: &str,
     udp_port: Option<u16>,
     proxy_settings: Option<&ProxySettings>,
 ) -> Result<String, String> {
-    let url = format!("https://secondlife.com/app/tos/tos.php?id={}", tos_id);
-    let client = build_proxied_client(proxy_settings);
+    let url = grid.tos_url(tos_id).ok_or_else(|| format!("Grid {} has no Terms of Service page", grid.name))?;
+    let client = proxy::http_client(proxy_settings).map_err(|e| e.to_string())?;
     let mut req = client.get(&url)
         .header("Accept-Encoding", "deflate, gzip")
         .header("Accept", "application/llsd+xml")
@@ -820,201 +642,80 @@ pub async fn fetch_tos_html(
     Ok(text)
 }
 
-/// Fetches SimulatorFeatures capability with correct UDP port header
-pub async fn fetch_simulator_features(
-    url: &str,
-    udp_port: u16,
-    proxy_settings: Option<&ProxySettings>,
-) -> Result<String, String> {
-    let client = build_proxied_client(proxy_settings);
-    let resp = client
-        .get(url)
-        .header("Accept-Encoding", "deflate, gzip")
-        .header("Connection", "keep-alive")
-        .header("Keep-alive", "300")
-        .header("Accept", "application/llsd+xml")
-        .header("Content-Type", "application/llsd+xml")
-        .header("X-SecondLife-UDP-Listen-Port", udp_port.to_string())
-        .send()
-        .await
-        .map_err(|e| format!("SimulatorFeatures GET error: {e}"))?;
-    let status = resp.status();
-    let text = resp.text().await.map_err(|e| format!("SimulatorFeatures GET error: {e}"))?;
-    if !status.is_success() {
-        return Err(format!("SimulatorFeatures GET failed: HTTP {}", status));
-    }
-    Ok(text)
-}
-
-/// Fetches my.secondlife.com homepage with session cookie and UDP port
-pub async fn fetch_my_secondlife_homepage(
-    session_cookie: &str,
-    udp_port: u16,
-    proxy_settings: Option<&ProxySettings>,
-) -> Result<String, String> {
-    let client = build_proxied_client(proxy_settings);
-    let resp = client
-        .get("https://my.secondlife.com/")
-        .header("Accept-Encoding", "deflate, gzip")
-        .header("Connection", "keep-alive")
-        .header("Keep-alive", "300")
-        .header("Accept", "*/*")
-        .header("Cookie", session_cookie)
-        .header("User-Agent", "SecondLife/7.1.15.15596336374 (Second Life Release; default skin)")
-        .header("Content-Type", "application/llsd+xml")
-        .header("X-SecondLife-UDP-Listen-Port", udp_port.to_string())
-        .header("Host", "my.secondlife.com")
-        .send()
-        .await
-        .map_err(|e| format!("my.secondlife.com GET error: {e}"))?;
-    let status = resp.status();
-    let text = resp.text().await.map_err(|e| format!("my.secondlife.com GET error: {e}"))?;
-    if !status.is_success() {
-        return Err(format!("my.secondlife.com GET failed: HTTP {}", status));
-    }
-    Ok(text)
-}
-
-/// Fetches NavMeshGenerationStatus capability with correct UDP port header
-pub async fn fetch_navmesh_generation_status(
-    url: &str,
-    udp_port: u16,
-    proxy_settings: Option<&ProxySettings>,
-) -> Result<String, String> {
-    let client = build_proxied_client(proxy_settings);
-    let resp = client
-        .get(url)
-        .header("Accept-Encoding", "deflate, gzip")
-        .header("Connection", "keep-alive")
-        .header("Keep-alive", "300")
-        .header("Accept", "application/llsd+xml")
-        .header("Content-Type", "application/llsd+xml")
-        .header("X-SecondLife-UDP-Listen-Port", udp_port.to_string())
-        .send()
-        .await
-        .map_err(|e| format!("NavMeshGenerationStatus GET error: {e}"))?;
-    let status = resp.status();
-    let text = resp.text().await.map_err(|e| format!("NavMeshGenerationStatus GET error: {e}"))?;
-    if !status.is_success() {
-        return Err(format!("NavMeshGenerationStatus GET failed: HTTP {}", status));
-    }
-    Ok(text)
-}
-
-/// Posts AgentPreferences capability with correct UDP port header and LLSD XML body
-pub async fn post_agent_preferences(
-    url: &str,
-    udp_port: u16,
-    proxy_settings: Option<&ProxySettings>,
-    llsd_body: &str,
-) -> Result<String, String> {
-    let client = build_proxied_client(proxy_settings);
-    let resp = client
-        .post(url)
-        .header("Accept-Encoding", "deflate, gzip")
-        .header("Connection", "keep-alive")
-        .header("Keep-alive", "300")
-        .header("Accept", "application/llsd+xml")
-        .header("Content-Type", "application/llsd+xml")
-        .header("X-SecondLife-UDP-Listen-Port", udp_port.to_string())
-        .body(llsd_body.to_string())
-        .send()
-        .await
-        .map_err(|e| format!("AgentPreferences POST error: {e}"))?;
-    let status = resp.status();
-    let text = resp.text().await.map_err(|e| format!("AgentPreferences POST error: {e}"))?;
-    if !status.is_success() {
-        return Err(format!("AgentPreferences POST failed: HTTP {}", status));
-    }
-    Ok(text)
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-/// Fetches ExtEnvironment capability with correct UDP port header
-pub async fn fetch_ext_environment(
-    url: &str,
-    udp_port: u16,
-    proxy_settings: Option<&ProxySettings>,
-) -> Result<String, String> {
-    let client = build_proxied_client(proxy_settings);
-    let resp = client
-        .get(url)
-        .header("Accept-Encoding", "deflate, gzip")
-        .header("Connection", "keep-alive")
-        .header("Keep-alive", "300")
-        .header("Accept", "application/llsd+xml")
-        .header("Content-Type", "application/llsd+xml")
-        .header("X-SecondLife-UDP-Listen-Port", udp_port.to_string())
-        .send()
-        .await
-        .map_err(|e| format!("ExtEnvironment GET error: {e}"))?;
-    let status = resp.status();
-    let text = resp.text().await.map_err(|e| format!("ExtEnvironment GET error: {e}"))?;
-    if !status.is_success() {
-        return Err(format!("ExtEnvironment GET failed: HTTP {}", status));
+    fn member(name: &str, value: &str) -> String {
+        format!("<member><name>{}</name><value>{}</value></member>", name, value)
     }
-    Ok(text)
-}
 
-/// Fetches OpenID checklogin with correct UDP port, session cookie, and referer
-pub async fn fetch_openid_checklogin(
-    udp_port: u16,
-    session_cookie: &str,
-    proxy_settings: Option<&ProxySettings>,
-    referer: &str,
-) -> Result<String, String> {
-    let url = "https://id.secondlife.com/openid/checklogin?return_to=https%3A%2F%2Fmy.secondlife.com%2F";
-    let client = build_proxied_client(proxy_settings);
-    let resp = client
-        .get(url)
-        .header("Accept-Encoding", "deflate, gzip")
-        .header("Connection", "keep-alive")
-        .header("Keep-alive", "300")
-        .header("Accept", "*/*")
-        .header("Cookie", session_cookie)
-        .header("User-Agent", "SecondLife/7.1.15.15596336374 (Second Life Release; default skin)")
-        .header("Content-Type", "application/llsd+xml")
-        .header("X-SecondLife-UDP-Listen-Port", udp_port.to_string())
-        .header("Referer", referer)
-        .send()
-        .await
-        .map_err(|e| format!("OpenID checklogin GET error: {e}"))?;
-    let status = resp.status();
-    let text = resp.text().await.map_err(|e| format!("OpenID checklogin GET error: {e}"))?;
-    if !status.is_success() {
-        return Err(format!("OpenID checklogin GET failed: HTTP {}", status));
-    }
-    Ok(text)
-}
+    #[test]
+    fn test_parse_login_response() {
+        let folder = |id: u128, parent: u128, name: &str| {
+            format!(
+                "<value><struct>{}{}{}{}{}</struct></value>",
+                member("folder_id", &format!("<string>{}</string>", Uuid::from_u128(id))),
+                member("parent_id", &format!("<string>{}</string>", Uuid::from_u128(parent))),
+                member("name", &format!("<string>{}</string>", name)),
+                member("type_default", "<int>8</int>"),
+                member("version", "<int>3</int>"),
+            )
+        };
+        let members = [
+            member("login", "<string>true</string>"),
+            member("first_name", "<string>\"Test\"</string>"),
+            member("last_name", "<string>Resident</string>"),
+            member("agent_id", "<string>a2e76fcd-9360-4f6d-a924-000000000003</string>"),
+            member("session_id", "<string>a2e76fcd-9360-4f6d-a924-000000000004</string>"),
+            member("secure_session_id", "<string>a2e76fcd-9360-4f6d-a924-000000000005</string>"),
+            member("sim_ip", "<string>10.0.0.7</string>"),
+            member("sim_port", "<int>13005</int>"),
+            member("circuit_code", "<int>123456</int>"),
+            member("region_x", "<int>256000</int>"),
+            member("region_y", "<int>256256</int>"),
+            member("look_at", "<string>[r1,r0,r0]</string>"),
+            member("start_location", "<string>last</string>"),
+            member("seconds_since_epoch", "<int>1700000000</int>"),
+            member("inventory-skeleton", &format!("<array><data>{}{}</data></array>", folder(1, 0, "My Inventory"), folder(2, 1, "Body &amp; Parts"))),
+            member("buddy-list", &format!("<array><data><value><struct>{}{}{}</struct></value></data></array>",
+                member("buddy_id", &format!("<string>{}</string>", Uuid::from_u128(9))),
+                member("buddy_rights_given", "<int>1</int>"),
+                member("buddy_rights_has", "<int>3</int>"))),
+            member("login-flags", &format!("<array><data><value><struct>{}{}</struct></value></data></array>",
+                member("ever_logged_in", "<string>Y</string>"),
+                member("daylight_savings", "<string>N</string>"))),
+            member("max-agent-groups", "<int>70</int>"),
+        ];
+        let xml = format!(
+            "<?xml version=\"1.0\"?><methodResponse><params><param><value><struct>{}</struct></value></param></params></methodResponse>",
+            members.concat()
+        );
+        let Ok(LoginOutcome::Success(info)) = parse_login_response(&xml) else { panic!("expected a successful login") };
+        assert_eq!((info.first_name.as_str(), info.sim_port, info.circuit_code), ("Test", 13005, 123456));
+        assert_eq!(info.inventory_skeleton.len(), 2);
+        assert_eq!(info.inventory_skeleton[1].name, "Body & Parts");
+        assert_eq!(info.inventory_skeleton[1].parent_id, Uuid::from_u128(1));
+        assert_eq!(info.buddy_list, vec![Buddy { buddy_id: Uuid::from_u128(9), rights_given: 1, rights_has: 3 }]);
+        let flags = info.login_flags.unwrap();
+        assert!(flags.ever_logged_in && !flags.daylight_savings);
+        assert_eq!(info.max_agent_groups, Some(70));
+        assert!(info.global_textures.is_none());
 
-/// Fetches my.secondlife.com OpenID redirect with correct UDP port, session cookie, and referer
-pub async fn fetch_my_secondlife_openid_redirect(
-    udp_port: u16,
-    session_cookie: &str,
-    proxy_settings: Option<&ProxySettings>,
-    referer: &str,
-) -> Result<String, String> {
-    let url = "https://my.secondlife.com/?openid_identifier=https%3A%2F%2Fid.secondlife.com%2Fid%2Ffreshbreath";
-    let client = build_proxied_client(proxy_settings);
-    let resp = client
-        .get(url)
-        .header("Accept-Encoding", "deflate, gzip")
-        .header("Connection", "keep-alive")
-        .header("Keep-alive", "300")
-        .header("Accept", "*/*")
-        .header("Cookie", session_cookie)
-        .header("User-Agent", "SecondLife/7.1.15.15596336374 (Second Life Release; default skin)")
-        .header("Content-Type", "application/llsd+xml")
-        .header("X-SecondLife-UDP-Listen-Port", udp_port.to_string())
-        .header("Referer", referer)
-        .send()
-        .await
-        .map_err(|e| format!("my.secondlife.com OpenID redirect GET error: {e}"))?;
-    let status = resp.status();
-    let text = resp.text().await.map_err(|e| format!("my.secondlife.com OpenID redirect GET error: {e}"))?;
-    if !status.is_success() {
-        return Err(format!("my.secondlife.com OpenID redirect GET failed: HTTP {}", status));
+        let failed = |reason: &str| {
+            let xml = format!(
+                "<methodResponse><params><param><value><struct>{}{}{}</struct></value></param></params></methodResponse>",
+                member("login", "<string>false</string>"),
+                member("reason", &format!("<string>{}</string>", reason)),
+                member("message", "<string>Please accept</string>"),
+            );
+            parse_login_response(&xml).unwrap()
+        };
+        assert!(matches!(failed("tos"), LoginOutcome::TosRequired { ref message } if message == "Please accept"));
+        assert!(matches!(failed("mfa_challenge"), LoginOutcome::MfaChallenge { .. }));
+        assert!(matches!(failed("optional"), LoginOutcome::UpdateRequired { optional: true, .. }));
+        assert!(!failed("key").is_resumable());
     }
-    Ok(text)
 }
 
 #[cfg(test)]
@@ -1,34 +1,39 @@
 use tokio::net::{TcpStream, UdpSocket};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::task::JoinHandle;
+use tokio::time::{timeout, Duration};
 use std::net::{SocketAddr, IpAddr, Ipv4Addr};
 use std::io;
-use tracing::{info, error, debug};
+use std::sync::{Arc, RwLock};
+use tracing::{info, error, debug, warn};
 use crate::networking::transport::UdpSocketExt;
 use async_trait::async_trait;
 
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+const REASSOCIATE_MIN_DELAY: Duration = Duration::from_millis(250);
+const REASSOCIATE_MAX_DELAY: Duration = Duration::from_secs(10);
+
+/// RFC 1929 username/password credentials for the SOCKS5 proxy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Socks5Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+/// A UDP socket relayed through a SOCKS5 UDP ASSOCIATE.
+///
+/// The proxy keeps the association only while the TCP control connection is open. A background
+/// task watches that connection and, if the proxy drops it, associates again from the same local
+/// UDP socket, so the circuit keeps its port and only loses packets sent in the gap.
 pub struct Socks5UdpSocket {
     pub udp_socket: UdpSocket,
-    pub relay_addr: SocketAddr,
-    _tcp_stream: TcpStream, // Keep this alive!
+    relay_addr: Arc<RwLock<SocketAddr>>,
+    monitor: JoinHandle<()>,
 }
 
 impl Socks5UdpSocket {
-    pub async fn connect(proxy_host: &str, proxy_port: u16, local_port: Option<u16>) -> io::Result<Self> {
+    pub async fn connect(proxy_host: &str, proxy_port: u16, local_port: Option<u16>, credentials: Option<Socks5Credentials>) -> io::Result<Self> {
         let proxy_addr = format!("{}:{}", proxy_host, proxy_port);
-        info!("[SOCKS5] Connecting to SOCKS5 proxy at {}", proxy_addr);
-        let mut tcp_stream = TcpStream::connect(&proxy_addr).await?;
-
-        // SOCKS5 handshake (no auth)
-        tcp_stream.write_all(&[0x05, 0x01, 0x00]).await?;
-        let mut resp = [0u8; 2];
-        tcp_stream.read_exact(&mut resp).await?;
-        if resp != [0x05, 0x00] {
-            error!("[SOCKS5] SOCKS5 handshake failed: {:?}", resp);
-            return Err(io::Error::new(io::ErrorKind::Other, "SOCKS5 handshake failed"));
-        }
-        debug!("[SOCKS5] SOCKS5 handshake succeeded");
-
-        // UDP ASSOCIATE
         let bind_addr = match local_port {
             Some(port) => format!("0.0.0.0:{}", port),
             None => "0.0.0.0:0".to_string(),
@@ -36,6 +41,29 @@ impl Socks5UdpSocket {
         let local_udp = UdpSocket::bind(&bind_addr).await?;
         let local_addr = local_udp.local_addr()?;
         info!("[SOCKS5] Local UDP socket bound to {}", local_addr);
+
+        let (control, relay_addr) = Self::associate(&proxy_addr, credentials.as_ref(), local_addr).await?;
+        let relay_addr = Arc::new(RwLock::new(relay_addr));
+        let monitor = tokio::spawn(Self::monitor(control, proxy_addr, credentials, local_addr, relay_addr.clone()));
+        Ok(Self {
+            udp_socket: local_udp,
+            relay_addr,
+            monitor,
+        })
+    }
+
+    /// The proxy's current UDP relay address.
+    pub fn relay_addr(&self) -> SocketAddr {
+        *self.relay_addr.read().unwrap()
+    }
+
+    /// Open a control connection and UDP ASSOCIATE `local_addr`, returning the connection and relay address.
+    async fn associate(proxy_addr: &str, credentials: Option<&Socks5Credentials>, local_addr: SocketAddr) -> io::Result<(TcpStream, SocketAddr)> {
+        info!("[SOCKS5] Connecting to SOCKS5 proxy at {}", proxy_addr);
+        let mut tcp_stream = TcpStream::connect(proxy_addr).await?;
+        Self::negotiate(&mut tcp_stream, credentials).await?;
+
+        // UDP ASSOCIATE
         let local_ip = match local_addr.ip() {
             IpAddr::V4(ip) => ip.octets(),
             IpAddr::V6(_) => {
@@ -43,10 +71,9 @@ impl Socks5UdpSocket {
                 return Err(io::Error::new(io::ErrorKind::Other, "IPv6 not supported"));
             }
         };
-        let local_port = local_addr.port();
         let mut req = vec![0x05, 0x03, 0x00, 0x01];
         req.extend_from_slice(&local_ip);
-        req.extend_from_slice(&local_port.to_be_bytes());
+        req.extend_from_slice(&local_addr.port().to_be_bytes());
         tcp_stream.write_all(&req).await?;
 
         // Parse response
@@ -57,7 +84,7 @@ impl Socks5UdpSocket {
             return Err(io::Error::new(io::ErrorKind::Other, "SOCKS5 UDP associate failed"));
         }
         let atyp = resp[3];
-        let relay_addr = match atyp {
+        let mut relay_addr = match atyp {
             0x01 => {
                 let mut ip = [0u8; 4];
                 tcp_stream.read_exact(&mut ip).await?;
@@ -70,13 +97,88 @@ impl Socks5UdpSocket {
                 return Err(io::Error::new(io::ErrorKind::Other, "Unsupported ATYP in UDP associate reply"));
             }
         };
+        // An unspecified relay address means "the address you reached me on".
+        if relay_addr.ip().is_unspecified() {
+            relay_addr.set_ip(tcp_stream.peer_addr()?.ip());
+        }
         info!("[SOCKS5] SOCKS5 UDP relay address: {}", relay_addr);
+        Ok((tcp_stream, relay_addr))
+    }
 
-        Ok(Self {
-            udp_socket: local_udp,
-            relay_addr,
-            _tcp_stream: tcp_stream,
-        })
+    /// Method negotiation, with RFC 1929 username/password auth when credentials are given.
+    async fn negotiate(tcp_stream: &mut TcpStream, credentials: Option<&Socks5Credentials>) -> io::Result<()> {
+        let greeting: &[u8] = if credentials.is_some() { &[0x05, 0x02, 0x00, 0x02] } else { &[0x05, 0x01, 0x00] };
+        tcp_stream.write_all(greeting).await?;
+        let mut resp = [0u8; 2];
+        tcp_stream.read_exact(&mut resp).await?;
+        match (resp, credentials) {
+            ([0x05, 0x00], _) => {}
+            ([0x05, 0x02], Some(credentials)) => {
+                let (username, password) = (credentials.username.as_bytes(), credentials.password.as_bytes());
+                if username.is_empty() || username.len() > 255 || password.len() > 255 {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput, "SOCKS5 username and password must be 1-255 bytes"));
+                }
+                let mut req = vec![0x01, username.len() as u8];
+                req.extend_from_slice(username);
+                req.push(password.len() as u8);
+                req.extend_from_slice(password);
+                tcp_stream.write_all(&req).await?;
+                let mut status = [0u8; 2];
+                tcp_stream.read_exact(&mut status).await?;
+                if status[1] != 0x00 {
+                    error!("[SOCKS5] SOCKS5 authentication rejected: {:?}", status);
+                    return Err(io::Error::new(io::ErrorKind::PermissionDenied, "SOCKS5 authentication rejected"));
+                }
+            }
+            ([0x05, 0xFF], _) => {
+                error!("[SOCKS5] SOCKS5 proxy accepted none of the offered auth methods");
+                return Err(io::Error::new(io::ErrorKind::PermissionDenied, "SOCKS5 proxy requires an unsupported auth method or credentials"));
+            }
+            _ => {
+                error!("[SOCKS5] SOCKS5 handshake failed: {:?}", resp);
+                return Err(io::Error::new(io::ErrorKind::Other, "SOCKS5 handshake failed"));
+            }
+        }
+        debug!("[SOCKS5] SOCKS5 handshake succeeded");
+        Ok(())
+    }
+
+    /// Wait for the control connection to close, then re-associate with backoff until it succeeds.
+    async fn monitor(
+        mut control: TcpStream,
+        proxy_addr: String,
+        credentials: Option<Socks5Credentials>,
+        local_addr: SocketAddr,
+        relay_addr: Arc<RwLock<SocketAddr>>,
+    ) {
+        let mut buf = [0u8; 64];
+        loop {
+            // The proxy sends nothing on the control connection; any read completing means it closed.
+            loop {
+                match control.read(&mut buf).await {
+                    Ok(0) => break,
+                    Ok(_) => continue,
+                    Err(e) => {
+                        debug!("[SOCKS5] Control connection error: {}", e);
+                        break;
+                    }
+                }
+            }
+            warn!("[SOCKS5] Control connection to {} closed, re-associating", proxy_addr);
+            let mut delay = REASSOCIATE_MIN_DELAY;
+            control = loop {
+                match timeout(HANDSHAKE_TIMEOUT, Self::associate(&proxy_addr, credentials.as_ref(), local_addr)).await {
+                    Ok(Ok((stream, relay))) => {
+                        *relay_addr.write().unwrap() = relay;
+                        break stream;
+                    }
+                    Ok(Err(e)) => warn!("[SOCKS5] Re-association with {} failed: {}", proxy_addr, e),
+                    Err(_) => warn!("[SOCKS5] Re-association with {} timed out", proxy_addr),
+                }
+                tokio::time::sleep(delay).await;
+                delay = (delay * 2).min(REASSOCIATE_MAX_DELAY);
+            };
+        }
     }
 
     pub fn build_udp_packet(data: &[u8], dest: &SocketAddr) -> Vec<u8> {
@@ -144,9 +246,10 @@ impl Socks5UdpSocket {
 #[async_trait]
 impl UdpSocketExt for Socks5UdpSocket {
     async fn send_to(&self, buf: &[u8], target: &SocketAddr) -> std::io::Result<usize> {
-        info!("[SOCKS5] Actually sending UDP packet to relay {} (real dest: {})", self.relay_addr, target);
+        let relay_addr = self.relay_addr();
+        info!("[SOCKS5] Actually sending UDP packet to relay {} (real dest: {})", relay_addr, target);
         let packet = Self::build_udp_packet(buf, target);
-        self.udp_socket.send_to(&packet, self.relay_addr).await
+        self.udp_socket.send_to(&packet, relay_addr).await
     }
     async fn recv_from(&self, buf: &mut [u8]) -> std::io::Result<(usize, SocketAddr)> {
         let (n, _src) = self.udp_socket.recv_from(buf).await?;
@@ -158,4 +261,78 @@ impl UdpSocketExt for Socks5UdpSocket {
     fn local_addr(&self) -> std::io::Result<SocketAddr> {
         self.udp_socket.local_addr()
     }
+}
+
+impl Drop for Socks5UdpSocket {
+    fn drop(&mut self) {
+        self.monitor.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    /// In-process SOCKS5 stand-in: accept one control connection, require user/pass, and associate
+    /// on a fresh relay socket, replying with 0.0.0.0 so the client falls back to the proxy's address.
+    async fn accept_association(listener: &TcpListener) -> (TcpStream, UdpSocket) {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut greeting = [0u8; 2];
+        stream.read_exact(&mut greeting).await.unwrap();
+        let mut methods = vec![0u8; greeting[1] as usize];
+        stream.read_exact(&mut methods).await.unwrap();
+        assert!(methods.contains(&0x02));
+        stream.write_all(&[0x05, 0x02]).await.unwrap();
+        let mut ver_ulen = [0u8; 2];
+        stream.read_exact(&mut ver_ulen).await.unwrap();
+        let mut username = vec![0u8; ver_ulen[1] as usize];
+        stream.read_exact(&mut username).await.unwrap();
+        let mut plen = [0u8; 1];
+        stream.read_exact(&mut plen).await.unwrap();
+        let mut password = vec![0u8; plen[0] as usize];
+        stream.read_exact(&mut password).await.unwrap();
+        assert_eq!((username.as_slice(), password.as_slice()), (&b"user"[..], &b"pass"[..]));
+        stream.write_all(&[0x01, 0x00]).await.unwrap();
+        let mut request = [0u8; 10];
+        stream.read_exact(&mut request).await.unwrap();
+        assert_eq!(&request[..4], &[0x05, 0x03, 0x00, 0x01]);
+        let relay = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut reply = vec![0x05, 0x00, 0x00, 0x01, 0, 0, 0, 0];
+        reply.extend_from_slice(&relay.local_addr().unwrap().port().to_be_bytes());
+        stream.write_all(&reply).await.unwrap();
+        (stream, relay)
+    }
+
+    #[tokio::test]
+    async fn test_auth_and_reassociate_after_control_drop() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let credentials = Socks5Credentials { username: "user".to_string(), password: "pass".to_string() };
+        let (socket, (control, relay)) = tokio::join!(
+            Socks5UdpSocket::connect("127.0.0.1", port, None, Some(credentials)),
+            accept_association(&listener),
+        );
+        let socket = socket.unwrap();
+        let dest: SocketAddr = "10.0.0.1:13000".parse().unwrap();
+        let mut buf = [0u8; 64];
+        socket.send_to(b"one", &dest).await.unwrap();
+        let (n, _) = relay.recv_from(&mut buf).await.unwrap();
+        assert_eq!(&buf[..n], &Socks5UdpSocket::build_udp_packet(b"one", &dest)[..]);
+
+        // The proxy drops the association; the socket associates again on a new relay.
+        drop(control);
+        let (_control, relay) = accept_association(&listener).await;
+        let new_relay = relay.local_addr().unwrap();
+        timeout(Duration::from_secs(5), async {
+            while socket.relay_addr() != new_relay {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        socket.send_to(b"two", &dest).await.unwrap();
+        let (n, _) = relay.recv_from(&mut buf).await.unwrap();
+        assert_eq!(&buf[..n], &Socks5UdpSocket::build_udp_packet(b"two", &dest)[..]);
+    }
 } 
\ No newline at end of file
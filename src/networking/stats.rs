@@ -0,0 +1,279 @@
+//! Per-circuit network statistics: ping RTT and jitter, inbound loss, resends and bandwidth.
+//!
+//! `StatsRecorder` is fed by the circuit's send and receive paths; `CircuitStats` is the
+//! snapshot handed out to callers (statistics panel, periodic quality logging).
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use tokio::time::{Duration, Instant};
+
+/// How long an outbound ping may go unanswered before it counts as lost.
+pub const PING_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Window over which send/receive bandwidth is averaged.
+const BANDWIDTH_WINDOW: Duration = Duration::from_secs(1);
+
+/// Number of missing inbound sequence numbers remembered so late arrivals can be un-counted.
+const MISSING_WINDOW: usize = 1024;
+
+/// Snapshot of a circuit's statistics.
+#[derive(Debug, Clone, Default)]
+pub struct CircuitStats {
+    /// RTT of the most recent StartPingCheck/CompletePingCheck exchange.
+    pub last_ping_rtt: Option<Duration>,
+    /// Smoothed RTT from acked reliable packets.
+    pub smoothed_rtt: Option<Duration>,
+    /// Mean deviation between consecutive ping RTTs.
+    pub jitter: Duration,
+    pub pings_sent: u64,
+    pub pings_lost: u64,
+    pub packets_sent: u64,
+    pub packets_received: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    /// Reliable packets we had to send again.
+    pub packets_resent: u64,
+    /// Reliable packets that ran out of resends.
+    pub packets_expired: u64,
+    /// Duplicate reliable packets from the simulator (usually our ack was lost).
+    pub duplicates_received: u64,
+    /// Inbound sequence numbers that never arrived.
+    pub packets_lost: u64,
+    pub unacked: usize,
+    /// Bits per second over the last bandwidth window.
+    pub send_bps: f64,
+    pub recv_bps: f64,
+}
+
+impl CircuitStats {
+    /// Fraction of inbound packets lost, from sequence number gaps.
+    pub fn inbound_loss(&self) -> f64 {
+        let expected = self.packets_received + self.packets_lost;
+        if expected == 0 { 0.0 } else { self.packets_lost as f64 / expected as f64 }
+    }
+
+    /// Fraction of our outbound pings that were never answered.
+    pub fn ping_loss(&self) -> f64 {
+        if self.pings_sent == 0 { 0.0 } else { self.pings_lost as f64 / self.pings_sent as f64 }
+    }
+}
+
+impl fmt::Display for CircuitStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ms = |d: Option<Duration>| d.map(|d| format!("{}ms", d.as_millis())).unwrap_or_else(|| "-".to_string());
+        write!(
+            f,
+            "ping {} srtt {} jitter {}ms loss {:.1}% resent {} unacked {} in {:.1}kbps out {:.1}kbps",
+            ms(self.last_ping_rtt),
+            ms(self.smoothed_rtt),
+            self.jitter.as_millis(),
+            self.inbound_loss() * 100.0,
+            self.packets_resent,
+            self.unacked,
+            self.recv_bps / 1000.0,
+            self.send_bps / 1000.0,
+        )
+    }
+}
+
+#[derive(Debug)]
+struct RateWindow {
+    started: Instant,
+    bytes: u64,
+    bps: f64,
+}
+
+impl RateWindow {
+    fn new(now: Instant) -> Self {
+        Self { started: now, bytes: 0, bps: 0.0 }
+    }
+
+    fn add(&mut self, bytes: usize, now: Instant) {
+        self.roll(now);
+        self.bytes += bytes as u64;
+    }
+
+    fn roll(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.started);
+        if elapsed >= BANDWIDTH_WINDOW {
+            self.bps = self.bytes as f64 * 8.0 / elapsed.as_secs_f64();
+            self.bytes = 0;
+            self.started = now;
+        }
+    }
+}
+
+/// Accumulates statistics for one circuit.
+#[derive(Debug)]
+pub struct StatsRecorder {
+    stats: CircuitStats,
+    next_ping_id: u8,
+    outstanding_pings: HashMap<u8, Instant>,
+    highest_sequence: Option<u32>,
+    missing: VecDeque<u32>,
+    missing_set: HashSet<u32>,
+    sent_rate: RateWindow,
+    recv_rate: RateWindow,
+}
+
+impl StatsRecorder {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            stats: CircuitStats::default(),
+            next_ping_id: 0,
+            outstanding_pings: HashMap::new(),
+            highest_sequence: None,
+            missing: VecDeque::new(),
+            missing_set: HashSet::new(),
+            sent_rate: RateWindow::new(now),
+            recv_rate: RateWindow::new(now),
+        }
+    }
+
+    pub fn record_sent(&mut self, bytes: usize, now: Instant) {
+        self.stats.packets_sent += 1;
+        self.stats.bytes_sent += bytes as u64;
+        self.sent_rate.add(bytes, now);
+    }
+
+    pub fn record_resent(&mut self, bytes: usize, now: Instant) {
+        self.stats.packets_resent += 1;
+        self.record_sent(bytes, now);
+    }
+
+    pub fn record_expired(&mut self, count: usize) {
+        self.stats.packets_expired += count as u64;
+    }
+
+    /// Record an inbound datagram. Gaps in the sequence are counted as lost until they show up late.
+    pub fn record_received(&mut self, sequence: u32, bytes: usize, now: Instant) {
+        self.stats.packets_received += 1;
+        self.stats.bytes_received += bytes as u64;
+        self.recv_rate.add(bytes, now);
+
+        match self.highest_sequence {
+            Some(highest) if sequence > highest => {
+                for missing in (highest + 1..sequence).rev().take(MISSING_WINDOW).rev() {
+                    self.stats.packets_lost += 1;
+                    self.missing.push_back(missing);
+                    self.missing_set.insert(missing);
+                }
+                while self.missing.len() > MISSING_WINDOW {
+                    if let Some(old) = self.missing.pop_front() {
+                        self.missing_set.remove(&old);
+                    }
+                }
+                self.highest_sequence = Some(sequence);
+            }
+            Some(_) => {
+                if self.missing_set.remove(&sequence) {
+                    self.missing.retain(|&s| s != sequence);
+                    self.stats.packets_lost -= 1;
+                }
+            }
+            None => self.highest_sequence = Some(sequence),
+        }
+    }
+
+    pub fn record_duplicate(&mut self) {
+        self.stats.duplicates_received += 1;
+    }
+
+    /// Start a new outbound ping and return its id. Pings left unanswered past `PING_TIMEOUT` count as lost.
+    pub fn start_ping(&mut self, now: Instant) -> u8 {
+        self.expire_pings(now);
+        let id = self.next_ping_id;
+        self.next_ping_id = self.next_ping_id.wrapping_add(1);
+        // A wrapped id still outstanding is far past the timeout in practice; count it lost.
+        if self.outstanding_pings.insert(id, now).is_some() {
+            self.stats.pings_lost += 1;
+        }
+        self.stats.pings_sent += 1;
+        id
+    }
+
+    /// Match a CompletePingCheck to its ping. Returns the RTT, or None for an unknown or expired id.
+    pub fn complete_ping(&mut self, ping_id: u8, now: Instant) -> Option<Duration> {
+        let sent = self.outstanding_pings.remove(&ping_id)?;
+        let rtt = now.saturating_duration_since(sent);
+        if let Some(previous) = self.stats.last_ping_rtt {
+            let delta = rtt.abs_diff(previous);
+            // RFC 3550 interarrival jitter estimator.
+            self.stats.jitter = if delta > self.stats.jitter {
+                self.stats.jitter + (delta - self.stats.jitter) / 16
+            } else {
+                self.stats.jitter - (self.stats.jitter - delta) / 16
+            };
+        }
+        self.stats.last_ping_rtt = Some(rtt);
+        Some(rtt)
+    }
+
+    pub fn snapshot(&mut self, now: Instant) -> CircuitStats {
+        self.expire_pings(now);
+        self.sent_rate.roll(now);
+        self.recv_rate.roll(now);
+        let mut stats = self.stats.clone();
+        stats.send_bps = self.sent_rate.bps;
+        stats.recv_bps = self.recv_rate.bps;
+        stats
+    }
+
+    fn expire_pings(&mut self, now: Instant) {
+        let before = self.outstanding_pings.len();
+        self.outstanding_pings.retain(|_, sent| now.saturating_duration_since(*sent) < PING_TIMEOUT);
+        self.stats.pings_lost += (before - self.outstanding_pings.len()) as u64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ping_rtt_jitter_and_loss() {
+        let start = Instant::now();
+        let mut recorder = StatsRecorder::new(start);
+
+        let first = recorder.start_ping(start);
+        assert_eq!(recorder.complete_ping(first, start + Duration::from_millis(100)), Some(Duration::from_millis(100)));
+        assert_eq!(recorder.complete_ping(first, start + Duration::from_millis(110)), None);
+
+        let second = recorder.start_ping(start + Duration::from_secs(1));
+        recorder.complete_ping(second, start + Duration::from_millis(1260));
+        let stats = recorder.snapshot(start + Duration::from_secs(2));
+        assert_eq!(stats.last_ping_rtt, Some(Duration::from_millis(260)));
+        assert_eq!(stats.jitter, Duration::from_millis(10));
+
+        recorder.start_ping(start + Duration::from_secs(3));
+        let stats = recorder.snapshot(start + Duration::from_secs(3) + PING_TIMEOUT);
+        assert_eq!(stats.pings_sent, 3);
+        assert_eq!(stats.pings_lost, 1);
+    }
+
+    #[test]
+    fn test_sequence_gaps_counted_until_late_arrival() {
+        let start = Instant::now();
+        let mut recorder = StatsRecorder::new(start);
+        for sequence in [1, 2, 5, 3] {
+            recorder.record_received(sequence, 100, start);
+        }
+        let stats = recorder.snapshot(start);
+        assert_eq!(stats.packets_received, 4);
+        assert_eq!(stats.packets_lost, 1);
+        assert!((stats.inbound_loss() - 0.2).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_bandwidth_over_window() {
+        let start = Instant::now();
+        let mut recorder = StatsRecorder::new(start);
+        recorder.record_sent(1000, start);
+        recorder.record_resent(250, start + Duration::from_millis(500));
+        let stats = recorder.snapshot(start + Duration::from_secs(1));
+        assert_eq!(stats.send_bps, 10_000.0);
+        assert_eq!(stats.packets_sent, 2);
+        assert_eq!(stats.packets_resent, 1);
+        assert_eq!(stats.recv_bps, 0.0);
+    }
+}
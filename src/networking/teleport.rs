@@ -0,0 +1,100 @@
+//! Teleport workflow: what to ask for, and the progress the simulator reports back.
+//!
+//! The networking actor sends the request on the primary circuit, then follows TeleportStart,
+//! TeleportProgress and TeleportFinish/TeleportFailed, which arrive over UDP or (for Finish and
+//! Failed on current grids) the event queue. On TeleportFinish it opens a circuit to the
+//! destination, moves the agent there and closes the old circuits.
+
+use std::net::SocketAddr;
+use uuid::Uuid;
+
+use crate::networking::protocol::builders;
+use crate::networking::protocol::generated::{self, Message};
+
+/// Where a teleport should take the agent.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TeleportTarget {
+    /// A position in region-local coordinates in the region with the given handle.
+    Location {
+        region_handle: u64,
+        position: (f32, f32, f32),
+        look_at: (f32, f32, f32),
+    },
+    /// The location stored in a landmark inventory asset.
+    Landmark { landmark_id: Uuid },
+}
+
+impl TeleportTarget {
+    /// The request to send on the primary circuit.
+    pub fn request(&self, agent_id: Uuid, session_id: Uuid) -> Message {
+        match *self {
+            TeleportTarget::Location { region_handle, position, look_at } => {
+                builders::teleport_location_request(agent_id, session_id, region_handle, position, look_at)
+            }
+            TeleportTarget::Landmark { landmark_id } => builders::teleport_landmark_request(agent_id, session_id, landmark_id),
+        }
+    }
+}
+
+/// Destination details from TeleportFinish.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TeleportFinish {
+    pub region_handle: u64,
+    pub sim_addr: SocketAddr,
+    /// Seed capability of the destination region.
+    pub seed_capability: String,
+    pub location_id: u32,
+    pub sim_access: u8,
+    pub teleport_flags: u32,
+}
+
+impl TeleportFinish {
+    pub fn from_message(msg: &generated::TeleportFinish) -> Self {
+        let info = &msg.info;
+        Self {
+            region_handle: info.region_handle,
+            sim_addr: SocketAddr::new(info.sim_ip.into(), info.sim_port),
+            seed_capability: String::from_utf8_lossy(&info.seed_capability).trim_end_matches('\0').to_string(),
+            location_id: info.location_id,
+            sim_access: info.sim_access,
+            teleport_flags: info.teleport_flags,
+        }
+    }
+}
+
+/// A step of a teleport in flight, as reported by the simulator.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TeleportUpdate {
+    Started { teleport_flags: u32 },
+    Progress { message: String },
+    /// Teleport within the current region; no circuit change.
+    Local { position: (f32, f32, f32), look_at: (f32, f32, f32) },
+    Finished(TeleportFinish),
+    Failed { reason: String },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn test_teleport_finish_from_message() {
+        let msg = generated::TeleportFinish {
+            info: generated::teleport_finish::Info {
+                agent_id: Uuid::nil(),
+                location_id: 4,
+                sim_ip: Ipv4Addr::new(10, 0, 0, 7),
+                sim_port: 13005,
+                region_handle: 0x0003_E800_0003_E900,
+                seed_capability: b"https://sim.example/cap/abc\0".to_vec(),
+                sim_access: 13,
+                teleport_flags: 1 << 4,
+            },
+        };
+        let finish = TeleportFinish::from_message(&msg);
+        assert_eq!(finish.sim_addr, "10.0.0.7:13005".parse().unwrap());
+        assert_eq!(finish.seed_capability, "https://sim.example/cap/abc");
+        assert_eq!(finish.region_handle, 0x0003_E800_0003_E900);
+    }
+}
@@ -1,15 +1,15 @@
 use tokio::net::UdpSocket;
 use tokio::time::{timeout, Duration};
-use uuid::Uuid;
 use bytes::{BytesMut, BufMut, Buf};
 use std::net::SocketAddr;
 use std::io;
-use crate::utils::lludp::{build_use_circuit_code_packet, build_lludp_packet, LluPacket, LluPacketFlags, LLUDPFrequency};
+use crate::utils::lludp::LluPacket;
 use crate::networking::protocol::messages::Message;
 use bincode::{Encode, Decode};
 use async_trait::async_trait;
 use crate::ui::proxy::ProxySettings;
 use crate::networking::socks5_udp::Socks5UdpSocket;
+use crate::networking::capture::{CaptureWriter, Direction};
 
 /// Minimal UDP message parser for Second Life protocol
 pub fn parse_message_id(packet: &[u8]) -> Option<u16> {
@@ -41,6 +41,29 @@ impl UdpSocketExt for UdpSocket {
     }
 }
 
+/// Socket wrapper that appends every datagram to a packet capture.
+pub struct CapturingSocket {
+    inner: std::sync::Arc<dyn UdpSocketExt>,
+    capture: std::sync::Arc<CaptureWriter>,
+}
+
+#[async_trait]
+impl UdpSocketExt for CapturingSocket {
+    async fn send_to(&self, buf: &[u8], target: &SocketAddr) -> io::Result<usize> {
+        let sent = self.inner.send_to(buf, target).await?;
+        self.capture.record(Direction::Outbound, *target, &buf[..sent]);
+        Ok(sent)
+    }
+    async fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        let (len, addr) = self.inner.recv_from(buf).await?;
+        self.capture.record(Direction::Inbound, addr, &buf[..len]);
+        Ok((len, addr))
+    }
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+}
+
 pub struct UdpTransport {
     socket: std::sync::Arc<dyn UdpSocketExt>,
     sim_addr: SocketAddr,
@@ -49,101 +72,53 @@ pub struct UdpTransport {
 
 impl UdpTransport {
     pub async fn new(local_port: u16, sim_addr: SocketAddr, proxy_settings: Option<&ProxySettings>) -> io::Result<Self> {
-        if let Some(proxy) = proxy_settings {
-            if proxy.enabled {
-                // Use SOCKS5 proxy, bind to the specified local_port
-                let socks5 = Socks5UdpSocket::connect(&proxy.socks5_host, proxy.socks5_port, Some(local_port)).await?;
-                let arc_socket: std::sync::Arc<dyn UdpSocketExt> = std::sync::Arc::new(socks5);
-                return UdpTransport::new_with_socket(arc_socket, sim_addr, 1).await;
-            }
-        }
-        // Use direct UDP socket, bind to the specified local_port
-        let bind_addr = format!("0.0.0.0:{}", local_port);
-        let socket = tokio::net::UdpSocket::bind(&bind_addr).await?;
-        println!("[DEBUG] UDP socket bound to {}", socket.local_addr().unwrap());
-        // TEMP: Send a test UDP packet to 127.0.0.1:54322
-        let test_addr = "127.0.0.1:54322".parse::<SocketAddr>().unwrap();
-        let test_msg = b"slv-rust authflow test";
-        match socket.send_to(test_msg, &test_addr).await {
-            Ok(sent) => println!("[TEMP TEST] Sent {} bytes to {} from auth flow UDP socket", sent, test_addr),
-            Err(e) => println!("[TEMP TEST] UDP send error in auth flow: {}", e),
-        }
-        let arc_socket: std::sync::Arc<dyn UdpSocketExt> = std::sync::Arc::new(socket);
-        UdpTransport::new_with_socket(arc_socket, sim_addr, 1).await // Start packet_id_counter at 1 // Start packet_id_counter at 1
+        let socket = Self::bind(local_port, proxy_settings).await?;
+        UdpTransport::new_with_socket(socket, sim_addr, 1).await // Start packet_id_counter at 1
+    }
+
+    /// Bind the UDP socket (directly or through the SOCKS5 proxy) without tying it to a simulator.
+    /// With `SLV_CAPTURE` set, the socket records its traffic; see `networking::capture`.
+    pub async fn bind(local_port: u16, proxy_settings: Option<&ProxySettings>) -> io::Result<std::sync::Arc<dyn UdpSocketExt>> {
+        let socket: std::sync::Arc<dyn UdpSocketExt> = if let Some((host, port)) = proxy_settings.and_then(ProxySettings::socks5_proxy) {
+            // Use SOCKS5 proxy, bind to the specified local_port
+            let credentials = proxy_settings.and_then(ProxySettings::socks5_credentials);
+            let socks5 = match timeout(Duration::from_secs(10), Socks5UdpSocket::connect(host, port, Some(local_port), credentials)).await {
+                Ok(Ok(socks5)) => socks5,
+                Ok(Err(e)) => return Err(io::Error::new(e.kind(), format!("SOCKS5 proxy {}:{} unavailable: {}", host, port, e))),
+                Err(_) => return Err(io::Error::new(io::ErrorKind::TimedOut, format!("SOCKS5 proxy {}:{} did not respond", host, port))),
+            };
+            std::sync::Arc::new(socks5)
+        } else {
+            // Use direct UDP socket, bind to the specified local_port
+            let bind_addr = format!("0.0.0.0:{}", local_port);
+            let socket = tokio::net::UdpSocket::bind(&bind_addr).await?;
+            println!("[DEBUG] UDP socket bound to {}", socket.local_addr()?);
+            std::sync::Arc::new(socket)
+        };
+        Ok(match CaptureWriter::from_env() {
+            Some(capture) => std::sync::Arc::new(CapturingSocket { inner: socket, capture }),
+            None => socket,
+        })
     }
 
     pub async fn new_with_socket(socket: std::sync::Arc<dyn UdpSocketExt>, sim_addr: SocketAddr, initial_packet_id: u32) -> io::Result<Self> {
         Ok(UdpTransport { socket, sim_addr, packet_id_counter: initial_packet_id })
     }
 
-    /// Send a UseCircuitCode packet using the LLUDP binary format (message_template.msg)
-    /// Only to be called by Circuit::advance_handshake
-    pub(crate) async fn send_usecircuitcode_packet_lludp(&mut self, circuit_code: u32, session_id: uuid::Uuid, agent_id: uuid::Uuid) -> std::io::Result<usize> {
-        let packet_id = self.packet_id_counter;
-        self.packet_id_counter += 1;
-        let packet = build_use_circuit_code_packet(circuit_code, session_id, agent_id, packet_id);
-        println!("[LLUDP OUT] UseCircuitCode (Low frequency, unencoded) seq={} to {}:", packet_id, self.sim_addr);
-        self.send_to(&packet, &self.sim_addr).await
-    }
-
-    /// Only to be called by Circuit::advance_handshake
-    pub(crate) async fn send_complete_agent_movement_packet(&mut self, agent_id: Uuid, session_id: Uuid, circuit_code: u32, position: (f32, f32, f32), look_at: (f32, f32, f32)) -> std::io::Result<usize> {
-        let packet_id = self.packet_id_counter;
-        self.packet_id_counter += 1;
-        let packet = crate::utils::lludp::build_complete_agent_movement_packet(
-            agent_id,
-            session_id,
-            circuit_code,
-            packet_id,
-            position,
-            look_at,
-        );
-        println!("[LLUDP OUT] CompleteAgentMovement (Low frequency, unencoded) seq={} to {}:", packet_id, self.sim_addr);
-        self.send_to(&packet, &self.sim_addr).await
-    }
-
-    /// Only to be called by Circuit::advance_handshake or for RegionHandshakeReply with custom sequence
-    pub(crate) async fn send_region_handshake_reply_packet_with_seq(&mut self, agent_id: Uuid, session_id: Uuid, flags: u32, sequence_id: u32) -> std::io::Result<usize> {
-        let packet = crate::utils::lludp::build_region_handshake_reply_packet(
-            agent_id,
-            session_id,
-            flags,
-            sequence_id,
-        );
-        println!("[LLUDP OUT] RegionHandshakeReply (Low frequency, unencoded) seq={} to {}:", sequence_id, self.sim_addr);
-        self.send_to(&packet, &self.sim_addr).await
-    }
-
-    /// Only to be called by Circuit::advance_handshake
-    pub(crate) async fn send_agent_throttle_packet(&mut self, agent_id: Uuid, session_id: Uuid, circuit_code: u32, throttle: [f32; 7]) -> std::io::Result<usize> {
-        let packet_id = self.packet_id_counter;
-        self.packet_id_counter += 1;
-        let packet = crate::utils::lludp::build_agent_throttle_packet(
-            agent_id,
-            session_id,
-            circuit_code,
-            throttle,
-            packet_id,
-        );
-        println!("[LLUDP OUT] AgentThrottle (Low frequency, unencoded) seq={} to {}:", packet_id, self.sim_addr);
-        self.send_to(&packet, &self.sim_addr).await
-    }
-
-    /// Only to be called by Circuit::advance_handshake
-    pub(crate) async fn send_agent_update_packet(&mut self, agent_id: Uuid, session_id: Uuid, position: (f32, f32, f32), camera_at: (f32, f32, f32), camera_eye: (f32, f32, f32), controls: u32) -> std::io::Result<usize> {
-        let packet_id = self.packet_id_counter;
-        self.packet_id_counter += 1;
-        let packet = crate::utils::lludp::build_agent_update_packet(
-            agent_id,
-            session_id,
-            position,
-            camera_at,
-            camera_eye,
-            controls,
-            packet_id,
-        );
-        println!("[LLUDP OUT] AgentUpdate (High frequency, unencoded) seq={} to {}:", packet_id, self.sim_addr);
-        self.send_to(&packet, &self.sim_addr).await
+    /// Next outgoing sequence number. Every packet on the circuit (reliable or not) takes one.
+    pub(crate) fn next_sequence(&mut self) -> u32 {
+        let sequence = self.packet_id_counter;
+        self.packet_id_counter = self.packet_id_counter.wrapping_add(1);
+        sequence
+    }
+
+    pub fn sim_addr(&self) -> SocketAddr {
+        self.sim_addr
+    }
+
+    /// The underlying socket, for a reader that demultiplexes datagrams to circuits.
+    pub(crate) fn socket(&self) -> std::sync::Arc<dyn UdpSocketExt> {
+        self.socket.clone()
     }
 
     /// Log incoming LLUDP packets (for UseCircuitCode response and others)
@@ -176,15 +151,7 @@ impl UdpTransport {
             println!("[UDP OUT] WARNING: Attempted to send packet < 7 bytes ({} bytes): {:02X?}", buf.len(), buf);
             return Ok(0);
         }
-        println!("[UdpTransport] send_to: target = {}", target);
-        // Log hex and ASCII
-        fn to_hex_ascii(data: &[u8]) -> (String, String) {
-            let hex = data.iter().map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join(" ");
-            let ascii = data.iter().map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' }).collect::<String>();
-            (hex, ascii)
-        }
-        let (hex, ascii) = to_hex_ascii(buf);
-        println!("[UDP OUT] To {} ({} bytes):\nHEX:   {}\nASCII: {}", target, buf.len(), hex, ascii);
+        tracing::trace!("[UDP OUT] {} bytes to {}", buf.len(), target);
         self.socket.send_to(buf, target).await
     }
 
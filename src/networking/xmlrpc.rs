@@ -0,0 +1,349 @@
+//! XML-RPC, the encoding of the login protocol (`login_to_simulator`).
+//!
+//! Only what the login server speaks: method calls with positional params, and responses
+//! carrying a single value or a fault. Both directions are implemented so tests can stand in
+//! for the login server.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::ops::Index;
+
+use uuid::Uuid;
+
+use crate::networking::llsd::xml::escape;
+use crate::networking::llsd::{base64_decode, base64_encode};
+
+#[derive(Debug, thiserror::Error)]
+pub enum XmlRpcError {
+    #[error("XML-RPC parse error: {0}")]
+    Xml(String),
+    #[error("XML-RPC fault {code}: {message}")]
+    Fault { code: i32, message: String },
+}
+
+pub type Struct = BTreeMap<String, Value>;
+
+/// An XML-RPC value.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Value {
+    #[default]
+    Nil,
+    Int(i32),
+    Boolean(bool),
+    String(String),
+    Double(f64),
+    /// `dateTime.iso8601`, kept as sent.
+    DateTime(String),
+    Base64(Vec<u8>),
+    Array(Vec<Value>),
+    Struct(Struct),
+}
+
+static NIL: Value = Value::Nil;
+
+impl Value {
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Struct(members) => members.get(key),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Integers, including the numeric strings the login server sends for some fields.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Value::Int(i) => Some(*i as i64),
+            Value::String(s) => s.trim().parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_i32(&self) -> Option<i32> {
+        self.as_i64().and_then(|i| i32::try_from(i).ok())
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Double(d) => Some(*d),
+            Value::Int(i) => Some(*i as f64),
+            Value::String(s) => s.trim().parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// Booleans, including the `"Y"`/`"N"` and `1`/`0` flags used throughout the login response.
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Boolean(b) => Some(*b),
+            Value::Int(i) => Some(*i != 0),
+            Value::String(s) => match s.trim() {
+                "Y" | "y" | "true" | "1" => Some(true),
+                "N" | "n" | "false" | "0" | "" => Some(false),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    pub fn as_uuid(&self) -> Option<Uuid> {
+        self.as_str().and_then(|s| Uuid::parse_str(s.trim()).ok())
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub fn as_struct(&self) -> Option<&Struct> {
+        match self {
+            Value::Struct(members) => Some(members),
+            _ => None,
+        }
+    }
+}
+
+/// Lenient lookup: a missing member is `Nil`.
+impl Index<&str> for Value {
+    type Output = Value;
+
+    fn index(&self, key: &str) -> &Value {
+        self.get(key).unwrap_or(&NIL)
+    }
+}
+
+impl Index<usize> for Value {
+    type Output = Value;
+
+    fn index(&self, index: usize) -> &Value {
+        self.as_array().and_then(|items| items.get(index)).unwrap_or(&NIL)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::String(s.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::String(s)
+    }
+}
+
+impl From<i32> for Value {
+    fn from(i: i32) -> Self {
+        Value::Int(i)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Boolean(b)
+    }
+}
+
+impl<K: Into<String>, V: Into<Value>> FromIterator<(K, V)> for Value {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        Value::Struct(iter.into_iter().map(|(k, v)| (k.into(), v.into())).collect())
+    }
+}
+
+/// Encode a `<methodCall>` document.
+pub fn method_call(method: &str, params: &[Value]) -> String {
+    let mut out = String::from(r#"<?xml version="1.0" ?><methodCall>"#);
+    let _ = write!(out, "<methodName>{}</methodName><params>", escape(method));
+    for param in params {
+        out.push_str("<param>");
+        write_value(&mut out, param);
+        out.push_str("</param>");
+    }
+    out.push_str("</params></methodCall>");
+    out
+}
+
+/// Encode a `<methodResponse>` carrying one value.
+pub fn method_response(value: &Value) -> String {
+    let mut out = String::from(r#"<?xml version="1.0" ?><methodResponse><params><param>"#);
+    write_value(&mut out, value);
+    out.push_str("</param></params></methodResponse>");
+    out
+}
+
+/// Decode a `<methodCall>` into its method name and params.
+pub fn parse_method_call(text: &str) -> Result<(String, Vec<Value>), XmlRpcError> {
+    let doc = roxmltree::Document::parse(text).map_err(|e| XmlRpcError::Xml(e.to_string()))?;
+    let root = doc.root_element();
+    if !root.has_tag_name("methodCall") {
+        return Err(XmlRpcError::Xml(format!("root element is <{}>, expected <methodCall>", root.tag_name().name())));
+    }
+    let method = root
+        .children()
+        .find(|n| n.has_tag_name("methodName"))
+        .and_then(|n| n.text())
+        .ok_or_else(|| XmlRpcError::Xml("missing <methodName>".to_string()))?;
+    let params = match root.children().find(|n| n.has_tag_name("params")) {
+        Some(params) => params
+            .children()
+            .filter(|n| n.has_tag_name("param"))
+            .map(|param| child(param).ok_or_else(|| XmlRpcError::Xml("empty <param>".to_string())).and_then(parse_value))
+            .collect::<Result<_, _>>()?,
+        None => Vec::new(),
+    };
+    Ok((method.trim().to_string(), params))
+}
+
+/// Decode a `<methodResponse>`, returning its first param or the fault it carries.
+pub fn parse_method_response(text: &str) -> Result<Value, XmlRpcError> {
+    let doc = roxmltree::Document::parse(text).map_err(|e| XmlRpcError::Xml(e.to_string()))?;
+    let root = doc.root_element();
+    if !root.has_tag_name("methodResponse") {
+        return Err(XmlRpcError::Xml(format!("root element is <{}>, expected <methodResponse>", root.tag_name().name())));
+    }
+    let body = child(root).ok_or_else(|| XmlRpcError::Xml("empty <methodResponse>".to_string()))?;
+    match body.tag_name().name() {
+        "fault" => {
+            let fault = parse_value(child(body).ok_or_else(|| XmlRpcError::Xml("empty <fault>".to_string()))?)?;
+            Err(XmlRpcError::Fault {
+                code: fault["faultCode"].as_i32().unwrap_or_default(),
+                message: fault["faultString"].as_str().unwrap_or_default().to_string(),
+            })
+        }
+        "params" => match body.children().find(|n| n.has_tag_name("param")).and_then(child) {
+            Some(value) => parse_value(value),
+            None => Ok(Value::Nil),
+        },
+        other => Err(XmlRpcError::Xml(format!("unexpected <{}> in <methodResponse>", other))),
+    }
+}
+
+fn child<'a, 'i>(node: roxmltree::Node<'a, 'i>) -> Option<roxmltree::Node<'a, 'i>> {
+    node.children().find(|n| n.is_element())
+}
+
+/// Parse a `<value>` element; a value without a type element is a string.
+fn parse_value(node: roxmltree::Node) -> Result<Value, XmlRpcError> {
+    if !node.has_tag_name("value") {
+        return Err(XmlRpcError::Xml(format!("expected <value>, found <{}>", node.tag_name().name())));
+    }
+    let Some(typed) = child(node) else {
+        return Ok(Value::String(node.text().unwrap_or("").to_string()));
+    };
+    let text = typed.text().unwrap_or("");
+    let invalid = |what: &str| XmlRpcError::Xml(format!("invalid {} {:?}", what, text));
+    Ok(match typed.tag_name().name() {
+        "i4" | "int" => Value::Int(text.trim().parse().map_err(|_| invalid("int"))?),
+        "boolean" => Value::Boolean(matches!(text.trim(), "1" | "true")),
+        "string" => Value::String(text.to_string()),
+        "double" => Value::Double(text.trim().parse().map_err(|_| invalid("double"))?),
+        "dateTime.iso8601" => Value::DateTime(text.trim().to_string()),
+        "base64" => Value::Base64(base64_decode(text).ok_or_else(|| invalid("base64"))?),
+        "nil" => Value::Nil,
+        "array" => match typed.children().find(|n| n.has_tag_name("data")) {
+            Some(data) => Value::Array(data.children().filter(|n| n.is_element()).map(parse_value).collect::<Result<_, _>>()?),
+            None => Value::Array(Vec::new()),
+        },
+        "struct" => {
+            let mut members = Struct::new();
+            for member in typed.children().filter(|n| n.has_tag_name("member")) {
+                let name = member.children().find(|n| n.has_tag_name("name")).and_then(|n| n.text()).unwrap_or("");
+                let value = match member.children().find(|n| n.has_tag_name("value")) {
+                    Some(value) => parse_value(value)?,
+                    None => Value::Nil,
+                };
+                members.insert(name.to_string(), value);
+            }
+            Value::Struct(members)
+        }
+        other => return Err(XmlRpcError::Xml(format!("unknown element <{}>", other))),
+    })
+}
+
+fn write_value(out: &mut String, value: &Value) {
+    out.push_str("<value>");
+    match value {
+        Value::Nil => out.push_str("<nil/>"),
+        Value::Int(i) => {
+            let _ = write!(out, "<int>{}</int>", i);
+        }
+        Value::Boolean(b) => {
+            let _ = write!(out, "<boolean>{}</boolean>", *b as u8);
+        }
+        Value::String(s) => {
+            let _ = write!(out, "<string>{}</string>", escape(s));
+        }
+        Value::Double(d) => {
+            let _ = write!(out, "<double>{:?}</double>", d);
+        }
+        Value::DateTime(s) => {
+            let _ = write!(out, "<dateTime.iso8601>{}</dateTime.iso8601>", escape(s));
+        }
+        Value::Base64(bytes) => {
+            let _ = write!(out, "<base64>{}</base64>", base64_encode(bytes));
+        }
+        Value::Array(items) => {
+            out.push_str("<array><data>");
+            for item in items {
+                write_value(out, item);
+            }
+            out.push_str("</data></array>");
+        }
+        Value::Struct(members) => {
+            out.push_str("<struct>");
+            for (name, item) in members {
+                let _ = write!(out, "<member><name>{}</name>", escape(name));
+                write_value(out, item);
+                out.push_str("</member>");
+            }
+            out.push_str("</struct>");
+        }
+    }
+    out.push_str("</value>");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_value_roundtrip_and_escaping() {
+        let value: Value = [
+            ("passwd", Value::from("$1$a<b&c")),
+            ("port", Value::Int(13000)),
+            ("flag", Value::Boolean(true)),
+            ("scale", Value::Double(0.5)),
+            ("blob", Value::Base64(vec![1, 2, 3])),
+            ("options", Value::Array(vec![Value::from("buddy-list"), Value::Nil])),
+        ]
+        .into_iter()
+        .collect();
+        let call = method_call("login_to_simulator", &[value.clone()]);
+        assert!(call.contains("<string>$1$a&lt;b&amp;c</string>"));
+        assert_eq!(parse_method_call(&call).unwrap(), ("login_to_simulator".to_string(), vec![value.clone()]));
+        assert_eq!(parse_method_response(&method_response(&value)).unwrap(), value);
+    }
+
+    #[test]
+    fn test_untyped_value_and_fault() {
+        let value = parse_method_response(
+            "<methodResponse><params><param><value><struct><member><name>login</name><value>false</value></member></struct></value></param></params></methodResponse>",
+        )
+        .unwrap();
+        assert_eq!(value["login"].as_str(), Some("false"));
+        assert_eq!(value["missing"], Value::Nil);
+
+        let fault = parse_method_response(
+            "<methodResponse><fault><value><struct><member><name>faultCode</name><value><int>4</int></value></member><member><name>faultString</name><value><string>Too many parameters</string></value></member></struct></value></fault></methodResponse>",
+        );
+        assert!(matches!(fault, Err(XmlRpcError::Fault { code: 4, ref message }) if message == "Too many parameters"));
+    }
+}
@@ -3,9 +3,8 @@
 // TODO: Implement HUD and settings panels here
 
 use crate::ui::{UiState, LoginUiState, LoginProgress, LoginResult, UdpConnectionProgress};
-use crate::networking::session::{login_to_secondlife, LoginRequest, LoginSessionInfo};
-use crate::networking::circuit::Circuit;
-use crate::networking::protocol::messages::Message;
+use crate::networking::session::{hash_password, login_to_secondlife, LoginOutcome, LoginRequest, LoginSessionInfo};
+use crate::networking::actor::{NetCommand, NetEvent};
 use std::net::SocketAddr;
 use crossbeam_channel::{unbounded, Sender, Receiver};
 use tokio::task::JoinHandle;
@@ -17,16 +16,16 @@ use eframe::egui;
 use crate::networking::socks5_udp::Socks5UdpSocket;
 use crate::networking::transport::UdpTransport;
 use crate::config::settings;
-use crate::networking::session::fetch_tos_html;
+use crate::config::accounts::SavedAccount;
+use crate::networking::session::{fetch_grid_info, fetch_tos_html};
 use scraper::{Html, Selector, ElementRef};
 use egui::{RichText, Ui};
 use bytes::BufMut;
 use crate::ui::AgentState;
-use roxmltree::Document;
+use crate::networking::llsd::Llsd;
 use rand::Rng;
 use std::net::UdpSocket as StdUdpSocket;
 use crate::utils::lludp::{LluPacket, LluPacketFlags};
-use tokio::sync::oneshot;
 
 fn render_tos_html(ui: &mut Ui, html: &str) {
     let document = Html::parse_document(html);
@@ -92,61 +91,29 @@ fn render_tos_html(ui: &mut Ui, html: &str) {
     }
 }
 
-fn parse_agent_state_update(llsd_xml: &str) -> Option<AgentState> {
-    let doc = Document::parse(llsd_xml).ok()?;
-    let mut state = AgentState::default();
-    let map = doc.descendants().find(|n| n.has_tag_name("map"))?;
-    for (k, v) in map.children().collect::<Vec<_>>().chunks(2).filter(|c| c.len() == 2).map(|c| (c[0].text(), &c[1])) {
-        match k? {
-            "can_modify_navmesh" => state.can_modify_navmesh = v.text().unwrap_or("") == "true",
-            "has_modified_navmesh" => state.has_modified_navmesh = v.text().unwrap_or("") == "true",
-            "preferences" => {
-                for (pk, pv) in v.children().collect::<Vec<_>>().chunks(2).filter(|c| c.len() == 2).map(|c| (c[0].text(), &c[1])) {
-                    match pk? {
-                        "god_level" => state.god_level = pv.text().unwrap_or("0").parse().unwrap_or(0),
-                        "hover_height" => state.hover_height = pv.text().unwrap_or("0.0").parse().unwrap_or(0.0),
-                        "language" => state.language = pv.text().unwrap_or("").to_string(),
-                        "language_is_public" => state.language_is_public = pv.text().unwrap_or("") == "true",
-                        "access_prefs" => {
-                            for (ak, av) in pv.children().collect::<Vec<_>>().chunks(2).filter(|c| c.len() == 2).map(|c| (c[0].text(), &c[1])) {
-                                if ak? == "max" {
-                                    state.access_prefs_max = av.text().unwrap_or("").to_string();
-                                }
-                            }
-                        }
-                        "default_object_perm_masks" => {
-                            let mut everyone = 0;
-                            let mut group = 0;
-                            let mut next_owner = 0;
-                            for (dk, dv) in pv.children().collect::<Vec<_>>().chunks(2).filter(|c| c.len() == 2).map(|c| (c[0].text(), &c[1])) {
-                                match dk? {
-                                    "Everyone" => everyone = dv.text().unwrap_or("0").parse().unwrap_or(0),
-                                    "Group" => group = dv.text().unwrap_or("0").parse().unwrap_or(0),
-                                    "NextOwner" => next_owner = dv.text().unwrap_or("0").parse().unwrap_or(0),
-                                    _ => {}
-                                }
-                            }
-                            state.default_object_perm_masks = (everyone, group, next_owner);
-                        }
-                        _ => {}
-                    }
-                }
-            }
-            _ => {}
-        }
+fn agent_state_from_llsd(body: &Llsd) -> AgentState {
+    let prefs = &body["preferences"];
+    let masks = &prefs["default_object_perm_masks"];
+    AgentState {
+        can_modify_navmesh: body["can_modify_navmesh"].as_bool().unwrap_or(false),
+        has_modified_navmesh: body["has_modified_navmesh"].as_bool().unwrap_or(false),
+        god_level: prefs["god_level"].as_integer().unwrap_or(0),
+        hover_height: prefs["hover_height"].as_real().unwrap_or(0.0) as f32,
+        language: prefs["language"].as_string().unwrap_or_default(),
+        language_is_public: prefs["language_is_public"].as_bool().unwrap_or(false),
+        access_prefs_max: prefs["access_prefs"]["max"].as_string().unwrap_or_default(),
+        default_object_perm_masks: (
+            masks["Everyone"].as_integer().unwrap_or(0),
+            masks["Group"].as_integer().unwrap_or(0),
+            masks["NextOwner"].as_integer().unwrap_or(0),
+        ),
     }
-    Some(state)
 }
 
-fn handle_agent_state_update_event(llsd_xml: &str, ui_state: &mut UiState) {
-    if let Some(agent_state) = parse_agent_state_update(llsd_xml) {
-        ui_state.agent_state = Some(agent_state.clone());
-        println!("[AgentStateUpdate] Parsed: {:?}", agent_state);
-    }
-}
-
-pub struct UdpConnectResult {
-    pub result: Result<std::sync::Arc<tokio::sync::Mutex<Circuit>>, String>,
+fn handle_agent_state_update_event(body: &Llsd, ui_state: &mut UiState) {
+    let agent_state = agent_state_from_llsd(body);
+    ui_state.agent_state = Some(agent_state.clone());
+    println!("[AgentStateUpdate] Parsed: {:?}", agent_state);
 }
 
 // Remove pick_random_udp_port from this file except for UiState::default.
@@ -156,7 +123,19 @@ pub fn show_main_window(ctx: &egui::Context, ui_state: &mut UiState) {
     // Poll for login result
     while let Ok(result) = ui_state.login_result_rx.try_recv() {
         match result.result {
-            Ok(session_info) => {
+            Ok(LoginOutcome::Success(session_info)) => {
+                if let (Some(mfa_hash), Some(store)) = (&session_info.mfa_hash, &ui_state.account_store) {
+                    if let Err(e) = store.save_mfa_hash(&result.first, &result.last, &result.grid, mfa_hash) {
+                        tracing::warn!("[ACCOUNTS] Failed to save MFA hash: {}", e);
+                    }
+                }
+                if let (Some(account), Some(store)) = (ui_state.login_state.pending_account.take(), &ui_state.account_store) {
+                    match store.remember(account).and_then(|_| store.load()) {
+                        Ok(accounts) => ui_state.login_state.saved_accounts = accounts,
+                        Err(e) => tracing::warn!("[ACCOUNTS] Failed to save account: {}", e),
+                    }
+                }
+                let session_info = *session_info;
                 ui_state.login_progress = LoginProgress::Success;
                 ui_state.login_ui_state = LoginUiState::MainApp;
                 ui_state.login_state.session_info = Some(session_info.clone());
@@ -165,280 +144,123 @@ pub fn show_main_window(ctx: &egui::Context, ui_state: &mut UiState) {
                 // Add a small delay to ensure proxy can process login
                 std::thread::sleep(std::time::Duration::from_millis(100));
                 println!("[DEBUG] Starting UDP handshake and EQ polling now.");
-                // Start UDP connection
-                if let (Ok(ip), port) = (session_info.sim_ip.parse(), session_info.sim_port) {
-                    let sim_addr = SocketAddr::new(ip, port);
-                    ui_state.udp_progress = UdpConnectionProgress::Connecting;
-                    let udp_connect_tx = ui_state.udp_connect_tx.clone();
-                    let session_udp_port = ui_state.session_udp_port;
-                    let proxy_settings = ui_state.proxy_settings.clone();
-                    let session_info = session_info.clone();
-                    let ui_event_tx = ui_state.ui_event_tx.clone();
-                    // --- Coordination channels ---
-                    let (udp_handshake_tx, mut udp_handshake_rx) = oneshot::channel::<()>();
-                    let (eq_ready_tx, mut eq_ready_rx) = oneshot::channel::<()>();
-                    // --- Start EQ polling ---
-                    let eq_caps_info = session_info.clone();
-                    let eq_proxy_settings = proxy_settings.clone();
-                    let eq_ui_event_tx = ui_event_tx.clone();
-                    let udp_connect_tx2 = udp_connect_tx.clone();
-                    let session_udp_port2 = session_udp_port;
-                    let proxy_settings2 = proxy_settings.clone();
-                    let session_info2 = session_info.clone();
-                    tokio::spawn(async move {
-                        let mut capabilities = eq_caps_info.capabilities.clone();
-                        if capabilities.is_none() {
-                            match crate::networking::session::fetch_seed_capabilities(
-                                &eq_caps_info.seed_capability,
-                                session_udp_port2,
-                                Some(&eq_proxy_settings),
-                                eq_caps_info.session_cookie.as_deref(),
-                            ).await {
-                                Ok(caps) => {
-                                    capabilities = Some(caps);
-                                }
-                                Err(e) => {
-                                    eprintln!("[CAPS] Failed to fetch seed capabilities: {}", e);
-                                }
-                            }
-                        }
-                        if let Some(caps) = capabilities {
-                            let mut eq_ready_tx = Some(eq_ready_tx);
-                            let eq_ui_event_tx = eq_ui_event_tx.clone();
-                            let udp_connect_tx2 = udp_connect_tx2.clone();
-                            let proxy_settings2 = proxy_settings2.clone();
-                            let session_info2 = session_info2.clone();
-                            let mut udp_socket_opened = false;
-                            let mut udp_circuit_mutex: Option<std::sync::Arc<tokio::sync::Mutex<Circuit>>> = None;
-                            let _ = crate::networking::session::poll_event_queue(&caps, session_udp_port2, Some(&eq_proxy_settings), move |event_xml| {
-                                // Forward all events to UI as before
-                                let _ = eq_ui_event_tx.send(crate::ui::UiEvent::AgentStateUpdate(event_xml.clone()));
-                                // Look for sim address messages
-                                if !udp_socket_opened {
-                                    if let Some((ip, port)) = parse_sim_address_from_event(&event_xml) {
-                                        // Open UDP socket and start handshake
-                                        let sim_addr = match ip.parse() {
-                                            Ok(ip) => std::net::SocketAddr::new(ip, port),
-                                            Err(_) => return,
-                                        };
-                                        let udp_tx = udp_connect_tx2.clone();
-                                        let proxy_settings = proxy_settings2.clone();
-                                        let session_info = session_info2.clone();
-                                        let session_udp_port = session_udp_port2;
-                                        tokio::spawn(async move {
-                                            match crate::networking::transport::UdpTransport::new(session_udp_port, sim_addr, Some(&proxy_settings)).await {
-                                                Ok(mut udp) => {
-                                                    let session_id = uuid::Uuid::parse_str(&session_info.session_id).unwrap_or_default();
-                                                    let agent_id = uuid::Uuid::parse_str(&session_info.agent_id).unwrap_or_default();
-                                                    let circuit_code = session_info.circuit_code;
-                                                    // Create Circuit and start handshake as before
-                                                    let agent_state = std::sync::Arc::new(tokio::sync::Mutex::new(crate::networking::circuit::AgentState {
-                                                        position: (0.0, 0.0, 0.0),
-                                                        camera_at: (0.0, 0.0, 0.0),
-                                                        camera_eye: (0.0, 0.0, 0.0),
-                                                        controls: 0,
-                                                    }));
-                                                    let mut circuit = crate::networking::circuit::Circuit::new_with_transport(
-                                                        std::sync::Arc::new(tokio::sync::Mutex::new(udp)),
-                                                        agent_state
-                                                    ).await.unwrap();
-                                                    // Start handshake
-                                                    circuit.advance_handshake(agent_id, session_id, circuit_code, (0.0,0.0,0.0), (0.0,0.0,0.0), [207360.0, 165376.0, 33075.19921875, 33075.19921875, 682700.75, 682700.75, 269312.0], 0, 0, (0.0,0.0,0.0), (0.0,0.0,0.0)).await;
-                                                    circuit.advance_handshake(agent_id, session_id, circuit_code, (0.0,0.0,0.0), (0.0,0.0,0.0), [207360.0, 165376.0, 33075.19921875, 33075.19921875, 682700.75, 682700.75, 269312.0], 0, 0, (0.0,0.0,0.0), (0.0,0.0,0.0)).await;
-                                                    // Start receive loop
-                                                    let circuit_mutex = std::sync::Arc::new(tokio::sync::Mutex::new(circuit));
-                                                    let _ = udp_tx.send(UdpConnectResult { result: Ok(circuit_mutex.clone()) });
-                                                }
-                                                Err(e) => {
-                                                    let _ = udp_tx.send(UdpConnectResult { result: Err(format!("Failed to bind UDP socket: {e}")) });
-                                                }
-                                            }
-                                        });
-                                        udp_socket_opened = true;
-                                    }
-                                }
-                                // Optionally, signal EQ ready if region handshake or enable simulator
-                                if event_xml.contains("EnableSimulator") || event_xml.contains("RegionHandshake") {
-                                    if let Some(tx) = eq_ready_tx.take() {
-                                        let _ = tx.send(());
-                                    }
-                                }
-                            }).await;
-                        }
-                    });
-                } else {
-                    ui_state.udp_progress = UdpConnectionProgress::Error("Invalid sim IP/port".to_string());
+                // Start UDP connection; the networking actor opens the circuit, runs the handshake
+                // and polls the region's event queue
+                match session_info.sim_ip.parse::<std::net::IpAddr>() {
+                    Ok(ip) => {
+                        ui_state.udp_progress = UdpConnectionProgress::Connecting;
+                        ui_state.net.send(NetCommand::Connect {
+                            session: Box::new(session_info.clone()),
+                            sim_addr: std::net::SocketAddr::new(ip, session_info.sim_port),
+                            udp_port: ui_state.session_udp_port,
+                            proxy_settings: Some(ui_state.proxy_settings.clone()),
+                        });
+                    }
+                    Err(_) => {
+                        ui_state.udp_progress = UdpConnectionProgress::Error("Invalid sim IP/port".to_string());
+                    }
                 }
             }
+            Ok(LoginOutcome::TosRequired { message }) => {
+                ui_state.tos_required = true;
+                ui_state.tos_html = Some(message.clone()); // Replaced by the fetched ToS page when it arrives
+                ui_state.tos_message = Some(message);
+                // Block login until user accepts
+                ui_state.login_progress = LoginProgress::Idle;
+                ui_state.login_state.status_message = "You must accept the Terms of Service to continue.".to_string();
+            }
+            Ok(LoginOutcome::CriticalMessage { message }) => {
+                ui_state.tos_required = true;
+                ui_state.tos_html = Some(message.clone());
+                ui_state.tos_message = Some(message);
+                // Block login until user accepts
+                ui_state.login_progress = LoginProgress::Idle;
+                ui_state.login_state.read_critical_next_login = true; // Set read_critical for next login
+                ui_state.login_state.status_message = "You must read and accept a critical message to continue.".to_string();
+            }
+            Ok(LoginOutcome::MfaChallenge { message }) => {
+                ui_state.login_progress = LoginProgress::Idle;
+                ui_state.login_state.mfa_token.clear();
+                ui_state.login_state.mfa_message = Some(message);
+                ui_state.login_state.status_message = "Enter your authentication code to continue.".to_string();
+            }
+            Ok(LoginOutcome::UpdateRequired { message, update_url, .. }) => {
+                let message = match update_url {
+                    Some(url) => format!("{} ({})", message, url),
+                    None => message,
+                };
+                ui_state.login_progress = LoginProgress::Error(message);
+            }
+            Ok(outcome) => {
+                let message = outcome.message().unwrap_or_default().to_string();
+                ui_state.login_progress = LoginProgress::Error(message);
+            }
             Err(err_msg) => {
-                // Robust ToS/critical message detection
-                if err_msg.starts_with("TOS_REQUIRED::") {
-                    let message = err_msg.trim_start_matches("TOS_REQUIRED::").to_string();
-                    ui_state.tos_required = true;
-                    ui_state.tos_html = Some(message.clone()); // TODO: fetch real ToS HTML if available
-                    ui_state.tos_message = Some(message);
-                    // Block login until user accepts
-                    ui_state.login_progress = LoginProgress::Idle;
-                    ui_state.login_state.agree_to_tos_next_login = true;
-                    ui_state.login_state.status_message = "You must accept the Terms of Service to continue.".to_string();
-                    // TODO: Extract and store tos_id if available
-                } else if err_msg.starts_with("CRITICAL_REQUIRED::") {
-                    let message = err_msg.trim_start_matches("CRITICAL_REQUIRED::").to_string();
-                    ui_state.tos_required = true;
-                    ui_state.tos_html = Some(message.clone()); // TODO: fetch real critical message HTML if available
-                    ui_state.tos_message = Some(message);
-                    // Block login until user accepts
-                    ui_state.login_progress = LoginProgress::Idle;
-                    ui_state.login_state.read_critical_next_login = true; // Set read_critical for next login
-                    ui_state.login_state.status_message = "You must read and accept a critical message to continue.".to_string();
-                } else {
-                    ui_state.login_progress = LoginProgress::Error(err_msg);
-                }
+                ui_state.login_progress = LoginProgress::Error(err_msg);
             }
         }
     }
 
-    // Poll for UDP connection result
-    while let Ok(result) = ui_state.udp_connect_rx.try_recv() {
-        match result.result {
-            Ok(circuit_mutex) => {
-                ui_state.udp_progress = UdpConnectionProgress::Connected;
-                ui_state.udp_circuit = Some(circuit_mutex.clone());
+    // Poll for events from the networking actor
+    while let Ok(event) = ui_state.net_events.try_recv() {
+        match event {
+            NetEvent::Connecting { .. } => {
+                ui_state.udp_progress = UdpConnectionProgress::Connecting;
                 ui_state.login_ui_state = LoginUiState::LoadingWorld;
-                // Spawn world entry listener task
-                let world_entry_tx = ui_state.udp_connect_tx.clone();
-                let circuit_mutex_clone = circuit_mutex.clone();
-                let session_info = ui_state.login_state.session_info.clone();
-                let proxy_settings = ui_state.proxy_settings.clone();
-                let ui_event_tx = ui_state.ui_event_tx.clone();
-                let session_udp_port = ui_state.session_udp_port;
-                let handle = tokio::spawn(async move {
-                    // Wait for first message from sim (now: look for AgentMovementComplete)
-                    let entry_result = tokio::time::timeout(std::time::Duration::from_secs(5), async {
-                        let mut circuit = circuit_mutex_clone.lock().await;
-                        circuit.recv_message().await
-                    }).await;
-                    match entry_result {
-                        Ok(Ok((_header, Message::AgentMovementComplete { agent_id: _, session_id: _ /* TODO: extract more fields */ }, _addr))) => {
-                            // World entry success
-                            let _ = world_entry_tx.send(UdpConnectResult { result: Ok(circuit_mutex_clone.clone()) });
-                            // --- Region Handshake sequence ---
-                            let mut circuit = circuit_mutex_clone.lock().await;
-                            // Wait for RegionHandshake (loop until received)
-                            let mut region_handshake_received = false;
-                            while !region_handshake_received {
-                                match circuit.recv_message().await {
-                                    Ok((_header, msg, addr)) => {
-                                        match msg {
-                                            Message::RegionHandshake { .. } => {
-                                    // Send RegionHandshakeReply
-                                    let reply = Message::RegionHandshakeReply {
-                                        agent_id: session_info.as_ref().map(|s| s.agent_id.clone()).unwrap_or_default(),
-                                        session_id: session_info.as_ref().map(|s| s.session_id.clone()).unwrap_or_default(),
-                                        flags: 0x07, // SUPPORTS_SELF_APPEARANCE | VOCACHE_CULLING_ENABLED (example)
-                                    };
-                                                match circuit.send_message(&reply, &addr).await {
-                                                    Ok(_) => println!("[DEBUG] Sent RegionHandshakeReply"),
-                                                    Err(e) => eprintln!("[ERROR] Failed to send RegionHandshakeReply: {e}"),
-                                                }
-                                    // Send AgentThrottle
-                                    let throttle = Message::AgentThrottle {
-                                        agent_id: session_info.as_ref().map(|s| s.agent_id.clone()).unwrap_or_default(),
-                                        session_id: session_info.as_ref().map(|s| s.session_id.clone()).unwrap_or_default(),
-                                        circuit_code: session_info.as_ref().map(|s| s.circuit_code).unwrap_or(0),
-                                        throttle: [1000.0, 1000.0, 1000.0, 1000.0, 1000.0, 1000.0, 1000.0], // placeholder values
-                                    };
-                                    let _ = circuit.send_message(&throttle, &addr).await;
-                                    // Send AgentUpdate
-                                    let update = Message::AgentUpdate {
-                                        agent_id: session_info.as_ref().map(|s| s.agent_id.clone()).unwrap_or_default(),
-                                        session_id: session_info.as_ref().map(|s| s.session_id.clone()).unwrap_or_default(),
-                                        position: (128.0, 128.0, 25.0), // placeholder
-                                        camera_at: (0.0, 1.0, 0.0),
-                                        camera_eye: (128.0, 128.0, 25.0),
-                                        controls: 0,
-                                    };
-                                    let _ = circuit.send_message(&update, &addr).await;
-                                                region_handshake_received = true;
-                                            }
-                                            other => {
-                                                println!("[DEBUG] Received message before RegionHandshake: {:?}", other);
-                                            }
-                                        }
-                                    }
-                                    Err(e) => {
-                                        eprintln!("[ERROR] UDP receive error while waiting for RegionHandshake: {e}");
-                                        break;
-                                    }
-                                }
-                            }
-                            // --- Fetch seed capabilities and start event queue polling ---
-                            if let Some(session_info) = &session_info {
-                                let mut capabilities = session_info.capabilities.clone();
-                                // If not present, fetch them
-                                if capabilities.is_none() {
-                                    match crate::networking::session::fetch_seed_capabilities(
-                                        &session_info.seed_capability,
-                                        session_udp_port,
-                                        Some(&proxy_settings),
-                                        session_info.session_cookie.as_deref(),
-                                    ).await {
-                                        Ok(caps) => {
-                                            capabilities = Some(caps);
-                                        }
-                                        Err(e) => {
-                                            eprintln!("[CAPS] Failed to fetch seed capabilities: {}", e);
-                                        }
-                                    }
-                                }
-                                if let Some(caps) = capabilities {
-                                    // Start event queue polling
-                                    let ui_event_tx = ui_event_tx.clone();
-                                    tokio::spawn(async move {
-                                        let _ = crate::networking::session::poll_event_queue(&caps, session_udp_port, Some(&proxy_settings), move |event_xml| {
-                                            // For now, just forward the raw XML as an AgentStateUpdate event
-                                            let _ = ui_event_tx.send(crate::ui::UiEvent::AgentStateUpdate(event_xml));
-                                        }).await;
-                                    });
-                                }
-                            }
-                        }
-                        Ok(Ok((_header, _msg, _addr))) => {
-                            // Unexpected message, treat as error or ignore
-                            let _ = world_entry_tx.send(UdpConnectResult { result: Err("Unexpected message instead of AgentMovementComplete".to_string()) });
-                        }
-                        Ok(Err(e)) => {
-                            let _ = world_entry_tx.send(UdpConnectResult { result: Err(format!("UDP receive error: {e}")) });
-                        }
-                        Err(_) => {
-                            let _ = world_entry_tx.send(UdpConnectResult { result: Err("World entry timed out".to_string()) });
-                        }
-                    }
-                });
-                ui_state.udp_connect_task = Some(handle);
-
-                // Add to UiState:
-                // pub chat_event_rx: Option<Receiver<(String, String)>>,
-                // pub chat_event_tx: Option<Sender<(String, String)>>,
-
-                // In the UDP connection result handler, after world entry:
-                // let (chat_event_tx, chat_event_rx) = unbounded();
-                // ui_state.chat_event_tx = Some(chat_event_tx.clone());
-                // ui_state.chat_event_rx = Some(chat_event_rx);
-                // let mut circuit = circuit.clone();
-                // tokio::spawn(async move {
-                //     loop {
-                //         if let Ok((_header, msg, _addr)) = circuit.recv_message().await {
-                //             if let Message::ChatFromSimulator { sender, message, .. } = msg {
-                //                 let _ = chat_event_tx.send((sender, message));
-                //             }
-                //         }
-                //     }
-                // });
-            }
-            Err(msg) => {
+            }
+            NetEvent::Connected { .. } => {
+                // The handshake only completes once the simulator has placed the agent
+                ui_state.udp_progress = UdpConnectionProgress::Connected;
+                ui_state.login_ui_state = LoginUiState::InWorld;
+            }
+            NetEvent::ConnectFailed(msg) => {
                 ui_state.udp_progress = UdpConnectionProgress::Error(msg);
             }
+            NetEvent::AgentMovementComplete { region_handle, position } => {
+                tracing::info!("[NET] AgentMovementComplete in region {} at {:?}", region_handle, position);
+            }
+            NetEvent::ChatReceived { from_name, message, .. } => {
+                chat::append_incoming_chat(&mut ui_state.chat_messages, &from_name, &message);
+            }
+            NetEvent::ChatSessionInvitation { from_name, message, .. } => {
+                chat::append_incoming_chat(&mut ui_state.chat_messages, &from_name, &message);
+            }
+            NetEvent::AgentStateUpdate(body) => {
+                handle_agent_state_update_event(&body, ui_state);
+            }
+            NetEvent::TeleportStarted => {
+                chat::append_incoming_chat(&mut ui_state.chat_messages, "Teleport", "Teleport started");
+            }
+            NetEvent::TeleportProgress { message } => {
+                chat::append_incoming_chat(&mut ui_state.chat_messages, "Teleport", &message);
+            }
+            NetEvent::TeleportFinished { region_handle, sim_addr } => {
+                tracing::info!("[NET] Teleport finished in region {} via {}", region_handle, sim_addr);
+            }
+            NetEvent::TeleportFailed { reason } => {
+                chat::append_incoming_chat(&mut ui_state.chat_messages, "Teleport failed", &reason);
+            }
+            NetEvent::RegionCrossed { region_handle, sim_addr } => {
+                tracing::info!("[NET] Crossed into region {} via {}", region_handle, sim_addr);
+            }
+            NetEvent::RegionEnabled { region_handle, sim_addr } => {
+                tracing::info!("[NET] Child agent in region {} via {}", region_handle, sim_addr);
+            }
+            NetEvent::RegionDisabled { region_handle } => {
+                tracing::info!("[NET] Left neighbouring region {}", region_handle);
+            }
+            NetEvent::CircuitDead { sim_addr } => {
+                ui_state.udp_progress = UdpConnectionProgress::Error(format!("Lost connection to {}", sim_addr));
+            }
+            NetEvent::LoggedOut => {
+                ui_state.udp_progress = UdpConnectionProgress::NotStarted;
+            }
+            NetEvent::Stats(stats) => {
+                tracing::debug!("[NET] {}", stats);
+            }
+            NetEvent::Error(msg) => {
+                tracing::warn!("[NET] {}", msg);
+            }
         }
     }
 
@@ -451,12 +273,18 @@ pub fn show_main_window(ctx: &egui::Context, ui_state: &mut UiState) {
                 ui_state.tos_html = Some(tos_html);
                 ui_state.tos_message = Some(message);
             }
-            crate::ui::UiEvent::AgentStateUpdate(llsd_xml) => {
-                handle_agent_state_update_event(&llsd_xml, ui_state);
-            }
             crate::ui::UiEvent::InWorldReady => {
                 ui_state.login_ui_state = LoginUiState::InWorld;
             }
+            crate::ui::UiEvent::GridImported(Ok(grid)) => {
+                ui_state.grid_import_status = Some(format!("Added {}", grid.name));
+                ui_state.grids.selected = grid.nick.clone();
+                ui_state.grids.insert(grid);
+                let _ = settings::save_grids(&ui_state.grids);
+            }
+            crate::ui::UiEvent::GridImported(Err(e)) => {
+                ui_state.grid_import_status = Some(format!("Import failed: {}", e));
+            }
             // Handle other events as needed
         }
     }
@@ -483,16 +311,64 @@ pub fn show_main_window(ctx: &egui::Context, ui_state: &mut UiState) {
                         ui.label("Port:");
                         changed |= ui.add(egui::DragValue::new(&mut ui_state.proxy_settings.socks5_port).range(1..=65535)).changed();
                     });
+                    ui.horizontal(|ui| {
+                        ui.label("SOCKS5 Username:");
+                        changed |= ui.text_edit_singleline(&mut ui_state.proxy_settings.socks5_username).changed();
+                        ui.label("Password:");
+                        changed |= ui.add(egui::TextEdit::singleline(&mut ui_state.proxy_settings.socks5_password).password(true)).changed();
+                    });
                     ui.horizontal(|ui| {
                         ui.label("HTTP Proxy Host:");
                         changed |= ui.text_edit_singleline(&mut ui_state.proxy_settings.http_host).changed();
                         ui.label("Port:");
                         changed |= ui.add(egui::DragValue::new(&mut ui_state.proxy_settings.http_port).range(1..=65535)).changed();
                     });
+                    ui.horizontal(|ui| {
+                        ui.label("CA Bundle (PEM):");
+                        changed |= ui.text_edit_singleline(&mut ui_state.proxy_settings.ca_bundle_path).changed();
+                    });
                     changed |= ui.checkbox(&mut ui_state.proxy_settings.disable_cert_validation, "Disable HTTPS Certificate Validation").changed();
                 }
                 if changed {
                     let _ = settings::save_general_settings(&ui_state.preferences, &ui_state.proxy_settings);
+                    if let Some(store) = &ui_state.account_store {
+                        if let Err(e) = store.save_socks5_password(&ui_state.proxy_settings.socks5_password) {
+                            tracing::warn!("[ACCOUNTS] Failed to save the SOCKS5 password: {}", e);
+                        }
+                    }
+                }
+                ui.separator();
+                ui.heading("Grids");
+                let mut removed = None;
+                for grid in &ui_state.grids.grids {
+                    ui.horizontal(|ui| {
+                        ui.label(format!("{} ({:?})", grid.name, grid.kind));
+                        ui.label(grid.login_uri.as_str());
+                        if ui.add_enabled(ui_state.grids.grids.len() > 1, egui::Button::new("Remove")).clicked() {
+                            removed = Some(grid.nick.clone());
+                        }
+                    });
+                }
+                if let Some(nick) = removed {
+                    ui_state.grids.remove(&nick);
+                    let _ = settings::save_grids(&ui_state.grids);
+                }
+                ui.horizontal(|ui| {
+                    ui.label("OpenSim Login URI:");
+                    ui.text_edit_singleline(&mut ui_state.grid_import_uri);
+                    if ui.add_enabled(!ui_state.grid_import_uri.trim().is_empty(), egui::Button::new("Import")).clicked() {
+                        let login_uri = ui_state.grid_import_uri.trim().to_string();
+                        let proxy_settings = ui_state.proxy_settings.clone();
+                        let ui_event_tx = ui_state.ui_event_tx.clone();
+                        ui_state.grid_import_status = Some("Importing...".to_string());
+                        tokio::spawn(async move {
+                            let result = fetch_grid_info(&login_uri, Some(&proxy_settings)).await;
+                            let _ = ui_event_tx.send(crate::ui::UiEvent::GridImported(result));
+                        });
+                    }
+                });
+                if let Some(status) = &ui_state.grid_import_status {
+                    ui.label(status.as_str());
                 }
                 ui.separator();
                 if ui.button("Close").clicked() {
@@ -501,9 +377,10 @@ pub fn show_main_window(ctx: &egui::Context, ui_state: &mut UiState) {
                 // A. Add a Test ToS Modal button for manual testing
                 if ui.button("Test ToS Modal").clicked() {
                     let ui_event_tx = ui_state.ui_event_tx.clone();
+                    let grid = ui_state.grids.selected();
                     tokio::spawn(async move {
                         let tos_id = "5f4c3d82d7f18c19a1a2d23331c9ac36";
-                        match fetch_tos_html(tos_id, None, None).await {
+                        match fetch_tos_html(&grid, tos_id, None, None).await {
                             Ok(tos_html) => {
                                 let _ = ui_event_tx.send(crate::ui::UiEvent::ShowTos {
                                     tos_id: tos_id.to_string(),
@@ -524,6 +401,30 @@ pub fn show_main_window(ctx: &egui::Context, ui_state: &mut UiState) {
         ui_state.login_state.prefs_modal_open = prefs_open;
     }
 
+    // MFA modal
+    if let Some(message) = ui_state.login_state.mfa_message.clone() {
+        egui::Window::new("Multi-Factor Authentication")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                ui.label(message);
+                ui.add_space(8.0);
+                ui.text_edit_singleline(&mut ui_state.login_state.mfa_token);
+                ui.add_space(8.0);
+                ui.horizontal(|ui| {
+                    if ui.add_enabled(!ui_state.login_state.mfa_token.trim().is_empty(), egui::Button::new("Submit")).clicked() {
+                        ui_state.login_state.mfa_message = None;
+                        ui_state.login_state.mfa_token_next_login = true;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        ui_state.login_state.mfa_message = None;
+                        ui_state.login_state.mfa_token.clear();
+                    }
+                });
+            });
+    }
+
     // ToS modal
     if ui_state.tos_required {
         egui::Window::new("Terms of Service")
@@ -558,13 +459,6 @@ pub fn show_main_window(ctx: &egui::Context, ui_state: &mut UiState) {
             });
     }
 
-    // In show_main_window, poll chat_event_rx and append to chat_messages:
-    // if let Some(chat_event_rx) = &ui_state.chat_event_rx {
-    //     while let Ok((sender, message)) = chat_event_rx.try_recv() {
-    //         chat::append_incoming_chat(&mut ui_state.chat_messages, &sender, &message);
-    //     }
-    // }
-
     match ui_state.login_ui_state {
         LoginUiState::LoginSplash => {
             egui::CentralPanel::default().show(ctx, |ui| {
@@ -572,6 +466,44 @@ pub fn show_main_window(ctx: &egui::Context, ui_state: &mut UiState) {
                     ui.heading("slv-rust Login");
                     ui.add_space(16.0);
 
+                    // Saved accounts
+                    if !ui_state.login_state.saved_accounts.is_empty() {
+                        let mut chosen = None;
+                        let mut forget = None;
+                        ui.horizontal(|ui| {
+                            egui::ComboBox::from_id_salt("saved_accounts").selected_text("Saved accounts").show_ui(ui, |ui| {
+                                for account in &ui_state.login_state.saved_accounts {
+                                    if ui.selectable_label(false, format!("{} ({})", account.name(), account.grid)).clicked() {
+                                        chosen = Some(account.clone());
+                                    }
+                                }
+                            });
+                            let current = ui_state.login_state.saved_accounts.iter().find(|account| account.name().eq_ignore_ascii_case(ui_state.login_state.username.trim()));
+                            if let Some(account) = current {
+                                if ui.button("Forget").clicked() {
+                                    forget = Some(account.clone());
+                                }
+                            }
+                        });
+                        if let Some(account) = chosen {
+                            ui_state.login_state.username = account.name();
+                            ui_state.login_state.password = account.password_hash.clone();
+                            ui_state.login_state.start_location = account.last_start.clone();
+                            ui_state.login_state.remember_account = true;
+                            if ui_state.grids.get(&account.grid).is_some() {
+                                ui_state.grids.selected = account.grid.clone();
+                            }
+                        }
+                        if let (Some(account), Some(store)) = (forget, &ui_state.account_store) {
+                            match store.forget(&account).and_then(|_| store.load()) {
+                                Ok(accounts) => ui_state.login_state.saved_accounts = accounts,
+                                Err(e) => eprintln!("[ACCOUNTS] Failed to forget account: {}", e),
+                            }
+                            ui_state.login_state.password.clear();
+                        }
+                        ui.add_space(8.0);
+                    }
+
                     // Username field
                     ui.label("Username:");
                     ui.text_edit_singleline(&mut ui_state.login_state.username);
@@ -580,11 +512,27 @@ pub fn show_main_window(ctx: &egui::Context, ui_state: &mut UiState) {
                     // Password field
                     ui.label("Password:");
                     ui.add(egui::TextEdit::singleline(&mut ui_state.login_state.password).password(true));
+                    ui.checkbox(&mut ui_state.login_state.remember_account, "Remember account");
+                    ui.add_space(16.0);
+
+                    // Grid selector
+                    ui.label("Grid:");
+                    let selected_name = ui_state.grids.selected().name;
+                    let mut selected = ui_state.grids.selected.clone();
+                    egui::ComboBox::from_id_salt("grid_selector").selected_text(selected_name).show_ui(ui, |ui| {
+                        for grid in &ui_state.grids.grids {
+                            ui.selectable_value(&mut selected, grid.nick.clone(), grid.name.as_str());
+                        }
+                    });
+                    if selected != ui_state.grids.selected {
+                        ui_state.grids.selected = selected;
+                        let _ = settings::save_grids(&ui_state.grids);
+                    }
                     ui.add_space(16.0);
 
                     // Login button (disabled until fields are filled)
                     let login_enabled = !ui_state.login_state.username.is_empty() && !ui_state.login_state.password.is_empty() && matches!(ui_state.login_progress, LoginProgress::Idle);
-                    if ui.add_enabled(login_enabled, egui::Button::new("Login")).clicked() || ui_state.login_state.agree_to_tos_next_login {
+                    if ui.add_enabled(login_enabled, egui::Button::new("Login")).clicked() || ui_state.login_state.agree_to_tos_next_login || ui_state.login_state.mfa_token_next_login {
                         // Parse username into first/last ("First Last" or "first.last" or just "First")
                         let (first, last) = if ui_state.login_state.username.contains('.') {
                             let mut parts = ui_state.login_state.username.splitn(2, '.');
@@ -598,34 +546,35 @@ pub fn show_main_window(ctx: &egui::Context, ui_state: &mut UiState) {
                             let last = parts.next().unwrap_or("Resident").to_string();
                             (first, last)
                         };
-                        let password = ui_state.login_state.password.clone();
+                        // Keep only the hash from here on; retries after ToS/MFA send it as-is
+                        let password = hash_password(&ui_state.login_state.password);
+                        ui_state.login_state.password = password.clone();
+                        let grid = ui_state.grids.selected();
+                        ui_state.login_state.pending_account = ui_state.login_state.remember_account.then(|| SavedAccount {
+                            first: first.clone(),
+                            last: last.clone(),
+                            grid: grid.nick.clone(),
+                            last_start: ui_state.login_state.start_location.clone(),
+                            password_hash: password.clone(),
+                        });
                         let agree_to_tos = if ui_state.login_state.agree_to_tos_next_login { 1 } else { 0 };
+                        let token = if ui_state.login_state.mfa_token_next_login { ui_state.login_state.mfa_token.trim().to_string() } else { String::new() };
+                        ui_state.login_state.mfa_token_next_login = false;
+                        let mfa_hash = ui_state
+                            .account_store
+                            .as_ref()
+                            .and_then(|store| store.mfa_hash(&first, &last, &grid.nick).ok().flatten())
+                            .unwrap_or_default();
+                        let machine_ids = settings::machine_ids(ui_state.preferences.randomize_machine_ids);
                         let req = LoginRequest {
-                            first,
-                            last,
-                            password,
-                            start: "last".to_string(),
-                            channel: "slv-rust".to_string(),
-                            version: "0.3.0-alpha".to_string(),
-                            platform: "linux".to_string(), // TODO: detect platform
-                            platform_string: "macOS 12.7.4".to_string(), // TODO: real platform string
-                            platform_version: "12.7.4".to_string(), // TODO: real version
-                            mac: "00:00:00:00:00:00".to_string(), // TODO: real MAC
-                            id0: "00000000-0000-0000-0000-000000000000".to_string(), // TODO: real id0
                             agree_to_tos,
-                            address_size: 64,
-                            extended_errors: 1,
-                            host_id: String::new(),
-                            last_exec_duration: 30,
-                            last_exec_event: 0,
-                            last_exec_session_id: "00000000-0000-0000-0000-000000000000".to_string(),
-                            mfa_hash: String::new(),
-                            token: String::new(),
+                            mfa_hash,
+                            token,
                             read_critical: if ui_state.login_state.read_critical_next_login { 1 } else { 0 },
-                            options: LoginRequest::default_options(),
+                            start: ui_state.login_state.start_location.clone(),
+                            ..LoginRequest::new(first, last, password, &machine_ids)
                         };
-                        // Revert login endpoint to HTTPS for official test
-                        let grid_uri = "https://login.agni.lindenlab.com/cgi-bin/login.cgi".to_string();
+                        ui_state.login_state.read_critical_next_login = false;
                         ui_state.login_progress = LoginProgress::InProgress;
                         let session_udp_port = ui_state.session_udp_port;
                         let tx = ui_state.login_result_tx.clone();
@@ -636,38 +585,41 @@ pub fn show_main_window(ctx: &egui::Context, ui_state: &mut UiState) {
                         // Spawn async login task
                         let handle = tokio::spawn(async move {
                             eprintln!("[LOGIN TASK] Starting login for: first='{}', last='{}'", req.first, req.last);
-                            let result = login_to_secondlife(&grid_uri, &req, Some(&proxy_settings), session_udp_port).await;
+                            let result = login_to_secondlife(&grid, &req, Some(&proxy_settings), session_udp_port).await;
                             match &result {
-                                Ok(session_info) => {
+                                Ok(LoginOutcome::Success(session_info)) => {
                                     eprintln!("[LOGIN SUCCESS] agent_id={}, session_id={}", session_info.agent_id, session_info.session_id);
                                 }
-                                Err(err_msg) => {
-                                    // Check for TOS_REQUIRED error
-                                    if let Some(rest) = err_msg.strip_prefix("TOS_REQUIRED:") {
-                                        let mut parts = rest.splitn(2, ':');
-                                        let tos_id = parts.next().unwrap_or("").to_string();
-                                        let message = parts.next().unwrap_or("").to_string();
-                                        // B. If no tos_id, use a random/test one
-                                        let tos_id = if tos_id.is_empty() { "5f4c3d82d7f18c19a1a2d23331c9ac36".to_string() } else { tos_id };
-                                        // C. Log fetch errors
-                                        match fetch_tos_html(&tos_id, None, Some(&proxy_settings)).await {
-                                            Ok(tos_html) => {
-                                                let _ = ui_event_tx.send(crate::ui::UiEvent::ShowTos {
-                                                    tos_id,
-                                                    tos_html,
-                                                    message,
-                                                });
-                                            }
-                                            Err(e) => {
-                                                eprintln!("[TOS] Failed to fetch ToS: {}", e);
-                                            }
+                                Ok(LoginOutcome::TosRequired { message }) => {
+                                    // The login response carries no ToS id; use the current release's
+                                    let tos_id = "5f4c3d82d7f18c19a1a2d23331c9ac36".to_string();
+                                    // C. Log fetch errors
+                                    match fetch_tos_html(&grid, &tos_id, None, Some(&proxy_settings)).await {
+                                        Ok(tos_html) => {
+                                            let _ = ui_event_tx.send(crate::ui::UiEvent::ShowTos {
+                                                tos_id,
+                                                tos_html,
+                                                message: message.clone(),
+                                            });
+                                        }
+                                        Err(e) => {
+                                            eprintln!("[TOS] Failed to fetch ToS: {}", e);
                                         }
-                                    } else {
-                                        eprintln!("[LOGIN ERROR] {}", err_msg);
                                     }
                                 }
+                                Ok(outcome) => {
+                                    eprintln!("[LOGIN] {:?}", outcome);
+                                }
+                                Err(err_msg) => {
+                                    eprintln!("[LOGIN ERROR] {}", err_msg);
+                                }
                             }
-                            let login_result = LoginResult { result };
+                            let login_result = LoginResult {
+                                first: req.first.clone(),
+                                last: req.last.clone(),
+                                grid: grid.nick.clone(),
+                                result,
+                            };
                             let _ = tx.send(login_result);
                         });
                         ui_state.login_task = Some(handle);
@@ -721,6 +673,7 @@ pub fn show_main_window(ctx: &egui::Context, ui_state: &mut UiState) {
                     ui_state.login_state.status_message = "User requested logout.".to_string();
                     ui_state.login_ui_state = crate::ui::LoginUiState::LoginSplash;
                     ui_state.logout_requested = true;
+                    ui_state.net.send(NetCommand::Logout);
                 }
             });
         }
@@ -754,53 +707,3 @@ pub async fn connect_leap_bridge(host: &str, port: u16) -> std::io::Result<tokio
     // For now, just connect and return the stream
     tokio::net::TcpStream::connect((host, port)).await
 }
-
-// Helper function to parse sim address from event XML
-fn parse_sim_address_from_event(xml: &str) -> Option<(String, u16)> {
-    if let Ok(doc) = roxmltree::Document::parse(xml) {
-        // Find the event type
-        let mut event_type = None;
-        let mut sim_ip = None;
-        let mut sim_port = None;
-        // Look for <key>message</key><string>...</string>
-        let mut last_key = None;
-        for node in doc.descendants() {
-            if node.has_tag_name("key") {
-                last_key = node.text();
-            } else if let Some(key) = last_key {
-                if key == "message" && node.has_tag_name("string") {
-                    event_type = node.text();
-                }
-                if key == "body" && node.has_tag_name("map") {
-                    // Entering the body map
-                    // For EstablishAgentCommunication: look for sim-ip-and-port
-                    for (k, v) in node.children().collect::<Vec<_>>().chunks(2).filter(|c| c.len() == 2).map(|c| (c[0].text(), &c[1])) {
-                        if let Some("sim-ip-and-port") = k {
-                            if let Some(addr) = v.text() {
-                                let mut parts = addr.split(':');
-                                if let (Some(ip), Some(port)) = (parts.next(), parts.next()) {
-                                    if let Ok(port) = port.parse() {
-                                        return Some((ip.to_string(), port));
-                                    }
-                                }
-                            }
-                        }
-                        // For TeleportFinish/CrossedRegion: look for SimIP and SimPort
-                        if let Some("SimIP") = k {
-                            sim_ip = v.text().map(|s| s.to_string());
-                        }
-                        if let Some("SimPort") = k {
-                            sim_port = v.text().and_then(|s| s.parse().ok());
-                        }
-                    }
-                }
-                last_key = None;
-            }
-        }
-        // If we found SimIP and SimPort, return them
-        if let (Some(ip), Some(port)) = (sim_ip, sim_port) {
-            return Some((ip, port));
-        }
-    }
-    None
-}
@@ -10,8 +10,10 @@ use std::collections::VecDeque;
 use tokio::task::JoinHandle;
 use crossbeam_channel::{unbounded, Sender, Receiver};
 use crate::networking::session;
-use crate::networking::circuit::Circuit;
+use crate::networking::actor::{spawn_network_actor, NetEvent, NetHandle};
 use crate::config::settings;
+use crate::config::grids::{Grid, GridRegistry};
+use crate::config::accounts::{AccountStore, SavedAccount};
 use crate::ui::proxy::ProxySettings;
 use crate::ui::udp_port::pick_random_udp_port;
 
@@ -50,12 +52,23 @@ impl UiContext {
 
 pub struct LoginState {
     pub username: String,
+    /// The typed password until a login starts, then only its `$1$` hash.
     pub password: String,
+    /// `last`, `home` or a start URI; filled from a saved account.
+    pub start_location: String,
+    pub remember_account: bool,
+    pub saved_accounts: Vec<SavedAccount>,
+    /// Saved once the login in progress succeeds, if `remember_account` was set.
+    pub pending_account: Option<SavedAccount>,
     pub status_message: String,
     pub prefs_modal_open: bool,
     pub session_info: Option<session::LoginSessionInfo>,
     pub agree_to_tos_next_login: bool,
     pub read_critical_next_login: bool, // Track if user must accept critical message
+    /// Set while the grid is asking for a multi-factor authentication code.
+    pub mfa_message: Option<String>,
+    pub mfa_token: String,
+    pub mfa_token_next_login: bool,
 }
 
 impl Default for LoginState {
@@ -63,11 +76,18 @@ impl Default for LoginState {
         Self {
             username: String::new(),
             password: String::new(),
+            start_location: "last".to_string(),
+            remember_account: false,
+            saved_accounts: Vec::new(),
+            pending_account: None,
             status_message: String::new(),
             prefs_modal_open: false,
             session_info: None,
             agree_to_tos_next_login: false,
             read_critical_next_login: false,
+            mfa_message: None,
+            mfa_token: String::new(),
+            mfa_token_next_login: false,
         }
     }
 }
@@ -88,7 +108,11 @@ pub enum LoginProgress {
 }
 
 pub struct LoginResult {
-    pub result: Result<session::LoginSessionInfo, String>,
+    /// Account the login was for, as parsed from the username, and the grid's nick.
+    pub first: String,
+    pub last: String,
+    pub grid: String,
+    pub result: Result<session::LoginOutcome, String>,
 }
 
 pub enum UdpConnectionProgress {
@@ -104,8 +128,9 @@ pub enum UiEvent {
         tos_html: String,
         message: String,
     },
-    AgentStateUpdate(String),
     InWorldReady, // <-- Add this
+    /// Result of importing a grid from its `get_grid_info` document.
+    GridImported(Result<Grid, String>),
     // Add more events as needed
 }
 
@@ -120,15 +145,17 @@ pub struct UiState {
     pub login_task: Option<JoinHandle<()>>,
     pub login_result_tx: Sender<LoginResult>,
     pub login_result_rx: Receiver<LoginResult>,
-    pub udp_circuit: Option<std::sync::Arc<tokio::sync::Mutex<Circuit>>>,
+    /// Commands to the networking actor, which owns the circuit.
+    pub net: NetHandle,
+    pub net_events: Receiver<NetEvent>,
     pub udp_progress: UdpConnectionProgress,
-    pub udp_connect_tx: Sender<crate::ui::main_window::UdpConnectResult>,
-    pub udp_connect_rx: Receiver<crate::ui::main_window::UdpConnectResult>,
-    pub udp_connect_task: Option<JoinHandle<()>>,
     pub logout_requested: bool,
-    pub chat_event_tx: Option<Sender<(String, String)>>,
-    pub chat_event_rx: Option<Receiver<(String, String)>>,
     pub proxy_settings: ProxySettings,
+    pub grids: GridRegistry,
+    pub account_store: Option<AccountStore>,
+    /// Login URI typed into the grid import field.
+    pub grid_import_uri: String,
+    pub grid_import_status: Option<String>,
     pub tos_required: bool,
     pub tos_html: Option<String>,
     pub tos_id: Option<String>,
@@ -147,6 +174,8 @@ pub struct PreferencesState {
     pub render_distance: u32,
     pub max_bandwidth: u32,
     pub timeout: u32,
+    /// Send random per-install machine ids at login instead of hardware-derived ones.
+    pub randomize_machine_ids: bool,
     // UDP test fields
     pub udp_test_result: Option<String>,
     pub udp_test_in_progress: bool,
@@ -163,6 +192,7 @@ impl Default for PreferencesState {
             render_distance: 256,
             max_bandwidth: 1500,
             timeout: 30,
+            randomize_machine_ids: false,
             udp_test_result: None,
             udp_test_in_progress: false,
         }
@@ -172,7 +202,7 @@ impl Default for PreferencesState {
 impl Default for UiState {
     fn default() -> Self {
         let (login_result_tx, login_result_rx) = unbounded();
-        let (udp_connect_tx, udp_connect_rx) = unbounded();
+        let (net, net_events) = spawn_network_actor();
         let (ui_event_tx, ui_event_rx) = unbounded();
         let mut preferences = PreferencesState::default();
         let mut proxy_settings = ProxySettings::default();
@@ -182,6 +212,18 @@ impl Default for UiState {
         } else if let Some(loaded) = settings::load_preferences() {
             preferences = loaded;
         }
+        let account_store = AccountStore::open_default();
+        let mut login_state = LoginState::default();
+        if let Some(store) = &account_store {
+            match store.load() {
+                Ok(accounts) => login_state.saved_accounts = accounts,
+                Err(e) => tracing::warn!("[ACCOUNTS] {}", e),
+            }
+            match store.socks5_password() {
+                Ok(password) => proxy_settings.socks5_password = password,
+                Err(e) => tracing::warn!("[ACCOUNTS] {}", e),
+            }
+        }
         // Generate a random free 5-digit UDP port for the session
         let session_udp_port = pick_random_udp_port();
         Self {
@@ -189,21 +231,21 @@ impl Default for UiState {
             chat_messages: VecDeque::from(vec!["Welcome to slv-rust!".to_string()]),
             inventory_items: vec!["Test Item 1".to_string(), "Test Item 2".to_string()],
             preferences,
-            login_state: LoginState::default(),
+            login_state,
             login_ui_state: LoginUiState::LoginSplash,
             login_progress: LoginProgress::Idle,
             login_task: None,
             login_result_tx,
             login_result_rx,
-            udp_circuit: None,
+            net,
+            net_events,
             udp_progress: UdpConnectionProgress::NotStarted,
-            udp_connect_tx,
-            udp_connect_rx,
-            udp_connect_task: None,
             logout_requested: false,
-            chat_event_tx: None,
-            chat_event_rx: None,
             proxy_settings,
+            grids: settings::load_grids(),
+            account_store,
+            grid_import_uri: String::new(),
+            grid_import_status: None,
             tos_required: false,
             tos_html: None,
             tos_id: None,
@@ -1,11 +1,39 @@
+use std::fmt;
+
 use serde::{Serialize, Deserialize};
 /// Stores proxy configuration for UDP and HTTP(S) traffic.
-#[derive(Default, Clone, Serialize, Deserialize, Debug)]
+#[derive(Default, Clone, Serialize, Deserialize)]
 pub struct ProxySettings {
     pub enabled: bool,
     pub socks5_host: String,
     pub socks5_port: u16,
+    /// SOCKS5 username/password (RFC 1929); an empty username means no authentication.
+    #[serde(default)]
+    pub socks5_username: String,
+    /// Kept in the encrypted account store rather than the settings file.
+    #[serde(skip)]
+    pub socks5_password: String,
     pub http_host: String,
     pub http_port: u16,
     pub disable_cert_validation: bool,
-} 
\ No newline at end of file
+    /// PEM file with extra root certificates, e.g. an intercepting proxy's CA. Empty for none.
+    #[serde(default)]
+    pub ca_bundle_path: String,
+} 
+
+/// Settings are logged whole, so the password is left out.
+impl fmt::Debug for ProxySettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProxySettings")
+            .field("enabled", &self.enabled)
+            .field("socks5_host", &self.socks5_host)
+            .field("socks5_port", &self.socks5_port)
+            .field("socks5_username", &self.socks5_username)
+            .field("socks5_password", &if self.socks5_password.is_empty() { "" } else { "<redacted>" })
+            .field("http_host", &self.http_host)
+            .field("http_port", &self.http_port)
+            .field("disable_cert_validation", &self.disable_cert_validation)
+            .field("ca_bundle_path", &self.ca_bundle_path)
+            .finish()
+    }
+}
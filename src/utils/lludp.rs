@@ -4,13 +4,16 @@
 
 use bytes::{BytesMut, BufMut};
 use uuid::Uuid;
+use crate::networking::protocol::packet::{Packet, PacketFlags};
+use crate::networking::protocol::wire::{MessageNumber, WireWriter};
 
 bitflags::bitflags! {
     #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
     pub struct LluPacketFlags: u8 {
-        const RELIABLE = 0x01;
         const ZEROCODED = 0x80;
-        // Add more flags as needed
+        const RELIABLE = 0x40;
+        const RESENT = 0x20;
+        const ACK = 0x10;
     }
 }
 
@@ -165,7 +168,8 @@ pub fn build_use_circuit_code_packet(
     buf
 }
 
-/// Build a generic LLUDP packet.
+/// Build a generic LLUDP packet. Framing (big-endian sequence, zero-coding) goes through
+/// `protocol::packet::Packet`.
 pub fn build_lludp_packet(
     message_id: u16,
     frequency: LLUDPFrequency,
@@ -174,44 +178,27 @@ pub fn build_lludp_packet(
     zerocoded: bool,
     body: &[u8],
 ) -> Vec<u8> {
-    let mut buf = Vec::new();
+    let number = match frequency {
+        LLUDPFrequency::High => MessageNumber::High(message_id as u8),
+        LLUDPFrequency::Medium => MessageNumber::Medium(message_id as u8),
+        LLUDPFrequency::Low => MessageNumber::Low(message_id),
+        LLUDPFrequency::Fixed => MessageNumber::Fixed(message_id as u8),
+    };
+    let mut writer = WireWriter::new();
+    number.write(&mut writer);
+    writer.put_bytes(body);
 
-    let mut flags: u8 = 0x00;
+    let mut flags = PacketFlags::empty();
     if reliable {
-        flags |= LluPacketFlags::RELIABLE.bits();
+        flags |= PacketFlags::RELIABLE;
     }
     if zerocoded {
-        flags |= LluPacketFlags::ZEROCODED.bits();
+        flags |= PacketFlags::ZEROCODED;
     }
 
-    buf.push(flags); // 1 byte flags
-    buf.extend_from_slice(&packet_id.to_le_bytes()); // 4 bytes packet id
-    buf.push(0x00); // 1 byte offset, always 0 for now
-
-    // Message number based on frequency
-    match frequency {
-        LLUDPFrequency::High => {
-            buf.extend_from_slice(&[0x00, 0x00, 0x00]);
-        }
-        LLUDPFrequency::Medium => {
-            buf.extend_from_slice(&[0xFF, 0x00, 0x00]);
-        }
-        LLUDPFrequency::Low => {
-            buf.extend_from_slice(&[0xFF, 0xFF, 0x00]);
-        }
-        LLUDPFrequency::Fixed => {
-            buf.extend_from_slice(&[0xFF, 0xFF, 0xFF]);
-        }
-    }
-    buf.push(message_id as u8); // Last byte of message number
-
-    let mut final_body = body.to_vec();
-    if zerocoded {
-        final_body = zerocode(body);
-    }
-
-    buf.extend_from_slice(&final_body);
-    buf
+    Packet::new(flags, packet_id, writer.into_inner())
+        .encode()
+        .expect("packet without appended acks or extra header always encodes")
 }
 
 /// Build a CompleteAgentMovement LLUDP packet (Low frequency, ID 249) as RELIABLE and unencoded (flags = 0x40, no zerocoding)
@@ -1,3 +1,4 @@
-pub mod logging;
-pub mod math;
-pub mod lludp;
+pub mod logging;
+pub mod math;
+pub mod lludp;
+pub mod platform;
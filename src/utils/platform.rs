@@ -0,0 +1,135 @@
+//! What the login request reports about this machine and build: the viewer channel and
+//! version, the OS, and the hashed `id0`/`mac` machine identifiers.
+
+use std::fs;
+use std::process::Command;
+
+use serde::{Deserialize, Serialize};
+
+/// Viewer channel sent at login.
+pub const CHANNEL: &str = "slv-rust";
+
+/// Viewer version in the `major.minor.patch.build` form the grids expect. The build number
+/// comes from `SLV_BUILD_NUMBER` at compile time and defaults to 0.
+pub fn viewer_version() -> String {
+    format!("{}.{}", env!("CARGO_PKG_VERSION").split('-').next().unwrap_or("0.0.0"), option_env!("SLV_BUILD_NUMBER").unwrap_or("0"))
+}
+
+/// The operating system as reported in the login `platform` fields.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlatformInfo {
+    /// `win`, `mac` or `lnx`.
+    pub platform: String,
+    /// Human-readable OS name and version, e.g. `Ubuntu 24.04.1 LTS (6.8.0-45-generic)`.
+    pub platform_string: String,
+    /// Bare OS version, e.g. `6.8.0`.
+    pub platform_version: String,
+}
+
+impl PlatformInfo {
+    pub fn detect() -> Self {
+        match std::env::consts::OS {
+            "windows" => {
+                // `ver` prints e.g. "Microsoft Windows [Version 10.0.22631.4169]"
+                let version = command_output("cmd", &["/C", "ver"])
+                    .and_then(|out| out.split("Version").nth(1).map(|v| v.trim_matches(|c: char| c == ']' || c.is_whitespace()).to_string()))
+                    .unwrap_or_default();
+                Self { platform: "win".to_string(), platform_string: format!("Microsoft Windows {}", version).trim().to_string(), platform_version: version }
+            }
+            "macos" => {
+                let version = command_output("sw_vers", &["-productVersion"]).unwrap_or_default();
+                Self { platform: "mac".to_string(), platform_string: format!("macOS {}", version).trim().to_string(), platform_version: version }
+            }
+            os => {
+                let kernel = fs::read_to_string("/proc/sys/kernel/osrelease").map(|s| s.trim().to_string()).unwrap_or_default();
+                let name = fs::read_to_string("/etc/os-release")
+                    .ok()
+                    .and_then(|release| os_release_field(&release, "PRETTY_NAME"))
+                    .unwrap_or_else(|| if os == "linux" { "Linux".to_string() } else { os.to_string() });
+                Self {
+                    platform: "lnx".to_string(),
+                    platform_string: if kernel.is_empty() { name } else { format!("{} ({})", name, kernel) },
+                    platform_version: kernel.split(|c: char| c != '.' && !c.is_ascii_digit()).next().unwrap_or_default().to_string(),
+                }
+            }
+        }
+    }
+}
+
+/// The hashed machine identifiers sent as `id0` and `mac`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MachineIds {
+    pub id0: String,
+    pub mac: String,
+}
+
+impl MachineIds {
+    /// Stable ids derived from the OS machine id and the first hardware address. Only MD5
+    /// hashes leave the machine. Missing sources hash to a fixed per-OS value.
+    pub fn detect() -> Self {
+        let machine_id = machine_id().unwrap_or_else(|| std::env::consts::OS.to_string());
+        let hardware_addr = hardware_address().unwrap_or_else(|| format!("{}-mac", machine_id));
+        Self { id0: md5_hex(&machine_id), mac: md5_hex(&hardware_addr) }
+    }
+
+    /// Random ids for users who don't want to be linked across installs; persist them so
+    /// the grid sees one stable machine per install.
+    pub fn random() -> Self {
+        Self { id0: md5_hex(&uuid::Uuid::new_v4().to_string()), mac: md5_hex(&uuid::Uuid::new_v4().to_string()) }
+    }
+}
+
+fn md5_hex(input: &str) -> String {
+    format!("{:x}", md5::compute(input.trim().to_lowercase()))
+}
+
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    output.status.success().then(|| String::from_utf8_lossy(&output.stdout).trim().to_string()).filter(|s| !s.is_empty())
+}
+
+fn os_release_field(release: &str, key: &str) -> Option<String> {
+    release.lines().find_map(|line| {
+        let value = line.strip_prefix(key)?.strip_prefix('=')?;
+        Some(value.trim().trim_matches('"').to_string())
+    })
+}
+
+fn machine_id() -> Option<String> {
+    match std::env::consts::OS {
+        "windows" => command_output("reg", &["query", r"HKLM\SOFTWARE\Microsoft\Cryptography", "/v", "MachineGuid"])
+            .and_then(|out| out.split_whitespace().last().map(str::to_string)),
+        "macos" => command_output("ioreg", &["-rd1", "-c", "IOPlatformExpertDevice"]).and_then(|out| {
+            out.lines().find(|line| line.contains("IOPlatformUUID")).and_then(|line| line.split('"').nth(3).map(str::to_string))
+        }),
+        _ => ["/etc/machine-id", "/var/lib/dbus/machine-id"]
+            .iter()
+            .find_map(|path| fs::read_to_string(path).ok().map(|id| id.trim().to_string()).filter(|id| !id.is_empty())),
+    }
+}
+
+/// First non-zero hardware address of a non-loopback interface (Linux only).
+fn hardware_address() -> Option<String> {
+    let mut interfaces: Vec<_> = fs::read_dir("/sys/class/net").ok()?.filter_map(Result::ok).map(|entry| entry.path()).collect();
+    interfaces.sort();
+    interfaces.iter().filter(|path| !path.ends_with("lo")).find_map(|path| {
+        let addr = fs::read_to_string(path.join("address")).ok()?;
+        let addr = addr.trim();
+        (!addr.is_empty() && addr != "00:00:00:00:00:00").then(|| addr.to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ids_are_hashed_and_os_release_parsed() {
+        let ids = MachineIds::detect();
+        assert_eq!(ids, MachineIds::detect());
+        assert!(ids.id0.len() == 32 && ids.mac.len() == 32);
+        assert_ne!(MachineIds::random(), MachineIds::random());
+        assert_eq!(os_release_field("NAME=\"Ubuntu\"\nPRETTY_NAME=\"Ubuntu 24.04 LTS\"\n", "PRETTY_NAME").as_deref(), Some("Ubuntu 24.04 LTS"));
+        assert_eq!(viewer_version().split('.').count(), 4);
+    }
+}
@@ -0,0 +1,3 @@
+pub mod object_cache;
+pub mod object_update;
+pub mod objects;
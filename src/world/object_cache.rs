@@ -0,0 +1,364 @@
+//! On-disk object cache, the equivalent of the viewer's VOCache.
+//!
+//! Simulators announce cacheable objects with ObjectUpdateCached (local id and CRC) instead of
+//! sending them in full. Objects found here with a matching CRC are restored without asking;
+//! only misses are requested with RequestMultipleObjects. There is one file per region, named
+//! by region id. The header holds the cache id from RegionHandshake, and a simulator that
+//! hands out a different one has reset its objects, so the file is discarded.
+//!
+//! File layout: the 8-byte [`MAGIC`], region id (16), cache id (16), entry count (u32 LE), then
+//! per entry the local id and CRC (u32 LE each), the length (u32 LE) and the object encoded as
+//! an ObjectUpdateCompressed blob.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use directories::ProjectDirs;
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+use crate::networking::protocol::wire::{CodecError, WireReader, WireWriter};
+use crate::world::object_update::{ObjectState, PCODE_AVATAR};
+
+pub const MAGIC: &[u8; 8] = b"SLVOC001";
+
+/// Environment variable overriding where cache files go.
+pub const CACHE_DIR_ENV: &str = "SLV_CACHE_DIR";
+
+/// Objects that vanish when their owner leaves or after a minute are not worth keeping.
+const FLAGS_TEMPORARY_ON_REZ: u32 = 0x2000_0000;
+const FLAGS_TEMPORARY: u32 = 0x4000_0000;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ObjectCacheError {
+    #[error("object cache I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("object cache is damaged: {0}")]
+    Corrupt(String),
+}
+
+impl From<CodecError> for ObjectCacheError {
+    fn from(e: CodecError) -> Self {
+        ObjectCacheError::Corrupt(e.to_string())
+    }
+}
+
+/// The cached objects of one region.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegionCache {
+    pub region_id: Uuid,
+    pub cache_id: Uuid,
+    entries: HashMap<u32, ObjectState>,
+    dirty: bool,
+}
+
+impl RegionCache {
+    pub fn new(region_id: Uuid, cache_id: Uuid) -> Self {
+        Self { region_id, cache_id, entries: HashMap::new(), dirty: false }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The cached object, if it is still the version the simulator has.
+    pub fn lookup(&self, local_id: u32, crc: u32) -> Option<&ObjectState> {
+        self.entries.get(&local_id).filter(|state| state.crc == crc)
+    }
+
+    /// Remember an object from a full update. Avatars, attachments and temporary objects are
+    /// never announced with ObjectUpdateCached, so they are skipped.
+    pub fn insert(&mut self, state: &ObjectState) {
+        let attachment = state.name_values.contains("AttachItemID");
+        if state.pcode == PCODE_AVATAR || attachment || state.update_flags & (FLAGS_TEMPORARY | FLAGS_TEMPORARY_ON_REZ) != 0 {
+            return;
+        }
+        if self.entries.get(&state.local_id) != Some(state) {
+            self.entries.insert(state.local_id, state.clone());
+            self.dirty = true;
+        }
+    }
+
+    pub fn remove(&mut self, local_id: u32) {
+        self.dirty |= self.entries.remove(&local_id).is_some();
+    }
+
+    /// Read a region's cache from `dir`. A missing, damaged or outdated file gives an empty
+    /// cache. Blocks on the disk.
+    pub fn read(dir: &Path, region_id: Uuid, cache_id: Uuid) -> Self {
+        let path = region_path(dir, &region_id);
+        match fs::read(&path) {
+            Ok(data) => match Self::decode(&data) {
+                Ok(cache) if cache.region_id == region_id && cache.cache_id == cache_id => cache,
+                Ok(_) => {
+                    tracing::info!("Region {} reset its objects; discarding its object cache", region_id);
+                    Self::new(region_id, cache_id)
+                }
+                Err(e) => {
+                    tracing::warn!("Discarding object cache {}: {}", path.display(), e);
+                    Self::new(region_id, cache_id)
+                }
+            },
+            Err(e) => {
+                if e.kind() != io::ErrorKind::NotFound {
+                    tracing::warn!("Cannot read object cache {}: {}", path.display(), e);
+                }
+                Self::new(region_id, cache_id)
+            }
+        }
+    }
+
+    /// Write the cache into `dir`. Blocks on the disk.
+    pub fn write(&self, dir: &Path) {
+        let path = region_path(dir, &self.region_id);
+        if let Err(e) = write_atomically(&path, &self.encode()) {
+            tracing::warn!("Cannot write object cache {}: {}", path.display(), e);
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut w = WireWriter::new();
+        w.put_bytes(MAGIC);
+        w.put_uuid(&self.region_id);
+        w.put_uuid(&self.cache_id);
+        let entries: Vec<(&ObjectState, Vec<u8>)> = self
+            .entries
+            .values()
+            .filter_map(|state| match state.to_compressed() {
+                Ok(blob) => Some((state, blob)),
+                Err(e) => {
+                    tracing::warn!("Not caching object {}: {}", state.full_id, e);
+                    None
+                }
+            })
+            .collect();
+        w.put_u32(entries.len() as u32);
+        for (state, blob) in entries {
+            w.put_u32(state.local_id);
+            w.put_u32(state.crc);
+            w.put_u32(blob.len() as u32);
+            w.put_bytes(&blob);
+        }
+        w.into_inner()
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self, ObjectCacheError> {
+        let mut r = WireReader::new(data);
+        if r.read_bytes(MAGIC.len()).ok() != Some(MAGIC.as_slice()) {
+            return Err(ObjectCacheError::Corrupt("bad magic".to_string()));
+        }
+        let mut cache = Self::new(r.read_uuid()?, r.read_uuid()?);
+        let count = r.read_u32()?;
+        for _ in 0..count {
+            let local_id = r.read_u32()?;
+            let crc = r.read_u32()?;
+            let len = r.read_u32()? as usize;
+            let state = ObjectState::from_compressed(0, r.read_bytes(len)?)?;
+            if state.local_id != local_id || state.crc != crc {
+                return Err(ObjectCacheError::Corrupt(format!("entry {} does not match its object", local_id)));
+            }
+            cache.entries.insert(local_id, state);
+        }
+        Ok(cache)
+    }
+}
+
+/// Region caches by region handle, loaded as the regions' handshakes arrive. Nothing here
+/// touches the disk while the store is locked: the store hands [`RegionCache::read`] and
+/// [`RegionCache::write`] to [`CacheIo`] once its lock is released.
+pub struct ObjectCache {
+    dir: PathBuf,
+    regions: HashMap<u64, RegionCache>,
+    /// Caches of closed regions that still need writing.
+    closed: Vec<RegionCache>,
+    io: CacheIo,
+}
+
+impl ObjectCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into(), regions: HashMap::new(), closed: Vec::new(), io: CacheIo::default() }
+    }
+
+    /// The cache in `SLV_CACHE_DIR`, or else the user's cache dir.
+    pub fn open_default() -> Option<Self> {
+        let dir = match std::env::var_os(CACHE_DIR_ENV).filter(|dir| !dir.is_empty()) {
+            Some(dir) => PathBuf::from(dir),
+            None => ProjectDirs::from("com", "slv", "slv-rust")?.cache_dir().to_path_buf(),
+        };
+        Some(Self::new(dir.join("objects")))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn io(&self) -> CacheIo {
+        self.io.clone()
+    }
+
+    /// Start using a region's cache once it has been read, closing whatever the region had.
+    pub fn open_region(&mut self, region_handle: u64, cache: RegionCache) {
+        self.close_region(region_handle);
+        self.regions.insert(region_handle, cache);
+    }
+
+    pub fn region(&self, region_handle: u64) -> Option<&RegionCache> {
+        self.regions.get(&region_handle)
+    }
+
+    pub fn region_mut(&mut self, region_handle: u64) -> Option<&mut RegionCache> {
+        self.regions.get_mut(&region_handle)
+    }
+
+    /// Stop tracking a region, keeping its cache for the next [`take_unsaved`](Self::take_unsaved)
+    /// if it changed.
+    pub fn close_region(&mut self, region_handle: u64) {
+        if let Some(cache) = self.regions.remove(&region_handle).filter(|cache| cache.dirty) {
+            self.closed.push(cache);
+        }
+    }
+
+    pub fn close_all(&mut self) {
+        let handles: Vec<u64> = self.regions.keys().copied().collect();
+        for region_handle in handles {
+            self.close_region(region_handle);
+        }
+    }
+
+    /// Every cache that changed since it was last taken: closed regions, and copies of open ones.
+    pub fn take_unsaved(&mut self) -> Vec<RegionCache> {
+        let mut unsaved = std::mem::take(&mut self.closed);
+        for cache in self.regions.values_mut().filter(|cache| cache.dirty) {
+            cache.dirty = false;
+            unsaved.push(cache.clone());
+        }
+        unsaved
+    }
+}
+
+impl Drop for ObjectCache {
+    /// Whatever the last flush missed is written on the way out.
+    fn drop(&mut self) {
+        for cache in self.take_unsaved() {
+            cache.write(&self.dir);
+        }
+    }
+}
+
+/// Runs blocking work on cache files, in order per region file, so a region that closes and
+/// reopens reads back what was just written.
+#[derive(Clone, Default)]
+pub struct CacheIo {
+    last: Arc<Mutex<HashMap<Uuid, JoinHandle<()>>>>,
+}
+
+impl CacheIo {
+    /// Run `job` on tokio's blocking pool after earlier jobs on the region's file. Without a
+    /// runtime (tests and tools) it runs on the calling thread, so never call it with the store
+    /// locked.
+    pub fn run(&self, region_id: Uuid, job: impl FnOnce() + Send + 'static) {
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return job();
+        };
+        let mut last = self.last.lock().unwrap();
+        last.retain(|_, task| !task.is_finished());
+        let previous = last.remove(&region_id);
+        let task = runtime.spawn(async move {
+            if let Some(previous) = previous {
+                let _ = previous.await;
+            }
+            if let Err(e) = tokio::task::spawn_blocking(job).await {
+                tracing::warn!("Object cache job for region {} failed: {}", region_id, e);
+            }
+        });
+        last.insert(region_id, task);
+    }
+}
+
+fn region_path(dir: &Path, region_id: &Uuid) -> PathBuf {
+    dir.join(format!("{}.objects", region_id))
+}
+
+/// Write beside the file and rename over it, so a crash never leaves half a cache.
+fn write_atomically(path: &Path, data: &[u8]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, data)?;
+    fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::object_update::{Motion, ObjectUpdate, PrimShape, PCODE_PRIM};
+    use crate::world::objects::{flush_cache, load_region_cache, ObjectKey, ObjectStore};
+    use glam::{Quat, Vec3};
+
+    fn prim(local_id: u32, crc: u32) -> ObjectState {
+        ObjectState {
+            local_id,
+            full_id: Uuid::new_v4(),
+            parent_id: 0,
+            pcode: PCODE_PRIM,
+            state: 0,
+            crc,
+            material: 3,
+            click_action: 0,
+            update_flags: 0,
+            scale: Vec3::new(0.5, 0.5, 0.5),
+            motion: Motion::at(Vec3::new(1.0, 2.0, 3.0), Quat::IDENTITY),
+            shape: PrimShape { path_curve: 16, profile_curve: 1, path_scale_x: 100, path_scale_y: 100, ..PrimShape::default() },
+            owner_id: Uuid::new_v4(),
+            texture_entry: vec![1, 2, 3],
+            texture_anim: Vec::new(),
+            name_values: String::new(),
+            data: Vec::new(),
+            text: "Hello".to_string(),
+            text_color: [255, 0, 0, 255],
+            media_url: String::new(),
+            particles: Vec::new(),
+            extra_params: Vec::new(),
+            sound: None,
+        }
+    }
+
+    #[test]
+    fn test_cache_answers_cached_updates_and_invalidates() {
+        let dir = std::env::temp_dir().join(format!("slv-object-cache-{}", Uuid::new_v4()));
+        let (region_id, cache_id, handle) = (Uuid::new_v4(), Uuid::new_v4(), 1u64 << 40);
+
+        // Without a runtime the cache files are read and written straight away.
+        let store = ObjectStore::shared();
+        store.lock().unwrap().set_cache(ObjectCache::new(&dir));
+        load_region_cache(&store, handle, region_id, cache_id, |_| {});
+        store.lock().unwrap().apply(handle, [ObjectUpdate::Full(Box::new(prim(1, 10))), ObjectUpdate::Full(Box::new(prim(2, 20)))]);
+        flush_cache(&store);
+        store.lock().unwrap().remove_region(handle);
+        flush_cache(&store);
+        assert!(store.lock().unwrap().is_empty());
+
+        // Next visit: object 1 is unchanged, object 2 was edited and 3 is new.
+        load_region_cache(&store, handle, region_id, cache_id, |_| {});
+        let cached = [(1, 10), (2, 21), (3, 30)].map(|(local_id, crc)| ObjectUpdate::Cached { local_id, crc, update_flags: 0 });
+        assert_eq!(store.lock().unwrap().apply(handle, cached.clone()).cache_misses, vec![2, 3]);
+        let restored = store.lock().unwrap().get(ObjectKey::new(handle, 1)).unwrap().state.clone();
+        assert_eq!((restored.text.as_str(), restored.motion.position), ("Hello", Vec3::new(1.0, 2.0, 3.0)));
+        store.lock().unwrap().remove_region(handle);
+
+        // The simulator's cache id changed: nothing is trusted.
+        load_region_cache(&store, handle, region_id, Uuid::new_v4(), |_| {});
+        assert_eq!(store.lock().unwrap().apply(handle, cached).cache_misses, vec![1, 2, 3]);
+
+        assert!(RegionCache::decode(b"SLVOC001").is_err());
+        let _ = fs::remove_dir_all(dir);
+    }
+}
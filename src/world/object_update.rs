@@ -0,0 +1,681 @@
+//! Decoding of the object update messages into [`ObjectUpdate`]s for the object store.
+//!
+//! Simulators describe what the agent can see with five messages:
+//!
+//! * ObjectUpdate: full state, with the motion packed into the `ObjectData` field
+//! * ObjectUpdateCompressed: full state in one blob, optional fields gated by [`CompressedFlags`]
+//! * ImprovedTerseObjectUpdate: motion only, mostly quantized to U16
+//! * ObjectUpdateCached: local id and CRC of an object the viewer may already have
+//! * KillObject: the object is gone
+//!
+//! Quantized values map the full integer range linearly onto a fixed interval; see [`u16_to_f32`].
+
+use glam::{Quat, Vec3, Vec4};
+use uuid::Uuid;
+
+use crate::networking::protocol::generated::{self, Message};
+use crate::networking::protocol::wire::{CodecError, WireReader, WireWriter};
+
+pub const PCODE_PRIM: u8 = 9;
+pub const PCODE_AVATAR: u8 = 47;
+pub const PCODE_GRASS: u8 = 95;
+pub const PCODE_NEW_TREE: u8 = 111;
+pub const PCODE_PARTICLE_SYSTEM: u8 = 143;
+pub const PCODE_TREE: u8 = 255;
+
+/// Size of the legacy particle system block.
+const PARTICLE_BLOCK_SIZE: usize = 86;
+
+/// Region width in metres; quantized positions cover half a region either side of it.
+const REGION_WIDTH: f32 = 256.0;
+
+bitflags::bitflags! {
+    /// Optional fields present in an ObjectUpdateCompressed blob.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+    pub struct CompressedFlags: u32 {
+        const SCRATCH_PAD = 0x01;
+        const TREE = 0x02;
+        const HAS_TEXT = 0x04;
+        const HAS_PARTICLES = 0x08;
+        const HAS_SOUND = 0x10;
+        const HAS_PARENT = 0x20;
+        const TEXTURE_ANIMATION = 0x40;
+        const HAS_ANGULAR_VELOCITY = 0x80;
+        const HAS_NAME_VALUES = 0x100;
+        const MEDIA_URL = 0x200;
+        const HAS_PARTICLES_NEW = 0x400;
+    }
+}
+
+/// Map a quantized U16 onto `lower..=upper`. Values within one step of zero come out as
+/// exactly zero, so a resting object does not drift.
+pub fn u16_to_f32(value: u16, lower: f32, upper: f32) -> f32 {
+    let delta = upper - lower;
+    let f = lower + value as f32 * delta / u16::MAX as f32;
+    if f.abs() < delta / u16::MAX as f32 {
+        0.0
+    } else {
+        f
+    }
+}
+
+/// [`u16_to_f32`] for the U8-quantized updates.
+pub fn u8_to_f32(value: u8, lower: f32, upper: f32) -> f32 {
+    let delta = upper - lower;
+    let f = lower + value as f32 * delta / u8::MAX as f32;
+    if f.abs() < delta / u8::MAX as f32 {
+        0.0
+    } else {
+        f
+    }
+}
+
+fn read_u16_vec3(r: &mut WireReader<'_>, lower: f32, upper: f32) -> Result<Vec3, CodecError> {
+    Ok(Vec3::new(u16_to_f32(r.read_u16()?, lower, upper), u16_to_f32(r.read_u16()?, lower, upper), u16_to_f32(r.read_u16()?, lower, upper)))
+}
+
+fn read_u8_vec3(r: &mut WireReader<'_>, lower: f32, upper: f32) -> Result<Vec3, CodecError> {
+    Ok(Vec3::new(u8_to_f32(r.read_u8()?, lower, upper), u8_to_f32(r.read_u8()?, lower, upper), u8_to_f32(r.read_u8()?, lower, upper)))
+}
+
+/// Quantized rotations send all four components; renormalize away the rounding.
+fn normalized(x: f32, y: f32, z: f32, w: f32) -> Quat {
+    let q = Quat::from_xyzw(x, y, z, w);
+    if q.length_squared() > 0.0 {
+        q.normalize()
+    } else {
+        Quat::IDENTITY
+    }
+}
+
+fn read_u16_quat(r: &mut WireReader<'_>) -> Result<Quat, CodecError> {
+    let mut c = [0.0; 4];
+    for v in &mut c {
+        *v = u16_to_f32(r.read_u16()?, -1.0, 1.0);
+    }
+    Ok(normalized(c[0], c[1], c[2], c[3]))
+}
+
+fn read_u8_quat(r: &mut WireReader<'_>) -> Result<Quat, CodecError> {
+    let mut c = [0.0; 4];
+    for v in &mut c {
+        *v = u8_to_f32(r.read_u8()?, -1.0, 1.0);
+    }
+    Ok(normalized(c[0], c[1], c[2], c[3]))
+}
+
+/// A NUL-terminated string inside a packed blob.
+fn read_c_string(r: &mut WireReader<'_>) -> Result<String, CodecError> {
+    let rest = r.rest();
+    let len = rest.iter().position(|&b| b == 0).ok_or_else(|| CodecError::Malformed("unterminated string".to_string()))?;
+    let s = String::from_utf8_lossy(&rest[..len]).into_owned();
+    r.read_bytes(len + 1)?;
+    Ok(s)
+}
+
+/// Interior NULs would end the string early, so they are dropped.
+fn put_c_string(w: &mut WireWriter, s: &str) {
+    w.put_bytes(s.replace('\0', "").as_bytes());
+    w.put_u8(0);
+}
+
+/// Extra parameters (flexi, light, sculpt, ...) are kept in their wire form: a count, then
+/// type, length and data for each entry.
+fn read_extra_params(r: &mut WireReader<'_>) -> Result<Vec<u8>, CodecError> {
+    let start = r.rest();
+    let count = r.read_u8()?;
+    for _ in 0..count {
+        r.read_u16()?;
+        let len = r.read_u32()? as usize;
+        r.read_bytes(len)?;
+    }
+    Ok(start[..start.len() - r.remaining()].to_vec())
+}
+
+/// Text fields of ObjectUpdate are NUL-terminated variable blocks.
+fn variable_to_string(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).trim_end_matches('\0').to_string()
+}
+
+/// Where an object is and how it moves, relative to its parent (or the region, for roots).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Motion {
+    pub position: Vec3,
+    pub velocity: Vec3,
+    pub acceleration: Vec3,
+    pub rotation: Quat,
+    pub angular_velocity: Vec3,
+    /// Avatars only: the plane their feet rest on.
+    pub collision_plane: Option<Vec4>,
+}
+
+impl Motion {
+    pub fn at(position: Vec3, rotation: Quat) -> Self {
+        Self {
+            position,
+            velocity: Vec3::ZERO,
+            acceleration: Vec3::ZERO,
+            rotation,
+            angular_velocity: Vec3::ZERO,
+            collision_plane: None,
+        }
+    }
+
+    /// Decode the `ObjectData` field of a full ObjectUpdate. The length tells the encoding:
+    /// 60 bytes of floats, 32 of U16s or 16 of U8s, each optionally preceded by an avatar's
+    /// 16-byte collision plane.
+    pub fn from_object_data(data: &[u8]) -> Result<Self, CodecError> {
+        let mut r = WireReader::new(data);
+        let collision_plane = match data.len() {
+            76 | 48 => Some(r.read_vec4()?),
+            60 | 32 | 16 => None,
+            n => return Err(CodecError::Malformed(format!("{}-byte object motion block", n))),
+        };
+        let motion = match r.remaining() {
+            60 => Self {
+                position: r.read_vec3()?,
+                velocity: r.read_vec3()?,
+                acceleration: r.read_vec3()?,
+                rotation: r.read_quat()?,
+                angular_velocity: r.read_vec3()?,
+                collision_plane,
+            },
+            32 => Self {
+                position: read_u16_vec3(&mut r, -0.5 * REGION_WIDTH, 1.5 * REGION_WIDTH)?,
+                velocity: read_u16_vec3(&mut r, -REGION_WIDTH, REGION_WIDTH)?,
+                acceleration: read_u16_vec3(&mut r, -REGION_WIDTH, REGION_WIDTH)?,
+                rotation: read_u16_quat(&mut r)?,
+                angular_velocity: read_u16_vec3(&mut r, -REGION_WIDTH, REGION_WIDTH)?,
+                collision_plane,
+            },
+            _ => Self {
+                position: read_u8_vec3(&mut r, -0.5 * REGION_WIDTH, 1.5 * REGION_WIDTH)?,
+                velocity: read_u8_vec3(&mut r, -REGION_WIDTH, REGION_WIDTH)?,
+                acceleration: read_u8_vec3(&mut r, -REGION_WIDTH, REGION_WIDTH)?,
+                rotation: read_u8_quat(&mut r)?,
+                angular_velocity: read_u8_vec3(&mut r, -REGION_WIDTH, REGION_WIDTH)?,
+                collision_plane,
+            },
+        };
+        Ok(motion)
+    }
+}
+
+/// Path and profile parameters of a prim, quantized as on the wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PrimShape {
+    pub path_curve: u8,
+    pub path_begin: u16,
+    pub path_end: u16,
+    pub path_scale_x: u8,
+    pub path_scale_y: u8,
+    pub path_shear_x: u8,
+    pub path_shear_y: u8,
+    pub path_twist: i8,
+    pub path_twist_begin: i8,
+    pub path_radius_offset: i8,
+    pub path_taper_x: i8,
+    pub path_taper_y: i8,
+    pub path_revolutions: u8,
+    pub path_skew: i8,
+    pub profile_curve: u8,
+    pub profile_begin: u16,
+    pub profile_end: u16,
+    pub profile_hollow: u16,
+}
+
+impl PrimShape {
+    /// The 23-byte shape block of ObjectUpdateCompressed.
+    fn read(r: &mut WireReader<'_>) -> Result<Self, CodecError> {
+        Ok(Self {
+            path_curve: r.read_u8()?,
+            path_begin: r.read_u16()?,
+            path_end: r.read_u16()?,
+            path_scale_x: r.read_u8()?,
+            path_scale_y: r.read_u8()?,
+            path_shear_x: r.read_u8()?,
+            path_shear_y: r.read_u8()?,
+            path_twist: r.read_i8()?,
+            path_twist_begin: r.read_i8()?,
+            path_radius_offset: r.read_i8()?,
+            path_taper_x: r.read_i8()?,
+            path_taper_y: r.read_i8()?,
+            path_revolutions: r.read_u8()?,
+            path_skew: r.read_i8()?,
+            profile_curve: r.read_u8()?,
+            profile_begin: r.read_u16()?,
+            profile_end: r.read_u16()?,
+            profile_hollow: r.read_u16()?,
+        })
+    }
+}
+
+/// A sound looping on an object.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ObjectSound {
+    pub sound_id: Uuid,
+    pub gain: f32,
+    pub flags: u8,
+    pub radius: f32,
+}
+
+/// Everything a full (or compressed) update says about an object.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectState {
+    pub local_id: u32,
+    pub full_id: Uuid,
+    /// Local id of the linkset parent or the avatar an attachment is worn by; 0 for roots.
+    pub parent_id: u32,
+    pub pcode: u8,
+    /// Attachment point for attachments, otherwise unused.
+    pub state: u8,
+    /// Changes whenever the object does; compared against ObjectUpdateCached.
+    pub crc: u32,
+    pub material: u8,
+    pub click_action: u8,
+    pub update_flags: u32,
+    pub scale: Vec3,
+    pub motion: Motion,
+    pub shape: PrimShape,
+    pub owner_id: Uuid,
+    pub texture_entry: Vec<u8>,
+    pub texture_anim: Vec<u8>,
+    /// Name-value pairs, one per line; avatars carry their names here.
+    pub name_values: String,
+    /// Pcode-specific data, e.g. the species of trees and grass.
+    pub data: Vec<u8>,
+    /// Hover text.
+    pub text: String,
+    pub text_color: [u8; 4],
+    pub media_url: String,
+    pub particles: Vec<u8>,
+    pub extra_params: Vec<u8>,
+    pub sound: Option<ObjectSound>,
+}
+
+impl ObjectState {
+    pub fn from_object_update(block: &generated::object_update::ObjectData) -> Result<Self, CodecError> {
+        Ok(Self {
+            local_id: block.id,
+            full_id: block.full_id,
+            parent_id: block.parent_id,
+            pcode: block.p_code,
+            state: block.state,
+            crc: block.crc,
+            material: block.material,
+            click_action: block.click_action,
+            update_flags: block.update_flags,
+            scale: block.scale,
+            motion: Motion::from_object_data(&block.object_data)?,
+            shape: PrimShape {
+                path_curve: block.path_curve,
+                path_begin: block.path_begin,
+                path_end: block.path_end,
+                path_scale_x: block.path_scale_x,
+                path_scale_y: block.path_scale_y,
+                path_shear_x: block.path_shear_x,
+                path_shear_y: block.path_shear_y,
+                path_twist: block.path_twist,
+                path_twist_begin: block.path_twist_begin,
+                path_radius_offset: block.path_radius_offset,
+                path_taper_x: block.path_taper_x,
+                path_taper_y: block.path_taper_y,
+                path_revolutions: block.path_revolutions,
+                path_skew: block.path_skew,
+                profile_curve: block.profile_curve,
+                profile_begin: block.profile_begin,
+                profile_end: block.profile_end,
+                profile_hollow: block.profile_hollow,
+            },
+            owner_id: block.owner_id,
+            texture_entry: block.texture_entry.clone(),
+            texture_anim: block.texture_anim.clone(),
+            name_values: variable_to_string(&block.name_value),
+            data: block.data.clone(),
+            text: variable_to_string(&block.text),
+            text_color: block.text_color,
+            media_url: variable_to_string(&block.media_url),
+            particles: block.ps_block.clone(),
+            extra_params: block.extra_params.clone(),
+            sound: (!block.sound.is_nil()).then_some(ObjectSound {
+                sound_id: block.sound,
+                gain: block.gain,
+                flags: block.flags,
+                radius: block.radius,
+            }),
+        })
+    }
+
+    /// Decode the `Data` blob of an ObjectUpdateCompressed block.
+    pub fn from_compressed(update_flags: u32, data: &[u8]) -> Result<Self, CodecError> {
+        let mut r = WireReader::new(data);
+        let full_id = r.read_uuid()?;
+        let local_id = r.read_u32()?;
+        let pcode = r.read_u8()?;
+        let state = r.read_u8()?;
+        let crc = r.read_u32()?;
+        let material = r.read_u8()?;
+        let click_action = r.read_u8()?;
+        let scale = r.read_vec3()?;
+        let mut motion = Motion::at(r.read_vec3()?, r.read_quat()?);
+        let flags = CompressedFlags::from_bits_retain(r.read_u32()?);
+        let owner_id = r.read_uuid()?;
+        if flags.contains(CompressedFlags::HAS_ANGULAR_VELOCITY) {
+            motion.angular_velocity = r.read_vec3()?;
+        }
+        let parent_id = if flags.contains(CompressedFlags::HAS_PARENT) { r.read_u32()? } else { 0 };
+        let data = if flags.contains(CompressedFlags::TREE) {
+            vec![r.read_u8()?]
+        } else if flags.contains(CompressedFlags::SCRATCH_PAD) {
+            let len = r.read_u8()? as usize;
+            r.read_bytes(len)?.to_vec()
+        } else {
+            Vec::new()
+        };
+        let (text, text_color) = if flags.contains(CompressedFlags::HAS_TEXT) {
+            (read_c_string(&mut r)?, r.read_fixed()?)
+        } else {
+            (String::new(), [0; 4])
+        };
+        let media_url = if flags.contains(CompressedFlags::MEDIA_URL) { read_c_string(&mut r)? } else { String::new() };
+        let particles = if flags.contains(CompressedFlags::HAS_PARTICLES) {
+            r.read_bytes(PARTICLE_BLOCK_SIZE)?.to_vec()
+        } else {
+            Vec::new()
+        };
+        let extra_params = read_extra_params(&mut r)?;
+        let sound = if flags.contains(CompressedFlags::HAS_SOUND) {
+            Some(ObjectSound { sound_id: r.read_uuid()?, gain: r.read_f32()?, flags: r.read_u8()?, radius: r.read_f32()? })
+        } else {
+            None
+        };
+        let name_values = if flags.contains(CompressedFlags::HAS_NAME_VALUES) { read_c_string(&mut r)? } else { String::new() };
+        let shape = PrimShape::read(&mut r)?;
+        let len = r.read_u32()? as usize;
+        let texture_entry = r.read_bytes(len)?.to_vec();
+        let texture_anim = if flags.contains(CompressedFlags::TEXTURE_ANIMATION) {
+            let len = r.read_u32()? as usize;
+            r.read_bytes(len)?.to_vec()
+        } else {
+            Vec::new()
+        };
+        // HAS_PARTICLES_NEW appends a variable-length particle system; nothing reads it yet.
+        Ok(Self {
+            local_id,
+            full_id,
+            parent_id,
+            pcode,
+            state,
+            crc,
+            material,
+            click_action,
+            update_flags,
+            scale,
+            motion,
+            shape,
+            owner_id,
+            texture_entry,
+            texture_anim,
+            name_values,
+            data,
+            text,
+            text_color,
+            media_url,
+            particles,
+            extra_params,
+            sound,
+        })
+    }
+
+    /// Encode as an ObjectUpdateCompressed `Data` blob, the inverse of [`from_compressed`](Self::from_compressed).
+    /// Velocity, acceleration and the collision plane have no place in it and are dropped.
+    pub fn to_compressed(&self) -> Result<Vec<u8>, CodecError> {
+        let mut flags = CompressedFlags::empty();
+        flags.set(CompressedFlags::HAS_ANGULAR_VELOCITY, self.motion.angular_velocity != Vec3::ZERO);
+        flags.set(CompressedFlags::HAS_PARENT, self.parent_id != 0);
+        let tree = matches!(self.pcode, PCODE_TREE | PCODE_NEW_TREE | PCODE_GRASS) && self.data.len() == 1;
+        flags.set(CompressedFlags::TREE, tree);
+        flags.set(CompressedFlags::SCRATCH_PAD, !tree && !self.data.is_empty());
+        flags.set(CompressedFlags::HAS_TEXT, !self.text.is_empty());
+        flags.set(CompressedFlags::MEDIA_URL, !self.media_url.is_empty());
+        flags.set(CompressedFlags::HAS_PARTICLES, self.particles.len() == PARTICLE_BLOCK_SIZE);
+        flags.set(CompressedFlags::HAS_SOUND, self.sound.is_some());
+        flags.set(CompressedFlags::HAS_NAME_VALUES, !self.name_values.is_empty());
+        flags.set(CompressedFlags::TEXTURE_ANIMATION, !self.texture_anim.is_empty());
+
+        let mut w = WireWriter::new();
+        w.put_uuid(&self.full_id);
+        w.put_u32(self.local_id);
+        w.put_u8(self.pcode);
+        w.put_u8(self.state);
+        w.put_u32(self.crc);
+        w.put_u8(self.material);
+        w.put_u8(self.click_action);
+        w.put_vec3(self.scale);
+        w.put_vec3(self.motion.position);
+        w.put_quat(self.motion.rotation);
+        w.put_u32(flags.bits());
+        w.put_uuid(&self.owner_id);
+        if flags.contains(CompressedFlags::HAS_ANGULAR_VELOCITY) {
+            w.put_vec3(self.motion.angular_velocity);
+        }
+        if flags.contains(CompressedFlags::HAS_PARENT) {
+            w.put_u32(self.parent_id);
+        }
+        if tree {
+            w.put_u8(self.data[0]);
+        } else if flags.contains(CompressedFlags::SCRATCH_PAD) {
+            w.put_variable1("ScratchPad", &self.data)?;
+        }
+        if flags.contains(CompressedFlags::HAS_TEXT) {
+            put_c_string(&mut w, &self.text);
+            w.put_bytes(&self.text_color);
+        }
+        if flags.contains(CompressedFlags::MEDIA_URL) {
+            put_c_string(&mut w, &self.media_url);
+        }
+        if flags.contains(CompressedFlags::HAS_PARTICLES) {
+            w.put_bytes(&self.particles);
+        }
+        if self.extra_params.is_empty() {
+            w.put_u8(0);
+        } else {
+            w.put_bytes(&self.extra_params);
+        }
+        if let Some(sound) = &self.sound {
+            w.put_uuid(&sound.sound_id);
+            w.put_f32(sound.gain);
+            w.put_u8(sound.flags);
+            w.put_f32(sound.radius);
+        }
+        if flags.contains(CompressedFlags::HAS_NAME_VALUES) {
+            put_c_string(&mut w, &self.name_values);
+        }
+        let shape = &self.shape;
+        w.put_u8(shape.path_curve);
+        w.put_u16(shape.path_begin);
+        w.put_u16(shape.path_end);
+        w.put_u8(shape.path_scale_x);
+        w.put_u8(shape.path_scale_y);
+        w.put_u8(shape.path_shear_x);
+        w.put_u8(shape.path_shear_y);
+        w.put_i8(shape.path_twist);
+        w.put_i8(shape.path_twist_begin);
+        w.put_i8(shape.path_radius_offset);
+        w.put_i8(shape.path_taper_x);
+        w.put_i8(shape.path_taper_y);
+        w.put_u8(shape.path_revolutions);
+        w.put_i8(shape.path_skew);
+        w.put_u8(shape.profile_curve);
+        w.put_u16(shape.profile_begin);
+        w.put_u16(shape.profile_end);
+        w.put_u16(shape.profile_hollow);
+        w.put_u32(self.texture_entry.len() as u32);
+        w.put_bytes(&self.texture_entry);
+        if flags.contains(CompressedFlags::TEXTURE_ANIMATION) {
+            w.put_u32(self.texture_anim.len() as u32);
+            w.put_bytes(&self.texture_anim);
+        }
+        Ok(w.into_inner())
+    }
+}
+
+/// Motion-only update from ImprovedTerseObjectUpdate.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TerseUpdate {
+    pub local_id: u32,
+    pub state: u8,
+    pub motion: Motion,
+    /// Replacement texture entry, if the simulator sent one along.
+    pub texture_entry: Option<Vec<u8>>,
+}
+
+impl TerseUpdate {
+    /// Position is sent at full precision, the rest as U16s over fixed ranges.
+    pub fn decode(data: &[u8], texture_entry: &[u8]) -> Result<Self, CodecError> {
+        let mut r = WireReader::new(data);
+        let local_id = r.read_u32()?;
+        let state = r.read_u8()?;
+        let collision_plane = if r.read_bool()? { Some(r.read_vec4()?) } else { None };
+        let motion = Motion {
+            position: r.read_vec3()?,
+            velocity: read_u16_vec3(&mut r, -128.0, 128.0)?,
+            acceleration: read_u16_vec3(&mut r, -64.0, 64.0)?,
+            rotation: read_u16_quat(&mut r)?,
+            angular_velocity: read_u16_vec3(&mut r, -64.0, 64.0)?,
+            collision_plane,
+        };
+        // A non-empty texture entry carries a 4-byte length prefix.
+        let texture_entry = match texture_entry.len() {
+            0 => None,
+            n if n < 4 => return Err(CodecError::Malformed(format!("{}-byte terse texture entry", n))),
+            _ => Some(texture_entry[4..].to_vec()),
+        };
+        Ok(Self { local_id, state, motion, texture_entry })
+    }
+}
+
+/// One object's worth of an update message.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ObjectUpdate {
+    Full(Box<ObjectState>),
+    Terse(TerseUpdate),
+    /// The simulator thinks we may have this object cached; a CRC mismatch means we do not.
+    Cached { local_id: u32, crc: u32, update_flags: u32 },
+    Kill { local_id: u32 },
+}
+
+/// Decode an object message; any other message decodes to nothing. Blocks that do not parse
+/// are logged and skipped so one bad object does not hide the rest of the packet.
+pub fn decode(message: &Message) -> Vec<ObjectUpdate> {
+    match message {
+        Message::ObjectUpdate(msg) => from_object_update(msg),
+        Message::ObjectUpdateCompressed(msg) => from_object_update_compressed(msg),
+        Message::ImprovedTerseObjectUpdate(msg) => from_improved_terse_object_update(msg),
+        Message::ObjectUpdateCached(msg) => from_object_update_cached(msg),
+        Message::KillObject(msg) => from_kill_object(msg),
+        _ => Vec::new(),
+    }
+}
+
+fn keep_decoded<T>(message: &str, decoded: Result<T, CodecError>) -> Option<T> {
+    decoded.map_err(|e| tracing::warn!("Skipping undecodable {} block: {}", message, e)).ok()
+}
+
+pub fn from_object_update(msg: &generated::ObjectUpdate) -> Vec<ObjectUpdate> {
+    msg.object_data
+        .iter()
+        .filter_map(|block| keep_decoded("ObjectUpdate", ObjectState::from_object_update(block)))
+        .map(|state| ObjectUpdate::Full(Box::new(state)))
+        .collect()
+}
+
+pub fn from_object_update_compressed(msg: &generated::ObjectUpdateCompressed) -> Vec<ObjectUpdate> {
+    msg.object_data
+        .iter()
+        .filter_map(|block| keep_decoded("ObjectUpdateCompressed", ObjectState::from_compressed(block.update_flags, &block.data)))
+        .map(|state| ObjectUpdate::Full(Box::new(state)))
+        .collect()
+}
+
+pub fn from_improved_terse_object_update(msg: &generated::ImprovedTerseObjectUpdate) -> Vec<ObjectUpdate> {
+    msg.object_data
+        .iter()
+        .filter_map(|block| keep_decoded("ImprovedTerseObjectUpdate", TerseUpdate::decode(&block.data, &block.texture_entry)))
+        .map(ObjectUpdate::Terse)
+        .collect()
+}
+
+pub fn from_object_update_cached(msg: &generated::ObjectUpdateCached) -> Vec<ObjectUpdate> {
+    msg.object_data
+        .iter()
+        .map(|block| ObjectUpdate::Cached { local_id: block.id, crc: block.crc, update_flags: block.update_flags })
+        .collect()
+}
+
+pub fn from_kill_object(msg: &generated::KillObject) -> Vec<ObjectUpdate> {
+    msg.object_data.iter().map(|block| ObjectUpdate::Kill { local_id: block.id }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_u16_quantization() {
+        assert_eq!(u16_to_f32(0, -64.0, 64.0), -64.0);
+        assert_eq!(u16_to_f32(u16::MAX, -64.0, 64.0), 64.0);
+        // 32767 is half a step below the midpoint
+        assert_eq!(u16_to_f32(32767, -64.0, 64.0), 0.0);
+        assert_eq!(u8_to_f32(u8::MAX, -256.0, 256.0), 256.0);
+
+        // The U8 motion block spans the same position range as the U16 one.
+        let data = [0, 255, 0, 127, 127, 127, 127, 127, 127, 127, 127, 127, 255, 127, 127, 127];
+        let motion = Motion::from_object_data(&data).unwrap();
+        assert_eq!(motion.position, Vec3::new(-128.0, 384.0, -128.0));
+        assert_eq!((motion.velocity, motion.rotation), (Vec3::ZERO, Quat::IDENTITY));
+    }
+
+    #[test]
+    fn test_decode_terse_and_compressed() {
+        let mut terse = 7u32.to_le_bytes().to_vec();
+        terse.extend_from_slice(&[0, 0]);
+        terse.extend([1.0f32, 2.0, 3.0].iter().flat_map(|f| f.to_le_bytes()));
+        // Velocity x at the top of its range, the rest at rest; identity rotation (w = 1)
+        for v in [u16::MAX, 32767, 32767, 32767, 32767, 32767, 32767, 32767, 32767, u16::MAX, 32767, 32767, 32767] {
+            terse.extend_from_slice(&v.to_le_bytes());
+        }
+        let update = TerseUpdate::decode(&terse, &[]).unwrap();
+        assert_eq!(update.local_id, 7);
+        assert_eq!(update.motion.position, Vec3::new(1.0, 2.0, 3.0));
+        assert_eq!(update.motion.velocity, Vec3::new(128.0, 0.0, 0.0));
+        assert_eq!(update.motion.rotation, Quat::IDENTITY);
+        assert_eq!(update.motion.collision_plane, None);
+
+        let full_id = Uuid::new_v4();
+        let mut blob = full_id.as_bytes().to_vec();
+        blob.extend_from_slice(&42u32.to_le_bytes());
+        blob.extend_from_slice(&[PCODE_PRIM, 0]);
+        blob.extend_from_slice(&0xDEADu32.to_le_bytes());
+        blob.extend_from_slice(&[3, 0]);
+        blob.extend([0.5f32, 0.5, 0.5, 10.0, 20.0, 30.0, 0.0, 0.0, 0.0].iter().flat_map(|f| f.to_le_bytes()));
+        let flags = CompressedFlags::HAS_PARENT | CompressedFlags::HAS_TEXT;
+        blob.extend_from_slice(&flags.bits().to_le_bytes());
+        blob.extend_from_slice(Uuid::nil().as_bytes());
+        blob.extend_from_slice(&41u32.to_le_bytes());
+        blob.extend_from_slice(b"For sale\0");
+        blob.extend_from_slice(&[255, 255, 255, 255]);
+        blob.push(0); // no extra params
+        blob.extend_from_slice(&[16, 0, 0, 0, 0, 100, 100, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0]);
+        blob.extend_from_slice(&3u32.to_le_bytes());
+        blob.extend_from_slice(&[1, 2, 3]);
+        let state = ObjectState::from_compressed(0, &blob).unwrap();
+        assert_eq!((state.local_id, state.full_id, state.parent_id, state.crc), (42, full_id, 41, 0xDEAD));
+        assert_eq!(state.motion.position, Vec3::new(10.0, 20.0, 30.0));
+        assert_eq!(state.text, "For sale");
+        assert_eq!((state.shape.path_curve, state.shape.path_scale_x, state.shape.profile_curve), (16, 100, 1));
+        assert_eq!(state.texture_entry, vec![1, 2, 3]);
+        assert_eq!(state.to_compressed().unwrap(), blob);
+        assert!(ObjectState::from_compressed(0, &blob[..blob.len() - 1]).is_err());
+    }
+}
@@ -0,0 +1,480 @@
+//! Every object the agent can see, across the regions it has circuits to.
+//!
+//! Local ids are only unique within a region, so objects are keyed by region handle and local
+//! id, with a second index by full id. The networking actor feeds each circuit's decoded
+//! [`ObjectUpdate`]s in; the renderer and UI read the store and [`subscribe`](ObjectStore::subscribe)
+//! to [`ObjectEvent`]s to learn what changed. With an [`ObjectCache`] attached, full updates
+//! are remembered across sessions and ObjectUpdateCached is answered from disk. The cache files
+//! are read and written by [`load_region_cache`] and [`flush_cache`], never under the store's lock.
+
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use crate::world::object_cache::{ObjectCache, RegionCache};
+use crate::world::object_update::{ObjectState, ObjectUpdate, PCODE_AVATAR};
+
+/// Events buffered before slow subscribers start missing them.
+const EVENT_CHANNEL_CAPACITY: usize = 1024;
+
+/// The store shared between the networking actor, which writes it from the circuits' receive
+/// tasks, and its readers.
+pub type SharedObjectStore = Arc<Mutex<ObjectStore>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ObjectKey {
+    pub region_handle: u64,
+    pub local_id: u32,
+}
+
+impl ObjectKey {
+    pub fn new(region_handle: u64, local_id: u32) -> Self {
+        Self { region_handle, local_id }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Object {
+    pub region_handle: u64,
+    /// Latest full state; terse updates patch the motion in place.
+    pub state: ObjectState,
+}
+
+impl Object {
+    pub fn key(&self) -> ObjectKey {
+        ObjectKey::new(self.region_handle, self.state.local_id)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectEvent {
+    Added(ObjectKey),
+    /// A full update replaced the object's state.
+    Updated(ObjectKey),
+    /// A terse update changed only its motion.
+    Moved(ObjectKey),
+    Removed { key: ObjectKey, full_id: Uuid },
+}
+
+/// The local ids a batch of updates could not place, by how the simulator should resend them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MissingObjects {
+    /// Terse updates for objects the store has never seen.
+    pub unknown: Vec<u32>,
+    /// ObjectUpdateCached entries the cache could not answer.
+    pub cache_misses: Vec<u32>,
+}
+
+impl MissingObjects {
+    pub fn is_empty(&self) -> bool {
+        self.unknown.is_empty() && self.cache_misses.is_empty()
+    }
+}
+
+/// A region whose cache file is being read. Its ObjectUpdateCached entries wait here.
+struct LoadingRegion {
+    region_id: Uuid,
+    cache_id: Uuid,
+    cached: Vec<ObjectUpdate>,
+}
+
+pub struct ObjectStore {
+    objects: HashMap<ObjectKey, Object>,
+    by_full_id: HashMap<Uuid, ObjectKey>,
+    /// Child local ids by parent. Children often arrive before their parent, so entries may
+    /// name parents the store has not seen yet.
+    children: HashMap<ObjectKey, BTreeSet<u32>>,
+    events: broadcast::Sender<ObjectEvent>,
+    cache: Option<ObjectCache>,
+    loading: HashMap<u64, LoadingRegion>,
+}
+
+impl Default for ObjectStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ObjectStore {
+    pub fn new() -> Self {
+        Self {
+            objects: HashMap::new(),
+            by_full_id: HashMap::new(),
+            children: HashMap::new(),
+            events: broadcast::channel(EVENT_CHANNEL_CAPACITY).0,
+            cache: None,
+            loading: HashMap::new(),
+        }
+    }
+
+    pub fn set_cache(&mut self, cache: ObjectCache) {
+        self.cache = Some(cache);
+    }
+
+    /// Hold back the region's cached updates until [`finish_region_cache`](Self::finish_region_cache).
+    fn begin_region_cache(&mut self, region_handle: u64, region_id: Uuid, cache_id: Uuid) -> bool {
+        let Some(cache) = &mut self.cache else {
+            return false;
+        };
+        cache.close_region(region_handle);
+        self.loading.insert(region_handle, LoadingRegion { region_id, cache_id, cached: Vec::new() });
+        true
+    }
+
+    /// Start using a region's cache once it has been read, and answer the updates that waited for
+    /// it. Objects that arrived in full meanwhile go into the cache too.
+    fn finish_region_cache(&mut self, region_handle: u64, mut region: RegionCache) -> MissingObjects {
+        match self.loading.get(&region_handle) {
+            // Read for a circuit the region no longer has
+            Some(loading) if loading.region_id == region.region_id && loading.cache_id == region.cache_id => {}
+            _ => return MissingObjects::default(),
+        }
+        let Some(cache) = &mut self.cache else {
+            return MissingObjects::default();
+        };
+        for object in self.objects.values().filter(|object| object.region_handle == region_handle) {
+            region.insert(&object.state);
+        }
+        cache.open_region(region_handle, region);
+        let cached = self.loading.remove(&region_handle).map(|loading| loading.cached).unwrap_or_default();
+        self.apply(region_handle, cached)
+    }
+
+    pub fn shared() -> SharedObjectStore {
+        Arc::new(Mutex::new(Self::new()))
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<ObjectEvent> {
+        self.events.subscribe()
+    }
+
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    pub fn get(&self, key: ObjectKey) -> Option<&Object> {
+        self.objects.get(&key)
+    }
+
+    pub fn get_by_full_id(&self, full_id: &Uuid) -> Option<&Object> {
+        self.by_full_id.get(full_id).and_then(|key| self.objects.get(key))
+    }
+
+    pub fn objects(&self) -> impl Iterator<Item = &Object> {
+        self.objects.values()
+    }
+
+    pub fn region_objects(&self, region_handle: u64) -> impl Iterator<Item = &Object> {
+        self.objects.values().filter(move |object| object.region_handle == region_handle)
+    }
+
+    /// The object's known children: linked prims, attachments and seated avatars.
+    pub fn children(&self, key: ObjectKey) -> impl Iterator<Item = &Object> {
+        self.children
+            .get(&key)
+            .into_iter()
+            .flatten()
+            .filter_map(move |&local_id| self.objects.get(&ObjectKey::new(key.region_handle, local_id)))
+    }
+
+    /// The top of the object's hierarchy, as far as the store knows it.
+    pub fn root(&self, key: ObjectKey) -> ObjectKey {
+        let mut key = key;
+        // Bounded in case a simulator ever sends a parent cycle
+        for _ in 0..self.objects.len() {
+            match self.objects.get(&key).map(|object| object.state.parent_id) {
+                Some(parent_id) if parent_id != 0 && self.objects.contains_key(&ObjectKey::new(key.region_handle, parent_id)) => {
+                    key = ObjectKey::new(key.region_handle, parent_id);
+                }
+                _ => break,
+            }
+        }
+        key
+    }
+
+    /// The linkset `key` belongs to: its root, then every known descendant.
+    pub fn linkset(&self, key: ObjectKey) -> Vec<ObjectKey> {
+        let mut linkset = vec![self.root(key)];
+        let mut next = 0;
+        while next < linkset.len() {
+            let parent = linkset[next];
+            for child in self.children(parent) {
+                if !linkset.contains(&child.key()) {
+                    linkset.push(child.key());
+                }
+            }
+            next += 1;
+        }
+        linkset
+    }
+
+    /// Apply a batch of updates from one region. Returns the local ids that need a full
+    /// update: cache misses and terse updates for objects the store has never seen.
+    pub fn apply(&mut self, region_handle: u64, updates: impl IntoIterator<Item = ObjectUpdate>) -> MissingObjects {
+        let mut missing = MissingObjects::default();
+        for update in updates {
+            match update {
+                ObjectUpdate::Full(state) => {
+                    if let Some(cache) = self.cache.as_mut().and_then(|cache| cache.region_mut(region_handle)) {
+                        cache.insert(&state);
+                    }
+                    self.insert(region_handle, *state);
+                }
+                ObjectUpdate::Terse(terse) => {
+                    let key = ObjectKey::new(region_handle, terse.local_id);
+                    let Some(object) = self.objects.get_mut(&key) else {
+                        missing.unknown.push(terse.local_id);
+                        continue;
+                    };
+                    object.state.state = terse.state;
+                    object.state.motion = terse.motion;
+                    if let Some(texture_entry) = terse.texture_entry {
+                        object.state.texture_entry = texture_entry;
+                    }
+                    let _ = self.events.send(ObjectEvent::Moved(key));
+                }
+                ObjectUpdate::Cached { local_id, crc, update_flags } => {
+                    if let Some(loading) = self.loading.get_mut(&region_handle) {
+                        loading.cached.push(ObjectUpdate::Cached { local_id, crc, update_flags });
+                        continue;
+                    }
+                    let known = self.objects.get(&ObjectKey::new(region_handle, local_id));
+                    if known.is_some_and(|object| object.state.crc == crc) {
+                        continue;
+                    }
+                    let cached = self.cache.as_ref().and_then(|cache| cache.region(region_handle)?.lookup(local_id, crc));
+                    match cached.cloned() {
+                        Some(mut state) => {
+                            // Flags are per viewer (e.g. whether we may modify it), so they are not cached.
+                            state.update_flags = update_flags;
+                            self.insert(region_handle, state);
+                        }
+                        None => missing.cache_misses.push(local_id),
+                    }
+                }
+                ObjectUpdate::Kill { local_id } => {
+                    if let Some(cache) = self.cache.as_mut().and_then(|cache| cache.region_mut(region_handle)) {
+                        cache.remove(local_id);
+                    }
+                    self.kill(ObjectKey::new(region_handle, local_id));
+                }
+            }
+        }
+        missing
+    }
+
+    fn insert(&mut self, region_handle: u64, state: ObjectState) {
+        let key = ObjectKey::new(region_handle, state.local_id);
+        let (full_id, parent_id) = (state.full_id, state.parent_id);
+        let previous = self.objects.insert(key, Object { region_handle, state });
+        if let Some(previous) = &previous {
+            if previous.state.full_id != full_id {
+                // The simulator reused the local id for another object.
+                self.by_full_id.remove(&previous.state.full_id);
+            }
+            if previous.state.parent_id != parent_id {
+                self.unlink(key, previous.state.parent_id);
+            }
+        }
+        self.by_full_id.insert(full_id, key);
+        if parent_id != 0 {
+            self.children.entry(ObjectKey::new(region_handle, parent_id)).or_default().insert(key.local_id);
+        }
+        let _ = self.events.send(if previous.is_some() { ObjectEvent::Updated(key) } else { ObjectEvent::Added(key) });
+    }
+
+    fn unlink(&mut self, key: ObjectKey, parent_id: u32) {
+        let parent = ObjectKey::new(key.region_handle, parent_id);
+        if let Some(siblings) = self.children.get_mut(&parent) {
+            siblings.remove(&key.local_id);
+            if siblings.is_empty() {
+                self.children.remove(&parent);
+            }
+        }
+    }
+
+    /// Remove an object and the prims linked to it. Avatars sitting on it stay, unseated.
+    fn kill(&mut self, key: ObjectKey) {
+        let Some(object) = self.objects.remove(&key) else {
+            return;
+        };
+        if self.by_full_id.get(&object.state.full_id) == Some(&key) {
+            self.by_full_id.remove(&object.state.full_id);
+        }
+        self.unlink(key, object.state.parent_id);
+        for child_id in self.children.remove(&key).unwrap_or_default() {
+            let child = ObjectKey::new(key.region_handle, child_id);
+            match self.objects.get_mut(&child) {
+                Some(avatar) if avatar.state.pcode == PCODE_AVATAR => {
+                    avatar.state.parent_id = 0;
+                    let _ = self.events.send(ObjectEvent::Updated(child));
+                }
+                Some(_) => self.kill(child),
+                None => {}
+            }
+        }
+        let _ = self.events.send(ObjectEvent::Removed { key, full_id: object.state.full_id });
+    }
+
+    /// Forget a region's objects, e.g. when its circuit closes or reconnects. Its cache is
+    /// written by the next [`flush_cache`].
+    pub fn remove_region(&mut self, region_handle: u64) {
+        if let Some(cache) = &mut self.cache {
+            cache.close_region(region_handle);
+        }
+        self.loading.remove(&region_handle);
+        let keys: Vec<ObjectKey> = self.objects.keys().filter(|key| key.region_handle == region_handle).copied().collect();
+        for key in keys {
+            if let Some(object) = self.objects.remove(&key) {
+                if self.by_full_id.get(&object.state.full_id) == Some(&key) {
+                    self.by_full_id.remove(&object.state.full_id);
+                }
+                let _ = self.events.send(ObjectEvent::Removed { key, full_id: object.state.full_id });
+            }
+        }
+        self.children.retain(|parent, _| parent.region_handle != region_handle);
+    }
+
+    pub fn clear(&mut self) {
+        let regions: BTreeSet<u64> = self.objects.keys().map(|key| key.region_handle).collect();
+        for region_handle in regions {
+            self.remove_region(region_handle);
+        }
+        if let Some(cache) = &mut self.cache {
+            cache.close_all();
+        }
+        self.loading.clear();
+    }
+}
+
+/// Load a region's object cache from its RegionHandshake, before its objects arrive. The file is
+/// read on the blocking pool; cached updates that come in meanwhile are answered once it is, and
+/// whatever they miss goes to `on_missing`.
+pub fn load_region_cache(
+    store: &SharedObjectStore,
+    region_handle: u64,
+    region_id: Uuid,
+    cache_id: Uuid,
+    on_missing: impl FnOnce(MissingObjects) + Send + 'static,
+) {
+    let (dir, io) = {
+        let mut locked = store.lock().unwrap();
+        if !locked.begin_region_cache(region_handle, region_id, cache_id) {
+            return;
+        }
+        let cache = locked.cache.as_ref().expect("begin_region_cache checked for a cache");
+        (cache.dir().to_path_buf(), cache.io())
+    };
+    let store = Arc::clone(store);
+    io.run(region_id, move || {
+        let region = RegionCache::read(&dir, region_id, cache_id);
+        let missing = store.lock().unwrap().finish_region_cache(region_handle, region);
+        if !missing.is_empty() {
+            on_missing(missing);
+        }
+    });
+}
+
+/// Write out every region cache that changed since the last flush.
+pub fn flush_cache(store: &SharedObjectStore) {
+    let Some((dir, io, unsaved)) =
+        store.lock().unwrap().cache.as_mut().map(|cache| (cache.dir().to_path_buf(), cache.io(), cache.take_unsaved()))
+    else {
+        return;
+    };
+    for region in unsaved {
+        let dir = dir.clone();
+        io.run(region.region_id, move || region.write(&dir));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::object_update::{Motion, PrimShape, TerseUpdate, PCODE_PRIM};
+    use glam::{Quat, Vec3};
+
+    fn prim(local_id: u32, parent_id: u32, pcode: u8) -> ObjectUpdate {
+        ObjectUpdate::Full(Box::new(ObjectState {
+            local_id,
+            full_id: Uuid::new_v4(),
+            parent_id,
+            pcode,
+            state: 0,
+            crc: local_id,
+            material: 3,
+            click_action: 0,
+            update_flags: 0,
+            scale: Vec3::ONE,
+            motion: Motion::at(Vec3::ZERO, Quat::IDENTITY),
+            shape: PrimShape::default(),
+            owner_id: Uuid::nil(),
+            texture_entry: Vec::new(),
+            texture_anim: Vec::new(),
+            name_values: String::new(),
+            data: Vec::new(),
+            text: String::new(),
+            text_color: [0; 4],
+            media_url: String::new(),
+            particles: Vec::new(),
+            extra_params: Vec::new(),
+            sound: None,
+        }))
+    }
+
+    #[test]
+    fn test_linksets_terse_cached_and_kill() {
+        let (here, there) = (1u64 << 32, 2u64 << 32);
+        let mut store = ObjectStore::new();
+        let mut events = store.subscribe();
+        // Children first, as simulators often send them
+        let linked = [prim(11, 10, PCODE_PRIM), prim(12, 11, PCODE_PRIM), prim(13, 10, PCODE_AVATAR), prim(10, 0, PCODE_PRIM)];
+        let missing = store.apply(here, linked);
+        assert!(missing.is_empty());
+        store.apply(there, [prim(10, 0, PCODE_PRIM)]);
+        assert_eq!(store.len(), 5);
+        assert_eq!(store.root(ObjectKey::new(here, 12)), ObjectKey::new(here, 10));
+        let linkset = store.linkset(ObjectKey::new(here, 12));
+        assert_eq!(linkset.len(), 4);
+        assert_eq!(linkset[0], ObjectKey::new(here, 10));
+        let full_id = store.get(ObjectKey::new(here, 11)).unwrap().state.full_id;
+        assert_eq!(store.get_by_full_id(&full_id).unwrap().key(), ObjectKey::new(here, 11));
+
+        let motion = Motion::at(Vec3::new(5.0, 6.0, 7.0), Quat::IDENTITY);
+        let moved = TerseUpdate { local_id: 10, state: 0, motion, texture_entry: None };
+        let unknown = TerseUpdate { local_id: 99, ..moved.clone() };
+        let missing = store.apply(
+            here,
+            [
+                ObjectUpdate::Terse(moved),
+                ObjectUpdate::Terse(unknown),
+                ObjectUpdate::Cached { local_id: 11, crc: 11, update_flags: 0 },
+                ObjectUpdate::Cached { local_id: 12, crc: 0, update_flags: 0 },
+            ],
+        );
+        assert_eq!(missing, MissingObjects { unknown: vec![99], cache_misses: vec![12] });
+        assert_eq!(store.get(ObjectKey::new(here, 10)).unwrap().state.motion.position, Vec3::new(5.0, 6.0, 7.0));
+
+        store.apply(here, [ObjectUpdate::Kill { local_id: 10 }]);
+        assert!(store.get_by_full_id(&full_id).is_none());
+        assert_eq!(store.get(ObjectKey::new(here, 13)).unwrap().state.parent_id, 0);
+        assert_eq!(store.len(), 2);
+        store.remove_region(there);
+        assert_eq!(store.objects().map(Object::key).collect::<Vec<_>>(), vec![ObjectKey::new(here, 13)]);
+
+        let mut seen = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            seen.push(event);
+        }
+        assert_eq!(seen.iter().filter(|e| matches!(e, ObjectEvent::Added(_))).count(), 5);
+        assert_eq!(seen.iter().filter(|e| matches!(e, ObjectEvent::Removed { .. })).count(), 4);
+        assert!(seen.contains(&ObjectEvent::Moved(ObjectKey::new(here, 10))));
+        assert!(seen.contains(&ObjectEvent::Updated(ObjectKey::new(here, 13))));
+    }
+}
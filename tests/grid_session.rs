@@ -0,0 +1,369 @@
+//! Login → handshake → chat and objects → logout against the in-process mock grid, plus
+//! multi-region circuits sharing one socket, the agent moving between regions and the region's
+//! capabilities.
+
+mod mock_grid;
+
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::Receiver;
+use glam::Vec3;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+use mock_grid::{sim, MockGrid, MockSim, PASSWORD};
+use slv_rust::networking::actor::{self, NetCommand, NetEvent, NetHandle};
+use slv_rust::networking::caps::CapsClient;
+use slv_rust::networking::circuit::{AgentState, CircuitParams};
+use slv_rust::networking::circuit_manager::{self, CircuitManager};
+use slv_rust::networking::llsd::Llsd;
+use slv_rust::networking::protocol::generated;
+use slv_rust::networking::session::{login_to_secondlife, LoginOutcome};
+use slv_rust::world::objects::ObjectKey;
+
+/// Wait for the first event `matches` accepts, skipping the others.
+async fn next_event<T>(events: &Receiver<NetEvent>, mut matches: impl FnMut(NetEvent) -> Option<T>) -> T {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while Instant::now() < deadline {
+        while let Ok(event) = events.try_recv() {
+            if let Some(found) = matches(event) {
+                return found;
+            }
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("timed out waiting for network event");
+}
+
+fn agent_state() -> Arc<Mutex<AgentState>> {
+    Arc::new(Mutex::new(AgentState {
+        position: (0.0, 0.0, 0.0),
+        camera_at: (0.0, 0.0, 0.0),
+        camera_eye: (0.0, 0.0, 0.0),
+        controls: 0,
+    }))
+}
+
+async fn wait_until(mut done: impl FnMut() -> bool) {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !done() {
+        assert!(Instant::now() < deadline, "timed out waiting for the condition");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+/// Log in to the mock grid and wait for the actor's circuit to the login region.
+async fn log_in(grid: &mut MockGrid) -> (NetHandle, Receiver<NetEvent>) {
+    let session = match login_to_secondlife(&grid.grid(), &grid.login_request(PASSWORD), None, 0).await.unwrap() {
+        LoginOutcome::Success(session) => session,
+        other => panic!("login failed: {:?}", other),
+    };
+    let (net, events) = actor::spawn_network_actor_with_cache(Some(grid.object_cache()));
+    let sim_addr = SocketAddr::new(session.sim_ip.parse().unwrap(), session.sim_port);
+    net.send(NetCommand::Connect { session, sim_addr, udp_port: 0, proxy_settings: None });
+    grid.sim.expect::<generated::RegionHandshakeReply>().await;
+    next_event(&events, |event| match event {
+        NetEvent::Connected { .. } => Some(()),
+        NetEvent::ConnectFailed(e) => panic!("connect failed: {}", e),
+        _ => None,
+    })
+    .await;
+    (net, events)
+}
+
+/// The region fields TeleportFinish and CrossedRegion share, as simulators send them.
+fn region_fields(region_handle: u64, sim_addr: SocketAddr) -> [(&'static str, Llsd); 3] {
+    let IpAddr::V4(ip) = sim_addr.ip() else {
+        panic!("mock simulators listen on IPv4");
+    };
+    [
+        ("RegionHandle", Llsd::Binary(region_handle.to_be_bytes().to_vec())),
+        ("SimIP", Llsd::Binary(ip.octets().to_vec())),
+        ("SimPort", Llsd::from(sim_addr.port() as i32)),
+    ]
+}
+
+fn chatterbox_invitation(session_id: Uuid, message: &str) -> Llsd {
+    Llsd::from_iter([
+        ("session_id", Llsd::from(session_id)),
+        ("from_name", Llsd::from("Group Chat")),
+        ("instantmessage", Llsd::from_iter([("message_params", Llsd::from_iter([("message", Llsd::from(message))]))])),
+    ])
+}
+
+async fn next_chat(events: &Receiver<NetEvent>) -> String {
+    next_event(events, |event| match event {
+        NetEvent::ChatReceived { message, .. } => Some(message),
+        _ => None,
+    })
+    .await
+}
+
+async fn next_invitation(events: &Receiver<NetEvent>) -> (Uuid, String) {
+    next_event(events, |event| match event {
+        NetEvent::ChatSessionInvitation { session_id, message, .. } => Some((session_id, message)),
+        _ => None,
+    })
+    .await
+}
+
+#[tokio::test]
+async fn test_login_handshake_chat_logout() {
+    let mut grid = MockGrid::start().await;
+
+    let outcome = login_to_secondlife(&grid.grid(), &grid.login_request("wrong"), None, 0).await.unwrap();
+    assert!(matches!(outcome, LoginOutcome::InvalidCredentials { .. }));
+
+    let session = match login_to_secondlife(&grid.grid(), &grid.login_request(PASSWORD), None, 0).await.unwrap() {
+        LoginOutcome::Success(session) => session,
+        other => panic!("login failed: {:?}", other),
+    };
+    assert_eq!(session.first_name, mock_grid::FIRST);
+    assert_eq!(session.agent_id, grid.agent_id().to_string());
+    assert!(session.capabilities.as_ref().is_some_and(|caps| caps.contains("EventQueueGet")));
+
+    let (net, events) = actor::spawn_network_actor_with_cache(Some(grid.object_cache()));
+    let sim_addr = SocketAddr::new(session.sim_ip.parse().unwrap(), session.sim_port);
+    net.send(NetCommand::Connect { session, sim_addr, udp_port: 0, proxy_settings: None });
+    let use_circuit_code = grid.sim.expect::<generated::UseCircuitCode>().await;
+    assert_eq!(use_circuit_code.circuit_code.code, grid.circuit_code());
+    grid.sim.expect::<generated::RegionHandshakeReply>().await;
+    grid.sim.expect::<generated::AgentThrottle>().await;
+    grid.sim.expect::<generated::AgentUpdate>().await;
+    let connected = next_event(&events, |event| match event {
+        NetEvent::Connected { sim_addr } => Some(sim_addr),
+        NetEvent::ConnectFailed(e) => panic!("connect failed: {}", e),
+        _ => None,
+    })
+    .await;
+    assert_eq!(connected, sim_addr);
+
+    let speaker = Uuid::new_v4();
+    grid.sim.send(&sim::chat_from_simulator("Greeter", speaker, "Hello, avatar"), true).await;
+    let (from_name, source_id, message) = next_event(&events, |event| match event {
+        NetEvent::ChatReceived { from_name, source_id, message, .. } => Some((from_name, source_id, message)),
+        _ => None,
+    })
+    .await;
+    assert_eq!((from_name.as_str(), source_id, message.as_str()), ("Greeter", speaker, "Hello, avatar"));
+
+    net.send(NetCommand::SendChat { message: "hi there".to_string(), channel: 0 });
+    let chat = grid.sim.expect::<generated::ChatFromViewer>().await;
+    assert_eq!(chat.agent_data.session_id, grid.session_id());
+    assert_eq!(chat.chat_data.message, b"hi there\0");
+
+    let full_id = Uuid::new_v4();
+    grid.sim.send(&sim::object_update(MockGrid::region_handle(), 42, full_id, Vec3::new(10.0, 20.0, 30.0)), true).await;
+    let objects = net.objects();
+    wait_until(|| objects.lock().unwrap().get_by_full_id(&full_id).is_some()).await;
+    let object = objects.lock().unwrap().get(ObjectKey::new(MockGrid::region_handle(), 42)).cloned().unwrap();
+    assert_eq!(object.state.motion.position, Vec3::new(10.0, 20.0, 30.0));
+    grid.sim.send(&sim::kill_object(42), true).await;
+    wait_until(|| objects.lock().unwrap().is_empty()).await;
+
+    let conference = Uuid::new_v4();
+    grid.push_event("ChatterBoxInvitation", chatterbox_invitation(conference, "Welcome"));
+    assert_eq!(next_invitation(&events).await, (conference, "Welcome".to_string()));
+
+    net.send(NetCommand::Logout);
+    grid.sim.expect::<generated::LogoutRequest>().await;
+    next_event(&events, |event| matches!(event, NetEvent::LoggedOut).then_some(())).await;
+}
+
+#[tokio::test]
+async fn test_circuit_acks_injected_object_update() {
+    let mut grid = MockGrid::start().await;
+    let mut circuits = CircuitManager::bind(0, None, agent_state()).await.unwrap();
+    let params = CircuitParams::new(grid.agent_id(), grid.session_id(), grid.circuit_code());
+    let mut objects = None;
+    circuits
+        .open(MockGrid::region_handle(), grid.sim.addr(), params, Duration::from_secs(10), |circuit| {
+            objects = Some(circuit.bus().subscribe::<generated::ObjectUpdate>());
+        })
+        .await
+        .unwrap();
+    grid.sim.expect::<generated::RegionHandshakeReply>().await;
+
+    let full_id = Uuid::new_v4();
+    grid.sim.send(&sim::object_update(MockGrid::region_handle(), 42, full_id, Vec3::new(10.0, 20.0, 30.0)), true).await;
+    let update = tokio::time::timeout(Duration::from_secs(10), objects.unwrap().recv()).await.unwrap().unwrap();
+    let object = &update.message.object_data[0];
+    assert_eq!((object.id, object.full_id), (42, full_id));
+    assert_eq!(&object.object_data[..4], &10.0f32.to_le_bytes());
+
+    // The handshake and the update were all sent reliably; the circuit acks them in its next flush.
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while grid.sim.unacked() > 0 {
+        assert!(Instant::now() < deadline, "{} packets never acked", grid.sim.unacked());
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    circuits.logout().await;
+    grid.sim.expect::<generated::LogoutRequest>().await;
+}
+
+#[tokio::test]
+async fn test_two_regions_share_one_socket() {
+    let mut grid = MockGrid::start().await;
+    let neighbour_handle = circuit_manager::region_handle(mock_grid::REGION_X + 256, mock_grid::REGION_Y);
+    let mut neighbour = MockSim::bind(grid.agent_id(), grid.session_id(), neighbour_handle).await;
+    let mut circuits = CircuitManager::bind(0, None, agent_state()).await.unwrap();
+    let mut chats = Vec::new();
+    let regions = [(MockGrid::region_handle(), grid.sim.addr()), (neighbour_handle, neighbour.addr())];
+    for (region_handle, sim_addr) in regions {
+        let params = CircuitParams::new(grid.agent_id(), grid.session_id(), grid.circuit_code());
+        circuits
+            .open(region_handle, sim_addr, params, Duration::from_secs(10), |circuit| {
+                chats.push(circuit.bus().subscribe::<generated::ChatFromSimulator>());
+            })
+            .await
+            .unwrap();
+    }
+    grid.sim.expect::<generated::RegionHandshakeReply>().await;
+    neighbour.expect::<generated::RegionHandshakeReply>().await;
+
+    // Each simulator's datagrams reach only its own circuit.
+    let speaker = Uuid::new_v4();
+    neighbour.send(&sim::chat_from_simulator("Neighbour", speaker, "over here"), true).await;
+    grid.sim.send(&sim::chat_from_simulator("Home", speaker, "right here"), true).await;
+    let expected = [(grid.sim.addr(), "right here"), (neighbour.addr(), "over here")];
+    for (chat, (from, text)) in chats.iter_mut().zip(expected) {
+        let incoming = tokio::time::timeout(Duration::from_secs(10), chat.recv()).await.unwrap().unwrap();
+        assert_eq!(incoming.from, from);
+        assert_eq!(incoming.message.chat_data.message, format!("{}\0", text).into_bytes());
+    }
+
+    // Closing the neighbour leaves the home region's circuit running.
+    assert!(circuits.close(neighbour_handle).await);
+    neighbour.expect::<generated::CloseCircuit>().await;
+    neighbour.send(&sim::chat_from_simulator("Neighbour", speaker, "still here"), true).await;
+    grid.sim.send(&sim::chat_from_simulator("Home", speaker, "and here"), true).await;
+    let incoming = tokio::time::timeout(Duration::from_secs(10), chats[0].recv()).await.unwrap().unwrap();
+    assert_eq!((incoming.from, incoming.message.chat_data.message), (grid.sim.addr(), b"and here\0".to_vec()));
+    circuits.logout().await;
+    grid.sim.expect::<generated::LogoutRequest>().await;
+}
+
+#[tokio::test]
+async fn test_teleport_hands_over_to_the_destination() {
+    let mut grid = MockGrid::start().await;
+    let (net, events) = log_in(&mut grid).await;
+    let home = MockGrid::region_handle();
+    let destination = circuit_manager::region_handle(mock_grid::REGION_X + 2560, mock_grid::REGION_Y);
+    let mut sim = MockSim::bind(grid.agent_id(), grid.session_id(), destination).await;
+
+    let info = region_fields(destination, sim.addr()).into_iter().chain([
+        ("SeedCapability", Llsd::from(grid.seed_capability(destination))),
+        ("LocationID", Llsd::from(4)),
+        ("SimAccess", Llsd::from(13)),
+        ("TeleportFlags", Llsd::from(1 << 4)),
+    ]);
+    grid.push_event("TeleportFinish", Llsd::from_iter([("Info", Llsd::from(vec![Llsd::from_iter(info)]))]));
+    sim.expect::<generated::UseCircuitCode>().await;
+    sim.expect::<generated::CompleteAgentMovement>().await;
+    let finished = next_event(&events, |event| match event {
+        NetEvent::TeleportFinished { region_handle, sim_addr } => Some((region_handle, sim_addr)),
+        NetEvent::TeleportFailed { reason } => panic!("teleport failed: {}", reason),
+        _ => None,
+    })
+    .await;
+    assert_eq!(finished, (destination, sim.addr()));
+
+    // The old region's circuit and event queue are closed.
+    grid.sim.expect::<generated::CloseCircuit>().await;
+    wait_until(|| grid.event_queue_closed(home)).await;
+
+    // The destination is the agent's region now: its chat and event queue come through.
+    sim.send(&sim::chat_from_simulator("Greeter", Uuid::new_v4(), "Welcome back"), true).await;
+    assert_eq!(next_chat(&events).await, "Welcome back");
+    let conference = Uuid::new_v4();
+    grid.push_region_event(destination, "ChatterBoxInvitation", chatterbox_invitation(conference, "Arrived"));
+    assert_eq!(next_invitation(&events).await, (conference, "Arrived".to_string()));
+
+    net.send(NetCommand::Logout);
+    sim.expect::<generated::LogoutRequest>().await;
+    next_event(&events, |event| matches!(event, NetEvent::LoggedOut).then_some(())).await;
+}
+
+#[tokio::test]
+async fn test_crossing_promotes_the_neighbour() {
+    let mut grid = MockGrid::start().await;
+    let (net, events) = log_in(&mut grid).await;
+    let home = MockGrid::region_handle();
+    let neighbour = circuit_manager::region_handle(mock_grid::REGION_X + 256, mock_grid::REGION_Y);
+    let mut sim = MockSim::bind(grid.agent_id(), grid.session_id(), neighbour).await;
+
+    let [(_, handle), (_, ip), (_, port)] = region_fields(neighbour, sim.addr());
+    let info = Llsd::from_iter([("Handle", handle), ("IP", ip), ("Port", port)]);
+    grid.push_event("EnableSimulator", Llsd::from_iter([("SimulatorInfo", Llsd::from(vec![info]))]));
+    sim.expect::<generated::UseCircuitCode>().await;
+    sim.send(&sim::region_handshake(), true).await;
+    sim.expect::<generated::RegionHandshakeReply>().await;
+    let enabled = next_event(&events, |event| match event {
+        NetEvent::RegionEnabled { region_handle, .. } => Some(region_handle),
+        _ => None,
+    })
+    .await;
+    assert_eq!(enabled, neighbour);
+
+    let region_data = region_fields(neighbour, sim.addr())
+        .into_iter()
+        .chain([("SeedCapability", Llsd::from(grid.seed_capability(neighbour)))]);
+    let position = Llsd::from(vec![Llsd::from(2.0), Llsd::from(128.0), Llsd::from(25.0)]);
+    grid.push_event(
+        "CrossedRegion",
+        Llsd::from_iter([
+            ("RegionData", Llsd::from(vec![Llsd::from_iter(region_data)])),
+            ("Info", Llsd::from(vec![Llsd::from_iter([("Position", position)])])),
+        ]),
+    );
+    // The child circuit is promoted rather than reopened.
+    sim.expect::<generated::CompleteAgentMovement>().await;
+    let crossed = next_event(&events, |event| match event {
+        NetEvent::RegionCrossed { region_handle, sim_addr } => Some((region_handle, sim_addr)),
+        _ => None,
+    })
+    .await;
+    assert_eq!(crossed, (neighbour, sim.addr()));
+
+    // The region left behind stays open as a child: it still acks, but its chat is not the agent's.
+    grid.sim.send(&sim::chat_from_simulator("Greeter", Uuid::new_v4(), "Come back"), true).await;
+    wait_until(|| grid.sim.unacked() == 0).await;
+    sim.send(&sim::chat_from_simulator("Greeter", Uuid::new_v4(), "Welcome"), true).await;
+    assert_eq!(next_chat(&events).await, "Welcome");
+    assert!(!grid.event_queue_closed(home));
+
+    // The new region's event queue is followed.
+    let conference = Uuid::new_v4();
+    grid.push_region_event(neighbour, "ChatterBoxInvitation", chatterbox_invitation(conference, "Crossed"));
+    assert_eq!(next_invitation(&events).await, (conference, "Crossed".to_string()));
+
+    net.send(NetCommand::Logout);
+    sim.expect::<generated::LogoutRequest>().await;
+    next_event(&events, |event| matches!(event, NetEvent::LoggedOut).then_some(())).await;
+}
+
+#[tokio::test]
+async fn test_caps_refetch_the_seed_after_a_404() {
+    let grid = MockGrid::start().await;
+    let region = MockGrid::region_handle();
+    let mut caps = CapsClient::connect(grid.seed_capability(region), 0, None, None).await.unwrap();
+    let requested = &grid.seed_requests(region)[0];
+    assert!(["EventQueueGet", "SimulatorFeatures"].iter().all(|name| requested.iter().any(|r| r == name)));
+    assert!(caps.capabilities().contains("EventQueueGet"));
+
+    // An expired URL answers 404 once; the client re-posts to the seed and retries.
+    let stale = caps.url("SimulatorFeatures").unwrap().to_string();
+    grid.expire_capabilities();
+    let features = caps.simulator_features().await.unwrap();
+    assert_eq!(features["MeshRezEnabled"].as_bool(), Some(true));
+    assert_ne!(caps.url("SimulatorFeatures").unwrap(), stale);
+    assert_eq!(grid.seed_requests(region).len(), 2);
+
+    // Only the requested capabilities are granted.
+    let mut caps = CapsClient::new(grid.seed_capability(region), 0, None, None).unwrap().with_requested_caps(["SimulatorFeatures"]);
+    caps.refresh().await.unwrap();
+    assert_eq!(grid.seed_requests(region)[2], ["SimulatorFeatures"]);
+    assert!(caps.capabilities().contains("SimulatorFeatures") && !caps.capabilities().contains("EventQueueGet"));
+}
@@ -0,0 +1,333 @@
+//! An in-process fake grid for integration tests: an XML-RPC login server, per-region seed
+//! capabilities with EventQueueGet and SimulatorFeatures served over plain HTTP, and a UDP
+//! simulator ([`MockSim`]).
+//!
+//! ```ignore
+//! let mut grid = MockGrid::start().await;
+//! let outcome = login_to_secondlife(&grid.grid(), &grid.login_request(PASSWORD), None, 0).await?;
+//! grid.push_event("ChatterBoxInvitation", body);
+//! grid.sim.send(&sim::chat_from_simulator("Object", id, "hello"), true).await;
+//! ```
+
+pub mod sim;
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicI32, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+use slv_rust::config::grids::{Grid, GridKind};
+use slv_rust::networking::circuit_manager;
+use slv_rust::networking::llsd::{self, Llsd};
+use slv_rust::networking::session::{hash_password, LoginRequest};
+use slv_rust::networking::xmlrpc::{self, Value};
+use slv_rust::utils::platform::MachineIds;
+use slv_rust::world::object_cache::ObjectCache;
+
+pub use sim::MockSim;
+
+pub const FIRST: &str = "Test";
+pub const LAST: &str = "Resident";
+pub const PASSWORD: &str = "hunter2";
+
+/// Global position of the mock region, in metres.
+pub const REGION_X: u32 = 256000;
+pub const REGION_Y: u32 = 256256;
+
+/// How long EventQueueGet holds a poll open before answering 502, like a real simulator.
+const EVENT_POLL_TIMEOUT: Duration = Duration::from_millis(500);
+
+struct GridState {
+    base_url: String,
+    agent_id: Uuid,
+    session_id: Uuid,
+    circuit_code: u32,
+    sim_addr: SocketAddr,
+    /// Queued events by region handle.
+    events: Mutex<HashMap<u64, VecDeque<(String, Llsd)>>>,
+    events_ready: Notify,
+    /// Regions whose event queue the viewer closed with `done: true`.
+    closed_queues: Mutex<HashSet<u64>>,
+    /// The capability names each seed request asked for, by region handle.
+    seed_requests: Mutex<HashMap<u64, Vec<Vec<String>>>>,
+    /// Part of every SimulatorFeatures URL; bumping it expires the URLs handed out so far.
+    cap_generation: AtomicU32,
+    event_id: AtomicI32,
+}
+
+pub struct MockGrid {
+    pub sim: MockSim,
+    state: Arc<GridState>,
+    server: JoinHandle<()>,
+    /// Object cache for this grid's sessions, removed with it.
+    cache_dir: PathBuf,
+}
+
+impl Drop for MockGrid {
+    fn drop(&mut self) {
+        self.server.abort();
+        let _ = std::fs::remove_dir_all(&self.cache_dir);
+    }
+}
+
+impl MockGrid {
+    pub async fn start() -> Self {
+        let agent_id = Uuid::new_v4();
+        let session_id = Uuid::new_v4();
+        let sim = MockSim::bind(agent_id, session_id, Self::region_handle()).await;
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let state = Arc::new(GridState {
+            base_url: format!("http://{}", listener.local_addr().unwrap()),
+            agent_id,
+            session_id,
+            // XML-RPC ints are signed
+            circuit_code: rand::random::<u32>() & 0x7FFF_FFFF,
+            sim_addr: sim.addr(),
+            events: Mutex::new(HashMap::new()),
+            events_ready: Notify::new(),
+            closed_queues: Mutex::new(HashSet::new()),
+            seed_requests: Mutex::new(HashMap::new()),
+            cap_generation: AtomicU32::new(0),
+            event_id: AtomicI32::new(0),
+        });
+        let server = tokio::spawn(serve(listener, Arc::clone(&state)));
+        let cache_dir = std::env::temp_dir().join(format!("slv-mock-grid-{}", Uuid::new_v4()));
+        Self { sim, state, server, cache_dir }
+    }
+
+    /// An object cache private to this grid, to keep tests out of the user's cache directory.
+    pub fn object_cache(&self) -> ObjectCache {
+        ObjectCache::new(&self.cache_dir)
+    }
+
+    pub fn region_handle() -> u64 {
+        circuit_manager::region_handle(REGION_X, REGION_Y)
+    }
+
+    /// The grid as the login screen would have it after importing it.
+    pub fn grid(&self) -> Grid {
+        Grid {
+            nick: "mock".to_string(),
+            name: "Mock Grid".to_string(),
+            login_uri: format!("{}/login", self.state.base_url),
+            helper_uri: String::new(),
+            web_profile_url: String::new(),
+            openid_url: String::new(),
+            kind: GridKind::OpenSim,
+        }
+    }
+
+    /// A login request with the mock account's credentials.
+    pub fn login_request(&self, password: &str) -> LoginRequest {
+        LoginRequest::new(FIRST, LAST, password, &MachineIds::random())
+    }
+
+    pub fn agent_id(&self) -> Uuid {
+        self.state.agent_id
+    }
+
+    pub fn session_id(&self) -> Uuid {
+        self.state.session_id
+    }
+
+    pub fn circuit_code(&self) -> u32 {
+        self.state.circuit_code
+    }
+
+    /// The seed capability of a region; its EventQueueGet is only polled for that region's events.
+    pub fn seed_capability(&self, region_handle: u64) -> String {
+        seed_capability(&self.state, region_handle)
+    }
+
+    /// Queue an event for the login region's next EventQueueGet poll.
+    pub fn push_event(&self, message: &str, body: Llsd) {
+        self.push_region_event(Self::region_handle(), message, body);
+    }
+
+    /// Queue an event for another region's next EventQueueGet poll.
+    pub fn push_region_event(&self, region_handle: u64, message: &str, body: Llsd) {
+        self.state.events.lock().unwrap().entry(region_handle).or_default().push_back((message.to_string(), body));
+        self.state.events_ready.notify_waiters();
+    }
+
+    /// The capability names requested by each seed fetch for a region, oldest first.
+    pub fn seed_requests(&self, region_handle: u64) -> Vec<Vec<String>> {
+        self.state.seed_requests.lock().unwrap().get(&region_handle).cloned().unwrap_or_default()
+    }
+
+    /// Make the SimulatorFeatures URLs handed out so far answer 404, as after the agent's presence
+    /// in the region was re-established.
+    pub fn expire_capabilities(&self) {
+        self.state.cap_generation.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Whether the viewer told the region's event queue it is done with it.
+    pub fn event_queue_closed(&self, region_handle: u64) -> bool {
+        self.state.closed_queues.lock().unwrap().contains(&region_handle)
+    }
+}
+
+struct Request {
+    path: String,
+    body: Vec<u8>,
+}
+
+async fn serve(listener: TcpListener, state: Arc<GridState>) {
+    while let Ok((stream, _)) = listener.accept().await {
+        tokio::spawn(handle_connection(stream, Arc::clone(&state)));
+    }
+}
+
+/// One request per connection, answered with `Connection: close`.
+async fn handle_connection(mut stream: TcpStream, state: Arc<GridState>) {
+    let Some(request) = read_request(&mut stream).await else {
+        return;
+    };
+    let path = request.path.as_str();
+    let region = |prefix: &str| path.strip_prefix(prefix).and_then(|handle| handle.parse::<u64>().ok());
+    let (status, content_type, body) = if path == "/login" {
+        login(&state, &request.body)
+    } else if let Some(region_handle) = region("/seed/") {
+        seed(&state, region_handle, &request.body)
+    } else if let Some(region_handle) = region("/eq/") {
+        event_queue_get(&state, region_handle, &request.body).await
+    } else if path == format!("/cap/{}/SimulatorFeatures", state.cap_generation.load(Ordering::SeqCst)) {
+        let features = Llsd::from_iter([("MeshRezEnabled", Llsd::from(true)), ("MaxMaterialsPerTransaction", Llsd::from(50))]);
+        (200, "application/llsd+xml", llsd::xml::to_xml(&features))
+    } else {
+        (404, "text/plain", "not found".to_string())
+    };
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        if status == 200 { "OK" } else { "Error" },
+        content_type,
+        body.len()
+    );
+    let _ = stream.write_all(head.as_bytes()).await;
+    let _ = stream.write_all(body.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
+
+async fn read_request(stream: &mut TcpStream) -> Option<Request> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let header_end = loop {
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos + 4;
+        }
+        let n = stream.read(&mut chunk).await.ok().filter(|&n| n > 0)?;
+        buf.extend_from_slice(&chunk[..n]);
+    };
+    let head = String::from_utf8_lossy(&buf[..header_end]).to_string();
+    let mut lines = head.lines();
+    let path = lines.next()?.split_whitespace().nth(1)?.to_string();
+    let content_length = lines
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.trim().parse().ok())
+        .unwrap_or(0);
+    let mut body = buf[header_end..].to_vec();
+    while body.len() < content_length {
+        let n = stream.read(&mut chunk).await.ok().filter(|&n| n > 0)?;
+        body.extend_from_slice(&chunk[..n]);
+    }
+    body.truncate(content_length);
+    Some(Request { path, body })
+}
+
+fn login(state: &GridState, body: &[u8]) -> (u16, &'static str, String) {
+    let params = match xmlrpc::parse_method_call(&String::from_utf8_lossy(body)) {
+        Ok((method, params)) if method == "login_to_simulator" => params,
+        _ => return (400, "text/plain", "expected login_to_simulator".to_string()),
+    };
+    let request = params.first().cloned().unwrap_or_default();
+    let authorised = request["first"].as_str() == Some(FIRST)
+        && request["last"].as_str() == Some(LAST)
+        && request["passwd"].as_str() == Some(hash_password(PASSWORD).as_str());
+    let response: Value = if authorised {
+        [
+            ("login", Value::from("true")),
+            ("first_name", Value::from(format!("\"{}\"", FIRST))),
+            ("last_name", Value::from(LAST)),
+            ("agent_id", Value::from(state.agent_id.to_string())),
+            ("session_id", Value::from(state.session_id.to_string())),
+            ("secure_session_id", Value::from(Uuid::new_v4().to_string())),
+            ("sim_ip", Value::from(state.sim_addr.ip().to_string())),
+            ("sim_port", Value::Int(state.sim_addr.port() as i32)),
+            ("circuit_code", Value::Int(state.circuit_code as i32)),
+            ("region_x", Value::Int(REGION_X as i32)),
+            ("region_y", Value::Int(REGION_Y as i32)),
+            ("look_at", Value::from("[r1,r0,r0]")),
+            ("start_location", Value::from("last")),
+            ("seconds_since_epoch", Value::Int(0)),
+            ("seed_capability", Value::from(seed_capability(state, MockGrid::region_handle()))),
+            ("message", Value::from("Welcome to the mock grid")),
+        ]
+        .into_iter()
+        .collect()
+    } else {
+        [
+            ("login", Value::from("false")),
+            ("reason", Value::from("key")),
+            ("message", Value::from("Sorry! We couldn't log you in.")),
+        ]
+        .into_iter()
+        .collect()
+    };
+    (200, "text/xml", xmlrpc::method_response(&response))
+}
+
+fn seed_capability(state: &GridState, region_handle: u64) -> String {
+    format!("{}/seed/{}", state.base_url, region_handle)
+}
+
+/// Grant whichever of the mock's capabilities the posted LLSD array of names asks for.
+fn seed(state: &GridState, region_handle: u64, body: &[u8]) -> (u16, &'static str, String) {
+    let request = llsd::xml::from_xml(&String::from_utf8_lossy(body)).unwrap_or_default();
+    let requested: Vec<String> = request.as_array().unwrap_or_default().iter().filter_map(Llsd::as_string).collect();
+    let granted = [
+        ("EventQueueGet", format!("{}/eq/{}", state.base_url, region_handle)),
+        ("SimulatorFeatures", format!("{}/cap/{}/SimulatorFeatures", state.base_url, state.cap_generation.load(Ordering::SeqCst))),
+    ]
+    .into_iter()
+    .filter(|(name, _)| requested.iter().any(|r| r == name));
+    let response = llsd::xml::to_xml(&Llsd::from_iter(granted));
+    state.seed_requests.lock().unwrap().entry(region_handle).or_default().push(requested);
+    (200, "application/llsd+xml", response)
+}
+
+async fn event_queue_get(state: &GridState, region_handle: u64, body: &[u8]) -> (u16, &'static str, String) {
+    let request = llsd::xml::from_xml(&String::from_utf8_lossy(body)).unwrap_or_default();
+    let mut events = Vec::new();
+    if request["done"].as_bool() == Some(true) {
+        state.closed_queues.lock().unwrap().insert(region_handle);
+    } else {
+        let deadline = tokio::time::Instant::now() + EVENT_POLL_TIMEOUT;
+        loop {
+            let ready = state.events_ready.notified();
+            events.extend(state.events.lock().unwrap().get_mut(&region_handle).into_iter().flat_map(|queue| queue.drain(..)));
+            if !events.is_empty() || tokio::time::timeout_at(deadline, ready).await.is_err() {
+                break;
+            }
+        }
+        if events.is_empty() {
+            return (502, "text/plain", "Upstream error".to_string());
+        }
+    }
+    let events = events
+        .into_iter()
+        .map(|(message, body)| Llsd::from_iter([("message", Llsd::from(message)), ("body", body)]))
+        .collect::<Vec<_>>();
+    let id = state.event_id.fetch_add(1, Ordering::SeqCst) + 1;
+    let response = Llsd::from_iter([("events", Llsd::Array(events)), ("id", Llsd::Integer(id))]);
+    (200, "application/llsd+xml", llsd::xml::to_xml(&response))
+}
@@ -0,0 +1,291 @@
+//! A UDP simulator that speaks just enough LLUDP to log an agent in: it answers the
+//! UseCircuitCode / CompleteAgentMovement handshake, acks reliable packets, replies to pings
+//! and logout, and lets the test inject any message.
+
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use glam::Vec3;
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+use slv_rust::networking::protocol::builders;
+use slv_rust::networking::protocol::generated::{self, Message, TemplateMessage};
+use slv_rust::networking::protocol::packet::Packet;
+
+/// How long `expect` waits for the client before failing the test.
+const EXPECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+const SIM_NAME: &str = "Mock Region";
+
+struct SimState {
+    /// Where the viewer's packets come from; known after UseCircuitCode.
+    client: Option<SocketAddr>,
+    next_sequence: u32,
+    /// Our reliable packets the viewer has not acked yet.
+    unacked: HashSet<u32>,
+}
+
+pub struct MockSim {
+    addr: SocketAddr,
+    socket: Arc<UdpSocket>,
+    state: Arc<Mutex<SimState>>,
+    received: mpsc::UnboundedReceiver<Message>,
+    task: JoinHandle<()>,
+}
+
+impl Drop for MockSim {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl MockSim {
+    pub async fn bind(agent_id: Uuid, session_id: Uuid, region_handle: u64) -> Self {
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let addr = socket.local_addr().unwrap();
+        let state = Arc::new(Mutex::new(SimState { client: None, next_sequence: 1, unacked: HashSet::new() }));
+        let (received_tx, received) = mpsc::unbounded_channel();
+        let task = tokio::spawn(run(Arc::clone(&socket), Arc::clone(&state), received_tx, agent_id, session_id, region_handle));
+        Self { addr, socket, state, received, task }
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Send a message to the viewer. Panics if it has not opened a circuit yet.
+    pub async fn send(&self, message: &Message, reliable: bool) {
+        assert!(self.state.lock().unwrap().client.is_some(), "no viewer connected to the mock sim");
+        send(&self.socket, &self.state, message, reliable).await;
+    }
+
+    /// Wait for the viewer to send a `T`, skipping everything else.
+    pub async fn expect<T: TemplateMessage>(&mut self) -> T {
+        let wait = async {
+            while let Some(message) = self.received.recv().await {
+                if let Some(message) = T::from_message(&message) {
+                    return message.clone();
+                }
+            }
+            panic!("mock sim stopped while waiting for {}", T::NAME);
+        };
+        tokio::time::timeout(EXPECT_TIMEOUT, wait).await.unwrap_or_else(|_| panic!("viewer never sent {}", T::NAME))
+    }
+
+    /// Reliable packets sent to the viewer that it has not acked.
+    pub fn unacked(&self) -> usize {
+        self.state.lock().unwrap().unacked.len()
+    }
+}
+
+async fn send(socket: &UdpSocket, state: &Mutex<SimState>, message: &Message, reliable: bool) {
+    let (client, sequence) = {
+        let mut state = state.lock().unwrap();
+        let Some(client) = state.client else {
+            return;
+        };
+        let sequence = state.next_sequence;
+        state.next_sequence += 1;
+        if reliable {
+            state.unacked.insert(sequence);
+        }
+        (client, sequence)
+    };
+    let packet = Packet::from_message(message, sequence, reliable).unwrap().encode().unwrap();
+    socket.send_to(&packet, client).await.unwrap();
+}
+
+async fn run(
+    socket: Arc<UdpSocket>,
+    state: Arc<Mutex<SimState>>,
+    received: mpsc::UnboundedSender<Message>,
+    agent_id: Uuid,
+    session_id: Uuid,
+    region_handle: u64,
+) {
+    let mut buf = vec![0; 4096];
+    while let Ok((len, from)) = socket.recv_from(&mut buf).await {
+        let Ok(packet) = Packet::decode(&buf[..len]) else {
+            continue;
+        };
+        {
+            let mut state = state.lock().unwrap();
+            for ack in &packet.acks {
+                state.unacked.remove(ack);
+            }
+        }
+        let Ok(message) = packet.message() else {
+            continue;
+        };
+        if let Message::UseCircuitCode(_) = &message {
+            state.lock().unwrap().client = Some(from);
+        }
+        if packet.is_reliable() {
+            send(&socket, &state, &builders::packet_ack(&[packet.sequence]), false).await;
+        }
+        match &message {
+            Message::PacketAck(ack) => {
+                let mut state = state.lock().unwrap();
+                for packet in &ack.packets {
+                    state.unacked.remove(&packet.id);
+                }
+            }
+            Message::CompleteAgentMovement(_) => {
+                send(&socket, &state, &region_handshake(), true).await;
+                send(&socket, &state, &agent_movement_complete(agent_id, session_id, region_handle), true).await;
+            }
+            Message::StartPingCheck(ping) => {
+                send(&socket, &state, &builders::complete_ping_check(ping.ping_id.ping_id), false).await;
+            }
+            Message::LogoutRequest(_) => {
+                send(&socket, &state, &logout_reply(agent_id, session_id), true).await;
+            }
+            _ => {}
+        }
+        let _ = received.send(message);
+    }
+}
+
+/// Sent to an agent moving into the region, and by neighbours to child agents after UseCircuitCode.
+pub fn region_handshake() -> Message {
+    Message::RegionHandshake(generated::RegionHandshake {
+        region_info: generated::region_handshake::RegionInfo {
+            region_flags: 0,
+            sim_access: 13,
+            sim_name: format!("{}\0", SIM_NAME).into_bytes(),
+            sim_owner: Uuid::nil(),
+            is_estate_manager: false,
+            water_height: 20.0,
+            billable_factor: 1.0,
+            cache_id: Uuid::new_v4(),
+            terrain_base0: Uuid::nil(),
+            terrain_base1: Uuid::nil(),
+            terrain_base2: Uuid::nil(),
+            terrain_base3: Uuid::nil(),
+            terrain_detail0: Uuid::nil(),
+            terrain_detail1: Uuid::nil(),
+            terrain_detail2: Uuid::nil(),
+            terrain_detail3: Uuid::nil(),
+            terrain_start_height00: 0.0,
+            terrain_start_height01: 0.0,
+            terrain_start_height10: 0.0,
+            terrain_start_height11: 0.0,
+            terrain_height_range00: 0.0,
+            terrain_height_range01: 0.0,
+            terrain_height_range10: 0.0,
+            terrain_height_range11: 0.0,
+        },
+        region_info2: generated::region_handshake::RegionInfo2 { region_id: Uuid::new_v4() },
+        region_info3: generated::region_handshake::RegionInfo3 {
+            cpu_class_id: 0,
+            cpu_ratio: 1,
+            colo_name: b"\0".to_vec(),
+            product_sku: b"\0".to_vec(),
+            product_name: b"Mock\0".to_vec(),
+        },
+        region_info4: Vec::new(),
+    })
+}
+
+fn agent_movement_complete(agent_id: Uuid, session_id: Uuid, region_handle: u64) -> Message {
+    Message::AgentMovementComplete(generated::AgentMovementComplete {
+        agent_data: generated::agent_movement_complete::AgentData { agent_id, session_id },
+        data: generated::agent_movement_complete::Data {
+            position: Vec3::new(128.0, 128.0, 25.0),
+            look_at: Vec3::X,
+            region_handle,
+            timestamp: 0,
+        },
+        sim_data: generated::agent_movement_complete::SimData { channel_version: b"Mock Simulator 1.0\0".to_vec() },
+    })
+}
+
+fn logout_reply(agent_id: Uuid, session_id: Uuid) -> Message {
+    Message::LogoutReply(generated::LogoutReply {
+        agent_data: generated::logout_reply::AgentData { agent_id, session_id },
+        inventory_data: Vec::new(),
+    })
+}
+
+/// Local chat from an object or avatar next to the agent.
+pub fn chat_from_simulator(from_name: &str, source_id: Uuid, message: &str) -> Message {
+    Message::ChatFromSimulator(generated::ChatFromSimulator {
+        chat_data: generated::chat_from_simulator::ChatData {
+            from_name: format!("{}\0", from_name).into_bytes(),
+            source_id,
+            owner_id: source_id,
+            source_type: 1,
+            chat_type: 1,
+            audible: 1,
+            position: Vec3::new(128.0, 129.0, 25.0),
+            message: format!("{}\0", message).into_bytes(),
+        },
+    })
+}
+
+/// A full update for a half-metre box at `position`.
+pub fn object_update(region_handle: u64, local_id: u32, full_id: Uuid, position: Vec3) -> Message {
+    // Position, then zero velocity, acceleration, rotation and angular velocity
+    let mut motion: Vec<u8> = position.to_array().iter().flat_map(|f| f.to_le_bytes()).collect();
+    motion.resize(60, 0);
+    Message::ObjectUpdate(generated::ObjectUpdate {
+        region_data: generated::object_update::RegionData { region_handle, time_dilation: u16::MAX },
+        object_data: vec![generated::object_update::ObjectData {
+            id: local_id,
+            state: 0,
+            full_id,
+            crc: 1,
+            p_code: 9,
+            material: 3,
+            click_action: 0,
+            scale: Vec3::splat(0.5),
+            object_data: motion,
+            parent_id: 0,
+            update_flags: 0,
+            path_curve: 16,
+            profile_curve: 1,
+            path_begin: 0,
+            path_end: 0,
+            path_scale_x: 100,
+            path_scale_y: 100,
+            path_shear_x: 0,
+            path_shear_y: 0,
+            path_twist: 0,
+            path_twist_begin: 0,
+            path_radius_offset: 0,
+            path_taper_x: 0,
+            path_taper_y: 0,
+            path_revolutions: 0,
+            path_skew: 0,
+            profile_begin: 0,
+            profile_end: 0,
+            profile_hollow: 0,
+            texture_entry: Vec::new(),
+            texture_anim: Vec::new(),
+            name_value: Vec::new(),
+            data: Vec::new(),
+            text: Vec::new(),
+            text_color: [0; 4],
+            media_url: Vec::new(),
+            ps_block: Vec::new(),
+            extra_params: Vec::new(),
+            sound: Uuid::nil(),
+            owner_id: Uuid::nil(),
+            gain: 0.0,
+            flags: 0,
+            radius: 0.0,
+            joint_type: 0,
+            joint_pivot: Vec3::ZERO,
+            joint_axis_or_anchor: Vec3::ZERO,
+        }],
+    })
+}
+
+pub fn kill_object(local_id: u32) -> Message {
+    Message::KillObject(generated::KillObject { object_data: vec![generated::kill_object::ObjectData { id: local_id }] })
+}